@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::{
+    media_type, players, sql,
+    types::{AppState, Channel, ChannelHttpHeaders, CustomChannel},
+};
+
+/// Transient channels are never in the database, the negative id also keeps mpv ipc off
+const TRANSIENT_ID: i64 = -1;
+const SAVED_SOURCE_NAME: &str = "Ad-hoc";
+pub const PLAYABLE_EXTENSIONS: [&str; 2] = ["m3u8", "ts"];
+
+/// Plays a url or a local file without creating a channel. The last ad-hoc play
+/// is remembered so it can be saved as a custom channel afterwards
+pub async fn play(
+    app: &AppHandle,
+    url: String,
+    headers: Option<ChannelHttpHeaders>,
+    record: bool,
+) -> Result<()> {
+    let url = url.trim().to_string();
+    if url.is_empty() {
+        bail!("Nothing to play");
+    }
+    let channel = get_transient_channel(url);
+    let process = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().await;
+        state.last_adhoc = Some(CustomChannel {
+            data: channel.clone(),
+            headers: headers.clone(),
+        });
+        state.playback.player.clone()
+    };
+    let playing = channel.clone();
+    let play = players::play_transient(channel, headers, record, process);
+    players::report_playback(app, &playing, record, play).await
+}
+
+/// Persists the last ad-hoc play in the "Ad-hoc" custom source, `name` replaces the
+/// name guessed from the url
+pub async fn save_last(app: &AppHandle, name: Option<String>) -> Result<()> {
+    let mut channel = app
+        .state::<Mutex<AppState>>()
+        .lock()
+        .await
+        .last_adhoc
+        .clone()
+        .context("Nothing was played ad-hoc yet")?;
+    if let Some(name) = name.map(|name| name.trim().to_string()) {
+        if !name.is_empty() {
+            channel.data.name = name;
+        }
+    }
+    sql::do_tx(|tx| {
+        let source = sql::get_custom_source(SAVED_SOURCE_NAME.to_string());
+        channel.data.id = None;
+        channel.data.source_id = Some(sql::create_or_find_source_by_name(tx, &source)?);
+        sql::add_custom_channel(tx, channel)
+    })
+}
+
+pub fn is_playable_file(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            PLAYABLE_EXTENSIONS
+                .iter()
+                .any(|playable| ext.eq_ignore_ascii_case(playable))
+        })
+}
+
+fn get_transient_channel(url: String) -> Channel {
+    let remote = is_remote(&url);
+    Channel {
+        id: Some(TRANSIENT_ID),
+        name: get_name(&url),
+        url: Some(url),
+        group: None,
+        group_id: None,
+        image: None,
+        media_type: match remote {
+            true => media_type::LIVESTREAM,
+            false => media_type::MOVIE,
+        },
+        source_id: None,
+        series_id: None,
+        favorite: false,
+        watched: false,
+        stream_id: None,
+        tvg_id: None,
+        media_types: None,
+        catchup: None,
+    }
+}
+
+fn is_remote(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| url.scheme() != "file" && url.has_host())
+}
+
+/// The file name without its extension, or the last path segment of a url
+fn get_name(url: &str) -> String {
+    let path = match Url::parse(url) {
+        Ok(parsed) if parsed.has_host() => {
+            let segment = parsed
+                .path_segments()
+                .and_then(|mut segments| segments.rfind(|segment| !segment.is_empty()))
+                .map(|segment| segment.to_string());
+            match segment {
+                Some(segment) => segment,
+                None => return parsed.host_str().unwrap_or(url).to_string(),
+            }
+        }
+        Ok(parsed) if parsed.scheme() == "file" => parsed
+            .to_file_path()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or(url.to_string()),
+        _ => url.to_string(),
+    };
+    Path::new(&path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod test_adhoc {
+    use super::{get_name, get_transient_channel, is_playable_file};
+    use crate::media_type;
+
+    #[test]
+    fn test_get_name() {
+        assert_eq!(get_name("https://cdn.local/live/news/index.m3u8"), "index");
+        assert_eq!(get_name("https://cdn.local/live/news/"), "news");
+        assert_eq!(get_name("http://cdn.local"), "cdn.local");
+        assert_eq!(get_name("/home/fred/Videos/match.ts"), "match");
+        assert_eq!(get_name("file:///home/fred/Videos/match.ts"), "match");
+    }
+
+    #[test]
+    fn test_transient_channel() {
+        let channel = get_transient_channel("https://cdn.local/a.m3u8".to_string());
+        assert_eq!(channel.id, Some(-1));
+        assert_eq!(channel.media_type, media_type::LIVESTREAM);
+        let channel = get_transient_channel("/home/fred/Videos/match.ts".to_string());
+        assert_eq!(channel.media_type, media_type::MOVIE);
+        assert!(is_playable_file("/home/fred/Videos/MATCH.TS"));
+        assert!(!is_playable_file("/home/fred/notes.txt"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+    events,
+    settings::{get_settings, DEFAULT_AUTO_BACKUP_COUNT},
+    sql,
+};
+
+const DAY_SECONDS: i64 = 86400;
+const STARTUP_DELAY: Duration = Duration::from_secs(120);
+const CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+const FILE_PREFIX: &str = "open-tv-backup-";
+const FILE_EXTENSION: &str = ".sqlite";
+
+/// Backs the database up whenever one is due, first a little after startup so launching
+/// isn't slowed down. Failures are logged and reported without stopping the schedule
+pub async fn schedule_backups(app: AppHandle) {
+    tokio::time::sleep(STARTUP_DELAY).await;
+    loop {
+        let result = tokio::task::spawn_blocking(backup_if_due).await;
+        if let Err(e) = result
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result)
+        {
+            tracing::warn!("Automatic backup failed: {:?}", e);
+            if let Err(e) = app.emit(events::BACKUP_FAILED, e.to_string()) {
+                tracing::warn!("Failed to emit {}: {:?}", events::BACKUP_FAILED, e);
+            }
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+fn backup_if_due() -> Result<()> {
+    let settings = get_settings()?;
+    let Some(interval) = get_interval(settings.auto_backup.as_deref()) else {
+        return Ok(());
+    };
+    let now = chrono::Utc::now().timestamp();
+    if sql::get_last_backup_at()?.is_some_and(|last| now - last < interval) {
+        return Ok(());
+    }
+    let folder = match settings.auto_backup_path {
+        Some(path) => path,
+        None => get_default_backup_path()?,
+    };
+    std::fs::create_dir_all(&folder)?;
+    let name = format!(
+        "{FILE_PREFIX}{}{FILE_EXTENSION}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let path = folder.join(name);
+    if !sql::backup_to(&path.to_string_lossy())? {
+        tracing::info!("Automatic backup postponed, an import is running");
+        return Ok(());
+    }
+    tracing::info!("Database backed up to {}", path.to_string_lossy());
+    rotate(
+        &folder,
+        settings
+            .auto_backup_count
+            .unwrap_or(DEFAULT_AUTO_BACKUP_COUNT) as usize,
+    )
+}
+
+fn get_interval(frequency: Option<&str>) -> Option<i64> {
+    match frequency {
+        Some("daily") => Some(DAY_SECONDS),
+        Some("weekly") => Some(7 * DAY_SECONDS),
+        _ => None,
+    }
+}
+
+/// Next to the database, so a portable install keeps its backups with it
+fn get_default_backup_path() -> Result<PathBuf> {
+    let db = PathBuf::from(sql::get_db_path()?);
+    Ok(db
+        .parent()
+        .context("Database has no parent folder")?
+        .join("backups"))
+}
+
+fn rotate(folder: &Path, keep: usize) -> Result<()> {
+    let names = std::fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    for name in get_expired(names, keep) {
+        std::fs::remove_file(folder.join(&name))?;
+    }
+    Ok(())
+}
+
+/// Backups beyond the `keep` most recent, other files of the folder are left alone
+fn get_expired(names: Vec<String>, keep: usize) -> Vec<String> {
+    let mut backups: Vec<String> = names
+        .into_iter()
+        .filter(|name| name.starts_with(FILE_PREFIX) && name.ends_with(FILE_EXTENSION))
+        .collect();
+    // timestamps in the names sort chronologically
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+    backups.into_iter().skip(keep).collect()
+}
+
+#[cfg(test)]
+mod test_backup {
+    use super::{get_expired, get_interval, DAY_SECONDS};
+
+    #[test]
+    fn test_get_expired() {
+        let names = vec![
+            "open-tv-backup-20261001-030000.sqlite".to_string(),
+            "notes.txt".to_string(),
+            "open-tv-backup-20261015-030000.sqlite".to_string(),
+            "open-tv-backup-20261008-030000.sqlite".to_string(),
+        ];
+        assert_eq!(
+            get_expired(names.clone(), 2),
+            vec!["open-tv-backup-20261001-030000.sqlite"]
+        );
+        assert!(get_expired(names, 5).is_empty());
+    }
+
+    #[test]
+    fn test_get_interval() {
+        assert_eq!(get_interval(Some("weekly")), Some(7 * DAY_SECONDS));
+        assert_eq!(get_interval(Some("off")), None);
+        assert_eq!(get_interval(None), None);
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use regex::Regex;
+use rust_cast::{
+    channels::{
+        media::{Media, StreamType},
+        receiver::CastDeviceApp,
+    },
+    CastDevice as Chromecast,
+};
+use tauri::State;
+use tokio::{
+    join,
+    net::UdpSocket,
+    sync::Mutex,
+    time::{timeout, Instant},
+};
+use url::Url;
+
+use crate::{
+    cast_protocol, relay, sql,
+    types::{AppState, CastDevice, Channel},
+    utils::get_playable_url,
+};
+
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const CHROMECAST_SERVICE: &str = "_googlecast._tcp.local.";
+const CHROMECAST_RECEIVER: &str = "receiver-0";
+const DEFAULT_MEDIA_RECEIVER_ID: &str = "CC1AD845";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const RENDERING_CONTROL: &str = "urn:schemas-upnp-org:service:RenderingControl:1";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const SEARCH_REQUEST: &str = "M-SEARCH * HTTP/1.1\r\n\
+HOST: 239.255.255.250:1900\r\n\
+MAN: \"ssdp:discover\"\r\n\
+MX: 2\r\n\
+ST: urn:schemas-upnp-org:service:AVTransport:1\r\n\r\n";
+
+static LOCATION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?im)^location:\s*(?P<location>\S+)"#).unwrap());
+static FRIENDLY_NAME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<friendlyName>(?P<name>[^<]*)</friendlyName>"#).unwrap());
+static UDN_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<UDN>(?P<udn>[^<]*)</UDN>"#).unwrap());
+static SERVICE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)<service>(?P<service>.*?)</service>"#).unwrap());
+static SERVICE_TYPE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<serviceType>(?P<type>[^<]*)</serviceType>"#).unwrap());
+static CONTROL_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<controlURL>(?P<url>[^<]*)</controlURL>"#).unwrap());
+
+pub async fn get_cast_devices(
+    state: State<'_, Mutex<AppState>>,
+    refresh: bool,
+) -> Result<Vec<CastDevice>> {
+    if !refresh {
+        if let Some(devices) = state.lock().await.cast_devices.clone() {
+            return Ok(devices);
+        }
+    }
+    let devices = discover().await?;
+    state.lock().await.cast_devices = Some(devices.clone());
+    Ok(devices)
+}
+
+/// Looks for DLNA renderers over SSDP and Chromecasts over mDNS at the same time, one
+/// failing only hides its own devices
+async fn discover() -> Result<Vec<CastDevice>> {
+    let (dlna, chromecasts) = join!(discover_dlna(), discover_chromecasts());
+    match (dlna, chromecasts) {
+        (Ok(mut dlna), Ok(chromecasts)) => {
+            dlna.extend(chromecasts);
+            Ok(dlna)
+        }
+        (Ok(devices), Err(e)) | (Err(e), Ok(devices)) => {
+            tracing::warn!("Cast discovery failed: {:?}", e);
+            Ok(devices)
+        }
+        (Err(e), Err(_)) => Err(e),
+    }
+}
+
+async fn discover_chromecasts() -> Result<Vec<CastDevice>> {
+    let mdns = ServiceDaemon::new()?;
+    let receiver = mdns.browse(CHROMECAST_SERVICE)?;
+    let mut devices: HashMap<String, CastDevice> = HashMap::new();
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match timeout(remaining, receiver.recv_async()).await {
+            Ok(Ok(ServiceEvent::ServiceResolved(info))) => {
+                if let Some(device) = get_chromecast(&info) {
+                    devices.insert(device.id.clone(), device);
+                }
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(_)) | Err(_) => break,
+        }
+    }
+    if let Err(e) = mdns.shutdown() {
+        tracing::warn!("Failed to stop mDNS discovery: {:?}", e);
+    }
+    Ok(devices.into_values().collect())
+}
+
+fn get_chromecast(info: &ServiceInfo) -> Option<CastDevice> {
+    let address = info.get_addresses().iter().next()?;
+    Some(CastDevice {
+        id: info
+            .get_property_val_str("id")
+            .unwrap_or(info.get_fullname())
+            .to_string(),
+        name: info
+            .get_property_val_str("fn")
+            .unwrap_or(info.get_hostname())
+            .to_string(),
+        protocol: cast_protocol::CHROMECAST,
+        location: SocketAddr::from((*address, info.get_port())).to_string(),
+        av_transport_url: None,
+        rendering_control_url: None,
+    })
+}
+
+async fn discover_dlna() -> Result<Vec<CastDevice>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.send_to(SEARCH_REQUEST.as_bytes(), SSDP_ADDR).await?;
+    let mut locations: HashSet<String> = HashSet::new();
+    let mut buf = [0u8; 2048];
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match timeout(remaining, socket.recv_from(&mut buf)).await {
+            Ok(Ok((len, _))) => {
+                let response = String::from_utf8_lossy(&buf[..len]);
+                if let Some(location) = LOCATION_REGEX
+                    .captures(&response)
+                    .and_then(|c| c.name("location"))
+                {
+                    locations.insert(location.as_str().to_string());
+                }
+            }
+            Ok(Err(e)) => {
+                tracing::warn!("SSDP discovery failed: {:?}", e);
+                break;
+            }
+            Err(_) => break,
+        }
+    }
+    let mut devices = Vec::with_capacity(locations.len());
+    for location in locations {
+        match get_device(location).await {
+            Ok(device) => devices.push(device),
+            Err(e) => tracing::warn!("{:?}", e),
+        }
+    }
+    Ok(devices)
+}
+
+// Renderers are on the LAN, the proxy is left out of these requests
+async fn get_device(location: String) -> Result<CastDevice> {
+    let description = reqwest::Client::new()
+        .get(&location)
+        .timeout(HTTP_TIMEOUT)
+        .send()
+        .await?
+        .text()
+        .await?;
+    let base = Url::parse(&location)?;
+    let mut av_transport_url = None;
+    let mut rendering_control_url = None;
+    for service in SERVICE_REGEX.captures_iter(&description) {
+        let service = &service["service"];
+        let service_type = SERVICE_TYPE_REGEX
+            .captures(service)
+            .map(|c| c["type"].to_string());
+        let control_url = CONTROL_URL_REGEX
+            .captures(service)
+            .and_then(|c| base.join(c["url"].trim()).ok())
+            .map(|u| u.to_string());
+        match service_type.as_deref() {
+            Some(AV_TRANSPORT) => av_transport_url = control_url,
+            Some(RENDERING_CONTROL) => rendering_control_url = control_url,
+            _ => {}
+        }
+    }
+    Ok(CastDevice {
+        id: UDN_REGEX
+            .captures(&description)
+            .map(|c| c["udn"].to_string())
+            .unwrap_or(location.clone()),
+        name: FRIENDLY_NAME_REGEX
+            .captures(&description)
+            .map(|c| c["name"].to_string())
+            .unwrap_or(location.clone()),
+        protocol: cast_protocol::DLNA,
+        av_transport_url: Some(
+            av_transport_url.with_context(|| format!("{location} has no AVTransport service"))?,
+        ),
+        rendering_control_url,
+        location,
+    })
+}
+
+/// Where the device listens, the relay serves it from the address reaching it
+async fn get_device_ip(device: &CastDevice) -> Result<IpAddr> {
+    if device.protocol == cast_protocol::CHROMECAST {
+        return Ok(device.location.parse::<SocketAddr>()?.ip());
+    }
+    let url = Url::parse(&device.location)?;
+    let host = url.host_str().context("The cast device has no host")?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await?
+        .next()
+        .map(|addr| addr.ip())
+        .with_context(|| format!("Could not resolve {host}"))
+}
+
+fn get_av_transport_url(device: &CastDevice) -> Result<&str> {
+    device
+        .av_transport_url
+        .as_deref()
+        .context("This device has no AVTransport service")
+}
+
+async fn get_device_by_id(state: &State<'_, Mutex<AppState>>, id: &str) -> Result<CastDevice> {
+    state
+        .lock()
+        .await
+        .cast_devices
+        .as_ref()
+        .and_then(|devices| devices.iter().find(|d| d.id == id).cloned())
+        .context("Cast device not found, please refresh the device list")
+}
+
+pub async fn cast_channel(
+    state: State<'_, Mutex<AppState>>,
+    channel_id: i64,
+    device_id: String,
+) -> Result<()> {
+    let device = get_device_by_id(&state, &device_id).await?;
+    let channel = sql::get_channel_by_id(channel_id)?;
+    let playable_url = get_playable_url(&channel)?;
+    let content_type = guess_content_type(&playable_url);
+    // devices can't send the headers, they stream those channels through the relay
+    let url = match sql::get_channel_headers_by_id(channel_id)? {
+        Some(_) => relay::get_cast_url(&state, channel_id, get_device_ip(&device).await?).await?,
+        None => playable_url,
+    };
+    if device.protocol == cast_protocol::CHROMECAST {
+        let address = device.location.parse::<SocketAddr>()?;
+        return tokio::task::spawn_blocking(move || {
+            chromecast_load(address, url, content_type.to_string())
+        })
+        .await?;
+    }
+    let av_transport_url = get_av_transport_url(&device)?;
+    let metadata = get_didl_metadata(&channel, &url, content_type);
+    soap_call(
+        av_transport_url,
+        AV_TRANSPORT,
+        "SetAVTransportURI",
+        &format!(
+            "<InstanceID>0</InstanceID><CurrentURI>{}</CurrentURI><CurrentURIMetaData>{}</CurrentURIMetaData>",
+            escape_xml(&url),
+            escape_xml(&metadata)
+        ),
+    )
+    .await?;
+    soap_call(
+        av_transport_url,
+        AV_TRANSPORT,
+        "Play",
+        "<InstanceID>0</InstanceID><Speed>1</Speed>",
+    )
+    .await
+}
+
+pub async fn cast_control(
+    state: State<'_, Mutex<AppState>>,
+    device_id: String,
+    action: String,
+) -> Result<()> {
+    let device = get_device_by_id(&state, &device_id).await?;
+    let (action, args) = match action.as_str() {
+        "play" => ("Play", "<InstanceID>0</InstanceID><Speed>1</Speed>"),
+        "pause" => ("Pause", "<InstanceID>0</InstanceID>"),
+        "stop" => ("Stop", "<InstanceID>0</InstanceID>"),
+        _ => bail!("Invalid cast action"),
+    };
+    if device.protocol == cast_protocol::CHROMECAST {
+        let address = device.location.parse::<SocketAddr>()?;
+        return tokio::task::spawn_blocking(move || chromecast_control(address, action)).await?;
+    }
+    soap_call(get_av_transport_url(&device)?, AV_TRANSPORT, action, args).await
+}
+
+pub async fn cast_set_volume(
+    state: State<'_, Mutex<AppState>>,
+    device_id: String,
+    volume: u8,
+) -> Result<()> {
+    let device = get_device_by_id(&state, &device_id).await?;
+    if device.protocol == cast_protocol::CHROMECAST {
+        let address = device.location.parse::<SocketAddr>()?;
+        return tokio::task::spawn_blocking(move || chromecast_set_volume(address, volume)).await?;
+    }
+    let control_url = device
+        .rendering_control_url
+        .context("This device does not support volume control")?;
+    soap_call(
+        &control_url,
+        RENDERING_CONTROL,
+        "SetVolume",
+        &format!(
+            "<InstanceID>0</InstanceID><Channel>Master</Channel><DesiredVolume>{}</DesiredVolume>",
+            volume.min(100)
+        ),
+    )
+    .await
+}
+
+async fn soap_call(control_url: &str, service: &str, action: &str, args: &str) -> Result<()> {
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body><u:{action} xmlns:u="{service}">{args}</u:{action}></s:Body></s:Envelope>"#
+    );
+    let response = reqwest::Client::new()
+        .post(control_url)
+        .header("Content-Type", r#"text/xml; charset="utf-8""#)
+        .header("SOAPAction", format!(r#""{service}#{action}""#))
+        .body(body)
+        .timeout(HTTP_TIMEOUT)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!("Cast device rejected {action}: HTTP {}", response.status());
+    }
+    Ok(())
+}
+
+fn connect_chromecast(address: SocketAddr) -> Result<Chromecast<'static>> {
+    let device =
+        Chromecast::connect_without_host_verification(address.ip().to_string(), address.port())
+            .map_err(chromecast_error)?;
+    device
+        .connection
+        .connect(CHROMECAST_RECEIVER)
+        .map_err(chromecast_error)?;
+    Ok(device)
+}
+
+/// Opens the default media receiver and has it play `url`, it keeps playing once the
+/// connection drops
+fn chromecast_load(address: SocketAddr, url: String, content_type: String) -> Result<()> {
+    let device = connect_chromecast(address)?;
+    let app = device
+        .receiver
+        .launch_app(&CastDeviceApp::DefaultMediaReceiver)
+        .map_err(chromecast_error)?;
+    device
+        .connection
+        .connect(app.transport_id.as_str())
+        .map_err(chromecast_error)?;
+    device
+        .media
+        .load(
+            app.transport_id.as_str(),
+            app.session_id.as_str(),
+            &Media {
+                content_id: url,
+                stream_type: StreamType::Live,
+                content_type,
+                metadata: None,
+                duration: None,
+            },
+        )
+        .map_err(chromecast_error)?;
+    Ok(())
+}
+
+/// `action` is the DLNA name of the control
+fn chromecast_control(address: SocketAddr, action: &str) -> Result<()> {
+    let device = connect_chromecast(address)?;
+    let status = device.receiver.get_status().map_err(chromecast_error)?;
+    let app = status
+        .applications
+        .iter()
+        .find(|app| app.app_id == DEFAULT_MEDIA_RECEIVER_ID)
+        .context("Nothing is being cast to this device")?;
+    let transport_id = app.transport_id.as_str();
+    device
+        .connection
+        .connect(transport_id)
+        .map_err(chromecast_error)?;
+    let media_session_id = device
+        .media
+        .get_status(transport_id, None)
+        .map_err(chromecast_error)?
+        .entries
+        .first()
+        .context("Nothing is being cast to this device")?
+        .media_session_id;
+    match action {
+        "Play" => device.media.play(transport_id, media_session_id),
+        "Pause" => device.media.pause(transport_id, media_session_id),
+        _ => device.media.stop(transport_id, media_session_id),
+    }
+    .map_err(chromecast_error)?;
+    Ok(())
+}
+
+fn chromecast_set_volume(address: SocketAddr, volume: u8) -> Result<()> {
+    let device = connect_chromecast(address)?;
+    device
+        .receiver
+        .set_volume(volume.min(100) as f32 / 100.0)
+        .map_err(chromecast_error)?;
+    Ok(())
+}
+
+fn chromecast_error(e: rust_cast::errors::Error) -> anyhow::Error {
+    anyhow!("The Chromecast refused the request: {:?}", e)
+}
+
+fn get_didl_metadata(channel: &Channel, url: &str, content_type: &str) -> String {
+    format!(
+        r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/"><item id="0" parentID="-1" restricted="1"><dc:title>{}</dc:title><upnp:class>object.item.videoItem</upnp:class><res protocolInfo="http-get:*:{}:*">{}</res></item></DIDL-Lite>"#,
+        escape_xml(&channel.name),
+        content_type,
+        escape_xml(url)
+    )
+}
+
+pub fn guess_content_type(url: &str) -> &'static str {
+    let path = Url::parse(url)
+        .map(|u| u.path().to_lowercase())
+        .unwrap_or(url.to_lowercase());
+    if path.ends_with(".m3u8") || path.ends_with(".m3u") {
+        "application/vnd.apple.mpegurl"
+    } else if path.ends_with(".mp4") {
+        "video/mp4"
+    } else if path.ends_with(".mkv") {
+        "video/x-matroska"
+    } else {
+        "video/mp2t"
+    }
+}
+
+fn escape_xml(str: &str) -> String {
+    str.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,2 @@
+pub const DLNA: u8 = 0;
+pub const CHROMECAST: u8 = 1;
@@ -0,0 +1,381 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt,
+    future::Future,
+    net::SocketAddr,
+    sync::{atomic::Ordering::Relaxed, LazyLock, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+use tokio::{
+    sync::{oneshot, Mutex},
+    task::JoinHandle,
+};
+use warp::{
+    http::{
+        header::{CONTENT_TYPE, WWW_AUTHENTICATE},
+        HeaderValue, Response, StatusCode,
+    },
+    hyper::Body,
+    Filter,
+};
+
+use crate::{
+    epg, instance, last_channel, players, relay,
+    settings::{get_settings, DEFAULT_CONTROL_API_PORT},
+    sql,
+    types::{AppState, Channel},
+};
+
+const TOKEN_BYTES: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct ApiConfig {
+    port: u16,
+    lan: bool,
+}
+
+struct RunningApi {
+    config: ApiConfig,
+    stop: oneshot::Sender<()>,
+    server: JoinHandle<()>,
+}
+
+static API: LazyLock<Mutex<Option<RunningApi>>> = LazyLock::new(|| Mutex::new(None));
+/// The stored token, read once instead of on every request
+static TOKEN: RwLock<Option<String>> = RwLock::new(None);
+
+/// Answered with its status instead of a 500
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+fn api_error(status: StatusCode, message: impl Into<String>) -> anyhow::Error {
+    ApiError {
+        status,
+        message: message.into(),
+    }
+    .into()
+}
+
+/// Starts, restarts or stops the control api to match the settings. Its token is created
+/// the first time it is enabled
+pub async fn apply_settings(app: &AppHandle) -> Result<()> {
+    let settings = get_settings()?;
+    let config = ApiConfig {
+        port: settings
+            .control_api_port
+            .unwrap_or(DEFAULT_CONTROL_API_PORT),
+        lan: settings.control_api_lan.unwrap_or(false),
+    };
+    let enabled = settings.control_api_enabled == Some(true);
+    let mut api = API.lock().await;
+    if enabled && api.as_ref().is_some_and(|a| a.config == config) {
+        return Ok(());
+    }
+    // the old listener has to be released before the port is bound again
+    if let Some(running) = api.take() {
+        let _ = running.stop.send(());
+        let _ = running.server.await;
+    }
+    if !enabled {
+        return Ok(());
+    }
+    if get_token()?.is_none() {
+        set_token(new_token()?)?;
+    }
+    let (stop, rx) = oneshot::channel::<()>();
+    let ip = if config.lan {
+        [0, 0, 0, 0]
+    } else {
+        [127, 0, 0, 1]
+    };
+    let (addr, server) = warp::serve(routes(app.clone())).try_bind_with_graceful_shutdown(
+        SocketAddr::from((ip, config.port)),
+        async {
+            rx.await.ok();
+        },
+    )?;
+    let server = tokio::spawn(server);
+    tracing::info!("Control api listening on {addr}");
+    *api = Some(RunningApi {
+        config,
+        stop,
+        server,
+    });
+    Ok(())
+}
+
+/// Replaces the token, the running api accepts only the new one right away
+pub fn regenerate_token() -> Result<String> {
+    let token = new_token()?;
+    set_token(token.clone())?;
+    Ok(token)
+}
+
+pub fn get_token() -> Result<Option<String>> {
+    if let Some(token) = TOKEN.read().unwrap().clone() {
+        return Ok(Some(token));
+    }
+    let token = sql::get_control_api_token()?;
+    *TOKEN.write().unwrap() = token.clone();
+    Ok(token)
+}
+
+fn set_token(token: String) -> Result<()> {
+    sql::set_control_api_token(&token)?;
+    *TOKEN.write().unwrap() = Some(token);
+    Ok(())
+}
+
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow!("Failed to generate the control api token"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn routes(
+    app: AppHandle,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let app = warp::any().map(move || app.clone());
+    let auth = warp::header::optional::<String>("authorization");
+    let channel = warp::query::<HashMap<String, String>>()
+        .map(|mut query: HashMap<String, String>| query.remove("channel"));
+    let favorites = warp::path!("api" / "favorites")
+        .and(warp::get())
+        .and(auth)
+        .and_then(|auth| respond(auth, async { favorites() }));
+    let now_playing = warp::path!("api" / "now-playing")
+        .and(warp::get())
+        .and(auth)
+        .and_then(|auth| respond(auth, now_playing()));
+    let play = warp::path!("api" / "play")
+        .and(warp::post())
+        .and(auth)
+        .and(app.clone())
+        .and(channel.clone())
+        .and_then(|auth, app, channel| respond(auth, play(app, channel)));
+    let stop = warp::path!("api" / "stop")
+        .and(warp::post())
+        .and(auth)
+        .and(app.clone())
+        .and_then(|auth, app| respond(auth, stop(app)));
+    let record = warp::path!("api" / "record")
+        .and(warp::post())
+        .and(auth)
+        .and(app)
+        .and(channel)
+        .and_then(|auth, app, channel| respond(auth, toggle_recording(app, channel)));
+    // anything else also needs the token before it learns the endpoint doesn't exist
+    let unknown = warp::any().and(auth).and_then(|auth| {
+        respond(auth, async {
+            Err::<Value, _>(api_error(StatusCode::NOT_FOUND, "Unknown endpoint"))
+        })
+    });
+    favorites
+        .or(now_playing)
+        .or(play)
+        .or(stop)
+        .or(record)
+        .or(unknown)
+}
+
+async fn respond(
+    authorization: Option<String>,
+    action: impl Future<Output = Result<Value>>,
+) -> Result<Response<Body>, Infallible> {
+    let token = get_token().unwrap_or_else(|e| {
+        tracing::error!("Failed to read the control api token: {:?}", e);
+        None
+    });
+    if !is_authorized(authorization.as_deref(), token.as_deref()) {
+        let mut response =
+            relay::error_response(StatusCode::UNAUTHORIZED, "Missing or invalid token");
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        return Ok(response);
+    }
+    Ok(match action.await {
+        Ok(value) => Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(value.to_string()))
+            .unwrap_or_else(|e| {
+                relay::error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            }),
+        Err(e) => match e.downcast_ref::<ApiError>() {
+            Some(error) => relay::error_response(error.status, &error.message),
+            None => {
+                tracing::error!("{:?}", e);
+                relay::error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            }
+        },
+    })
+}
+
+/// Requests carry the token as `Authorization: Bearer <token>`
+fn is_authorized(authorization: Option<&str>, token: Option<&str>) -> bool {
+    let (Some(authorization), Some(token)) = (authorization, token) else {
+        return false;
+    };
+    let Some(given) = authorization.strip_prefix("Bearer ") else {
+        return false;
+    };
+    // compared in full so the time taken doesn't tell how much of it matched
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn channel_json(channel: &Channel) -> Value {
+    json!({
+        "id": channel.id,
+        "name": channel.name,
+        "image": channel.image,
+        "media_type": channel.media_type,
+    })
+}
+
+/// The live and radio favorites, in their order
+fn favorites() -> Result<Value> {
+    Ok(sql::get_lineup_channels(None)?
+        .iter()
+        .map(channel_json)
+        .collect())
+}
+
+async fn now_playing() -> Result<Value> {
+    let sessions = sql::get_open_sessions()?;
+    let Some(session) = sessions.iter().max_by_key(|session| session.started_at) else {
+        return Ok(json!({ "playing": false, "recording": false }));
+    };
+    // ad-hoc urls have no channel to show
+    let channel = sql::get_channel_by_id(session.channel_id).ok();
+    let programme = match &channel {
+        Some(channel) => epg::get_now_playing(channel).await,
+        None => None,
+    };
+    Ok(json!({
+        "playing": true,
+        "recording": sessions.iter().any(|session| session.record_path.is_some()),
+        "started_at": session.started_at,
+        "channel": channel.as_ref().map(channel_json),
+        "programme": programme,
+    }))
+}
+
+fn find_channel(channel: Option<String>) -> Result<Channel> {
+    let channel = channel
+        .filter(|channel| !channel.trim().is_empty())
+        .ok_or_else(|| api_error(StatusCode::BAD_REQUEST, "Pass a channel id or name"))?;
+    instance::find_channel(channel.trim())
+        .map_err(|e| api_error(StatusCode::NOT_FOUND, e.to_string()))
+}
+
+async fn play(app: AppHandle, channel: Option<String>) -> Result<Value> {
+    let channel = find_channel(channel)?;
+    let reply = json!({ "playing": true, "channel": channel_json(&channel) });
+    spawn_playback(app, channel, false);
+    Ok(reply)
+}
+
+/// Plays like the `play` command does, the request doesn't wait for the player to exit
+fn spawn_playback(app: AppHandle, channel: Channel, record: bool) {
+    tauri::async_runtime::spawn(async move {
+        if !record {
+            last_channel::remember(&app, &channel).await;
+        }
+        let process = players::get_process(&app).await;
+        let play = players::play(channel.clone(), record, None, process);
+        if let Err(e) = players::report_playback(&app, &channel, record, play).await {
+            tracing::error!(
+                "Failed to play {} for the control api: {:?}",
+                channel.name,
+                e
+            );
+        }
+    });
+}
+
+async fn stop(app: AppHandle) -> Result<Value> {
+    let state = app.state::<Mutex<AppState>>();
+    let process = state.lock().await.playback.player.clone();
+    if process.pid.load(Relaxed) == 0 {
+        return Err(api_error(StatusCode::CONFLICT, "Nothing is playing"));
+    }
+    players::stop_playback(&process)?;
+    Ok(json!({ "playing": false, "recording": false }))
+}
+
+/// Stops the recording in progress, or records `channel`, the current one without it
+async fn toggle_recording(app: AppHandle, channel: Option<String>) -> Result<Value> {
+    let sessions = sql::get_open_sessions()?;
+    if sessions.iter().any(|session| session.record_path.is_some()) {
+        return stop(app).await;
+    }
+    let channel = match channel {
+        Some(channel) => find_channel(Some(channel))?,
+        None => {
+            let session = sessions
+                .iter()
+                .max_by_key(|session| session.started_at)
+                .ok_or_else(|| {
+                    api_error(
+                        StatusCode::CONFLICT,
+                        "Nothing is playing, pass the channel to record",
+                    )
+                })?;
+            sql::get_channel_by_id(session.channel_id).map_err(|_| {
+                api_error(
+                    StatusCode::CONFLICT,
+                    "The current stream is not a saved channel and can't be recorded",
+                )
+            })?
+        }
+    };
+    let reply = json!({ "recording": true, "channel": channel_json(&channel) });
+    spawn_playback(app, channel, true);
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod test_control_api {
+    use super::{is_authorized, new_token};
+
+    #[test]
+    fn test_is_authorized() {
+        let token = new_token().unwrap();
+        assert_eq!(token.len(), 64);
+        assert!(is_authorized(
+            Some(&format!("Bearer {token}")),
+            Some(&token)
+        ));
+        assert!(!is_authorized(Some(&token), Some(&token)));
+        assert!(!is_authorized(Some("Bearer "), Some(&token)));
+        assert!(!is_authorized(
+            Some(&format!("Bearer {}", &token[1..])),
+            Some(&token)
+        ));
+        assert!(!is_authorized(None, Some(&token)));
+        assert!(!is_authorized(Some("Bearer "), None));
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context, Result};
+use fs2::FileExt;
+
+use crate::sql;
+
+const ARG_DB_PATH: &str = "--db-path";
+const ARG_PORTABLE: &str = "--portable";
+const ENV_DB_PATH: &str = "OPEN_TV_DB_PATH";
+const PORTABLE_FILE: &str = "portable.txt";
+const LOCATION_FILE: &str = "db_location.txt";
+const DB_NAME: &str = "db.sqlite";
+
+/// The lock file of the database in use, released when the app exits
+static LOCK: Mutex<Option<File>> = Mutex::new(None);
+
+#[derive(Debug)]
+pub struct DatabaseInUse {
+    pub path: String,
+}
+
+impl fmt::Display for DatabaseInUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is already used by another Open TV, close it first",
+            self.path
+        )
+    }
+}
+
+impl std::error::Error for DatabaseInUse {}
+
+/// Where the database lives, in order: --db-path, --portable, the OPEN_TV_DB_PATH
+/// environment variable, a portable.txt next to the executable, the location picked
+/// with `migrate_database_location` and finally the user data folder
+pub fn resolve(args: &[String]) -> Result<String> {
+    let path = match get_forced_path(args, std::env::var(ENV_DB_PATH).ok(), &get_exe_dir()?)? {
+        Some(path) => path,
+        None => match read_location_file()? {
+            Some(path) => path,
+            None => return sql::get_and_create_sqlite_db_path(),
+        },
+    };
+    prepare(&path)
+}
+
+/// Takes the lock of the database at `path`, two instances can't share a database
+pub fn lock(path: &str) -> Result<()> {
+    let file = try_lock(path)?;
+    *LOCK.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Copies the database to `new_path` and switches over to it. The old file is kept
+pub fn migrate_database_location(new_path: String) -> Result<String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if get_forced_path(&args, std::env::var(ENV_DB_PATH).ok(), &get_exe_dir()?)?.is_some() {
+        bail!("The database location is set by {ARG_DB_PATH}, {ARG_PORTABLE}, {ENV_DB_PATH} or {PORTABLE_FILE}, change it there instead");
+    }
+    let new_path = prepare(Path::new(new_path.trim()))?;
+    if Path::new(&new_path).exists() {
+        bail!("{new_path} already exists");
+    }
+    let lock = try_lock(&new_path)?;
+    if let Err(e) = sql::move_database(&new_path) {
+        let _ = std::fs::remove_file(&new_path);
+        return Err(e);
+    }
+    std::fs::write(get_location_file()?, &new_path)?;
+    *LOCK.lock().unwrap() = Some(lock);
+    Ok(new_path)
+}
+
+fn try_lock(path: &str) -> Result<File> {
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(format!("{path}.lock"))?;
+    if file.try_lock_exclusive().is_err() {
+        return Err(DatabaseInUse {
+            path: path.to_string(),
+        }
+        .into());
+    }
+    Ok(file)
+}
+
+fn get_forced_path(
+    args: &[String],
+    env: Option<String>,
+    exe_dir: &Path,
+) -> Result<Option<PathBuf>> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            ARG_DB_PATH => {
+                let path = args.next().context("--db-path requires a file path")?;
+                return Ok(Some(PathBuf::from(path)));
+            }
+            ARG_PORTABLE => return Ok(Some(exe_dir.join(DB_NAME))),
+            _ => {}
+        }
+    }
+    if let Some(path) = env.filter(|path| !path.trim().is_empty()) {
+        return Ok(Some(PathBuf::from(path.trim())));
+    }
+    let portable = exe_dir.join(PORTABLE_FILE);
+    if portable.is_file() {
+        // portable.txt may name the database, relative to the executable
+        let content = std::fs::read_to_string(&portable)?;
+        return Ok(Some(match content.trim() {
+            "" => exe_dir.join(DB_NAME),
+            name => exe_dir.join(name),
+        }));
+    }
+    Ok(None)
+}
+
+fn read_location_file() -> Result<Option<PathBuf>> {
+    let file = get_location_file()?;
+    if !file.is_file() {
+        return Ok(None);
+    }
+    let path = std::fs::read_to_string(file)?;
+    Ok(Some(path.trim())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from))
+}
+
+fn get_location_file() -> Result<PathBuf> {
+    let mut path = directories::ProjectDirs::from("dev", "fredol", "open-tv")
+        .context("can't find project folder")?
+        .data_dir()
+        .to_owned();
+    std::fs::create_dir_all(&path)?;
+    path.push(LOCATION_FILE);
+    Ok(path)
+}
+
+fn get_exe_dir() -> Result<PathBuf> {
+    Ok(std::env::current_exe()?
+        .parent()
+        .context("can't find the executable folder")?
+        .to_path_buf())
+}
+
+fn prepare(path: &Path) -> Result<String> {
+    let path = std::path::absolute(path)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod test_db_location {
+    use super::{get_forced_path, try_lock, DatabaseInUse};
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_forced_path_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path();
+        let env = || Some("/data/env.sqlite".to_string());
+        assert_eq!(get_forced_path(&args(&[]), None, exe).unwrap(), None);
+        std::fs::write(exe.join("portable.txt"), "").unwrap();
+        assert_eq!(
+            get_forced_path(&args(&[]), None, exe).unwrap(),
+            Some(exe.join("db.sqlite"))
+        );
+        std::fs::write(exe.join("portable.txt"), "data/tv.sqlite\n").unwrap();
+        assert_eq!(
+            get_forced_path(&args(&[]), None, exe).unwrap(),
+            Some(exe.join("data/tv.sqlite"))
+        );
+        assert_eq!(
+            get_forced_path(&args(&[]), env(), exe).unwrap(),
+            Some("/data/env.sqlite".into())
+        );
+        assert_eq!(
+            get_forced_path(&args(&["--portable"]), env(), exe).unwrap(),
+            Some(exe.join("db.sqlite"))
+        );
+        assert_eq!(
+            get_forced_path(&args(&["--db-path", "/tmp/cli.sqlite"]), env(), exe).unwrap(),
+            Some("/tmp/cli.sqlite".into())
+        );
+        assert!(get_forced_path(&args(&["--db-path"]), None, exe).is_err());
+    }
+
+    #[test]
+    fn test_database_can_only_be_locked_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.sqlite").to_string_lossy().to_string();
+        let lock = try_lock(&path).unwrap();
+        let e = try_lock(&path).unwrap_err();
+        assert!(e.downcast_ref::<DatabaseInUse>().is_some());
+        drop(lock);
+        assert!(try_lock(&path).is_ok());
+    }
+}
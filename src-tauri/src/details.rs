@@ -0,0 +1,60 @@
+use anyhow::Result;
+
+use crate::{
+    media_type, source_type, sql,
+    types::{Channel, ChannelDetails},
+    xtream,
+};
+
+pub async fn get_channel_details(channel_id: i64) -> Result<ChannelDetails> {
+    let channel = sql::get_channel_by_id(channel_id)?;
+    let group = match channel.group_id {
+        Some(id) => sql::get_group_by_id(id).unwrap_or_else(log_none),
+        None => None,
+    };
+    let headers = sql::get_channel_headers_by_id(channel_id).unwrap_or_else(log_none);
+    let last_watched = sql::get_last_watched(channel_id).unwrap_or_else(log_none);
+    let has_note = sql::get_channel_note(channel_id)
+        .unwrap_or_else(log_none)
+        .is_some();
+    let tags = sql::get_channel_tags(channel_id).unwrap_or_else(|e| {
+        tracing::warn!("{:?}", e);
+        Vec::new()
+    });
+    let mut epg = match is_xtream_live(&channel) {
+        true => xtream::get_short_epg(channel.clone())
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to get EPG for channel details: {:?}", e);
+                Vec::new()
+            })
+            .into_iter(),
+        false => Vec::new().into_iter(),
+    };
+    Ok(ChannelDetails {
+        favorite: channel.favorite,
+        catchup: channel.catchup.unwrap_or(false),
+        channel,
+        group,
+        headers,
+        last_watched,
+        now: epg.next(),
+        next: epg.next(),
+        has_note,
+        tags,
+    })
+}
+
+fn is_xtream_live(channel: &Channel) -> bool {
+    media_type::is_live(channel.media_type)
+        && channel.stream_id.is_some()
+        && channel
+            .source_id
+            .and_then(|id| sql::get_source_from_id(id).ok())
+            .is_some_and(|source| source.source_type == source_type::XTREAM)
+}
+
+fn log_none<T>(e: anyhow::Error) -> Option<T> {
+    tracing::warn!("{:?}", e);
+    None
+}
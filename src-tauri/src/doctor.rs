@@ -0,0 +1,158 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::LazyLock,
+    time::Duration,
+};
+
+use anyhow::Result;
+use regex::Regex;
+use tokio::{process::Command, sync::OnceCell};
+
+use crate::{
+    mpv::{get_mpv_path, get_ytdlp_path},
+    players::PlayerNotFound,
+    types::{DependencyReport, DependencyStatus},
+    utils::get_deps_dir,
+};
+
+pub const FOUND: u8 = 0;
+pub const MISSING: u8 = 1;
+pub const TOO_OLD: u8 = 2;
+const MIN_MPV_VERSION: [u32; 3] = [0, 34, 0];
+const MIN_YTDLP_VERSION: [u32; 3] = [2023, 1, 1];
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+const WINDOWS_DEPS: [&str; 3] = ["mpv.exe", "ffmpeg.exe", "yt-dlp.exe"];
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+static MPV_VERSION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"mpv v?(?P<version>\d+\.\d+(\.\d+)?)"#).unwrap());
+static YTDLP_VERSION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?P<version>\d{4}\.\d{2}\.\d{2})"#).unwrap());
+static MPV_CHECKED: OnceCell<()> = OnceCell::const_new();
+
+pub async fn check_dependencies() -> Result<DependencyReport> {
+    Ok(DependencyReport {
+        dependencies: vec![
+            check_dependency("mpv", get_mpv_path(), &MPV_VERSION_REGEX, MIN_MPV_VERSION).await,
+            check_dependency(
+                "yt-dlp",
+                get_ytdlp_path(),
+                &YTDLP_VERSION_REGEX,
+                MIN_YTDLP_VERSION,
+            )
+            .await,
+        ],
+        missing_deps_files: get_missing_deps_files(),
+    })
+}
+
+/// Checks mpv the first time it is needed to play, a missing mpv is reported with its
+/// resolved path and is checked again on the next play
+pub async fn require_mpv() -> Result<()> {
+    MPV_CHECKED
+        .get_or_try_init(|| async {
+            let mpv =
+                check_dependency("mpv", get_mpv_path(), &MPV_VERSION_REGEX, MIN_MPV_VERSION).await;
+            match mpv.status {
+                MISSING => Err(PlayerNotFound {
+                    player: mpv.name,
+                    path: mpv.path,
+                }),
+                TOO_OLD => {
+                    tracing::warn!(
+                        "mpv {} is older than the minimum supported {}",
+                        mpv.version.unwrap_or_default(),
+                        mpv.minimum_version
+                    );
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        })
+        .await?;
+    Ok(())
+}
+
+pub async fn get_ytdlp_version() -> Option<String> {
+    get_version(&get_ytdlp_path(), &YTDLP_VERSION_REGEX).await
+}
+
+async fn check_dependency(
+    name: &str,
+    path: PathBuf,
+    regex: &Regex,
+    minimum: [u32; 3],
+) -> DependencyStatus {
+    let version = get_version(&path, regex).await;
+    let status = match &version {
+        None => MISSING,
+        Some(version) if parse_version(version) < minimum => TOO_OLD,
+        Some(_) => FOUND,
+    };
+    DependencyStatus {
+        name: name.to_string(),
+        path: path.to_string_lossy().to_string(),
+        version,
+        minimum_version: minimum.map(|v| v.to_string()).join("."),
+        status,
+    }
+}
+
+async fn get_version(path: &Path, regex: &Regex) -> Option<String> {
+    let mut command = Command::new(path);
+    command
+        .arg("--version")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    #[cfg(target_os = "windows")]
+    command.creation_flags(CREATE_NO_WINDOW);
+    let output = tokio::time::timeout(VERSION_TIMEOUT, command.output())
+        .await
+        .ok()?
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    regex
+        .captures(&stdout)
+        .map(|caps| caps["version"].to_string())
+}
+
+fn parse_version(version: &str) -> [u32; 3] {
+    let mut parsed = [0; 3];
+    for (i, part) in version.split(".").take(3).enumerate() {
+        parsed[i] = part.parse().unwrap_or(0);
+    }
+    parsed
+}
+
+fn get_missing_deps_files() -> Vec<String> {
+    if !cfg!(target_os = "windows") {
+        return Vec::new();
+    }
+    let deps = get_deps_dir();
+    WINDOWS_DEPS
+        .iter()
+        .filter(|file| !Path::new(&deps).join(file).exists())
+        .map(|file| file.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod test_doctor {
+    use super::{parse_version, MPV_VERSION_REGEX, YTDLP_VERSION_REGEX};
+
+    #[test]
+    fn test_parse_version() {
+        let mpv = MPV_VERSION_REGEX
+            .captures("mpv v0.38.0 Copyright © 2000-2024 mpv/MPlayer/mplayer2 projects")
+            .unwrap();
+        assert_eq!(parse_version(&mpv["version"]), [0, 38, 0]);
+        let mpv = MPV_VERSION_REGEX
+            .captures("mpv 0.29.1 (C) 2000-2018 mpv/MPlayer/mplayer2 projects")
+            .unwrap();
+        assert!(parse_version(&mpv["version"]) < [0, 34, 0]);
+        let ytdlp = YTDLP_VERSION_REGEX.captures("2024.08.06\n").unwrap();
+        assert_eq!(parse_version(&ytdlp["version"]), [2024, 8, 6]);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::utils::name_from_url;
+
+const BOUQUET_PREFIX: &str = "userbouquet.";
+// service reference flags, hex encoded
+const MARKER_FLAGS: u32 = 0x40 | 0x100;
+
+/// Channels of an Enigma2 bouquet file (userbouquet.*.tv or .radio)
+#[derive(Debug, Default, PartialEq)]
+pub struct Bouquet {
+    pub name: Option<String>,
+    pub entries: Vec<BouquetEntry>,
+    /// Services of the box's own tuners, they can't be played over the network
+    pub skipped: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BouquetEntry {
+    pub name: String,
+    pub url: String,
+}
+
+enum Service {
+    Stream { url: String, name: Option<String> },
+    Tuner,
+    Marker,
+}
+
+pub fn parse_bouquet(data: &str) -> Bouquet {
+    let mut bouquet = Bouquet::default();
+    let mut lines = data.lines().map(str::trim).peekable();
+    while let Some(line) = lines.next() {
+        if let Some(name) = line.strip_prefix("#NAME") {
+            bouquet.name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+            continue;
+        }
+        let Some(service) = line.strip_prefix("#SERVICE") else {
+            continue;
+        };
+        let description = lines
+            .next_if(|line| line.starts_with("#DESCRIPTION"))
+            .map(|line| line["#DESCRIPTION".len()..].trim().to_string())
+            .filter(|description| !description.is_empty());
+        match parse_service(service.trim()) {
+            Service::Stream { url, name } => {
+                match description.or(name).or_else(|| name_from_url(&url)) {
+                    Some(name) => bouquet.entries.push(BouquetEntry { name, url }),
+                    None => bouquet.skipped += 1,
+                }
+            }
+            Service::Tuner => bouquet.skipped += 1,
+            Service::Marker => {}
+        }
+    }
+    bouquet
+}
+
+/// The name of a bouquet without one, from its file name
+pub fn name_from_file_name(file_name: &str) -> String {
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    stem.strip_prefix(BOUQUET_PREFIX)
+        .unwrap_or(stem)
+        .to_string()
+}
+
+// A service reference is ten hex fields, then the url and an optional name, both
+// percent encoded so their colons don't split them
+fn parse_service(service: &str) -> Service {
+    let fields: Vec<&str> = service.splitn(12, ':').collect();
+    let flags = fields
+        .get(1)
+        .and_then(|flags| u32::from_str_radix(flags, 16).ok())
+        .unwrap_or(0);
+    if flags & MARKER_FLAGS != 0 {
+        return Service::Marker;
+    }
+    let url = fields
+        .get(10)
+        .map(|url| percent_decode(url.trim()))
+        .unwrap_or_default();
+    if !url.contains("://") {
+        return Service::Tuner;
+    }
+    let name = fields
+        .get(11)
+        .map(|name| percent_decode(name.trim()))
+        .filter(|name| !name.is_empty());
+    Service::Stream { url, name }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test_enigma2 {
+    use super::{name_from_file_name, parse_bouquet, BouquetEntry};
+
+    const BOUQUET: &str = r#"#NAME IPTV - Sports
+#SERVICE 1:64:0:0:0:0:0:0:0:0::--- Football ---
+#DESCRIPTION --- Football ---
+#SERVICE 4097:0:1:1A2B:0:0:0:0:0:0:http%3a//provider.example.com%3a8080/live/user/pass/1234.ts:Sky Sports%3a F1
+#DESCRIPTION Sky Sports: F1 HD
+#SERVICE 5002:0:1:0:0:0:0:0:0:0:http%3a//provider.example.com%3a8080/live/user/pass/1235.ts:beIN Sports 1
+#SERVICE 4097:0:1:0:0:0:0:0:0:0:https%3a//cdn.example.com/hls/caf%c3%a9%20tv/index.m3u8
+#SERVICE 1:0:19:283D:3FB:1:C00000:0:0:0:
+#DESCRIPTION BBC One HD
+#SERVICE 1:0:1:1B1D:802:2:11A0000:0:0:0:
+#SERVICE 4097:0:1:0:0:0:0:0:0:0:rtmp%3a//stream.example.com/live/news:
+#DESCRIPTION News 24: Live
+"#;
+
+    #[test]
+    fn test_parse_bouquet() {
+        let bouquet = parse_bouquet(BOUQUET);
+        assert_eq!(bouquet.name.as_deref(), Some("IPTV - Sports"));
+        assert_eq!(bouquet.skipped, 2);
+        let entry = |name: &str, url: &str| BouquetEntry {
+            name: name.to_string(),
+            url: url.to_string(),
+        };
+        assert_eq!(
+            bouquet.entries,
+            [
+                entry(
+                    "Sky Sports: F1 HD",
+                    "http://provider.example.com:8080/live/user/pass/1234.ts"
+                ),
+                entry(
+                    "beIN Sports 1",
+                    "http://provider.example.com:8080/live/user/pass/1235.ts"
+                ),
+                entry("index", "https://cdn.example.com/hls/café tv/index.m3u8"),
+                entry("News 24: Live", "rtmp://stream.example.com/live/news"),
+            ]
+        );
+        assert_eq!(
+            name_from_file_name("userbouquet.iptv_sports.tv"),
+            "iptv_sports"
+        );
+    }
+}
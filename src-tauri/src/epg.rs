@@ -1,61 +1,291 @@
-use std::{
-    sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
-        Arc,
-    },
-    thread::{self, sleep},
-    time::Duration,
-};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
-use chrono::Local;
+use chrono::{DateTime, Days, Local, TimeDelta, TimeZone, Utc};
 use tauri::{AppHandle, State};
 use tauri_plugin_notification::NotificationExt;
 use tokio::sync::Mutex;
 
 use crate::{
-    log, sql,
-    types::{AppState, EPGNotify},
-    utils,
+    events::{self, ReminderFired},
+    instance::focus_main_window,
+    last_channel, media_type, players, source_type, sql,
+    tasks::CancelToken,
+    types::{ActiveReminder, AppState, Channel, EPGNotify, EpgWatcher, NowNext, Programme},
+    utils, xtream,
 };
 
-pub fn poll(mut to_watch: Vec<EPGNotify>, stop: Arc<AtomicBool>, app: AppHandle) -> Result<()> {
-    while !stop.load(Relaxed) && !to_watch.is_empty() {
-        to_watch.retain(|epg| {
-            let is_timestamp_over = match is_timestamp_over(epg.start_timestamp) {
-                Ok(v) => v,
-                Err(e) => {
-                    log::log(format!("{:?}", e));
-                    return false;
-                }
-            };
-            if is_timestamp_over {
-                match notify(epg, &app).context("Failed to notify EPG") {
-                    Ok(_) => {}
-                    Err(e) => log::log(format!("{:?}", e)),
+const EPG_ACTION_TYPE: &str = "epg";
+const NOW_PLAYING_TIMEOUT: Duration = Duration::from_secs(5);
+const NOW_NEXT_WINDOW: i64 = 12 * 3600;
+pub const REPEAT_NONE: u8 = 0;
+pub const REPEAT_DAILY: u8 = 1;
+pub const REPEAT_WEEKLY: u8 = 2;
+
+pub async fn poll(mut to_watch: Vec<EPGNotify>, stop: CancelToken, app: AppHandle) {
+    while !stop.is_cancelled() && !to_watch.is_empty() {
+        to_watch.retain_mut(|epg| {
+            let due = epg.snoozed_until.unwrap_or(epg.start_timestamp);
+            if is_timestamp_over(due) {
+                let result =
+                    sql::resolve_epg_channel(epg).and_then(|channel| fire(epg, channel, &app));
+                if let Err(e) = result.context("Failed to notify EPG") {
+                    tracing::error!("{:?}", e);
                 }
-                return false;
+                return reschedule(epg).unwrap_or_else(|e| {
+                    tracing::error!("Failed to reschedule EPG: {:?}", e);
+                    false
+                });
             }
             return true;
         });
-        sleep(Duration::from_secs(1));
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            _ = stop.cancelled() => {}
+        }
+    }
+}
+
+fn reschedule(epg: &mut EPGNotify) -> Result<bool> {
+    if !advance(epg, Local::now())? {
+        return Ok(false);
+    }
+    sql::add_epg(epg.clone())?;
+    Ok(true)
+}
+
+/// Moves a fired reminder to its next occurrence. A snooze only delays the occurrence it was
+/// made from, a repeating reminder already moved on keeps its schedule
+fn advance(epg: &mut EPGNotify, now: DateTime<Local>) -> Result<bool> {
+    let snoozed = epg.snoozed_until.take().is_some();
+    if snoozed && epg.start_timestamp > now.timestamp() {
+        return Ok(true);
+    }
+    let start = utils::get_local_time(epg.start_timestamp)?;
+    let repeat = epg.repeat.unwrap_or(REPEAT_NONE);
+    match next_occurrence(start, repeat, now) {
+        Some(next) => {
+            epg.start_timestamp = next.timestamp();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+fn next_occurrence<Tz: TimeZone>(
+    start: DateTime<Tz>,
+    repeat: u8,
+    now: DateTime<Tz>,
+) -> Option<DateTime<Tz>> {
+    let days = match repeat {
+        REPEAT_DAILY => Days::new(1),
+        REPEAT_WEEKLY => Days::new(7),
+        _ => return None,
+    };
+    let timezone = start.timezone();
+    let mut next = start.naive_local();
+    loop {
+        next = next.checked_add_days(days)?;
+        // Wall-clock times skipped by a DST change are pushed past the gap
+        let resolved = timezone.from_local_datetime(&next).earliest().or_else(|| {
+            timezone
+                .from_local_datetime(&(next + TimeDelta::hours(1)))
+                .earliest()
+        })?;
+        if resolved > now {
+            return Some(resolved);
+        }
+    }
+}
+
+fn fire(epg: &EPGNotify, channel: Option<Channel>, app: &AppHandle) -> Result<()> {
+    if is_source_disabled(epg, channel.as_ref())? {
+        return Ok(());
+    }
+    let fired = ReminderFired {
+        epg_id: epg.epg_id.clone(),
+        title: epg.title.clone(),
+        channel_id: channel.as_ref().and_then(|channel| channel.id),
+        channel_name: epg.channel_name.clone(),
+        found: channel.is_some(),
+    };
+    events::emit(
+        app,
+        events::REMINDER_FIRED,
+        events::new_operation_id(),
+        fired,
+    );
+    match channel {
+        Some(channel) => notify(epg, channel.id, app),
+        None => notify_missing(epg, app),
+    }
+}
+
+/// Reminders of a disabled source stay quiet until the source is enabled again
+fn is_source_disabled(epg: &EPGNotify, channel: Option<&Channel>) -> Result<bool> {
+    let source_id = epg
+        .source_id
+        .or(channel.and_then(|channel| channel.source_id));
+    match source_id {
+        Some(source_id) => Ok(!sql::is_source_enabled(source_id)?),
+        None => Ok(false),
     }
-    Ok(())
 }
 
-fn notify(epg: &EPGNotify, app: &AppHandle) -> Result<()> {
+fn notify(epg: &EPGNotify, channel_id: Option<i64>, app: &AppHandle) -> Result<()> {
     app.notification()
         .builder()
         .title(format!("LIVE: {}", epg.title))
         .body(format!("Watch on {}", epg.channel_name))
+        .action_type_id(EPG_ACTION_TYPE)
+        .extra("channel_id", channel_id)
+        .extra("channel_name", &epg.channel_name)
         .show()?;
     Ok(())
 }
 
-fn is_timestamp_over(timestamp: i64) -> Result<bool> {
-    let time = utils::get_local_time(timestamp)?;
-    let current_time = Local::now();
-    Ok(current_time >= time)
+fn notify_missing(epg: &EPGNotify, app: &AppHandle) -> Result<()> {
+    tracing::warn!(
+        "Reminder for {} could not find channel {}",
+        epg.title,
+        epg.channel_name
+    );
+    app.notification()
+        .builder()
+        .title(format!("LIVE: {}", epg.title))
+        .body(format!(
+            "{} is no longer in your sources, it may have been renamed",
+            epg.channel_name
+        ))
+        .show()?;
+    Ok(())
+}
+
+pub fn get_active_reminders() -> Result<Vec<ActiveReminder>> {
+    sql::clean_epgs()?;
+    let mut reminders = Vec::new();
+    for epg in sql::get_epgs()? {
+        let channel = sql::resolve_epg_channel(&epg)?;
+        if is_source_disabled(&epg, channel.as_ref())? {
+            continue;
+        }
+        reminders.push(ActiveReminder {
+            resolved_channel_id: channel.and_then(|c| c.id),
+            epg,
+        });
+    }
+    Ok(reminders)
+}
+
+pub async fn open_channel(
+    app: AppHandle,
+    channel_id: Option<i64>,
+    channel_name: String,
+) -> Result<()> {
+    focus_main_window(&app);
+    let channel = match channel_id.and_then(|id| sql::get_channel_by_id(id).ok()) {
+        Some(channel) => channel,
+        None => sql::find_channel_by_name(&channel_name)?
+            .with_context(|| format!("{channel_name} no longer exists"))?,
+    };
+    last_channel::remember(&app, &channel).await;
+    let play = players::play(
+        channel.clone(),
+        false,
+        None,
+        players::get_process(&app).await,
+    );
+    players::report_playback(&app, &channel, false, play).await
+}
+
+/// The title of the programme airing on a channel, None when there is no EPG for it
+pub async fn get_now_playing(channel: &Channel) -> Option<String> {
+    get_now_next(channel)
+        .await
+        .ok()?
+        .current
+        .map(|programme| programme.title)
+}
+
+/// The programme airing on a channel and the one after it. XMLTV feeds come first,
+/// live Xtream channels without one fall back to the provider's short EPG
+pub async fn get_now_next(channel: &Channel) -> Result<NowNext> {
+    let now = Utc::now().timestamp();
+    let mut programmes = match channel.id {
+        Some(id) if id >= 0 => sql::get_programmes(id, now, now + NOW_NEXT_WINDOW)?,
+        _ => Vec::new(),
+    };
+    if programmes.is_empty() {
+        programmes = get_xtream_programmes(channel).await.unwrap_or_default();
+    }
+    Ok(now_next(programmes, now))
+}
+
+async fn get_xtream_programmes(channel: &Channel) -> Option<Vec<Programme>> {
+    if !media_type::is_live(channel.media_type) || channel.stream_id.is_none() {
+        return None;
+    }
+    let source = sql::get_source_from_id(channel.source_id?).ok()?;
+    if source.source_type != source_type::XTREAM {
+        return None;
+    }
+    let epg = tokio::time::timeout(NOW_PLAYING_TIMEOUT, xtream::get_short_epg(channel.clone()))
+        .await
+        .ok()?
+        .ok()?;
+    Some(
+        epg.into_iter()
+            .map(|epg| Programme {
+                tvg_id: channel.tvg_id.clone().unwrap_or_default(),
+                title: epg.title,
+                description: Some(epg.description).filter(|desc| !desc.is_empty()),
+                start: epg.start_timestamp,
+                stop: epg.end_timestamp,
+            })
+            .collect(),
+    )
+}
+
+fn now_next(mut programmes: Vec<Programme>, now: i64) -> NowNext {
+    programmes.sort_by_key(|programme| programme.start);
+    let current = programmes
+        .iter()
+        .find(|programme| programme.start <= now && programme.stop > now)
+        .cloned();
+    let after = current.as_ref().map_or(now, |current| current.stop);
+    let next = programmes
+        .iter()
+        .find(|programme| programme.start >= after)
+        .cloned();
+    let progress = current.as_ref().map(|current| {
+        let length = (current.stop - current.start).max(1);
+        ((now - current.start) * 100 / length).clamp(0, 100) as u8
+    });
+    NowNext {
+        current,
+        next,
+        progress,
+    }
+}
+
+/// What mpv shows as the media title, e.g. "BBC One — News at Ten (22:00–22:30)"
+pub fn get_media_title(channel_name: &str, programme: Option<&Programme>) -> String {
+    let Some(programme) = programme else {
+        return channel_name.to_string();
+    };
+    let format =
+        |timestamp| utils::get_local_time(timestamp).map(|time| time.format("%H:%M").to_string());
+    match (format(programme.start), format(programme.stop)) {
+        (Ok(start), Ok(stop)) => {
+            format!("{channel_name} — {} ({start}–{stop})", programme.title)
+        }
+        _ => format!("{channel_name} — {}", programme.title),
+    }
+}
+
+/// Reminder timestamps are UTC epoch seconds, so no timezone is involved
+fn is_timestamp_over(timestamp: i64) -> bool {
+    Utc::now().timestamp() >= timestamp
 }
 
 pub async fn add_epg(
@@ -63,24 +293,16 @@ pub async fn add_epg(
     app: AppHandle,
     epg: EPGNotify,
 ) -> Result<()> {
-    let mut state = state.lock().await;
-    if state.thread_handle.is_some() {
-        state.notify_stop.store(true, Relaxed);
-        let _ = state
-            .thread_handle
-            .take()
-            .context("no thread in option")?
-            .join();
-    }
-    state.notify_stop.store(false, Relaxed);
-    let stop = state.notify_stop.clone();
-    sql::clean_epgs()?;
+    let mut epg = epg;
+    if let Some(channel) = epg
+        .channel_id
+        .and_then(|id| sql::get_channel_by_id(id).ok())
+    {
+        epg.tvg_id = channel.tvg_id;
+        epg.source_id = channel.source_id;
+    }
     sql::add_epg(epg)?;
-    let list = sql::get_epgs()?;
-    state
-        .thread_handle
-        .replace(thread::spawn(|| poll(list, stop, app)));
-    Ok(())
+    restart_poll(&mut state.lock().await.epg, app)
 }
 
 pub async fn remove_epg(
@@ -88,40 +310,178 @@ pub async fn remove_epg(
     app: AppHandle,
     epg_id: String,
 ) -> Result<()> {
-    let mut state = state.lock().await;
-    if state.thread_handle.is_some() {
-        state.notify_stop.store(true, Relaxed);
-        let _ = state
-            .thread_handle
-            .take()
-            .context("no thread in option")?
-            .join();
-    }
-    state.notify_stop.store(false, Relaxed);
-    let stop = state.notify_stop.clone();
-    sql::clean_epgs()?;
     sql::remove_epg(epg_id)?;
+    restart_poll(&mut state.lock().await.epg, app)
+}
+
+pub async fn snooze_epg(
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+    epg_id: String,
+    minutes: Option<u16>,
+) -> Result<()> {
+    sql::snooze_epg(epg_id, minutes)?;
+    restart_poll(&mut state.lock().await.epg, app)
+}
+
+/// The previous watcher is cancelled without waiting for it, it stops at its next tick
+fn restart_poll(watcher: &mut EpgWatcher, app: AppHandle) -> Result<()> {
+    watcher.stop.cancel();
+    watcher.handle.take();
+    sql::clean_epgs()?;
     let list = sql::get_epgs()?;
     if list.len() == 0 {
         return Ok(());
     }
-    state
-        .thread_handle
-        .replace(thread::spawn(|| poll(list, stop, app)));
+    let stop = CancelToken::default();
+    watcher.stop = stop.clone();
+    watcher
+        .handle
+        .replace(tauri::async_runtime::spawn(poll(list, stop, app)));
     Ok(())
 }
 
 pub async fn on_start_check_epg(state: State<'_, Mutex<AppState>>, app: AppHandle) -> Result<()> {
-    sql::clean_epgs()?;
-    let list = sql::get_epgs()?;
-    if list.len() == 0 {
-        return Ok(());
+    restart_poll(&mut state.lock().await.epg, app)
+}
+
+#[cfg(test)]
+mod test_epg {
+    use chrono::{FixedOffset, Local, TimeDelta, TimeZone, Utc};
+
+    use super::{
+        advance, get_active_reminders, get_media_title, next_occurrence, now_next, REPEAT_DAILY,
+        REPEAT_NONE, REPEAT_WEEKLY,
+    };
+    use crate::{
+        sql,
+        types::{EPGNotify, Programme},
+    };
+
+    fn programme(title: &str, start: i64, stop: i64) -> Programme {
+        Programme {
+            tvg_id: "bbc1.uk".to_string(),
+            title: title.to_string(),
+            description: None,
+            start,
+            stop,
+        }
+    }
+
+    #[test]
+    fn test_now_next() {
+        let programmes = vec![
+            programme("Weather", 2000, 2100),
+            programme("News at Ten", 1000, 2000),
+        ];
+        let result = now_next(programmes.clone(), 1250);
+        assert_eq!(result.current.unwrap().title, "News at Ten");
+        assert_eq!(result.next.unwrap().title, "Weather");
+        assert_eq!(result.progress, Some(25));
+        // Between two programmes there is nothing airing, only what comes next
+        let result = now_next(programmes[..1].to_vec(), 1500);
+        assert_eq!(result.current, None);
+        assert_eq!(result.next.unwrap().title, "Weather");
+        assert_eq!(result.progress, None);
+        assert_eq!(get_media_title("BBC One", None), "BBC One");
+        assert!(
+            get_media_title("BBC One", Some(&programmes[1])).starts_with("BBC One — News at Ten (")
+        );
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let tz = FixedOffset::west_opt(5 * 3600).unwrap();
+        let start = tz.with_ymd_and_hms(2024, 3, 4, 20, 0, 0).unwrap();
+        let now = tz.with_ymd_and_hms(2024, 3, 20, 12, 0, 0).unwrap();
+        assert_eq!(
+            next_occurrence(start, REPEAT_WEEKLY, now),
+            Some(tz.with_ymd_and_hms(2024, 3, 25, 20, 0, 0).unwrap())
+        );
+        assert_eq!(
+            next_occurrence(start, REPEAT_DAILY, now),
+            Some(tz.with_ymd_and_hms(2024, 3, 20, 20, 0, 0).unwrap())
+        );
+        assert_eq!(next_occurrence(Utc::now(), REPEAT_NONE, Utc::now()), None);
+    }
+
+    #[test]
+    fn test_next_occurrence_across_dst() {
+        let paris = chrono_tz::Europe::Paris;
+        let start = paris.with_ymd_and_hms(2024, 3, 30, 20, 0, 0).unwrap();
+        let next = next_occurrence(start, REPEAT_DAILY, start).unwrap();
+        // Same wall clock time, one hour less in UTC once summer time started
+        assert_eq!(next.timestamp() - start.timestamp(), 23 * 3600);
+        let start = paris.with_ymd_and_hms(2024, 3, 30, 2, 30, 0).unwrap();
+        let next = next_occurrence(start, REPEAT_DAILY, start).unwrap();
+        assert_eq!(
+            next.with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2024, 3, 31, 1, 30, 0).unwrap()
+        );
+        let start = paris.with_ymd_and_hms(2024, 10, 26, 20, 0, 0).unwrap();
+        let next = next_occurrence(start, REPEAT_DAILY, start).unwrap();
+        assert_eq!(next.timestamp() - start.timestamp(), 25 * 3600);
+    }
+
+    #[test]
+    fn test_snooze_keeps_repeat_schedule() {
+        let now = Local::now();
+        let start = now - TimeDelta::minutes(10);
+        let mut epg = EPGNotify {
+            epg_id: "news".to_string(),
+            title: "News".to_string(),
+            start_timestamp: start.timestamp(),
+            channel_id: None,
+            tvg_id: None,
+            source_id: None,
+            repeat: Some(REPEAT_WEEKLY),
+            snooze_minutes: None,
+            snoozed_until: None,
+            channel_name: "BBC One".to_string(),
+        };
+        assert!(advance(&mut epg, now).unwrap());
+        let next_week = epg.start_timestamp;
+        assert_eq!(
+            next_week,
+            next_occurrence(start, REPEAT_WEEKLY, now)
+                .unwrap()
+                .timestamp()
+        );
+        // Snoozed from the notification after it already moved to next week
+        epg.snoozed_until = Some(now.timestamp());
+        assert!(advance(&mut epg, now).unwrap());
+        assert_eq!(epg.start_timestamp, next_week);
+        assert_eq!(epg.snoozed_until, None);
+        epg.repeat = None;
+        epg.start_timestamp = start.timestamp();
+        epg.snoozed_until = Some(now.timestamp());
+        assert!(!advance(&mut epg, now).unwrap());
+    }
+
+    #[test]
+    fn test_disabled_source_hides_reminders() {
+        let _db = sql::init_test_db();
+        let source_id = sql::do_tx(|tx| {
+            sql::create_source(tx, &sql::get_custom_source("Reminders".to_string()))
+        })
+        .unwrap();
+        sql::add_epg(EPGNotify {
+            epg_id: "news".to_string(),
+            title: "News".to_string(),
+            start_timestamp: Utc::now().timestamp() + 3600,
+            channel_id: None,
+            tvg_id: Some("bbc1.uk".to_string()),
+            source_id: Some(source_id),
+            repeat: None,
+            snooze_minutes: None,
+            snoozed_until: None,
+            channel_name: "BBC One".to_string(),
+        })
+        .unwrap();
+        assert_eq!(get_active_reminders().unwrap().len(), 1);
+        sql::set_source_enabled(false, source_id).unwrap();
+        assert!(get_active_reminders().unwrap().is_empty());
+        sql::set_source_enabled(true, source_id).unwrap();
+        assert_eq!(get_active_reminders().unwrap().len(), 1);
     }
-    let mut state = state.lock().await;
-    state.notify_stop.store(false, Relaxed);
-    let stop = state.notify_stop.clone();
-    state
-        .thread_handle
-        .replace(thread::spawn(|| poll(list, stop, app)));
-    Ok(())
 }
@@ -0,0 +1,132 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::types::{EpgRefreshResult, XtreamThrottle};
+
+// Events tagged with an operation id, see `emit`
+pub const SOURCE_REFRESH_PROGRESS: &str = "source_refresh_progress";
+pub const IMPORT_PROGRESS: &str = "import_progress";
+pub const RECORDING_STATE_CHANGED: &str = "recording_state_changed";
+pub const PLAYBACK_STATE_CHANGED: &str = "playback_state_changed";
+pub const EPG_REFRESH_DONE: &str = "epg_refresh_done";
+pub const HEALTH_CHECK_PROGRESS: &str = "health_check_progress";
+pub const FOLDER_SCAN_PROGRESS: &str = "folder_scan_progress";
+pub const SERIES_PREFETCH_PROGRESS: &str = "series_prefetch_progress";
+pub const REMINDER_FIRED: &str = "reminder_fired";
+
+// Events carrying a bare value
+pub const DOWNLOAD_PROGRESS: &str = "progress";
+pub const YTDLP_UPDATE_PROGRESS: &str = "ytdlp_update_progress";
+pub const RESTREAM_STARTED: &str = "restream_started";
+pub const COMPACT_STARTED: &str = "compact_started";
+pub const COMPACT_FINISHED: &str = "compact_finished";
+pub const SLEEP_TIMER_WARNING: &str = "sleep_timer_warning";
+pub const SLEEP_TIMER_ENDED: &str = "sleep_timer_ended";
+pub const ADD_SOURCE: &str = "add_source";
+pub const BACKUP_FAILED: &str = "backup_failed";
+
+static NEXT_OPERATION_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Debug, Serialize)]
+struct Tagged<T> {
+    operation_id: u64,
+    #[serde(flatten)]
+    payload: T,
+}
+
+/// Identifies one run of a long operation, every event it emits carries the same id
+pub fn new_operation_id() -> u64 {
+    NEXT_OPERATION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Emits `payload` with its fields next to `operation_id`. A frontend that isn't
+/// listening is not an error, failures are only logged
+pub fn emit<T: Serialize + Clone>(app: &AppHandle, event: &str, operation_id: u64, payload: T) {
+    let tagged = Tagged {
+        operation_id,
+        payload,
+    };
+    if let Err(e) = app.emit(event, tagged) {
+        tracing::warn!("Failed to emit {event}: {:?}", e);
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RefreshProgress {
+    pub source_id: i64,
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ImportProgress {
+    pub name: String,
+    pub finished: bool,
+    pub error: Option<String>,
+    /// Set while a request the provider refused waits to be retried
+    pub throttle: Option<XtreamThrottle>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RecordingStateChanged {
+    pub channel_name: String,
+    pub recording: bool,
+    /// Why the recording ended when it wasn't stopped by the user
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct PlaybackStateChanged {
+    pub channel_id: Option<i64>,
+    pub channel_name: String,
+    pub playing: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct EpgRefreshDone {
+    pub epg_source_id: i64,
+    pub result: Option<EpgRefreshResult>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct HealthCheckProgress {
+    pub checked: usize,
+    pub total: usize,
+    pub dead: usize,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ReminderFired {
+    pub epg_id: String,
+    pub title: String,
+    pub channel_id: Option<i64>,
+    pub channel_name: String,
+    /// False when the channel of the reminder could not be found anymore
+    pub found: bool,
+}
+
+#[cfg(test)]
+mod test_events {
+    use super::{new_operation_id, HealthCheckProgress, Tagged};
+
+    #[test]
+    fn test_payload_is_tagged() {
+        let tagged = Tagged {
+            operation_id: 7,
+            payload: HealthCheckProgress {
+                checked: 1,
+                total: 2,
+                dead: 0,
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(tagged).unwrap(),
+            serde_json::json!({"operation_id": 7, "checked": 1, "total": 2, "dead": 0})
+        );
+        assert_ne!(new_operation_id(), new_operation_id());
+    }
+}
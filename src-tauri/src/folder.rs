@@ -0,0 +1,282 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use tauri::AppHandle;
+use url::Url;
+
+use crate::{
+    events, media_type,
+    settings::get_settings,
+    sql,
+    types::{Channel, FolderScanProgress, Source},
+};
+
+pub const DEFAULT_EXTENSIONS: &str = "mkv,mp4,ts,avi";
+const PROGRESS_INTERVAL: usize = 500;
+
+/// Scans a local media folder on a blocking thread. Every first-level subdirectory
+/// becomes a group. On refresh only new files are inserted and missing files are removed
+pub async fn scan_folder(source: Source, refresh: bool, app: Option<AppHandle>) -> Result<()> {
+    let extensions = get_extensions(get_settings()?.folder_extensions.as_deref());
+    let operation_id = events::new_operation_id();
+    tokio::task::spawn_blocking(move || {
+        scan(source, refresh, &extensions, |progress| {
+            if let Some(app) = &app {
+                events::emit(app, events::FOLDER_SCAN_PROGRESS, operation_id, progress);
+            }
+        })
+    })
+    .await?
+}
+
+pub fn get_extensions(setting: Option<&str>) -> HashSet<String> {
+    setting
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or(DEFAULT_EXTENSIONS)
+        .split(',')
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect()
+}
+
+fn scan(
+    mut source: Source,
+    refresh: bool,
+    extensions: &HashSet<String>,
+    on_progress: impl Fn(FolderScanProgress),
+) -> Result<()> {
+    let root = std::path::absolute(source.url.as_deref().context("no folder path")?)?;
+    if !root.is_dir() {
+        bail!("{} is not a folder", root.to_string_lossy());
+    }
+    let files = find_media_files(&root, extensions, &on_progress);
+    let mut conn = sql::get_conn()?;
+    let _import = sql::lock_import();
+    let tx = sql::import_transaction(&mut conn)?;
+    // a rescan keeps the source and its channels, only the files that changed are written
+    if !refresh {
+        sql::begin_import(&tx, &mut source, false)?;
+    }
+    let source_id = source.id.context("no source id")?;
+    let known = sql::get_channel_urls_by_source(&tx, source_id)?;
+    let mut found = HashSet::with_capacity(files.len());
+    let mut groups = HashMap::new();
+    let mut pending = Vec::with_capacity(sql::INSERT_BATCH_SIZE);
+    for (path, group) in files {
+        let Ok(url) = Url::from_file_path(&path).map(|url| url.to_string()) else {
+            tracing::warn!("Skipping {}, not a valid file url", path.to_string_lossy());
+            continue;
+        };
+        if !known.contains(&url) {
+            let mut channel = get_channel(&path, url.clone(), group, source_id);
+            sql::set_channel_group_id(&mut groups, &mut channel, &tx, &source_id)?;
+            pending.push(channel);
+            if pending.len() >= sql::INSERT_BATCH_SIZE {
+                sql::insert_channels_batch(&tx, &pending)?;
+                pending.clear();
+            }
+        }
+        found.insert(url);
+    }
+    sql::insert_channels_batch(&tx, &pending)?;
+    let missing: Vec<String> = known
+        .into_iter()
+        .filter(|url| !found.contains(url))
+        .collect();
+    sql::delete_channels_by_urls(&tx, source_id, &missing)?;
+    sql::delete_empty_groups(&tx, source_id)?;
+    sql::finish_import(&tx, source_id)?;
+    tx.commit()?;
+    on_progress(FolderScanProgress {
+        found: found.len(),
+        finished: true,
+    });
+    Ok(())
+}
+
+/// Walks the tree without following symlinked directories, which could loop
+fn find_media_files(
+    root: &Path,
+    extensions: &HashSet<String>,
+    on_progress: &impl Fn(FolderScanProgress),
+) -> Vec<(PathBuf, Option<String>)> {
+    let mut files = Vec::new();
+    let mut dirs: Vec<(PathBuf, Option<String>)> = vec![(root.to_path_buf(), None)];
+    while let Some((dir, group)) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Failed to read {}: {:?}", dir.to_string_lossy(), e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                let group = group
+                    .clone()
+                    .or_else(|| Some(entry.file_name().to_string_lossy().to_string()));
+                dirs.push((path, group));
+            } else if is_media_file(&path, extensions) && path.is_file() {
+                files.push((path, group.clone()));
+                if files.len() % PROGRESS_INTERVAL == 0 {
+                    on_progress(FolderScanProgress {
+                        found: files.len(),
+                        finished: false,
+                    });
+                }
+            }
+        }
+    }
+    files
+}
+
+fn is_media_file(path: &Path, extensions: &HashSet<String>) -> bool {
+    path.extension()
+        .is_some_and(|ext| extensions.contains(&ext.to_string_lossy().to_lowercase()))
+}
+
+fn get_channel(path: &Path, url: String, group: Option<String>, source_id: i64) -> Channel {
+    Channel {
+        id: None,
+        name: path
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| url.clone()),
+        url: Some(url),
+        group,
+        image: None,
+        media_type: media_type::MOVIE,
+        source_id: Some(source_id),
+        series_id: None,
+        group_id: None,
+        favorite: false,
+        watched: false,
+        stream_id: None,
+        tvg_id: None,
+        media_types: None,
+        catchup: None,
+    }
+}
+
+#[cfg(test)]
+mod test_folder {
+    use std::{collections::HashSet, fs};
+
+    use super::{get_extensions, scan};
+    use crate::{
+        source_type,
+        sql::{get_conn, init_test_db},
+        types::Source,
+    };
+
+    fn channels(source_id: i64) -> Vec<(String, Option<String>)> {
+        let conn = get_conn().unwrap();
+        let mut stmt = conn
+            .prepare(
+                r#"
+                SELECT c.name, g.name
+                FROM channels c
+                LEFT JOIN groups g ON g.id = c.group_id
+                WHERE c.source_id = ?
+                ORDER BY c.name
+            "#,
+            )
+            .unwrap();
+        stmt.query_map([source_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_folder_scan_and_rescan() {
+        let _db = init_test_db();
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Movies/Sci-Fi")).unwrap();
+        fs::write(dir.path().join("Movies/Alien.mkv"), "").unwrap();
+        fs::write(dir.path().join("Movies/Sci-Fi/Dune.MP4"), "").unwrap();
+        fs::write(dir.path().join("Intro.ts"), "").unwrap();
+        fs::write(dir.path().join("Movies/notes.txt"), "").unwrap();
+        let mut source = Source {
+            id: None,
+            name: "library".to_string(),
+            url: Some(dir.path().to_string_lossy().to_string()),
+            username: None,
+            password: None,
+            source_type: source_type::FOLDER,
+            url_origin: None,
+            enabled: true,
+            use_tvg_id: None,
+            last_refresh_at: None,
+            last_refresh_status: None,
+            last_refresh_error: None,
+            channel_count: None,
+            import_live: true,
+            import_vod: true,
+            import_series: true,
+            group_filter: None,
+        };
+        let extensions = get_extensions(None);
+        scan(source.clone(), false, &extensions, |_| {}).unwrap();
+        source.id = Some(
+            get_conn()
+                .unwrap()
+                .query_row("SELECT id FROM sources WHERE name = 'library'", [], |r| {
+                    r.get(0)
+                })
+                .unwrap(),
+        );
+        let source_id = source.id.unwrap();
+        assert_eq!(
+            channels(source_id),
+            vec![
+                ("Alien".to_string(), Some("Movies".to_string())),
+                ("Dune".to_string(), Some("Movies".to_string())),
+                ("Intro".to_string(), None),
+            ]
+        );
+        let alien_id: i64 = get_conn()
+            .unwrap()
+            .query_row("SELECT id FROM channels WHERE name = 'Alien'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+
+        fs::remove_file(dir.path().join("Intro.ts")).unwrap();
+        fs::create_dir_all(dir.path().join("Shows")).unwrap();
+        fs::write(dir.path().join("Shows/Pilot.avi"), "").unwrap();
+        scan(source, true, &extensions, |_| {}).unwrap();
+        assert_eq!(
+            channels(source_id),
+            vec![
+                ("Alien".to_string(), Some("Movies".to_string())),
+                ("Dune".to_string(), Some("Movies".to_string())),
+                ("Pilot".to_string(), Some("Shows".to_string())),
+            ]
+        );
+        let rescanned_id: i64 = get_conn()
+            .unwrap()
+            .query_row("SELECT id FROM channels WHERE name = 'Alien'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(rescanned_id, alien_id);
+    }
+
+    #[test]
+    fn test_get_extensions() {
+        assert_eq!(
+            get_extensions(Some(" .MKV, webm,,")),
+            HashSet::from(["mkv".to_string(), "webm".to_string()])
+        );
+        assert_eq!(get_extensions(Some("")).len(), 4);
+    }
+}
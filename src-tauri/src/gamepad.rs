@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc, LazyLock, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use gilrs::{Button, EventType, Gilrs};
+use tauri::{AppHandle, Emitter};
+
+use crate::settings::get_settings;
+
+pub const DEFAULT_MAPPING: &str =
+    "DPadUp=up,DPadDown=down,DPadLeft=left,DPadRight=right,South=select,East=back";
+const ACTIONS: [&str; 6] = ["up", "down", "left", "right", "select", "back"];
+const POLL_TIMEOUT: Duration = Duration::from_millis(250);
+
+static POLL_STOP: LazyLock<Mutex<Option<Arc<AtomicBool>>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn apply_settings(app: &AppHandle) -> Result<()> {
+    stop();
+    let settings = get_settings()?;
+    if settings.gamepad_enabled != Some(true) {
+        return Ok(());
+    }
+    let mapping = parse_mapping(
+        settings
+            .gamepad_mapping
+            .as_deref()
+            .unwrap_or(DEFAULT_MAPPING),
+    )?;
+    let stop = Arc::new(AtomicBool::new(false));
+    *POLL_STOP.lock().unwrap() = Some(stop.clone());
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = poll(app, mapping, stop) {
+            tracing::warn!("Gamepad polling stopped: {:?}", e);
+        }
+    });
+    Ok(())
+}
+
+fn stop() {
+    if let Some(stop) = POLL_STOP.lock().unwrap().take() {
+        stop.store(true, Relaxed);
+    }
+}
+
+fn poll(app: AppHandle, mapping: HashMap<Button, String>, stop: Arc<AtomicBool>) -> Result<()> {
+    let mut gilrs = Gilrs::new().map_err(|e| anyhow!("{e}"))?;
+    while !stop.load(Relaxed) {
+        while let Some(event) = gilrs.next_event_blocking(Some(POLL_TIMEOUT)) {
+            if let EventType::ButtonPressed(button, _) = event.event {
+                if let Some(action) = mapping.get(&button) {
+                    app.emit(&format!("nav_{action}"), ())?;
+                }
+            }
+            if stop.load(Relaxed) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_mapping(mapping: &str) -> Result<HashMap<Button, String>> {
+    let mut parsed = HashMap::new();
+    for pair in mapping
+        .split(",")
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+    {
+        let (button, action) = pair
+            .split_once("=")
+            .ok_or_else(|| anyhow!("Expected button=action, got {pair}"))?;
+        let action = action.trim().to_lowercase();
+        if !ACTIONS.contains(&action.as_str()) {
+            bail!("Unknown action {action}");
+        }
+        parsed.insert(parse_button(button.trim())?, action);
+    }
+    Ok(parsed)
+}
+
+fn parse_button(button: &str) -> Result<Button> {
+    Ok(match button {
+        "South" => Button::South,
+        "East" => Button::East,
+        "North" => Button::North,
+        "West" => Button::West,
+        "LeftTrigger" => Button::LeftTrigger,
+        "LeftTrigger2" => Button::LeftTrigger2,
+        "RightTrigger" => Button::RightTrigger,
+        "RightTrigger2" => Button::RightTrigger2,
+        "Select" => Button::Select,
+        "Start" => Button::Start,
+        "Mode" => Button::Mode,
+        "LeftThumb" => Button::LeftThumb,
+        "RightThumb" => Button::RightThumb,
+        "DPadUp" => Button::DPadUp,
+        "DPadDown" => Button::DPadDown,
+        "DPadLeft" => Button::DPadLeft,
+        "DPadRight" => Button::DPadRight,
+        _ => bail!("Unknown button {button}"),
+    })
+}
+
+#[cfg(test)]
+mod test_gamepad {
+    use gilrs::Button;
+
+    use super::{parse_mapping, DEFAULT_MAPPING};
+
+    #[test]
+    fn test_parse_mapping() {
+        let mapping = parse_mapping(DEFAULT_MAPPING).unwrap();
+        assert_eq!(mapping.get(&Button::South), Some(&"select".to_string()));
+        assert_eq!(mapping.len(), 6);
+        assert!(parse_mapping("South=jump").is_err());
+        assert!(parse_mapping("Turbo=select").is_err());
+        assert!(parse_mapping("South").is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use quick_xml::escape::escape;
+use ring::digest::{digest, SHA256};
+use serde_json::{json, Value};
+use tokio::sync::{oneshot, Semaphore};
+use warp::{
+    filters::BoxedFilter,
+    http::{header::CONTENT_TYPE, Response, StatusCode},
+    hyper::Body,
+    Filter,
+};
+
+use crate::{
+    relay,
+    settings::{get_settings, DEFAULT_HDHOMERUN_MAX_STREAMS, DEFAULT_HDHOMERUN_PORT},
+    sql,
+    types::{Channel, EpgGridRow},
+    xmltv,
+};
+
+const DEVICE_ID: &str = "4F50454E";
+const XMLTV_TIME_FORMAT: &str = "%Y%m%d%H%M%S +0000";
+/// Clients ask for the lineup often, and players for a part of the stream every few seconds
+const LINEUP_TTL: Duration = Duration::from_secs(30);
+const MAX_GUIDE_NUMBER: u32 = 99_999;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct TunerConfig {
+    port: u16,
+    lan: bool,
+    max_streams: u8,
+}
+
+struct RunningTuner {
+    config: TunerConfig,
+    stop: oneshot::Sender<()>,
+}
+
+struct LineupChannel {
+    guide_number: String,
+    channel: Channel,
+}
+
+static TUNER: LazyLock<Mutex<Option<RunningTuner>>> = LazyLock::new(|| Mutex::new(None));
+static LINEUP: LazyLock<Mutex<Option<(Instant, Arc<Vec<LineupChannel>>)>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+/// Starts, restarts or stops the emulated tuner to match the settings. A running tuner
+/// whose address didn't change is kept and reads the lineup again
+pub async fn apply_settings() -> Result<()> {
+    let settings = get_settings()?;
+    let config = TunerConfig {
+        port: settings.hdhomerun_port.unwrap_or(DEFAULT_HDHOMERUN_PORT),
+        lan: settings.hdhomerun_lan.unwrap_or(false),
+        max_streams: settings
+            .hdhomerun_max_streams
+            .unwrap_or(DEFAULT_HDHOMERUN_MAX_STREAMS),
+    };
+    let enabled = settings.hdhomerun_enabled == Some(true);
+    *LINEUP.lock().unwrap() = None;
+    let mut tuner = TUNER.lock().unwrap();
+    if enabled && tuner.as_ref().is_some_and(|t| t.config == config) {
+        return Ok(());
+    }
+    if let Some(running) = tuner.take() {
+        let _ = running.stop.send(());
+    }
+    if !enabled {
+        return Ok(());
+    }
+    let (stop, rx) = oneshot::channel::<()>();
+    let ip = if config.lan {
+        [0, 0, 0, 0]
+    } else {
+        [127, 0, 0, 1]
+    };
+    let (addr, server) = warp::serve(routes(config)).try_bind_with_graceful_shutdown(
+        SocketAddr::from((ip, config.port)),
+        async {
+            rx.await.ok();
+        },
+    )?;
+    tokio::spawn(server);
+    tracing::info!("HDHomeRun tuner listening on {addr}");
+    *tuner = Some(RunningTuner { config, stop });
+    Ok(())
+}
+
+/// The parts of HLS streams go through the relay's proxy route, which is only mounted when
+/// the tuner listens on this machine. On the LAN, clients only reach the tuner's own routes
+fn routes(config: TunerConfig) -> BoxedFilter<(Response<Body>,)> {
+    let slots = Arc::new(Semaphore::new(config.max_streams as usize));
+    let base = warp::header::optional::<String>("host").map(move |host: Option<String>| {
+        format!(
+            "http://{}",
+            host.unwrap_or_else(|| format!("127.0.0.1:{}", config.port))
+        )
+    });
+    let discover = warp::path!("discover.json")
+        .and(warp::get())
+        .and(base.clone())
+        .and_then(move |base: String| async move {
+            json_or_error(Ok(discover(&base, config.max_streams)))
+        });
+    let lineup_status = warp::path!("lineup_status.json")
+        .and(warp::get())
+        .and_then(|| async {
+            json_or_error(Ok(json!({
+                "ScanInProgress": 0,
+                "ScanPossible": 0,
+                "Source": "Cable",
+                "SourceList": ["Cable"],
+            })))
+        });
+    let lineup = warp::path!("lineup.json")
+        .and(warp::get())
+        .and(base)
+        .and_then(
+            |base: String| async move { json_or_error(get_lineup().map(|c| lineup(&base, &c))) },
+        );
+    let guide = warp::path!("xmltv.xml")
+        .and(warp::get())
+        .and_then(|| async { Ok::<_, Infallible>(guide_response()) });
+    let stream = warp::path!("stream" / i64)
+        .and(warp::get())
+        .and_then(move |channel_id| stream(channel_id, slots.clone()));
+    let routes = discover
+        .or(lineup_status)
+        .unify()
+        .or(lineup)
+        .unify()
+        .or(guide)
+        .unify()
+        .or(stream)
+        .unify();
+    if config.lan {
+        return routes.boxed();
+    }
+    routes.or(relay::proxy_route(in_lineup)).unify().boxed()
+}
+
+fn discover(base: &str, tuners: u8) -> Value {
+    json!({
+        "FriendlyName": "Open TV",
+        "Manufacturer": "Silicondust",
+        "ModelNumber": "HDTC-2US",
+        "FirmwareName": "hdhomeruntc_atsc",
+        "FirmwareVersion": "20200101",
+        "DeviceID": DEVICE_ID,
+        "DeviceAuth": "open-tv",
+        "BaseURL": base,
+        "LineupURL": format!("{base}/lineup.json"),
+        "TunerCount": tuners,
+    })
+}
+
+fn lineup(base: &str, channels: &[LineupChannel]) -> Value {
+    channels
+        .iter()
+        .filter_map(|entry| {
+            let id = entry.channel.id?;
+            Some(json!({
+                "GuideNumber": entry.guide_number,
+                "GuideName": entry.channel.name,
+                "URL": format!("{base}/stream/{id}"),
+            }))
+        })
+        .collect()
+}
+
+/// The lineup read in the last `LINEUP_TTL`, or read again
+fn get_lineup() -> Result<Arc<Vec<LineupChannel>>> {
+    let mut cached = LINEUP.lock().unwrap();
+    if let Some((read_at, lineup)) = cached.as_ref() {
+        if read_at.elapsed() < LINEUP_TTL {
+            return Ok(lineup.clone());
+        }
+    }
+    let channels = sql::get_lineup_channels(get_settings()?.hdhomerun_playlist_id)?;
+    let lineup = Arc::new(with_guide_numbers(channels));
+    *cached = Some((Instant::now(), lineup.clone()));
+    Ok(lineup)
+}
+
+fn in_lineup(channel_id: i64) -> bool {
+    get_lineup().is_ok_and(|lineup| lineup.iter().any(|c| c.channel.id == Some(channel_id)))
+}
+
+/// Ids change on every refresh while DVRs map channels by their guide number, so it is
+/// derived from the source and the name. A taken number moves to the next free one
+fn with_guide_numbers(channels: Vec<Channel>) -> Vec<LineupChannel> {
+    let mut taken = HashSet::new();
+    channels
+        .into_iter()
+        .filter(|channel| channel.id.is_some())
+        .map(|channel| {
+            let mut number = guide_number(&channel);
+            while !taken.insert(number) {
+                number = number % MAX_GUIDE_NUMBER + 1;
+            }
+            LineupChannel {
+                guide_number: number.to_string(),
+                channel,
+            }
+        })
+        .collect()
+}
+
+fn guide_number(channel: &Channel) -> u32 {
+    let key = format!("{}/{}", channel.source_id.unwrap_or_default(), channel.name);
+    let hash = digest(&SHA256, key.as_bytes());
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&hash.as_ref()[..4]);
+    u32::from_be_bytes(bytes) % MAX_GUIDE_NUMBER + 1
+}
+
+fn json_or_error(value: Result<Value>) -> Result<Response<Body>, Infallible> {
+    Ok(match value {
+        Ok(value) => Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(value.to_string()))
+            .unwrap_or_else(|e| {
+                relay::error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            }),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            relay::error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    })
+}
+
+// Each tuner is one connection to the provider, a client asking for more gets a 503
+// like a busy HDHomeRun
+async fn stream(channel_id: i64, slots: Arc<Semaphore>) -> Result<Response<Body>, Infallible> {
+    if !in_lineup(channel_id) {
+        return Ok(relay::error_response(
+            StatusCode::NOT_FOUND,
+            "Unknown channel",
+        ));
+    }
+    let Ok(permit) = slots.try_acquire_owned() else {
+        return Ok(relay::error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "All tuners are in use",
+        ));
+    };
+    relay::handle(channel_id, None, Some(permit)).await
+}
+
+fn guide_response() -> Response<Body> {
+    let guide = (|| -> Result<String> {
+        let channels = get_lineup()?;
+        let (from, to) = xmltv::get_retention_window(&get_settings()?, Utc::now().timestamp());
+        let ids: Vec<i64> = channels.iter().filter_map(|c| c.channel.id).collect();
+        let rows = sql::get_epg_grid(&ids, from, to)?;
+        Ok(guide_xml(&channels, &rows))
+    })();
+    match guide {
+        Ok(guide) => Response::builder()
+            .header(CONTENT_TYPE, "application/xml")
+            .body(Body::from(guide))
+            .unwrap_or_else(|e| {
+                relay::error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+            }),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            relay::error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
+
+/// An XMLTV document of the stored programmes, channels are identified by their guide number
+fn guide_xml(channels: &[LineupChannel], rows: &[EpgGridRow]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tv generator-info-name=\"open-tv\">\n",
+    );
+    let mut guide_numbers = HashMap::new();
+    for LineupChannel {
+        guide_number,
+        channel,
+    } in channels
+    {
+        let Some(id) = channel.id else {
+            continue;
+        };
+        guide_numbers.insert(id, guide_number);
+        xml += &format!(
+            "  <channel id=\"{guide_number}\">\n    <display-name>{}</display-name>\n",
+            escape(&channel.name)
+        );
+        if let Some(image) = channel.image.as_deref().filter(|i| !i.is_empty()) {
+            xml += &format!("    <icon src=\"{}\"/>\n", escape(image));
+        }
+        xml += "  </channel>\n";
+    }
+    for row in rows {
+        let Some(guide_number) = guide_numbers.get(&row.channel_id) else {
+            continue;
+        };
+        for programme in &row.programmes {
+            xml += &format!(
+                "  <programme start=\"{}\" stop=\"{}\" channel=\"{guide_number}\">\n    <title>{}</title>\n",
+                format_time(programme.start),
+                format_time(programme.stop),
+                escape(&programme.title)
+            );
+            if let Some(description) = &programme.description {
+                xml += &format!("    <desc>{}</desc>\n", escape(description));
+            }
+            xml += "  </programme>\n";
+        }
+    }
+    xml += "</tv>\n";
+    xml
+}
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .format(XMLTV_TIME_FORMAT)
+        .to_string()
+}
+
+#[cfg(test)]
+mod test_hdhomerun {
+    use chrono_tz::Tz;
+
+    use super::{guide_xml, lineup, with_guide_numbers};
+    use crate::{
+        test_fixtures,
+        types::{Channel, EpgGridRow, Programme},
+        xmltv,
+    };
+
+    fn channel(id: i64, name: &str) -> Channel {
+        Channel {
+            id: Some(id),
+            source_id: Some(1),
+            ..test_fixtures::channel(name)
+        }
+    }
+
+    #[test]
+    fn test_lineup_and_guide() {
+        let channels = with_guide_numbers(vec![channel(12, "France 2"), channel(40, "Arte & Co")]);
+        let guide_number = channels[1].guide_number.clone();
+        let lineup = lineup("http://192.168.1.5:5004", &channels);
+        assert_eq!(lineup[1]["GuideNumber"], guide_number);
+        assert_eq!(lineup[1]["URL"], "http://192.168.1.5:5004/stream/40");
+        let rows = [EpgGridRow {
+            channel_id: 40,
+            name: "Arte & Co".to_string(),
+            programmes: vec![Programme {
+                tvg_id: "arte.fr".to_string(),
+                title: "Tom & Jerry".to_string(),
+                description: None,
+                start: 1718013600,
+                stop: 1718017200,
+            }],
+        }];
+        let xml = guide_xml(&channels, &rows);
+        assert!(xml.contains("<display-name>Arte &amp; Co</display-name>"));
+        let mut programmes = Vec::new();
+        xmltv::parse(xml.as_bytes(), Tz::UTC, |programme| {
+            programmes.push(programme);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(programmes.len(), 1);
+        assert_eq!(programmes[0].tvg_id, guide_number);
+        assert_eq!(programmes[0].title, "Tom & Jerry");
+        assert_eq!(programmes[0].start, 1718013600);
+        assert_eq!(programmes[0].stop, 1718017200);
+    }
+
+    #[test]
+    fn test_guide_numbers_survive_a_refresh() {
+        let before = with_guide_numbers(vec![channel(12, "France 2"), channel(40, "Arte")]);
+        let after = with_guide_numbers(vec![channel(95, "Arte"), channel(96, "France 2")]);
+        assert_eq!(before[0].guide_number, after[1].guide_number);
+        assert_eq!(before[1].guide_number, after[0].guide_number);
+        assert_ne!(before[0].guide_number, before[1].guide_number);
+        let twins = with_guide_numbers(vec![channel(1, "Arte"), channel(2, "Arte")]);
+        assert_ne!(twins[0].guide_number, twins[1].guide_number);
+    }
+}
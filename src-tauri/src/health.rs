@@ -0,0 +1,121 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use reqwest::{header::RANGE, Client, StatusCode};
+use tauri::{AppHandle, State};
+use tokio::{sync::Mutex, task::JoinSet};
+
+use crate::{
+    events::{self, HealthCheckProgress},
+    media_type, proxy,
+    settings::{get_settings, DEFAULT_HEALTH_CHECK_CONCURRENCY},
+    sql,
+    tasks::CancelToken,
+    types::{AppState, ChannelHttpHeaders},
+    utils::{self, with_http_headers},
+};
+
+pub const STATUS_ALIVE: u8 = 0;
+pub const STATUS_DEAD: u8 = 1;
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub async fn check_source_health(
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+    source_id: i64,
+) -> Result<HealthCheckProgress> {
+    let stop = CancelToken::default();
+    state.lock().await.refreshes.health_check_stop = stop.clone();
+    let operation_id = events::new_operation_id();
+    let concurrency = get_settings()?
+        .health_check_concurrency
+        .unwrap_or(DEFAULT_HEALTH_CHECK_CONCURRENCY)
+        .max(1) as usize;
+    let channels = sql::get_channels_by_source(source_id, media_type::LIVESTREAM)?;
+    let source = sql::get_source_from_id(source_id)?;
+    let client = Arc::new(proxy::client_builder()?.timeout(CHECK_TIMEOUT).build()?);
+    let mut progress = HealthCheckProgress {
+        checked: 0,
+        total: channels.len(),
+        dead: 0,
+    };
+    let mut results: Vec<(i64, u8)> = Vec::with_capacity(channels.len());
+    let mut set: JoinSet<(i64, u8)> = JoinSet::new();
+    for channel in channels {
+        if stop.is_cancelled() {
+            break;
+        }
+        let Some(id) = channel.id else {
+            continue;
+        };
+        let Ok(url) = utils::resolve_url(&channel, &source) else {
+            continue;
+        };
+        while set.len() >= concurrency {
+            if let Some(result) = set.join_next().await {
+                on_result(result, &mut results, &mut progress, &app, operation_id);
+            }
+        }
+        let headers = sql::get_channel_headers_by_id(id)?;
+        let client = client.clone();
+        set.spawn(async move { (id, check_url(&client, &url, headers).await) });
+    }
+    while let Some(result) = set.join_next().await {
+        on_result(result, &mut results, &mut progress, &app, operation_id);
+    }
+    sql::do_tx(|tx| sql::set_check_statuses(tx, &results))?;
+    Ok(progress)
+}
+
+pub async fn cancel_health_check(state: State<'_, Mutex<AppState>>) -> Result<()> {
+    state.lock().await.refreshes.health_check_stop.cancel();
+    Ok(())
+}
+
+fn on_result(
+    result: Result<(i64, u8), tokio::task::JoinError>,
+    results: &mut Vec<(i64, u8)>,
+    progress: &mut HealthCheckProgress,
+    app: &AppHandle,
+    operation_id: u64,
+) {
+    match result {
+        Ok((id, status)) => {
+            if status == STATUS_DEAD {
+                progress.dead += 1;
+            }
+            results.push((id, status));
+        }
+        Err(e) => tracing::warn!("{:?}", e),
+    }
+    progress.checked += 1;
+    events::emit(
+        app,
+        events::HEALTH_CHECK_PROGRESS,
+        operation_id,
+        progress.clone(),
+    );
+}
+
+async fn check_url(client: &Client, url: &str, headers: Option<ChannelHttpHeaders>) -> u8 {
+    let headers = headers.unwrap_or_default();
+    let head = with_http_headers(client.head(url), &headers).send().await;
+    match head {
+        Ok(response) if response.status().is_success() => return STATUS_ALIVE,
+        Err(e) if e.is_timeout() || e.is_connect() => return STATUS_DEAD,
+        _ => {}
+    }
+    let get = with_http_headers(client.get(url), &headers)
+        .header(RANGE, "bytes=0-1023")
+        .send()
+        .await;
+    match get {
+        Ok(response)
+            if response.status().is_success()
+                || response.status() == StatusCode::PARTIAL_CONTENT =>
+        {
+            STATUS_ALIVE
+        }
+        _ => STATUS_DEAD,
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    LazyLock, Mutex,
+};
+
+use anyhow::Result;
+use tauri::AppHandle;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutEvent, ShortcutState};
+
+use crate::{
+    instance::focus_main_window,
+    mpv_ipc,
+    settings::get_settings,
+    types::{HotkeyConflict, Settings},
+};
+
+pub const SHOW_WINDOW: u8 = 0;
+pub const PLAY_PAUSE: u8 = 1;
+pub const DEFAULT_PLAY_PAUSE: &str = "MediaPlayPause";
+
+static BINDINGS: LazyLock<Mutex<Vec<(Shortcut, u8)>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+static PLAYING: AtomicUsize = AtomicUsize::new(0);
+
+pub fn init(app: &AppHandle) -> Result<()> {
+    app.plugin(
+        tauri_plugin_global_shortcut::Builder::new()
+            .with_handler(on_shortcut)
+            .build(),
+    )?;
+    for conflict in register_hotkeys(app)? {
+        tracing::warn!(
+            "Failed to register hotkey {}: {}",
+            conflict.accelerator,
+            conflict.message
+        );
+    }
+    Ok(())
+}
+
+/// The play/pause shortcut is only bound while something plays, so media keys keep
+/// working in other apps the rest of the time
+fn get_bindings(settings: &Settings, playing: bool) -> Vec<(String, u8)> {
+    let play_pause = settings
+        .hotkey_play_pause
+        .clone()
+        .unwrap_or(DEFAULT_PLAY_PAUSE.to_string());
+    [
+        (settings.hotkey_show_window.clone(), SHOW_WINDOW),
+        (playing.then_some(play_pause), PLAY_PAUSE),
+    ]
+    .into_iter()
+    .filter_map(|(accelerator, action)| Some((accelerator?, action)))
+    .filter(|(accelerator, _)| !accelerator.trim().is_empty())
+    .collect()
+}
+
+pub fn register_hotkeys(app: &AppHandle) -> Result<Vec<HotkeyConflict>> {
+    unregister_hotkeys(app)?;
+    let mut conflicts = Vec::new();
+    let mut registered = Vec::new();
+    let playing = PLAYING.load(Ordering::SeqCst) > 0;
+    for (accelerator, action) in get_bindings(&get_settings()?, playing) {
+        let shortcut = match accelerator.parse::<Shortcut>() {
+            Ok(shortcut) => shortcut,
+            Err(e) => {
+                conflicts.push(HotkeyConflict {
+                    accelerator,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        match app.global_shortcut().register(shortcut) {
+            Ok(_) => registered.push((shortcut, action)),
+            Err(e) => conflicts.push(HotkeyConflict {
+                accelerator,
+                message: e.to_string(),
+            }),
+        }
+    }
+    *BINDINGS.lock().unwrap() = registered;
+    Ok(conflicts)
+}
+
+/// Binds or releases the play/pause shortcut when the first playback starts or the last ends
+pub fn on_playback_changed(app: &AppHandle, playing: bool) {
+    let before = match playing {
+        true => PLAYING.fetch_add(1, Ordering::SeqCst),
+        false => PLAYING
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                Some(n.saturating_sub(1))
+            })
+            .unwrap_or_default(),
+    };
+    let after = match playing {
+        true => before + 1,
+        false => before.saturating_sub(1),
+    };
+    if (before > 0) == (after > 0) {
+        return;
+    }
+    match register_hotkeys(app) {
+        Ok(conflicts) => {
+            for conflict in conflicts {
+                tracing::warn!(
+                    "Failed to register hotkey {}: {}",
+                    conflict.accelerator,
+                    conflict.message
+                );
+            }
+        }
+        Err(e) => tracing::warn!("Failed to register hotkeys: {:?}", e),
+    }
+}
+
+pub fn unregister_hotkeys(app: &AppHandle) -> Result<()> {
+    app.global_shortcut().unregister_all()?;
+    BINDINGS.lock().unwrap().clear();
+    Ok(())
+}
+
+pub fn is_valid_accelerator(accelerator: &Option<String>) -> bool {
+    match accelerator.as_deref().map(|a| a.trim()) {
+        None | Some("") => true,
+        Some(accelerator) => accelerator.parse::<Shortcut>().is_ok(),
+    }
+}
+
+fn on_shortcut(app: &AppHandle, shortcut: &Shortcut, event: ShortcutEvent) {
+    if event.state() != ShortcutState::Pressed {
+        return;
+    }
+    let action = BINDINGS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(s, _)| s == shortcut)
+        .map(|(_, action)| *action);
+    match action {
+        Some(SHOW_WINDOW) => focus_main_window(app),
+        Some(PLAY_PAUSE) => {
+            tauri::async_runtime::spawn(async {
+                if let Err(e) = mpv_ipc::toggle_pause().await {
+                    tracing::warn!("Failed to toggle pause: {:?}", e);
+                }
+            });
+        }
+        _ => {}
+    }
+}
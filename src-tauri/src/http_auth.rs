@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use md5::{Digest, Md5};
+use ring::rand::{SecureRandom, SystemRandom};
+
+const NONCE_COUNT: &str = "00000001";
+
+/// The Authorization header answering the WWW-Authenticate `challenges` of a 401,
+/// Digest is preferred over Basic when the server offers both
+pub fn authorization(
+    challenges: &[String],
+    method: &str,
+    uri: &str,
+    username: &str,
+    password: &str,
+) -> Result<String> {
+    if let Some(params) = challenges.iter().find_map(|c| strip_scheme(c, "Digest")) {
+        return digest(
+            &parse_params(params),
+            method,
+            uri,
+            username,
+            password,
+            &new_cnonce()?,
+        );
+    }
+    if challenges
+        .iter()
+        .any(|c| strip_scheme(c, "Basic").is_some())
+    {
+        let credentials = BASE64_STANDARD.encode(format!("{username}:{password}"));
+        return Ok(format!("Basic {credentials}"));
+    }
+    bail!(
+        "Unsupported authentication scheme: {}",
+        challenges.join(", ")
+    )
+}
+
+fn strip_scheme<'a>(challenge: &'a str, scheme: &str) -> Option<&'a str> {
+    let challenge = challenge.trim_start();
+    let (name, params) = challenge.split_once(' ').unwrap_or((challenge, ""));
+    name.eq_ignore_ascii_case(scheme).then_some(params)
+}
+
+fn parse_params(params: &str) -> HashMap<String, String> {
+    let mut parsed = HashMap::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim_start();
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        parsed.insert(key, value.trim().to_string());
+        rest = remaining.trim_start().trim_start_matches(',').trim_start();
+    }
+    parsed
+}
+
+fn digest(
+    params: &HashMap<String, String>,
+    method: &str,
+    uri: &str,
+    username: &str,
+    password: &str,
+    cnonce: &str,
+) -> Result<String> {
+    let realm = params
+        .get("realm")
+        .context("Digest challenge without realm")?;
+    let nonce = params
+        .get("nonce")
+        .context("Digest challenge without nonce")?;
+    if let Some(algorithm) = params.get("algorithm") {
+        if !algorithm.eq_ignore_ascii_case("MD5") {
+            bail!("Unsupported digest algorithm {algorithm}");
+        }
+    }
+    let ha1 = md5_hex(&format!("{username}:{realm}:{password}"));
+    let ha2 = md5_hex(&format!("{method}:{uri}"));
+    let mut header =
+        format!(r#"Digest username="{username}", realm="{realm}", nonce="{nonce}", uri="{uri}""#);
+    match params.get("qop") {
+        Some(qop) if qop.split(',').any(|qop| qop.trim() == "auth") => {
+            let response = md5_hex(&format!("{ha1}:{nonce}:{NONCE_COUNT}:{cnonce}:auth:{ha2}"));
+            header += &format!(
+                r#", qop=auth, nc={NONCE_COUNT}, cnonce="{cnonce}", response="{response}""#
+            );
+        }
+        Some(qop) => bail!("Unsupported digest qop {qop}"),
+        None => {
+            let response = md5_hex(&format!("{ha1}:{nonce}:{ha2}"));
+            header += &format!(r#", response="{response}""#);
+        }
+    }
+    if let Some(opaque) = params.get("opaque") {
+        header += &format!(r#", opaque="{opaque}""#);
+    }
+    if params.contains_key("algorithm") {
+        header += ", algorithm=MD5";
+    }
+    Ok(header)
+}
+
+fn new_cnonce() -> Result<String> {
+    let mut bytes = [0u8; 8];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate a digest cnonce"))?;
+    Ok(to_hex(&bytes))
+}
+
+fn md5_hex(value: &str) -> String {
+    to_hex(&Md5::digest(value.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod test_http_auth {
+    use super::{authorization, digest, md5_hex, parse_params};
+
+    #[test]
+    fn test_md5() {
+        assert_eq!(md5_hex(""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5_hex("abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            md5_hex(
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    #[test]
+    fn test_digest() {
+        // the example of RFC 2617
+        let params = parse_params(
+            r#"realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+        );
+        let header = digest(
+            &params,
+            "GET",
+            "/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            "0a4f113b",
+        )
+        .unwrap();
+        assert!(header.starts_with(r#"Digest username="Mufasa", realm="testrealm@host.com""#));
+        assert!(header.contains(r#"response="6629fae49393a05397450978507c4ef1""#));
+        assert!(header.contains(r#"opaque="5ccc069c403ebaf9f0171e9517f40e41""#));
+        let basic = authorization(
+            &[r#"Basic realm="tvheadend""#.to_string()],
+            "GET",
+            "/",
+            "a",
+            "b",
+        );
+        assert_eq!(basic.unwrap(), "Basic YTpi");
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+
+use crate::{
+    m3u, source_type, sql,
+    types::{IdName, ImportCheckpoint, ImportSummary, StartupState},
+    utils, xtream,
+};
+
+const INTERRUPTED: &str = "The import was interrupted before it finished";
+
+/// Removes the new sources a crash left half imported when there is nothing to resume
+/// them from, the others are marked as failed and listed for `resume_import`
+pub fn recover_interrupted_imports(startup: &mut StartupState) -> Result<()> {
+    for pending in sql::get_pending_imports()? {
+        if pending.new_source && pending.checkpoint.is_none() {
+            tracing::warn!(
+                "Removing {}, its first import was interrupted",
+                pending.name
+            );
+            sql::delete_source(pending.source_id)?;
+            startup.rolled_back_imports.push(pending.name);
+            continue;
+        }
+        tracing::warn!("The import of {} was interrupted", pending.name);
+        let source = sql::get_source_from_id(pending.source_id)?;
+        // only the first start after the crash logs it
+        if source
+            .last_refresh_at
+            .map_or(true, |at| at < pending.started_at)
+        {
+            sql::record_refresh(pending.source_id, Some(INTERRUPTED.to_string()))?;
+        }
+        startup.interrupted_imports.push(IdName {
+            id: pending.source_id,
+            name: pending.name,
+        });
+    }
+    Ok(())
+}
+
+/// Continues an interrupted import from its checkpoint, or runs it again without one
+pub async fn resume_import(source_id: i64) -> Result<ImportSummary> {
+    let pending =
+        sql::get_pending_import(source_id)?.context("The source has no interrupted import")?;
+    let source = sql::get_source_from_id(source_id)?;
+    // a refresh only empties the source once its staged channels are all in
+    let wipe = !pending.new_source;
+    let result = match (source.source_type, pending.checkpoint) {
+        (
+            source_type::M3U | source_type::M3U_LINK,
+            Some(ImportCheckpoint::M3u { offset, file_size }),
+        ) => m3u::resume(source, offset, file_size, wipe).await,
+        (
+            source_type::XTREAM,
+            Some(ImportCheckpoint::Xtream {
+                media_type,
+                category_id,
+            }),
+        ) => xtream::resume(source, media_type, category_id, wipe).await,
+        _ => return utils::refresh_source(source).await,
+    };
+    let error = result.as_ref().err().map(|e| format!("{:?}", e));
+    sql::record_refresh(source_id, error).unwrap_or_else(|e| {
+        tracing::error!("Failed to record refresh of source {source_id}: {:?}", e)
+    });
+    result
+}
@@ -0,0 +1,277 @@
+use anyhow::{bail, Context, Result};
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::{
+    adhoc, events, last_channel, players, sql,
+    types::{AppState, Channel, LaunchAction},
+};
+
+pub const PLAY_CHANNEL: u8 = 0;
+pub const ADD_SOURCE: u8 = 1;
+pub const PLAY_URL: u8 = 2;
+pub const URL_SCHEME: &str = "opentv";
+const ARG_PLAY: &str = "--play";
+const ARG_SOURCE: &str = "--source";
+#[cfg(target_os = "linux")]
+const DESKTOP_FILE_NAME: &str = "open-tv-url-handler.desktop";
+
+pub fn parse_args(args: &[String]) -> Result<Option<LaunchAction>> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            ARG_PLAY => {
+                let value = args
+                    .next()
+                    .context("--play requires a channel name or id")?;
+                return Ok(Some(LaunchAction {
+                    action: PLAY_CHANNEL,
+                    value: value.to_string(),
+                }));
+            }
+            ARG_SOURCE => {
+                let value = args.next().context("--source requires a playlist url")?;
+                return Ok(Some(LaunchAction {
+                    action: ADD_SOURCE,
+                    value: parse_source_url(value)?,
+                }));
+            }
+            arg if arg.starts_with(&format!("{URL_SCHEME}:")) => {
+                return parse_deep_link(arg).map(Some);
+            }
+            // Files opened through a file association are passed as a bare path
+            arg if adhoc::is_playable_file(arg) => {
+                return Ok(Some(LaunchAction {
+                    action: PLAY_URL,
+                    value: std::path::absolute(arg)?.to_string_lossy().to_string(),
+                }));
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+pub fn parse_deep_link(link: &str) -> Result<LaunchAction> {
+    let url = Url::parse(link).with_context(|| format!("Invalid link: {link}"))?;
+    // macOS hands files opened through a file association over as file urls
+    if url.scheme() == "file" && adhoc::is_playable_file(url.path()) {
+        let path = url
+            .to_file_path()
+            .map_err(|_| anyhow::anyhow!("Invalid file: {link}"))?;
+        return Ok(LaunchAction {
+            action: PLAY_URL,
+            value: path.to_string_lossy().to_string(),
+        });
+    }
+    if url.scheme() != URL_SCHEME {
+        bail!("Unsupported link: {link}");
+    }
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+            .with_context(|| format!("Link is missing the {name} parameter"))
+    };
+    match url.host_str() {
+        Some("play") => Ok(LaunchAction {
+            action: PLAY_CHANNEL,
+            value: param("channel")?,
+        }),
+        Some("add-source") => Ok(LaunchAction {
+            action: ADD_SOURCE,
+            value: parse_source_url(&param("url")?)?,
+        }),
+        _ => bail!("Unsupported link: {link}"),
+    }
+}
+
+fn parse_source_url(value: &str) -> Result<String> {
+    let url = Url::parse(value).with_context(|| format!("Invalid playlist url: {value}"))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        bail!("Playlist url must be http or https: {value}");
+    }
+    Ok(url.to_string())
+}
+
+pub fn on_launch(app: &AppHandle, args: &[String]) {
+    match parse_args(args) {
+        Ok(Some(action)) => dispatch(app.clone(), action),
+        Ok(None) => {}
+        Err(e) => show_error(app, e),
+    }
+}
+
+pub fn on_deep_link(app: &AppHandle, link: &str) {
+    match parse_deep_link(link) {
+        Ok(action) => dispatch(app.clone(), action),
+        Err(e) => show_error(app, e),
+    }
+}
+
+pub fn on_second_instance(app: &AppHandle, args: Vec<String>) {
+    focus_main_window(app);
+    on_launch(app, args.get(1..).unwrap_or_default());
+}
+
+pub fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+pub fn dispatch(app: AppHandle, action: LaunchAction) {
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().await;
+        if !state.frontend_ready {
+            state.pending_actions.push(action);
+            return;
+        }
+        drop(state);
+        run_action(app.clone(), action);
+    });
+}
+
+pub async fn frontend_ready(app: AppHandle) {
+    let actions = {
+        let state = app.state::<Mutex<AppState>>();
+        let mut state = state.lock().await;
+        state.frontend_ready = true;
+        std::mem::take(&mut state.pending_actions)
+    };
+    for action in actions {
+        run_action(app.clone(), action);
+    }
+}
+
+fn run_action(app: AppHandle, action: LaunchAction) {
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = try_run_action(&app, action).await {
+            show_error(&app, e);
+        }
+    });
+}
+
+/// Looks a channel up by id, or by name when `value` isn't a number
+pub fn find_channel(value: &str) -> Result<Channel> {
+    match value.parse::<i64>() {
+        Ok(id) => sql::get_channel_by_id(id).ok(),
+        Err(_) => sql::find_channel_by_name(value)?,
+    }
+    .with_context(|| format!("No channel found for \"{value}\""))
+}
+
+async fn try_run_action(app: &AppHandle, action: LaunchAction) -> Result<()> {
+    match action.action {
+        PLAY_CHANNEL => {
+            let channel = find_channel(&action.value)?;
+            last_channel::remember(app, &channel).await;
+            let play = players::play(
+                channel.clone(),
+                false,
+                None,
+                players::get_process(app).await,
+            );
+            players::report_playback(app, &channel, false, play).await
+        }
+        ADD_SOURCE => Ok(app.emit(events::ADD_SOURCE, action.value)?),
+        PLAY_URL => adhoc::play(app, action.value, None, false).await,
+        _ => Ok(()),
+    }
+}
+
+pub fn register_url_scheme() {
+    #[cfg(target_os = "linux")]
+    if let Err(e) = register_linux_url_scheme() {
+        tracing::warn!("Failed to register {URL_SCHEME} links: {:?}", e);
+    }
+}
+
+/// The .desktop file is only written, and made the default handler, when missing or changed
+#[cfg(target_os = "linux")]
+fn register_linux_url_scheme() -> Result<()> {
+    let mut path = directories::BaseDirs::new()
+        .context("no home directory")?
+        .data_dir()
+        .to_owned();
+    path.push("applications");
+    std::fs::create_dir_all(&path)?;
+    path.push(DESKTOP_FILE_NAME);
+    // AppImages run from a new mount point on every launch, the image itself is what stays
+    let exe = match std::env::var_os("APPIMAGE") {
+        Some(appimage) => appimage.into(),
+        None => std::env::current_exe()?,
+    };
+    let entry = format!(
+        "[Desktop Entry]\nType=Application\nName=Open TV\nExec=\"{}\" %u\nNoDisplay=true\nMimeType=x-scheme-handler/{URL_SCHEME};\n",
+        exe.to_string_lossy()
+    );
+    if std::fs::read_to_string(&path).is_ok_and(|existing| existing == entry) {
+        return Ok(());
+    }
+    std::fs::write(&path, entry)?;
+    std::process::Command::new("xdg-mime")
+        .arg("default")
+        .arg(DESKTOP_FILE_NAME)
+        .arg(format!("x-scheme-handler/{URL_SCHEME}"))
+        .status()?;
+    Ok(())
+}
+
+fn show_error(app: &AppHandle, e: anyhow::Error) {
+    tracing::error!("{:?}", e);
+    app.dialog()
+        .message(e.to_string())
+        .kind(MessageDialogKind::Error)
+        .title("Open TV")
+        .show(|_| {});
+}
+
+#[cfg(test)]
+mod test_instance {
+    use super::{parse_args, parse_deep_link, ADD_SOURCE, PLAY_CHANNEL, PLAY_URL};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let action = parse_args(&args(&["--play", "BBC One"])).unwrap().unwrap();
+        assert_eq!(action.action, PLAY_CHANNEL);
+        assert_eq!(action.value, "BBC One");
+        let action = parse_args(&args(&["--source", "https://provider.local/get.m3u"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(action.action, ADD_SOURCE);
+        assert!(parse_args(&args(&["--play"])).is_err());
+        assert!(parse_args(&args(&["--source", "file:///etc/passwd"])).is_err());
+        assert!(parse_args(&args(&[])).unwrap().is_none());
+        let action = parse_args(&args(&["/home/fred/Videos/match.ts"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(action.action, PLAY_URL);
+        assert_eq!(action.value, "/home/fred/Videos/match.ts");
+    }
+
+    #[test]
+    fn test_parse_deep_link() {
+        let action =
+            parse_deep_link("opentv://add-source?url=https%3A%2F%2Fprovider.local%2Fget.m3u")
+                .unwrap();
+        assert_eq!(action.action, ADD_SOURCE);
+        assert_eq!(action.value, "https://provider.local/get.m3u");
+        let action = parse_deep_link("opentv://play?channel=42").unwrap();
+        assert_eq!(action.action, PLAY_CHANNEL);
+        assert_eq!(action.value, "42");
+        assert!(parse_deep_link("opentv://delete").is_err());
+        let action = parse_deep_link("file:///Users/fred/Movies/match.ts").unwrap();
+        assert_eq!(action.action, PLAY_URL);
+        assert!(parse_deep_link("file:///Users/fred/notes.txt").is_err());
+    }
+}
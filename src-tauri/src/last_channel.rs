@@ -0,0 +1,91 @@
+use anyhow::{bail, Context, Result};
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+
+use crate::{
+    mpv_ipc, sql,
+    types::{AppState, Channel},
+    utils,
+};
+
+/// Keeps `channel` in the last channel slots. Ad-hoc urls and local files such as
+/// recordings are left out, they can't be found again by id
+pub async fn remember(app: &AppHandle, channel: &Channel) {
+    let Some(channel_id) = channel.id.filter(|id| *id > 0) else {
+        return;
+    };
+    if channel
+        .url
+        .as_deref()
+        .is_some_and(|url| url.starts_with("file://"))
+    {
+        return;
+    }
+    let state = app.state::<Mutex<AppState>>();
+    let mut state = state.lock().await;
+    let history = push(&state.last_channels, channel_id);
+    if history == state.last_channels {
+        return;
+    }
+    state.last_channels = history;
+    sql::set_last_channels(&state.last_channels)
+        .unwrap_or_else(|e| tracing::error!("Failed to save the last channels: {:?}", e));
+}
+
+/// The channel played before the current one, None without history
+pub async fn get_last_channel(state: &Mutex<AppState>) -> Result<Option<Channel>> {
+    let previous = state.lock().await.last_channels.get(1).copied();
+    Ok(previous.and_then(|id| sql::get_channel_by_id(id).ok()))
+}
+
+/// Loads the previous channel into the running mpv and makes it the current one.
+/// Does nothing without history or when nothing is playing
+pub async fn swap(app: &AppHandle) -> Result<Option<Channel>> {
+    let state = app.state::<Mutex<AppState>>();
+    let Some(channel) = get_last_channel(state.inner()).await? else {
+        return Ok(None);
+    };
+    // a recording keeps its player, the swap goes to the latest live one
+    let Some(session) = sql::get_open_sessions()?
+        .into_iter()
+        .filter(|session| session.record_path.is_none())
+        .max_by_key(|session| session.started_at)
+    else {
+        return Ok(None);
+    };
+    let channel_id = channel.id.context("no channel id?")?;
+    if sql::get_channel_headers_by_id(channel_id)?.is_some() {
+        bail!(
+            "{} needs its own http headers and can't be loaded into the running player",
+            channel.name
+        );
+    }
+    let url = utils::get_playable_url(&channel)?;
+    mpv_ipc::play_next(session.channel_id, &url, &channel.name)
+        .await
+        .context("The running player can't switch channels, only mpv can")?;
+    remember(app, &channel).await;
+    Ok(Some(channel))
+}
+
+/// The history once `channel_id` played, replaying the current channel changes nothing
+fn push(history: &[i64], channel_id: i64) -> Vec<i64> {
+    std::iter::once(channel_id)
+        .chain(history.iter().copied().filter(|id| *id != channel_id))
+        .take(2)
+        .collect()
+}
+
+#[cfg(test)]
+mod test_last_channel {
+    use super::push;
+
+    #[test]
+    fn test_push() {
+        assert_eq!(push(&[], 1), vec![1]);
+        assert_eq!(push(&[1], 1), vec![1]);
+        assert_eq!(push(&[1], 2), vec![2, 1]);
+        assert_eq!(push(&[2, 1], 1), vec![1, 2]);
+        assert_eq!(push(&[2, 1], 3), vec![3, 2]);
+    }
+}
@@ -1,4 +1,8 @@
+use std::collections::HashMap;
+
 use anyhow::Error;
+use events::{EpgRefreshDone, HealthCheckProgress};
+use preview::PreparedImport;
 use tauri::{
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
@@ -6,33 +10,82 @@ use tauri::{
 };
 use tokio::sync::Mutex;
 use types::{
-    AppState, Channel, CustomChannel, CustomChannelExtraData, EPGNotify, Filters, Group, IdName,
-    NetworkInfo, Settings, Source, EPG,
+    ActiveReminder, AppState, AppliedFilterPreset, CastDevice, Channel, ChannelDetails,
+    ChannelHttpHeaders, ChannelNote, CompactResult, CustomChannel, CustomChannelExtraData, DbInfo,
+    DeleteImpact, DependencyReport, DuplicatedGroup, EPGNotify, EpgGridRow, EpgRefreshResult,
+    EpgSource, FilterPreset, Filters, Group, GroupItem, GroupMerge, GroupMergeRule, HotkeyConflict,
+    IdName, ImportSummary, NetworkInfo, NowNext, Playlist, Programme, ProgrammeSearchResult,
+    RefreshLogEntry, SearchPage, SeriesPrefetchProgress, SeriesSummary, Settings,
+    SettingsFieldError, Source, SourceListImport, SourcePreview, SourceRefreshResult, SourceStats,
+    StartupState, StreamProbe, StreamUrl, TableCheck, Tag, UrlValidation, WatchStats,
+    XtreamThrottle, YtdlpUpdate, EPG,
 };
 
+pub mod adhoc;
+pub mod backup;
+pub mod cast;
+pub mod cast_protocol;
+pub mod control_api;
+pub mod db_location;
+pub mod details;
+pub mod doctor;
+pub mod enigma2;
 pub mod epg;
-pub mod log;
+pub mod events;
+pub mod folder;
+pub mod gamepad;
+pub mod hdhomerun;
+pub mod health;
+pub mod hotkeys;
+pub mod http_auth;
+pub mod import_recovery;
+pub mod instance;
+pub mod last_channel;
+pub mod logger;
 pub mod m3u;
+pub mod maintenance;
 pub mod media_type;
 pub mod mpv;
+pub mod mpv_ipc;
+pub mod playback_error;
+pub mod players;
+pub mod preview;
+pub mod probe;
+pub mod proxy;
+pub mod recording;
+pub mod refresh_status;
+pub mod relay;
 pub mod restream;
+pub mod search_syntax;
+pub mod secrets;
+pub mod sessions;
 pub mod settings;
 pub mod share;
+pub mod sleep_timer;
+pub mod source_detection;
 pub mod source_type;
 pub mod sql;
+pub mod stats;
+pub mod tasks;
+#[cfg(test)]
+mod test_fixtures;
+pub mod thumbnail;
+pub mod tvheadend;
 pub mod types;
 pub mod utils;
+pub mod validation;
 pub mod view_type;
+pub mod vlc;
+pub mod xmltv;
 pub mod xtream;
+pub mod xtream_http;
+pub mod ytdlp;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        .plugin(tauri_plugin_single_instance::init(|app, _, _| {
-            let window = app.get_webview_window("main").expect("no main window");
-            let _ = window.unminimize();
-            let _ = window.show();
-            let _ = window.set_focus();
+        .plugin(tauri_plugin_single_instance::init(|app, args, _| {
+            instance::on_second_instance(app, args);
         }))
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_shell::init())
@@ -40,20 +93,28 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
             get_m3u8,
+            get_folder,
             get_m3u8_from_link,
             play,
             get_settings,
             update_settings,
             search,
             get_xtream,
+            get_tvheadend,
             refresh_source,
-            get_episodes,
+            ensure_series_loaded,
+            refresh_series,
+            cancel_series_load,
             favorite_channel,
             unfavorite_channel,
+            favorite_group,
+            unfavorite_group,
             source_name_exists,
             get_sources,
+            get_sources_with_stats,
             delete_source,
-            refresh_all,
+            refresh_all_sources,
+            resume_import,
             get_enabled_sources,
             toggle_source,
             delete_database,
@@ -85,10 +146,145 @@ pub fn run() {
             stop_restream,
             watch_self,
             get_network_info,
-            share_restream
+            share_restream,
+            get_new_channel_count,
+            set_channel_player,
+            get_cast_devices,
+            cast_channel,
+            cast_control,
+            cast_set_volume,
+            get_relay_url,
+            get_stream_url,
+            check_source_health,
+            cancel_health_check,
+            remove_dead_channels,
+            probe_stream,
+            cancel_probe,
+            validate_settings,
+            reset_channel_volumes,
+            check_dependencies,
+            update_ytdlp,
+            get_recent_logs,
+            get_log_path,
+            get_resume_candidate,
+            get_last_channel,
+            swap_last_channel,
+            frontend_ready,
+            register_hotkeys,
+            unregister_hotkeys,
+            open_epg_channel,
+            get_active_reminders,
+            snooze_epg,
+            get_channel_details,
+            verify_database,
+            get_db_info,
+            get_startup_state,
+            compact_database,
+            get_refresh_log,
+            set_group_image,
+            set_channel_group,
+            merge_groups,
+            get_group_merge_rules,
+            delete_group_merge_rule,
+            create_playlist,
+            rename_playlist,
+            delete_playlist,
+            get_playlists,
+            add_to_playlist,
+            remove_from_playlist,
+            reorder_playlist,
+            save_filter_preset,
+            list_filter_presets,
+            apply_filter_preset,
+            delete_filter_preset,
+            channel_auto_complete,
+            duplicate_custom_channel,
+            duplicate_custom_group,
+            reorder_custom_channels,
+            reorder_favorites,
+            preview_source_groups,
+            get_source_group_filter,
+            set_source_group_filter,
+            get_media_type_counts,
+            stop_playback,
+            toggle_pip,
+            set_channel_media_type,
+            set_sleep_timer,
+            cancel_sleep_timer,
+            generate_thumbnail,
+            refresh_episodes,
+            get_series_summary,
+            set_watched,
+            set_series_watched,
+            get_orphaned_favorites,
+            get_delete_impact,
+            play_adhoc,
+            save_adhoc_channel,
+            export_sources,
+            import_sources,
+            get_epg_sources,
+            add_epg_source,
+            remove_epg_source,
+            refresh_epg_source,
+            set_source_epg_url,
+            get_programmes,
+            set_epg_source_timezone,
+            get_now_playing_epg,
+            get_epg_grid,
+            search_epg,
+            migrate_database_location,
+            search_groups,
+            get_catchup_url,
+            prefetch_series_episodes,
+            preview_source,
+            set_channel_note,
+            get_channel_note,
+            create_tag,
+            rename_tag,
+            delete_tag,
+            get_tags,
+            tag_channel,
+            untag_channel,
+            get_watch_stats,
+            clear_watch_stats,
+            test_proxy,
+            get_control_api_token,
+            regenerate_control_api_token,
+            detect_source_type
         ])
         .setup(|app| {
+            let mut startup = StartupState::default();
+            let args: Vec<String> = std::env::args().skip(1).collect();
+            let database = db_location::resolve(&args)
+                .and_then(|path| {
+                    db_location::lock(&path)?;
+                    sql::init_db(path)
+                })
+                .and_then(|moved| {
+                    startup.corrupt_database_backup = moved;
+                    sql::create_or_initialize_db()
+                });
+            if let Err(e) = database {
+                tracing::error!("{:?}", e);
+                startup.database_error = Some(e.to_string());
+            }
+            let mut resume_candidate = None;
+            let mut last_channels = Vec::new();
+            if startup.database_error.is_none() {
+                settings::apply_platform_defaults().unwrap_or_else(|e| tracing::error!("{:?}", e));
+                resume_candidate = sessions::recover_interrupted_sessions()
+                    .inspect_err(|e| tracing::error!("{:?}", e))
+                    .unwrap_or(None);
+                import_recovery::recover_interrupted_imports(&mut startup)
+                    .unwrap_or_else(|e| tracing::error!("{:?}", e));
+                last_channels = sql::get_last_channels()
+                    .inspect_err(|e| tracing::error!("{:?}", e))
+                    .unwrap_or_default();
+            }
             app.manage(Mutex::new(AppState {
+                resume_candidate,
+                last_channels,
+                startup,
                 ..Default::default()
             }));
             let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
@@ -127,6 +323,27 @@ pub fn run() {
                 })
                 .icon(app.default_window_icon().unwrap().clone())
                 .build(app)?;
+            tauri::async_runtime::spawn(xmltv::schedule_cleanup());
+            instance::register_url_scheme();
+            hotkeys::init(app.handle())
+                .unwrap_or_else(|e| tracing::warn!("Failed to register hotkeys: {:?}", e));
+            gamepad::apply_settings(app.handle())
+                .unwrap_or_else(|e| tracing::warn!("Failed to start gamepad support: {:?}", e));
+            tauri::async_runtime::spawn(async {
+                hdhomerun::apply_settings()
+                    .await
+                    .unwrap_or_else(|e| tracing::warn!("Failed to start HDHomeRun tuner: {:?}", e));
+            });
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                control_api::apply_settings(&handle)
+                    .await
+                    .unwrap_or_else(|e| tracing::warn!("Failed to start the control api: {:?}", e));
+            });
+            instance::on_launch(app.handle(), &args);
+            tauri::async_runtime::spawn(ytdlp::auto_update(app.handle().clone()));
+            tauri::async_runtime::spawn(maintenance::auto_compact(app.handle().clone()));
+            tauri::async_runtime::spawn(backup::schedule_backups(app.handle().clone()));
             Ok(())
         })
         .on_window_event(|window, event| match event {
@@ -138,36 +355,210 @@ pub fn run() {
         })
         .build(tauri::generate_context!())
         .expect("error while running tauri application")
-        .run(|_app, event| match event {
+        .run(|app, event| match event {
             #[cfg(target_os = "macos")]
             tauri::RunEvent::Reopen { .. } => {
-                let window = _app.get_webview_window("main").expect("no main window");
+                let window = app.get_webview_window("main").expect("no main window");
                 let _ = window.show();
                 let _ = window.set_focus();
             }
+            #[cfg(target_os = "macos")]
+            tauri::RunEvent::Opened { urls } => {
+                for url in urls {
+                    instance::on_deep_link(app, url.as_str());
+                }
+            }
+            tauri::RunEvent::Exit => {
+                tasks::shutdown(app);
+                let _ = sql::end_open_sessions(false).inspect_err(|e| tracing::error!("{:?}", e));
+            }
             _ => {}
         });
 }
 
 fn map_err_frontend(e: Error) -> String {
+    tracing::error!("{:?}", e);
     return format!("{:?}", e);
 }
 
+#[tauri::command]
+async fn get_m3u8(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    source: Source,
+) -> Result<ImportSummary, String> {
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
+    utils::report_import(&app, source.name.clone(), async {
+        m3u::import_m3u8(source, false).await
+    })
+    .await
+    .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn preview_source(
+    state: State<'_, Mutex<AppState>>,
+    mut source: Source,
+) -> Result<SourcePreview, String> {
+    utils::normalize_source_url(&mut source).map_err(map_err_frontend)?;
+    preview::preview_source(state, source)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn preview_source_groups(mut source: Source) -> Result<Vec<String>, String> {
+    utils::normalize_source_url(&mut source).map_err(map_err_frontend)?;
+    utils::preview_source_groups(source)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn detect_source_type(url: String) -> Result<Option<Source>, String> {
+    source_detection::detect_source_type(&url)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_source_group_filter(source_id: i64) -> Result<Vec<String>, String> {
+    sql::get_source_group_filter(source_id).map_err(map_err_frontend)
+}
+
 #[tauri::command(async)]
-fn get_m3u8(source: Source) -> Result<(), String> {
-    m3u::read_m3u8(source, false).map_err(map_err_frontend)
+fn set_source_group_filter(source_id: i64, groups: Vec<String>) -> Result<(), String> {
+    sql::set_source_group_filter(source_id, groups).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_folder(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    source: Source,
+) -> Result<(), String> {
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
+    let name = source.name.clone();
+    utils::report_import(
+        &app,
+        name,
+        folder::scan_folder(source, false, Some(app.clone())),
+    )
+    .await
+    .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_m3u8_from_link(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    mut source: Source,
+    preview_token: Option<u64>,
+) -> Result<ImportSummary, String> {
+    utils::normalize_source_url(&mut source).map_err(map_err_frontend)?;
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
+    let name = source.name.clone();
+    let import = async {
+        let prepared = preview::take_prepared(&state, preview_token, &source).await;
+        match prepared {
+            Some(PreparedImport::Playlist(path)) => m3u::read_prepared_m3u8(source, path).await,
+            _ => m3u::get_m3u8_from_link(source, false).await,
+        }
+    };
+    utils::report_import(&app, name, import)
+        .await
+        .map_err(map_err_frontend)
 }
 
 #[tauri::command]
-async fn get_m3u8_from_link(source: Source) -> Result<(), String> {
-    m3u::get_m3u8_from_link(source, false)
+async fn play(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    channel: Channel,
+    record: bool,
+    pip: Option<bool>,
+) -> Result<(), String> {
+    let process = state.lock().await.playback.player.clone();
+    if !record {
+        last_channel::remember(&app, &channel).await;
+    }
+    let playing = channel.clone();
+    let play = players::play(channel, record, pip, process);
+    players::report_playback(&app, &playing, record, play)
         .await
         .map_err(map_err_frontend)
 }
 
 #[tauri::command]
-async fn play(channel: Channel, record: bool) -> Result<(), String> {
-    mpv::play(channel, record).await.map_err(map_err_frontend)
+async fn play_adhoc(
+    app: AppHandle,
+    url: String,
+    headers: Option<ChannelHttpHeaders>,
+    record: bool,
+) -> Result<(), String> {
+    adhoc::play(&app, url, headers, record)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn save_adhoc_channel(app: AppHandle, name: Option<String>) -> Result<(), String> {
+    adhoc::save_last(&app, name).await.map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_channel_media_type(channel_id: i64, media_type: Option<u8>) -> Result<(), String> {
+    sql::set_channel_media_type(channel_id, media_type).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_orphaned_favorites() -> Result<Vec<Channel>, String> {
+    sql::get_orphaned_favorites().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_watched(channel_id: i64, watched: bool) -> Result<(), String> {
+    sql::set_watched(channel_id, watched).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_series_watched(series_id: i64, source_id: i64, watched: bool) -> Result<(), String> {
+    sql::set_series_watched(series_id, source_id, watched).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn toggle_pip() -> Result<(), String> {
+    mpv_ipc::toggle_ontop().await.map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn stop_playback(state: State<'_, Mutex<AppState>>) -> Result<(), String> {
+    let process = state.lock().await.playback.player.clone();
+    players::stop_playback(&process).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn set_sleep_timer(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    minutes: u16,
+    stop_recording: bool,
+) -> Result<i64, String> {
+    sleep_timer::set(&state, app, minutes, stop_recording)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn cancel_sleep_timer(state: State<'_, Mutex<AppState>>) -> Result<(), String> {
+    sleep_timer::cancel(&state.lock().await.playback.player);
+    Ok(())
 }
 
 #[tauri::command(async)]
@@ -175,42 +566,188 @@ fn get_settings() -> Result<Settings, String> {
     settings::get_settings().map_err(map_err_frontend)
 }
 
+#[tauri::command]
+async fn update_settings(
+    app: AppHandle,
+    settings: Settings,
+) -> Result<Vec<HotkeyConflict>, String> {
+    settings::update_settings(settings).map_err(map_err_frontend)?;
+    proxy::reset_client();
+    gamepad::apply_settings(&app).map_err(map_err_frontend)?;
+    hdhomerun::apply_settings()
+        .await
+        .map_err(map_err_frontend)?;
+    control_api::apply_settings(&app)
+        .await
+        .map_err(map_err_frontend)?;
+    hotkeys::register_hotkeys(&app).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_control_api_token() -> Result<Option<String>, String> {
+    control_api::get_token().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn regenerate_control_api_token() -> Result<String, String> {
+    control_api::regenerate_token().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn search(filters: Filters) -> Result<SearchPage, String> {
+    sql::search_page(filters).map_err(map_err_frontend)
+}
+
 #[tauri::command(async)]
-fn update_settings(settings: Settings) -> Result<(), String> {
-    settings::update_settings(settings).map_err(map_err_frontend)
+fn search_groups(filters: Filters) -> Result<Vec<GroupItem>, String> {
+    sql::search_groups(filters).map_err(map_err_frontend)
 }
 
 #[tauri::command(async)]
-fn search(filters: Filters) -> Result<Vec<Channel>, String> {
-    sql::search(filters).map_err(map_err_frontend)
+fn get_media_type_counts(filters: Filters) -> Result<HashMap<u8, u64>, String> {
+    sql::get_media_type_counts(filters).map_err(map_err_frontend)
 }
 
 #[tauri::command]
-async fn get_xtream(source: Source) -> Result<(), String> {
-    xtream::get_xtream(source, false)
+async fn get_xtream(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    mut source: Source,
+    preview_token: Option<u64>,
+) -> Result<ImportSummary, String> {
+    utils::normalize_source_url(&mut source).map_err(map_err_frontend)?;
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
+    let report = utils::ImportReport::start(&app, source.name.clone());
+    let on_retry = |throttle: &XtreamThrottle| report.throttled(throttle);
+    let prepared = preview::take_prepared(&state, preview_token, &source).await;
+    let result = match prepared {
+        Some(PreparedImport::Xtream(data)) => xtream::import_prepared(source, data).await,
+        _ => xtream::get_xtream(source, false, &on_retry).await,
+    };
+    report.finish(&result);
+    result.map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_tvheadend(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+    mut source: Source,
+) -> Result<ImportSummary, String> {
+    utils::normalize_source_url(&mut source).map_err(map_err_frontend)?;
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
+    let name = source.name.clone();
+    utils::report_import(&app, name, tvheadend::get_tvheadend(source, false))
         .await
         .map_err(map_err_frontend)
 }
 
 #[tauri::command]
-async fn refresh_source(source: Source) -> Result<(), String> {
+async fn refresh_source(
+    state: State<'_, Mutex<AppState>>,
+    source: Source,
+) -> Result<ImportSummary, String> {
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
     utils::refresh_source(source)
         .await
         .map_err(map_err_frontend)
 }
 
 #[tauri::command]
-async fn refresh_all() -> Result<(), String> {
-    utils::refresh_all().await.map_err(map_err_frontend)
+async fn resume_import(
+    state: State<'_, Mutex<AppState>>,
+    source_id: i64,
+) -> Result<ImportSummary, String> {
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
+    import_recovery::resume_import(source_id)
+        .await
+        .map_err(map_err_frontend)
 }
 
 #[tauri::command]
-async fn get_episodes(channel: Channel) -> Result<(), String> {
-    xtream::get_episodes(channel)
+async fn refresh_all_sources(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Vec<SourceRefreshResult>, String> {
+    let _import = maintenance::ImportGuard::new(&state)
+        .await
+        .map_err(map_err_frontend)?;
+    let stop = state.lock().await.refreshes.stop.clone();
+    utils::refresh_all_sources(Some(app), stop)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn ensure_series_loaded(
+    state: State<'_, Mutex<AppState>>,
+    series_id: u64,
+    source_id: i64,
+    load_id: u64,
+) -> Result<Vec<Channel>, String> {
+    xtream::run_series_load(&state, load_id, |stop| {
+        xtream::ensure_series_loaded(stop, series_id, source_id)
+    })
+    .await
+    .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn refresh_series(
+    state: State<'_, Mutex<AppState>>,
+    series_id: u64,
+    source_id: i64,
+    load_id: u64,
+) -> Result<Vec<Channel>, String> {
+    xtream::run_series_load(&state, load_id, |stop| {
+        xtream::refresh_series(stop, series_id, source_id)
+    })
+    .await
+    .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn cancel_series_load(state: State<'_, Mutex<AppState>>, load_id: u64) -> Result<(), String> {
+    xtream::cancel_series_load(&state, load_id).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn refresh_episodes(
+    state: State<'_, Mutex<AppState>>,
+    channel: Channel,
+    load_id: u64,
+) -> Result<u32, String> {
+    xtream::run_series_load(&state, load_id, |stop| {
+        xtream::refresh_episodes(stop, channel)
+    })
+    .await
+    .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn prefetch_series_episodes(
+    app: AppHandle,
+    source_id: i64,
+) -> Result<SeriesPrefetchProgress, String> {
+    xtream::prefetch_episodes(app, source_id)
         .await
         .map_err(map_err_frontend)
 }
 
+#[tauri::command(async)]
+fn get_series_summary(series_ids: Vec<i64>, source_id: i64) -> Result<Vec<SeriesSummary>, String> {
+    sql::get_series_summary(&series_ids, source_id).map_err(map_err_frontend)
+}
+
 #[tauri::command(async)]
 fn favorite_channel(channel_id: i64) -> Result<(), String> {
     sql::favorite_channel(channel_id, true).map_err(map_err_frontend)
@@ -221,6 +758,16 @@ fn unfavorite_channel(channel_id: i64) -> Result<(), String> {
     sql::favorite_channel(channel_id, false).map_err(map_err_frontend)
 }
 
+#[tauri::command(async)]
+fn favorite_group(group_id: i64) -> Result<(), String> {
+    sql::favorite_group(group_id, true).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn unfavorite_group(group_id: i64) -> Result<(), String> {
+    sql::favorite_group(group_id, false).map_err(map_err_frontend)
+}
+
 #[tauri::command(async)]
 fn source_name_exists(name: String) -> Result<bool, String> {
     sql::source_name_exists(&name).map_err(map_err_frontend)
@@ -231,6 +778,169 @@ fn get_sources() -> Result<Vec<Source>, String> {
     sql::get_sources().map_err(map_err_frontend)
 }
 
+#[tauri::command(async)]
+fn get_sources_with_stats() -> Result<Vec<SourceStats>, String> {
+    sql::get_sources_with_stats().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn channel_auto_complete(
+    query: String,
+    source_ids: Vec<i64>,
+    media_types: Vec<u8>,
+    limit: u16,
+) -> Result<Vec<IdName>, String> {
+    sql::channel_auto_complete(query, source_ids, media_types, limit).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn save_filter_preset(name: String, filters: Filters) -> Result<i64, String> {
+    sql::save_filter_preset(name, filters).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn list_filter_presets() -> Result<Vec<FilterPreset>, String> {
+    sql::list_filter_presets().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn apply_filter_preset(id: i64) -> Result<AppliedFilterPreset, String> {
+    sql::apply_filter_preset(id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn delete_filter_preset(id: i64) -> Result<(), String> {
+    sql::delete_filter_preset(id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_channel_note(channel_id: i64, note: String) -> Result<(), String> {
+    sql::set_channel_note(channel_id, note).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_channel_note(channel_id: i64) -> Result<Option<ChannelNote>, String> {
+    sql::get_channel_note(channel_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn create_tag(name: String) -> Result<i64, String> {
+    sql::create_tag(name).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn rename_tag(id: i64, name: String) -> Result<(), String> {
+    sql::rename_tag(id, name).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn delete_tag(id: i64) -> Result<(), String> {
+    sql::delete_tag(id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_tags() -> Result<Vec<Tag>, String> {
+    sql::get_tags().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn tag_channel(channel_id: i64, tag_id: i64) -> Result<(), String> {
+    sql::tag_channel(channel_id, tag_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn untag_channel(channel_id: i64, tag_id: i64) -> Result<(), String> {
+    sql::untag_channel(channel_id, tag_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_watch_stats(from: i64, to: i64) -> Result<WatchStats, String> {
+    stats::get_watch_stats(from, to).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn clear_watch_stats() -> Result<(), String> {
+    sql::clear_watch_sessions().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn create_playlist(name: String) -> Result<i64, String> {
+    sql::create_playlist(name).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn rename_playlist(id: i64, name: String) -> Result<(), String> {
+    sql::rename_playlist(id, name).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn delete_playlist(id: i64) -> Result<(), String> {
+    sql::delete_playlist(id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_playlists() -> Result<Vec<Playlist>, String> {
+    sql::get_playlists().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn add_to_playlist(playlist_id: i64, channel_id: i64) -> Result<(), String> {
+    sql::add_to_playlist(playlist_id, channel_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn remove_from_playlist(playlist_id: i64, channel_id: i64) -> Result<(), String> {
+    sql::remove_from_playlist(playlist_id, channel_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn reorder_playlist(playlist_id: i64, channel_ids: Vec<i64>) -> Result<(), String> {
+    sql::reorder_playlist(playlist_id, channel_ids).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_channel_group(channel_id: i64, group_id: Option<i64>) -> Result<(), String> {
+    sql::set_channel_group(channel_id, group_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn merge_groups(
+    target_group_id: i64,
+    source_group_ids: Vec<i64>,
+    cross_source: bool,
+    dry_run: bool,
+    pattern: Option<String>,
+) -> Result<GroupMerge, String> {
+    sql::merge_groups(
+        target_group_id,
+        source_group_ids,
+        cross_source,
+        dry_run,
+        pattern,
+    )
+    .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_group_merge_rules(source_id: i64) -> Result<Vec<GroupMergeRule>, String> {
+    sql::get_group_merge_rules(source_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn delete_group_merge_rule(id: i64) -> Result<(), String> {
+    sql::delete_group_merge_rule(id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_group_image(group_id: i64, image: Option<String>) -> Result<(), String> {
+    sql::set_group_image(group_id, image).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_refresh_log(source_id: i64) -> Result<Vec<RefreshLogEntry>, String> {
+    sql::get_refresh_log(source_id).map_err(map_err_frontend)
+}
+
 #[tauri::command(async)]
 fn get_enabled_sources() -> Result<Vec<Source>, String> {
     sql::get_enabled_sources().map_err(map_err_frontend)
@@ -251,14 +961,36 @@ fn delete_database() -> Result<(), String> {
     sql::delete_database().map_err(map_err_frontend)
 }
 
-#[tauri::command(async)]
-fn add_custom_channel(channel: CustomChannel) -> Result<(), String> {
-    sql::do_tx(|tx| sql::add_custom_channel(tx, channel)).map_err(map_err_frontend)
+#[tauri::command]
+async fn add_custom_channel(
+    channel: CustomChannel,
+    validate: Option<bool>,
+    probe: Option<bool>,
+) -> Result<Option<UrlValidation>, String> {
+    validation::save_custom_channel(
+        channel,
+        validate.unwrap_or(false),
+        probe.unwrap_or(false),
+        |channel| sql::do_tx(|tx| sql::add_custom_channel(tx, channel)),
+    )
+    .await
+    .map_err(map_err_frontend)
 }
 
-#[tauri::command(async)]
-fn edit_custom_channel(channel: CustomChannel) -> Result<(), String> {
-    sql::edit_custom_channel(channel).map_err(map_err_frontend)
+#[tauri::command]
+async fn edit_custom_channel(
+    channel: CustomChannel,
+    validate: Option<bool>,
+    probe: Option<bool>,
+) -> Result<Option<UrlValidation>, String> {
+    validation::save_custom_channel(
+        channel,
+        validate.unwrap_or(false),
+        probe.unwrap_or(false),
+        sql::edit_custom_channel,
+    )
+    .await
+    .map_err(map_err_frontend)
 }
 
 #[tauri::command(async)]
@@ -266,6 +998,26 @@ fn delete_custom_channel(id: i64) -> Result<(), String> {
     sql::delete_custom_channel(id).map_err(map_err_frontend)
 }
 
+#[tauri::command(async)]
+fn reorder_custom_channels(group_id: Option<i64>, ordered_ids: Vec<i64>) -> Result<(), String> {
+    sql::reorder_custom_channels(group_id, ordered_ids).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn reorder_favorites(ordered_ids: Vec<i64>) -> Result<(), String> {
+    sql::reorder_favorites(ordered_ids).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn duplicate_custom_channel(id: i64) -> Result<i64, String> {
+    sql::duplicate_custom_channel(id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn duplicate_custom_group(group_id: i64) -> Result<DuplicatedGroup, String> {
+    sql::duplicate_custom_group(group_id).map_err(map_err_frontend)
+}
+
 #[tauri::command(async)]
 fn get_custom_channel_extra_data(
     id: i64,
@@ -276,7 +1028,7 @@ fn get_custom_channel_extra_data(
 
 #[tauri::command(async)]
 fn add_custom_source(name: String) -> Result<(), String> {
-    sql::do_tx(|tx| sql::create_or_find_source_by_name(tx, &mut sql::get_custom_source(name)))
+    sql::do_tx(|tx| sql::create_source(tx, &sql::get_custom_source(name)))
         .map_err(map_err_frontend)?;
     Ok(())
 }
@@ -310,8 +1062,22 @@ fn delete_custom_group(
     id: i64,
     new_id: Option<i64>,
     do_channels_update: bool,
+    cascade: bool,
 ) -> Result<(), String> {
-    sql::delete_custom_group(id, new_id, do_channels_update).map_err(map_err_frontend)
+    sql::delete_custom_group(id, new_id, do_channels_update, cascade).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_delete_impact(
+    source_id: Option<i64>,
+    group_id: Option<i64>,
+) -> Result<DeleteImpact, String> {
+    match (source_id, group_id) {
+        (Some(id), None) => sql::get_source_delete_impact(id),
+        (None, Some(id)) => sql::get_group_delete_impact(id),
+        _ => Err(anyhow::anyhow!("Pass either a source or a group")),
+    }
+    .map_err(map_err_frontend)
 }
 
 #[tauri::command(async)]
@@ -334,12 +1100,97 @@ fn share_custom_source(source: Source) -> Result<(), String> {
     share::share_custom_source(source).map_err(map_err_frontend)
 }
 
+#[tauri::command(async)]
+fn get_epg_sources() -> Result<Vec<EpgSource>, String> {
+    sql::get_epg_sources().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn add_epg_source(name: String, url: String) -> Result<i64, String> {
+    sql::add_epg_source(&name, &url).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn remove_epg_source(id: i64) -> Result<(), String> {
+    sql::remove_epg_source(id).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn refresh_epg_source(app: AppHandle, id: i64) -> Result<EpgRefreshResult, String> {
+    let result = xmltv::refresh_epg_source(id).await;
+    let done = EpgRefreshDone {
+        epg_source_id: id,
+        result: result.as_ref().ok().cloned(),
+        error: result.as_ref().err().map(|e| format!("{e:#}")),
+    };
+    events::emit(
+        &app,
+        events::EPG_REFRESH_DONE,
+        events::new_operation_id(),
+        done,
+    );
+    result.map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_source_epg_url(source_id: i64, url: Option<String>) -> Result<Option<i64>, String> {
+    sql::set_source_epg_url(source_id, url).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_epg_source_timezone(id: i64, timezone: Option<String>) -> Result<(), String> {
+    let timezone = timezone.filter(|timezone| !timezone.trim().is_empty());
+    timezone
+        .as_deref()
+        .map(xmltv::parse_timezone)
+        .transpose()
+        .and_then(|_| sql::set_epg_source_timezone(id, timezone.as_deref().map(str::trim)))
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_programmes(channel_id: i64, from: i64, to: i64) -> Result<Vec<Programme>, String> {
+    sql::get_programmes(channel_id, from, to).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_epg_grid(channel_ids: Vec<i64>, start: i64, end: i64) -> Result<Vec<EpgGridRow>, String> {
+    sql::get_epg_grid(&channel_ids, start, end).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn search_epg(
+    query: String,
+    source_ids: Vec<i64>,
+    from: i64,
+    to: i64,
+    page: u8,
+) -> Result<Vec<ProgrammeSearchResult>, String> {
+    sql::search_epg(&query, &source_ids, from, to, page).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_now_playing_epg(channel_id: i64) -> Result<NowNext, String> {
+    let channel = sql::get_channel_by_id(channel_id).map_err(map_err_frontend)?;
+    epg::get_now_next(&channel).await.map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn export_sources(path: String, include_credentials: bool) -> Result<(), String> {
+    share::export_sources(path, include_credentials).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn import_sources(path: String) -> Result<SourceListImport, String> {
+    share::import_sources(path).map_err(map_err_frontend)
+}
+
 #[tauri::command(async)]
 fn import(
     path: String,
     source_id: Option<i64>,
     name_override: Option<String>,
-) -> Result<(), String> {
+) -> Result<ImportSummary, String> {
     share::import(path, source_id, name_override).map_err(map_err_frontend)
 }
 
@@ -349,7 +1200,8 @@ fn channel_exists(name: String, url: String, source_id: i64) -> Result<bool, Str
 }
 
 #[tauri::command(async)]
-fn update_source(source: Source) -> Result<(), String> {
+fn update_source(mut source: Source) -> Result<(), String> {
+    utils::normalize_source_url(&mut source).map_err(map_err_frontend)?;
     sql::update_source(source).map_err(map_err_frontend)
 }
 
@@ -424,8 +1276,11 @@ async fn stop_restream(state: State<'_, Mutex<AppState>>) -> Result<(), String>
 }
 
 #[tauri::command]
-async fn watch_self(port: u16) -> Result<(), String> {
-    restream::watch_self(port).await.map_err(map_err_frontend)
+async fn watch_self(state: State<'_, Mutex<AppState>>, port: u16) -> Result<(), String> {
+    let process = state.lock().await.playback.player.clone();
+    restream::watch_self(port, process)
+        .await
+        .map_err(map_err_frontend)
 }
 
 #[tauri::command]
@@ -437,3 +1292,258 @@ async fn get_network_info() -> Result<NetworkInfo, String> {
 fn share_restream(address: String, channel: Channel) -> Result<(), String> {
     restream::share_restream(address, channel).map_err(map_err_frontend)
 }
+
+#[tauri::command(async)]
+fn get_new_channel_count(since: i64) -> Result<u64, String> {
+    sql::get_new_channel_count(since).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn set_channel_player(channel_id: i64, player: Option<u8>) -> Result<(), String> {
+    sql::set_channel_player(channel_id, player).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_cast_devices(
+    state: State<'_, Mutex<AppState>>,
+    refresh: bool,
+) -> Result<Vec<CastDevice>, String> {
+    cast::get_cast_devices(state, refresh)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn cast_channel(
+    state: State<'_, Mutex<AppState>>,
+    channel_id: i64,
+    device_id: String,
+) -> Result<(), String> {
+    cast::cast_channel(state, channel_id, device_id)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn cast_control(
+    state: State<'_, Mutex<AppState>>,
+    device_id: String,
+    action: String,
+) -> Result<(), String> {
+    cast::cast_control(state, device_id, action)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn cast_set_volume(
+    state: State<'_, Mutex<AppState>>,
+    device_id: String,
+    volume: u8,
+) -> Result<(), String> {
+    cast::cast_set_volume(state, device_id, volume)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_relay_url(
+    state: State<'_, Mutex<AppState>>,
+    channel_id: i64,
+) -> Result<String, String> {
+    relay::get_relay_url(state, channel_id)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_stream_url(channel_id: i64) -> Result<StreamUrl, String> {
+    utils::get_stream_url(channel_id).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_catchup_url(channel_id: i64, start: i64, stop: i64) -> Result<StreamUrl, String> {
+    utils::get_catchup_url(channel_id, start, stop).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn check_source_health(
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+    source_id: i64,
+) -> Result<HealthCheckProgress, String> {
+    health::check_source_health(state, app, source_id)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn cancel_health_check(state: State<'_, Mutex<AppState>>) -> Result<(), String> {
+    health::cancel_health_check(state)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn remove_dead_channels(source_id: i64) -> Result<usize, String> {
+    sql::delete_dead_channels(source_id).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn probe_stream(
+    state: State<'_, Mutex<AppState>>,
+    channel_id: i64,
+) -> Result<StreamProbe, String> {
+    probe::probe_stream(state, channel_id)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn cancel_probe(state: State<'_, Mutex<AppState>>) -> Result<(), String> {
+    probe::cancel_probe(state).await.map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn generate_thumbnail(channel_id: i64) -> Result<Option<String>, String> {
+    thumbnail::generate_thumbnail(channel_id)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn validate_settings(settings: Settings) -> Result<Vec<SettingsFieldError>, String> {
+    let saved = settings::get_settings().map_err(map_err_frontend)?;
+    Ok(settings.validate(&saved))
+}
+
+#[tauri::command(async)]
+fn reset_channel_volumes() -> Result<(), String> {
+    sql::reset_channel_volumes().map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn check_dependencies() -> Result<DependencyReport, String> {
+    doctor::check_dependencies().await.map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn update_ytdlp(app: AppHandle) -> Result<YtdlpUpdate, String> {
+    ytdlp::update_ytdlp(app).await.map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_recent_logs(lines: usize) -> Result<Vec<String>, String> {
+    logger::get_recent_logs(lines).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+fn get_log_path() -> String {
+    logger::get_log_path()
+}
+
+#[tauri::command]
+async fn get_resume_candidate(
+    state: State<'_, Mutex<AppState>>,
+) -> Result<Option<Channel>, String> {
+    sessions::get_resume_candidate(state)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_last_channel(state: State<'_, Mutex<AppState>>) -> Result<Option<Channel>, String> {
+    last_channel::get_last_channel(&state)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn swap_last_channel(app: AppHandle) -> Result<Option<Channel>, String> {
+    last_channel::swap(&app).await.map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn frontend_ready(app: AppHandle) {
+    instance::frontend_ready(app).await;
+}
+
+#[tauri::command(async)]
+fn register_hotkeys(app: AppHandle) -> Result<Vec<HotkeyConflict>, String> {
+    hotkeys::register_hotkeys(&app).map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn unregister_hotkeys(app: AppHandle) -> Result<(), String> {
+    hotkeys::unregister_hotkeys(&app).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn open_epg_channel(
+    app: AppHandle,
+    channel_id: Option<i64>,
+    channel_name: String,
+) -> Result<(), String> {
+    epg::open_channel(app, channel_id, channel_name)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_active_reminders() -> Result<Vec<ActiveReminder>, String> {
+    epg::get_active_reminders().map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn snooze_epg(
+    state: State<'_, Mutex<AppState>>,
+    app: AppHandle,
+    epg_id: String,
+    minutes: Option<u16>,
+) -> Result<(), String> {
+    epg::snooze_epg(state, app, epg_id, minutes)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_channel_details(channel_id: i64) -> Result<ChannelDetails, String> {
+    details::get_channel_details(channel_id)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn verify_database() -> Result<Vec<TableCheck>, String> {
+    sql::verify_database().map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn compact_database(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<CompactResult, String> {
+    maintenance::compact_database(app, state)
+        .await
+        .map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn test_proxy(proxy_url: Option<String>) -> Result<(), String> {
+    proxy::test_proxy(proxy_url).await.map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn get_db_info() -> Result<DbInfo, String> {
+    sql::get_db_info().map_err(map_err_frontend)
+}
+
+#[tauri::command(async)]
+fn migrate_database_location(new_path: String) -> Result<String, String> {
+    db_location::migrate_database_location(new_path).map_err(map_err_frontend)
+}
+
+#[tauri::command]
+async fn get_startup_state(state: State<'_, Mutex<AppState>>) -> Result<StartupState, String> {
+    Ok(state.lock().await.startup.clone())
+}
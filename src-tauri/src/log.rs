@@ -1,53 +0,0 @@
-use chrono::Local;
-use directories::ProjectDirs;
-use std::{fs, sync::LazyLock};
-
-static USE_LOGGER: LazyLock<bool> = LazyLock::new(|| init_logger());
-
-pub fn log(message: String) {
-    if *USE_LOGGER {
-        log::error!("{message}");
-    } else {
-        eprintln!("{message}");
-    }
-}
-
-fn init_logger() -> bool {
-    let file = match fs::File::create(get_and_create_log_path()) {
-        Ok(val) => val,
-        Err(e) => {
-            eprint!("Failed to create file for logger, {:?}", e);
-            return false;
-        }
-    };
-    match simplelog::WriteLogger::init(
-        simplelog::LevelFilter::Error,
-        simplelog::Config::default(),
-        file,
-    ) {
-        Ok(_) => true,
-        Err(e) => {
-            eprint!("Failed to init logger, {:?}", e);
-            return false;
-        }
-    }
-}
-
-fn get_and_create_log_path() -> String {
-    let mut path = ProjectDirs::from("dev", "fredol", "open-tv")
-        .unwrap()
-        .cache_dir()
-        .to_owned();
-    path.push("logs");
-    if !path.exists() {
-        std::fs::create_dir_all(&path).unwrap();
-    }
-    path.push(get_log_name());
-    return path.to_string_lossy().to_string();
-}
-
-fn get_log_name() -> String {
-    let current_time = Local::now();
-    let formatted_time = current_time.format("%Y-%m-%d-%H-%M-%S").to_string();
-    format!("{formatted_time}.log")
-}
@@ -0,0 +1,157 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use directories::ProjectDirs;
+use tracing_subscriber::{filter::LevelFilter, fmt::format::FmtSpan};
+
+const LOG_FILE_NAME: &str = "open-tv";
+const MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_LOG_FILES: usize = 3;
+
+struct RotatingWriter {
+    dir: PathBuf,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingWriter {
+    fn new(dir: PathBuf) -> io::Result<RotatingWriter> {
+        fs::create_dir_all(&dir)?;
+        let file = open_log_file(&dir)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingWriter {
+            dir,
+            file: Some(file),
+            size,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.take();
+        for i in (1..MAX_LOG_FILES).rev() {
+            let from = get_log_file(&self.dir, i - 1);
+            if from.exists() {
+                fs::rename(from, get_log_file(&self.dir, i))?;
+            }
+        }
+        self.file = Some(open_log_file(&self.dir)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > MAX_LOG_SIZE {
+            self.rotate()?;
+        }
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Err(io::Error::other("log file is closed")),
+        };
+        let written = file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn init() {
+    // closing spans log how long the searches and import batches they wrap took
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::INFO)
+        .with_span_events(FmtSpan::CLOSE);
+    let result = match RotatingWriter::new(get_log_dir()) {
+        Ok(writer) => builder
+            .with_ansi(false)
+            .with_writer(Mutex::new(writer))
+            .try_init(),
+        Err(e) => {
+            eprintln!("Failed to create file for logger, {:?}", e);
+            builder.with_writer(io::stderr).try_init()
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to init logger, {:?}", e);
+    }
+}
+
+pub fn get_log_dir() -> PathBuf {
+    let mut path = ProjectDirs::from("dev", "fredol", "open-tv")
+        .unwrap()
+        .data_dir()
+        .to_owned();
+    path.push("logs");
+    path
+}
+
+pub fn get_log_path() -> String {
+    get_log_file(&get_log_dir(), 0)
+        .to_string_lossy()
+        .to_string()
+}
+
+pub fn get_recent_logs(lines: usize) -> Result<Vec<String>> {
+    let file = match File::open(get_log_file(&get_log_dir(), 0)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut recent = VecDeque::with_capacity(lines);
+    for line in BufReader::new(file).lines() {
+        if recent.len() == lines {
+            recent.pop_front();
+        }
+        recent.push_back(line?);
+    }
+    Ok(recent.into())
+}
+
+fn open_log_file(dir: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_log_file(dir, 0))
+}
+
+fn get_log_file(dir: &Path, index: usize) -> PathBuf {
+    match index {
+        0 => dir.join(format!("{LOG_FILE_NAME}.log")),
+        _ => dir.join(format!("{LOG_FILE_NAME}.{index}.log")),
+    }
+}
+
+#[cfg(test)]
+mod test_logger {
+    use std::io::Write;
+
+    use super::{get_log_file, RotatingWriter, MAX_LOG_FILES, MAX_LOG_SIZE};
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join("open-tv-test-logger");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut writer = RotatingWriter::new(dir.clone()).unwrap();
+        let line = vec![b'a'; (MAX_LOG_SIZE / 2) as usize + 1];
+        for _ in 0..MAX_LOG_FILES * 2 {
+            writer.write_all(&line).unwrap();
+        }
+        for i in 0..MAX_LOG_FILES {
+            assert!(get_log_file(&dir, i).exists());
+        }
+        assert!(!get_log_file(&dir, MAX_LOG_FILES).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
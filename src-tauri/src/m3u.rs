@@ -1,20 +1,24 @@
-use std::io::Write;
+use std::io::{Seek, SeekFrom, Write};
 use std::sync::LazyLock;
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashSet},
     fs::File,
     io::{BufRead, BufReader},
+    path::{Path, PathBuf},
 };
 
 use anyhow::{bail, Context, Result};
 use regex::{Captures, Regex};
-use rusqlite::Transaction;
+use rusqlite::{Connection, Transaction};
+use tempfile::TempPath;
 use types::{Channel, Source};
 
 use crate::{
-    log, media_type, source_type,
-    sql::{self, set_channel_group_id},
-    types::{self, ChannelHttpHeaders},
+    media_type,
+    preview::PreviewBuilder,
+    proxy, source_type, sql,
+    types::{self, ChannelHttpHeaders, ImportCheckpoint, ImportSummary},
+    utils,
 };
 
 static NAME_REGEX: LazyLock<Regex> =
@@ -25,8 +29,12 @@ static ID_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"tvg-id="(?P<id>[^"]*)""#).unwrap());
 static LOGO_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"tvg-logo="(?P<logo>[^"]*)""#).unwrap());
+static CATCHUP_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"catchup(?:-days)?="(?P<catchup>[^"]*)""#).unwrap());
 static GROUP_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"group-title="(?P<group>[^"]*)""#).unwrap());
+static RADIO_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"radio="(?P<radio>[^"]*)""#).unwrap());
 
 static HTTP_ORIGIN_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"http-origin=(?P<origin>.+)"#).unwrap());
@@ -36,153 +44,396 @@ static HTTP_USER_AGENT_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"http-user-agent=(?P<user_agent>.+)"#).unwrap());
 
 struct M3UProcessing {
+    source_id: i64,
+    line_count: usize,
+    pending: Vec<(Channel, Option<ChannelHttpHeaders>)>,
+    source: Source,
+    group_filter: Option<HashSet<String>>,
+    dedup: sql::ImportDedup,
+}
+
+/// One channel of a playlist: its `#EXTINF` line, the last line before the next one
+/// and the headers set in between
+struct M3uEntry {
+    channel_line: String,
+    last_line: Option<String>,
+    headers: Option<ChannelHttpHeaders>,
+}
+
+/// Groups the lines of a playlist into channel entries, for the import and the preview
+#[derive(Default)]
+struct M3uReader {
     channel_line: Option<String>,
     channel_headers: Option<ChannelHttpHeaders>,
     channel_headers_set: bool,
     last_non_empty_line: Option<String>,
-    groups: HashMap<String, i64>,
-    source_id: i64,
-    use_tvg_id: Option<bool>,
-    line_count: usize,
 }
 
-pub fn read_m3u8(mut source: Source, wipe: bool) -> Result<()> {
-    let path = match source.source_type {
-        source_type::M3U_LINK => get_tmp_path(),
-        _ => source.url.clone().context("no file path found")?,
+impl M3uReader {
+    /// The entry `line` completed, when it starts the next one
+    fn push(&mut self, line: String) -> Option<M3uEntry> {
+        let upper = line.to_uppercase();
+        if upper.starts_with("#EXTINF") {
+            let entry = self.take_entry();
+            self.channel_line = Some(line);
+            self.channel_headers_set = false;
+            return entry;
+        }
+        if upper.starts_with("#EXTVLCOPT") {
+            let headers = self.channel_headers.get_or_insert_with(Default::default);
+            if set_http_headers(&line, headers) {
+                self.channel_headers_set = true;
+            }
+        } else if !line.trim().is_empty() {
+            self.last_non_empty_line = Some(line);
+        }
+        None
+    }
+
+    /// The entry in progress, called once more when the playlist ends
+    fn take_entry(&mut self) -> Option<M3uEntry> {
+        let channel_line = self.channel_line.take()?;
+        if !self.channel_headers_set {
+            self.channel_headers = None;
+        }
+        Some(M3uEntry {
+            channel_line,
+            last_line: self.last_non_empty_line.take(),
+            headers: self.channel_headers.take(),
+        })
+    }
+}
+
+/// A playlist on disk, the ones downloaded from a link are removed once dropped
+enum Playlist {
+    File(PathBuf),
+    Downloaded(TempPath),
+}
+
+impl Playlist {
+    async fn get(source: &Source) -> Result<Self> {
+        match source.source_type {
+            source_type::M3U_LINK => Ok(Playlist::Downloaded(download_tmp_m3u8(source).await?)),
+            _ => Ok(Playlist::File(
+                source.url.clone().context("no file path found")?.into(),
+            )),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Playlist::File(path) => path,
+            Playlist::Downloaded(path) => path,
+        }
+    }
+}
+
+pub fn read_m3u8(source: Source, wipe: bool) -> Result<ImportSummary> {
+    let path = source.url.clone().context("no file path found")?;
+    read_m3u8_file(source, wipe, Path::new(&path), None)
+}
+
+/// `read_m3u8` on a blocking thread, it waits for the import lock
+pub async fn import_m3u8(source: Source, wipe: bool) -> Result<ImportSummary> {
+    sql::spawn_blocking(move || read_m3u8(source, wipe)).await
+}
+
+/// Continues an interrupted import from the line it had reached. A playlist that changed
+/// size since is imported again from the start
+pub async fn resume(
+    source: Source,
+    offset: u64,
+    file_size: u64,
+    wipe: bool,
+) -> Result<ImportSummary> {
+    let playlist = Playlist::get(&source).await?;
+    sql::spawn_blocking(move || {
+        let path = playlist.path();
+        if std::fs::metadata(path)?.len() != file_size {
+            tracing::info!(
+                "The playlist of {} changed since its import was interrupted, importing it again",
+                source.name
+            );
+            return read_m3u8_file(source, wipe, path, None);
+        }
+        read_m3u8_file(source, wipe, path, Some(offset))
+    })
+    .await
+}
+
+/// Imports a new source from the playlist a preview downloaded, the file is removed afterwards
+pub async fn read_prepared_m3u8(source: Source, path: TempPath) -> Result<ImportSummary> {
+    sql::spawn_blocking(move || read_m3u8_file(source, false, &path, None)).await
+}
+
+/// `resume_at` continues the pending import of the source from that byte offset
+fn read_m3u8_file(
+    mut source: Source,
+    wipe: bool,
+    path: &Path,
+    resume_at: Option<u64>,
+) -> Result<ImportSummary> {
+    let mut file = File::open(path).context("Failed to open m3u8 file")?;
+    let file_size = file.metadata()?.len();
+    let offset = resume_at.unwrap_or(0);
+    file.seek(SeekFrom::Start(offset))?;
+    let lines = OffsetLines {
+        reader: BufReader::new(file),
+        offset,
     };
-    let file = File::open(path).context("Failed to open m3u8 file")?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines().enumerate();
+    let _span =
+        tracing::debug_span!("m3u_import", source_id = source.id.unwrap_or_default()).entered();
     let mut sql = sql::get_conn()?;
-    let tx = sql.transaction()?;
-    if wipe {
-        sql::wipe(&tx, source.id.context("no source id")?)?;
-    } else {
-        source.id = Some(sql::create_or_find_source_by_name(&tx, &source)?);
+    sql::set_bulk_import(&sql, true)?;
+    let result = import_lines(
+        &mut sql,
+        lines,
+        &mut source,
+        wipe,
+        resume_at.is_some(),
+        file_size,
+    );
+    sql::set_bulk_import(&sql, false)?;
+    let (line_count, summary) = result?;
+    tracing::debug!(
+        "imported {} m3u lines, {} duplicates skipped",
+        line_count,
+        summary.duplicates
+    );
+    Ok(summary)
+}
+
+/// Lines along with the byte offset they start at
+struct OffsetLines<R> {
+    reader: R,
+    offset: u64,
+}
+
+impl<R: BufRead> Iterator for OffsetLines<R> {
+    type Item = (u64, std::io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        let mut line = Vec::new();
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(read) => {
+                self.offset += read as u64;
+                if line.last() == Some(&b'\n') {
+                    line.pop();
+                    if line.last() == Some(&b'\r') {
+                        line.pop();
+                    }
+                }
+                let line = String::from_utf8(line)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+                Some((start, line))
+            }
+            Err(e) => Some((start, Err(e))),
+        }
     }
+}
+
+fn import_lines(
+    sql: &mut Connection,
+    lines: impl Iterator<Item = (u64, std::io::Result<String>)>,
+    source: &mut Source,
+    wipe: bool,
+    resuming: bool,
+    file_size: u64,
+) -> Result<(usize, ImportSummary)> {
+    let _import = sql::lock_import();
+    let tx = sql::import_transaction(sql)?;
+    let dedup = match resuming {
+        true => sql::ImportDedup::resume(&tx, source.id.context("no source id")?)?,
+        false => {
+            sql::begin_staged_import(&tx, source, wipe)?;
+            sql::ImportDedup::default()
+        }
+    };
     let mut processing = M3UProcessing {
-        channel_headers: None,
-        channel_headers_set: false,
-        channel_line: None,
-        groups: HashMap::new(),
-        last_non_empty_line: None,
         source_id: source.id.context("no source id")?,
-        use_tvg_id: source.use_tvg_id,
         line_count: 0,
+        pending: Vec::with_capacity(sql::INSERT_BATCH_SIZE),
+        source: source.clone(),
+        group_filter: sql::get_group_filter(&tx, source.id.context("no source id")?)?,
+        dedup,
     };
-    while let Some((c1, l1)) = lines.next() {
-        processing.line_count = c1;
+    let mut reader = M3uReader::default();
+    for (offset, l1) in lines {
+        processing.line_count += 1;
+        let c1 = processing.line_count;
         let l1 = match l1.with_context(|| format!("Failed to process line {c1}")) {
             Ok(r) => r,
             Err(e) => {
-                log::log(format!("{:?}", e));
+                tracing::warn!("{:?}", e);
                 continue;
             }
         };
-        let l1_upper = l1.to_uppercase();
-        if l1_upper.starts_with("#EXTINF") {
-            try_commit_channel(&mut processing, &tx);
-            processing.channel_line = Some(l1);
-            processing.channel_headers_set = false;
-        } else if l1_upper.starts_with("#EXTVLCOPT") {
-            if processing.channel_headers.is_none() {
-                processing.channel_headers = Some(ChannelHttpHeaders {
-                    ..Default::default()
-                });
-            }
-            if set_http_headers(
-                &l1,
-                processing.channel_headers.as_mut().context("no headers")?,
-            ) {
-                processing.channel_headers_set = true;
+        // an entry only ends on the `#EXTINF` line of the next one, where resuming can start
+        if let Some(entry) = reader.push(l1) {
+            try_commit_channel(&mut processing, entry);
+            if processing.pending.len() >= sql::INSERT_BATCH_SIZE {
+                flush_channels(&mut processing, &tx)?;
+                let checkpoint = ImportCheckpoint::M3u { offset, file_size };
+                sql::set_import_checkpoint(&tx, processing.source_id, &checkpoint)?;
+                sql::commit_batch(&tx)?;
             }
-        } else if !l1.trim().is_empty() {
-            processing.last_non_empty_line = Some(l1);
         }
     }
-    try_commit_channel(&mut processing, &tx);
+    if let Some(entry) = reader.take_entry() {
+        try_commit_channel(&mut processing, entry);
+    }
+    flush_channels(&mut processing, &tx)?;
+    sql::swap_in_staged(&tx, processing.source_id, wipe)?;
+    sql::finish_import(&tx, processing.source_id)?;
     tx.commit()?;
+    Ok((processing.line_count, processing.dedup.summary))
+}
+
+fn flush_channels(processing: &mut M3UProcessing, tx: &Transaction) -> Result<()> {
+    sql::stage_channels(tx, processing.source_id, &processing.pending)?;
+    processing.pending.clear();
     Ok(())
 }
 
-fn try_commit_channel(processing: &mut M3UProcessing, tx: &Transaction) {
-    if let Some(channel) = processing.channel_line.take() {
-        if !processing.channel_headers_set {
-            processing.channel_headers = None;
+fn try_commit_channel(processing: &mut M3UProcessing, entry: M3uEntry) {
+    get_channel(
+        entry.channel_line,
+        entry.last_line,
+        processing.source_id,
+        &processing.source,
+        processing.group_filter.as_ref(),
+    )
+    .map(|channel| {
+        if let Some(channel) = channel.filter(|channel| processing.dedup.is_new(channel)) {
+            processing.pending.push((channel, entry.headers))
         }
-        commit_channel(
-            channel,
-            processing.last_non_empty_line.take(),
-            &mut processing.groups,
-            processing.channel_headers.take(),
-            processing.source_id,
-            processing.use_tvg_id,
-            &tx,
+    })
+    .with_context(|| {
+        format!(
+            "Failed to process channel ending at line {}",
+            processing.line_count
         )
-        .with_context(|| {
-            format!(
-                "Failed to process channel ending at line {}",
-                processing.line_count
-            )
-        })
-        .unwrap_or_else(|e| {
-            log::log(format!("{:?}", e));
-        });
-    }
+    })
+    .unwrap_or_else(|e| {
+        tracing::warn!("{:?}", e);
+    });
 }
 
-fn commit_channel(
+fn get_channel(
     channel_line: String,
     last_line: Option<String>,
-    groups: &mut HashMap<String, i64>,
-    headers: Option<ChannelHttpHeaders>,
     source_id: i64,
-    use_tvg_id: Option<bool>,
-    tx: &Transaction,
-) -> Result<()> {
-    let mut channel = get_channel_from_lines(
+    source: &Source,
+    group_filter: Option<&HashSet<String>>,
+) -> Result<Option<Channel>> {
+    let channel = get_channel_from_lines(
         channel_line,
         last_line.context("missing last line")?,
         source_id,
-        use_tvg_id,
+        source.use_tvg_id,
     )?;
-    set_channel_group_id(groups, &mut channel, tx, &source_id).unwrap_or_else(|e| {
-        log::log(format!(
-            "Failed to set group id for channel: {}, Error: {:?}",
-            channel.name, e
-        ))
-    });
-    sql::insert_channel(tx, channel)?;
-    if let Some(mut headers) = headers {
-        headers.channel_id = Some(tx.last_insert_rowid());
-        sql::insert_channel_headers(tx, headers)?;
+    if !source.imports_media_type(channel.media_type) || !is_group_allowed(&channel, group_filter) {
+        return Ok(None);
     }
-    Ok(())
+    Ok(Some(channel))
 }
 
-pub async fn get_m3u8_from_link(source: Source, wipe: bool) -> Result<()> {
-    let client = reqwest::Client::new();
+fn is_group_allowed(channel: &Channel, group_filter: Option<&HashSet<String>>) -> bool {
+    match group_filter {
+        Some(filter) => channel
+            .group
+            .as_ref()
+            .is_some_and(|group| filter.contains(group)),
+        None => true,
+    }
+}
+
+pub async fn get_m3u8_from_link(source: Source, wipe: bool) -> Result<ImportSummary> {
+    let path = download_tmp_m3u8(&source).await?;
+    sql::spawn_blocking(move || read_m3u8_file(source, wipe, &path, None)).await
+}
+
+/// Every download gets a file of its own, so imports running side by side don't
+/// overwrite each other's playlist
+pub async fn download_tmp_m3u8(source: &Source) -> Result<TempPath> {
+    let path = tempfile::Builder::new()
+        .prefix("get-")
+        .suffix(".m3u")
+        .tempfile_in(get_cache_dir()?)?
+        .into_temp_path();
+    download_m3u8(source, &path).await?;
+    Ok(path)
+}
+
+async fn download_m3u8(source: &Source, path: &Path) -> Result<()> {
+    let client = proxy::get_client()?;
     let url = source.url.clone().context("Invalid source")?;
     let mut response = client.get(&url).send().await?;
 
-    let mut file = std::fs::File::create(get_tmp_path())?;
+    let mut file = std::fs::File::create(path)?;
     while let Some(chunk) = response.chunk().await? {
         file.write(&chunk)?;
     }
-    read_m3u8(source, wipe)
+    Ok(())
+}
+
+/// Collects the distinct group titles of a playlist without importing anything
+pub async fn preview_groups(source: &Source) -> Result<Vec<String>> {
+    let playlist = Playlist::get(source).await?;
+    let file = File::open(playlist.path()).context("Failed to open m3u8 file")?;
+    let groups: BTreeSet<String> = BufReader::new(file)
+        .lines()
+        .filter_map(|line| line.ok())
+        .filter(|line| line.to_uppercase().starts_with("#EXTINF"))
+        .filter_map(|line| {
+            GROUP_REGEX
+                .captures(&line)
+                .and_then(extract_non_empty_capture)
+                .map(|group| group.trim().to_string())
+        })
+        .collect();
+    Ok(groups.into_iter().collect())
+}
+
+/// Parses a playlist the way the import does, without touching the database
+pub fn preview_file(source: &Source, path: &Path, preview: &mut PreviewBuilder) -> Result<()> {
+    let file = File::open(path).context("Failed to open m3u8 file")?;
+    let group_filter = source.get_group_filter();
+    let mut add = |entry: M3uEntry| match get_channel(
+        entry.channel_line,
+        entry.last_line,
+        0,
+        source,
+        group_filter.as_ref(),
+    ) {
+        Ok(Some(mut channel)) => {
+            channel.source_id = None;
+            preview.add(channel);
+        }
+        Ok(None) => {}
+        Err(e) => tracing::debug!("Skipped a channel while previewing: {:?}", e),
+    };
+    let mut reader = M3uReader::default();
+    for line in BufReader::new(file).lines().filter_map(|line| line.ok()) {
+        if let Some(entry) = reader.push(line) {
+            add(entry);
+        }
+    }
+    if let Some(entry) = reader.take_entry() {
+        add(entry);
+    }
+    Ok(())
 }
 
-fn get_tmp_path() -> String {
-    let mut path = directories::ProjectDirs::from("dev", "fredol", "open-tv")
-        .unwrap()
+fn get_cache_dir() -> Result<PathBuf> {
+    let path = directories::ProjectDirs::from("dev", "fredol", "open-tv")
+        .context("can't find project folder")?
         .cache_dir()
         .to_owned();
-    if !path.exists() {
-        std::fs::create_dir_all(&path).unwrap();
-    }
-    path.push("get.m3u");
-    return path.to_string_lossy().to_string();
+    std::fs::create_dir_all(&path)?;
+    Ok(path)
 }
 
 fn extract_non_empty_capture(caps: Captures) -> Option<String> {
@@ -244,25 +495,44 @@ fn get_channel_from_lines(
                 return name_alt().or(id());
             }
         })
-        .context("Couldn't find name from Name or ID")?;
+        .or_else(|| utils::name_from_url(&second))
+        .context("Couldn't find name from Name, ID or url")?;
     let group = GROUP_REGEX
         .captures(&first)
         .and_then(extract_non_empty_capture);
     let image = LOGO_REGEX
         .captures(&first)
         .and_then(extract_non_empty_capture);
+    let media_type = match RADIO_REGEX
+        .captures(&first)
+        .and_then(extract_non_empty_capture)
+        .is_some_and(|radio| radio.trim().eq_ignore_ascii_case("true"))
+    {
+        true => media_type::RADIO,
+        false => media_type::detect_radio(get_media_type(second.clone()), group.as_deref()),
+    };
     let channel = Channel {
         id: None,
         name: name.trim().to_string(),
         group: group.map(|x| x.trim().to_string()),
         image: image.map(|x| x.trim().to_string()),
         url: Some(second.clone()),
-        media_type: get_media_type(second),
+        media_type,
         source_id: Some(source_id),
         series_id: None,
         group_id: None,
         favorite: false,
+        watched: false,
         stream_id: None,
+        tvg_id: ID_REGEX
+            .captures(&first)
+            .and_then(extract_non_empty_capture)
+            .map(|id| id.trim().to_string()),
+        media_types: None,
+        catchup: CATCHUP_REGEX
+            .captures(&first)
+            .and_then(extract_non_empty_capture)
+            .map(|catchup| catchup.trim() != "0"),
     };
     Ok(channel)
 }
@@ -278,14 +548,17 @@ fn get_media_type(url: String) -> u8 {
 
 #[cfg(test)]
 mod test_m3u {
-    use std::{env, time::Instant};
+    use std::{env, io::BufReader, time::Instant};
 
     use crate::{
         m3u::{get_channel_from_lines, get_m3u8_from_link},
+        media_type, source_type, sql, test_fixtures,
         types::Source,
     };
 
-    use super::read_m3u8;
+    use super::{preview_file, preview_groups, read_m3u8, read_m3u8_file, OffsetLines};
+    use crate::preview::PreviewBuilder;
+    use crate::types::ImportCheckpoint;
 
     #[test]
     fn test_get_channel_from_lines() {
@@ -293,50 +566,280 @@ mod test_m3u {
        , r#"http://myurl.local/1234/1234/1234"#.to_string(), 0,Some(true)).unwrap();
         get_channel_from_lines(r#"#EXTINF:-1 tvg-id="Amazing Channel" tvg-name="" tvg-logo="http://myurl.local/logos/amazing/amazing-1.png" group-title="The Best Channels"#.to_string()
        , r#"http://myurl.local/1234/1234/1234"#.to_string(), 0, Some(true)).unwrap();
-        assert!(get_channel_from_lines(r#"#EXTINF:-1 tvg-id="" tvg-name="" tvg-logo="http://myurl.local/logos/amazing/amazing-1.png" group-title="The Best Channels"#.to_string()
-       , r#"http://myurl.local/1234/1234/1234"#.to_string(), 0, Some(true)).is_err());
-        assert!(get_channel_from_lines(r#"#EXTINF:-1 tvg-id=" " tvg-name="" tvg-logo="http://myurl.local/logos/amazing/amazing-1.png" group-title="The Best Channels"#.to_string()
-       , r#"http://myurl.local/1234/1234/1234"#.to_string(), 0, Some(true)).is_err());
+        assert_eq!(get_channel_from_lines(r#"#EXTINF:-1 tvg-id="" tvg-name="" tvg-logo="http://myurl.local/logos/amazing/amazing-1.png" group-title="The Best Channels"#.to_string()
+       , r#"http://myurl.local/1234/1234/1234"#.to_string(), 0, Some(true)).unwrap().name, "1234");
+        assert_eq!(get_channel_from_lines(r#"#EXTINF:-1 tvg-id=" " tvg-name="" tvg-logo="http://myurl.local/logos/amazing/amazing-1.png" group-title="The Best Channels"#.to_string()
+       , r#"http://myurl.local/1234/1234/1234.ts"#.to_string(), 0, Some(true)).unwrap().name, "1234");
+        assert!(get_channel_from_lines(
+            r#"#EXTINF:-1 tvg-id="" tvg-name="""#.to_string(),
+            r#"http://"#.to_string(),
+            0,
+            Some(true)
+        )
+        .is_err());
         assert!(get_channel_from_lines(r#"#EXTINF:-1 tvg-id="Id Of Channel" tvg-name="Name Of Channel" tvg-logo="http://myurl.local/amazing/stuff.png" group-title="|EU| FRANCE HEVC",Alt Name Of Channel"#.to_string(), "http://myurl.local/1111/1111.ts".to_string(), 0, Some(true)).unwrap().name == "Name Of Channel");
         assert!(get_channel_from_lines(r#"#EXTINF:-1 tvg-id="Id Of Channel" tvg-name="" tvg-logo="http://myurl.local/amazing/stuff.png" group-title="|EU| FRANCE HEVC",Alt Name Of Channel"#.to_string(), "http://myurl.local/1111/1111.ts".to_string(), 0, Some(true)).unwrap().name == "Id Of Channel");
         assert!(get_channel_from_lines(r#"#EXTINF:-1 tvg-id="Id Of Channel" tvg-name="" tvg-logo="http://myurl.local/amazing/stuff.png" group-title="|EU| FRANCE HEVC",Alt Name Of Channel"#.to_string(), "http://myurl.local/1111/1111.ts".to_string(), 0, Some(false)).unwrap().name == "Alt Name Of Channel");
     }
 
+    #[test]
+    fn test_radio_detection() {
+        let media_type = |first: &str, url: &str| {
+            get_channel_from_lines(first.to_string(), url.to_string(), 0, None)
+                .unwrap()
+                .media_type
+        };
+        assert_eq!(
+            media_type(
+                r#"#EXTINF:-1 tvg-name="FIP" radio="true" group-title="France",FIP"#,
+                "http://myurl.local/fip"
+            ),
+            media_type::RADIO
+        );
+        assert_eq!(
+            media_type(
+                r#"#EXTINF:-1 tvg-name="Nova" group-title="Radios FR",Nova"#,
+                "http://myurl.local/nova"
+            ),
+            media_type::RADIO
+        );
+        assert_eq!(
+            media_type(
+                r#"#EXTINF:-1 tvg-name="Radio Show" group-title="Movies",Radio Show"#,
+                "http://myurl.local/show.mp4"
+            ),
+            media_type::MOVIE
+        );
+        assert_eq!(
+            media_type(
+                r#"#EXTINF:-1 tvg-name="TF1" radio="false" group-title="France",TF1"#,
+                "http://myurl.local/tf1"
+            ),
+            media_type::LIVESTREAM
+        );
+    }
+
     #[test]
     fn test_read_m3u8() {
-        crate::sql::drop_db().unwrap_or_default();
-        crate::sql::create_or_initialize_db().unwrap();
+        let _db = crate::sql::init_test_db();
         let now = Instant::now();
         let source = Source {
             url: Some("/home/fred/Downloads/get.php".to_string()),
-            name: "main".to_string(),
-            id: None,
-            password: None,
-            username: None,
-            url_origin: None,
-            source_type: crate::source_type::M3U,
-            enabled: true,
-            use_tvg_id: Some(true),
+            ..test_fixtures::source("main", source_type::M3U)
         };
         read_m3u8(source, false).unwrap();
         std::fs::write("bench.txt", now.elapsed().as_millis().to_string()).unwrap();
     }
 
+    #[tokio::test]
+    async fn test_group_filter() {
+        let _db = crate::sql::init_test_db();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            r#"#EXTM3U
+#EXTINF:-1 tvg-name="France 24" group-title="News",France 24
+http://example.com/france24.ts
+#EXTINF:-1 tvg-name="Eurosport" group-title="Sports",Eurosport
+http://example.com/eurosport.ts
+#EXTINF:-1 tvg-name="Ungrouped",Ungrouped
+http://example.com/ungrouped.ts
+"#,
+        )
+        .unwrap();
+        let mut source = Source {
+            url: Some(file.path().to_string_lossy().to_string()),
+            group_filter: Some(vec!["News".to_string()]),
+            ..test_fixtures::source("filtered", source_type::M3U)
+        };
+        assert_eq!(preview_groups(&source).await.unwrap(), ["News", "Sports"]);
+        read_m3u8(source.clone(), false).unwrap();
+        let names = || -> Vec<String> {
+            sql::get_conn()
+                .unwrap()
+                .prepare("SELECT name FROM channels ORDER BY name")
+                .unwrap()
+                .query_map([], |r| r.get(0))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect()
+        };
+        assert_eq!(names(), ["France 24"]);
+        source.id = Some(sql::get_sources().unwrap()[0].id.unwrap());
+        sql::set_source_group_filter(source.id.unwrap(), vec![]).unwrap();
+        read_m3u8(source, true).unwrap();
+        assert_eq!(names(), ["Eurosport", "France 24", "Ungrouped"]);
+    }
+
+    #[test]
+    fn test_resume_import() {
+        let _db = crate::sql::init_test_db();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let playlist = "#EXTM3U\r\n#EXTINF:-1 group-title=\"News\",Arte\r\nhttp://example.com/arte.ts\r\n#EXTINF:-1 group-title=\"News\",France 24\r\nhttp://example.com/france24.ts\r\n#EXTINF:-1 group-title=\"Sports\",Eurosport\r\nhttp://example.com/eurosport.ts\r\n";
+        std::fs::write(file.path(), playlist).unwrap();
+        let offset = playlist
+            .find("#EXTINF:-1 group-title=\"News\",France 24")
+            .unwrap() as u64;
+        let lines: Vec<u64> = OffsetLines {
+            reader: BufReader::new(playlist.as_bytes()),
+            offset: 0,
+        }
+        .map(|(offset, _)| offset)
+        .collect();
+        assert_eq!(lines[3], offset);
+        let mut source = Source {
+            url: Some(file.path().to_string_lossy().to_string()),
+            ..test_fixtures::source("interrupted", source_type::M3U)
+        };
+        // the first channel was staged before the crash
+        sql::do_tx(|tx| {
+            let source_id = sql::begin_staged_import(tx, &mut source, false)?;
+            let arte = get_channel_from_lines(
+                "#EXTINF:-1 group-title=\"News\",Arte".to_string(),
+                "http://example.com/arte.ts".to_string(),
+                source_id,
+                Some(true),
+            )?;
+            sql::stage_channels(tx, source_id, &[(arte, None)])?;
+            let checkpoint = ImportCheckpoint::M3u {
+                offset,
+                file_size: playlist.len() as u64,
+            };
+            sql::set_import_checkpoint(tx, source_id, &checkpoint)
+        })
+        .unwrap();
+        let pending = sql::get_pending_imports().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert!(pending[0].new_source);
+        let channels: u32 = sql::get_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM channels", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(channels, 0);
+        let summary = read_m3u8_file(source, false, file.path(), Some(offset)).unwrap();
+        assert_eq!(summary.channels, 2);
+        let names: Vec<(String, String)> = sql::get_conn()
+            .unwrap()
+            .prepare(
+                "SELECT c.name, g.name FROM channels c JOIN groups g ON g.id = c.group_id ORDER BY c.name",
+            )
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("Arte".to_string(), "News".to_string()),
+                ("Eurosport".to_string(), "Sports".to_string()),
+                ("France 24".to_string(), "News".to_string()),
+            ]
+        );
+        assert!(sql::get_pending_imports().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_duplicates_and_unnamed() {
+        let _db = crate::sql::init_test_db();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            r#"#EXTM3U
+#EXTINF:-1 tvg-name="" group-title="Misc"
+http://example.com/live/1234.ts
+#EXTINF:-1 tvg-name="Arte" group-title="News",Arte
+http://example.com/arte.ts
+#EXTINF:-1 tvg-name="Arte HD" group-title="News",Arte HD
+http://example.com/arte.ts
+#EXTINF:-1 tvg-name="France 24" group-title="News",France 24
+http://example.com/france24.ts
+#EXTINF:-1 tvg-name="France 24" group-title="News",France 24
+http://example.com/france24-backup.ts
+#EXTINF:-1 tvg-name="Arte" group-title="News",Arte
+http://example.com/arte.ts
+"#,
+        )
+        .unwrap();
+        let source = Source {
+            url: Some(file.path().to_string_lossy().to_string()),
+            ..test_fixtures::source("duplicates", source_type::M3U)
+        };
+        let summary = read_m3u8(source, false).unwrap();
+        assert_eq!(summary.channels, 5);
+        assert_eq!(summary.duplicates, 1);
+        let channels: Vec<(String, String)> = sql::get_conn()
+            .unwrap()
+            .prepare("SELECT name, url FROM channels ORDER BY name, url")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        let channels: Vec<(&str, &str)> = channels
+            .iter()
+            .map(|(name, url)| (name.as_str(), url.as_str()))
+            .collect();
+        assert_eq!(
+            channels,
+            [
+                ("1234", "http://example.com/live/1234.ts"),
+                ("Arte", "http://example.com/arte.ts"),
+                ("Arte HD", "http://example.com/arte.ts"),
+                ("France 24", "http://example.com/france24-backup.ts"),
+                ("France 24", "http://example.com/france24.ts"),
+            ]
+        );
+    }
+    #[test]
+    fn test_preview_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            r#"#EXTM3U
+#EXTINF:-1 tvg-id="france24.fr" tvg-name="France 24" group-title="News",France 24
+#EXTVLCOPT:http-user-agent=Open TV
+http://example.com/france24.ts
+#EXTINF:-1 tvg-name="Dune" group-title="Films",Dune
+http://example.com/dune.mkv
+#EXTINF:-1 tvg-name="Broken" group-title="News",Broken
+#EXTINF:-1 tvg-name="Arte" group-title="News",Arte
+http://example.com/arte.ts
+"#,
+        )
+        .unwrap();
+        let mut source = Source {
+            url: Some(file.path().to_string_lossy().to_string()),
+            ..test_fixtures::source("preview", source_type::M3U)
+        };
+        let mut preview = PreviewBuilder::default();
+        preview_file(&source, file.path(), &mut preview).unwrap();
+        let preview = preview.build(None);
+        assert_eq!(preview.channel_count, 3);
+        assert_eq!(preview.media_type_counts[&media_type::LIVESTREAM], 2);
+        assert_eq!(preview.tvg_id_count, 1);
+        assert_eq!(preview.groups.len(), 2);
+        assert_eq!(preview.sample[0].name, "France 24");
+
+        source.import_vod = false;
+        let mut preview = PreviewBuilder::default();
+        preview_file(&source, file.path(), &mut preview).unwrap();
+        assert_eq!(preview.build(None).channel_count, 2);
+
+        source.import_vod = true;
+        source.group_filter = Some(vec!["Films".to_string()]);
+        let mut preview = PreviewBuilder::default();
+        preview_file(&source, file.path(), &mut preview).unwrap();
+        assert_eq!(preview.build(None).sample[0].name, "Dune");
+    }
+
     #[tokio::test]
     async fn test_get_m3u8_from_link() {
-        crate::sql::drop_db().unwrap_or_default();
-        crate::sql::create_or_initialize_db().unwrap();
+        let _db = crate::sql::init_test_db();
         let now = Instant::now();
         let source = Source {
             url: Some(env::var("OPEN_TV_TEST_LINK").unwrap()),
-            name: "m3ulink1".to_string(),
-            id: None,
-            password: None,
-            username: None,
-            url_origin: None,
-            source_type: crate::source_type::M3U_LINK,
-            enabled: true,
-            use_tvg_id: Some(true),
+            ..test_fixtures::source("m3ulink1", source_type::M3U_LINK)
         };
         get_m3u8_from_link(source, false).await.unwrap();
         let time = now.elapsed().as_millis().to_string();
@@ -2,7 +2,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 use anyhow::Result;
 fn main() -> Result<()> {
-    open_tv_lib::sql::create_or_initialize_db()?;
+    open_tv_lib::logger::init();
     open_tv_lib::run();
     Ok(())
 }
@@ -0,0 +1,86 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use anyhow::{bail, Result};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Mutex;
+
+use crate::{
+    events, sql,
+    types::{AppState, CompactResult},
+};
+
+const AUTO_COMPACT_DELETED_ROWS: u64 = 200_000;
+/// Stored in place of the import count while the database is compacted
+const COMPACTING: usize = usize::MAX;
+
+/// Marks an import or refresh as running until dropped, so maintenance can wait for it
+pub struct ImportGuard(Arc<AtomicUsize>);
+
+impl ImportGuard {
+    /// Counted in the same step as the check, a compaction can't start in between
+    pub async fn new(state: &State<'_, Mutex<AppState>>) -> Result<Self> {
+        let imports_running = state.lock().await.refreshes.running.clone();
+        let counted = imports_running.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+            (running != COMPACTING).then_some(running + 1)
+        });
+        if counted.is_err() {
+            bail!("The database is being compacted, try again once it is done");
+        }
+        Ok(ImportGuard(imports_running))
+    }
+}
+
+impl Drop for ImportGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Holds imports off until dropped
+struct CompactGuard(Arc<AtomicUsize>);
+
+impl Drop for CompactGuard {
+    fn drop(&mut self) {
+        self.0.store(0, Ordering::SeqCst);
+    }
+}
+
+pub async fn compact_database(
+    app: AppHandle,
+    state: State<'_, Mutex<AppState>>,
+) -> Result<CompactResult> {
+    let imports_running = state.lock().await.refreshes.running.clone();
+    if imports_running
+        .compare_exchange(0, COMPACTING, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        bail!("Cannot compact the database while a source is being imported or refreshed");
+    }
+    let _compacting = CompactGuard(imports_running);
+    let before = sql::get_db_file_size()?;
+    app.emit(events::COMPACT_STARTED, before)?;
+    tokio::task::spawn_blocking(sql::compact).await??;
+    let result = CompactResult {
+        before,
+        after: sql::get_db_file_size()?,
+    };
+    app.emit(events::COMPACT_FINISHED, result.clone())?;
+    Ok(result)
+}
+
+pub async fn auto_compact(app: AppHandle) {
+    let deleted = sql::get_deleted_rows_since_vacuum().unwrap_or_else(|e| {
+        tracing::warn!("{:?}", e);
+        0
+    });
+    if deleted < AUTO_COMPACT_DELETED_ROWS {
+        return;
+    }
+    let state = app.state::<Mutex<AppState>>();
+    if let Err(e) = compact_database(app.clone(), state).await {
+        tracing::warn!("Failed to compact database: {:?}", e);
+    }
+}
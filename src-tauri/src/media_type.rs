@@ -2,3 +2,32 @@ pub const LIVESTREAM: u8 = 0;
 pub const MOVIE: u8 = 1;
 pub const SERIE: u8 = 2;
 pub const GROUP: u8 = 3;
+pub const RADIO: u8 = 4;
+pub const CHANNEL_TYPES: [u8; 4] = [LIVESTREAM, MOVIE, SERIE, RADIO];
+
+pub fn is_live(media_type: u8) -> bool {
+    matches!(media_type, LIVESTREAM | RADIO)
+}
+
+/// Live channels of a group mentioning radio are assumed to have no video
+pub fn detect_radio(media_type: u8, group: Option<&str>) -> u8 {
+    if media_type == LIVESTREAM && group.is_some_and(|group| group.to_lowercase().contains("radio"))
+    {
+        return RADIO;
+    }
+    media_type
+}
+
+/// Each media type sets the bit of its value, groups store the types of their channels this way
+pub fn to_mask(media_types: &[u8]) -> u8 {
+    media_types
+        .iter()
+        .fold(0, |mask, media_type| mask | (1 << media_type))
+}
+
+pub fn from_mask(mask: u8) -> Vec<u8> {
+    CHANNEL_TYPES
+        .into_iter()
+        .filter(|media_type| mask & (1 << media_type) != 0)
+        .collect()
+}
@@ -1,16 +1,20 @@
-use crate::settings::get_default_record_path;
-use crate::sql;
-use crate::types::ChannelHttpHeaders;
-use crate::utils::{find_macos_bin, get_bin};
-use crate::{media_type, settings::get_settings, types::Channel};
-use anyhow::{bail, Context, Result};
-use chrono::Local;
-use std::sync::LazyLock;
-use std::{env::consts::OS, path::Path, process::Stdio};
-use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    process::Command,
+use crate::players::{self, Player};
+use crate::settings::{
+    get_default_record_path, get_lang_list, get_settings, DEFAULT_RADIO_MPV_PARAMS,
+    DEFAULT_RECORDING_FILENAME_TEMPLATE,
 };
+use crate::types::{ChannelHttpHeaders, PlayerProcess, Settings};
+use crate::utils::{find_macos_bin, get_bin, sanitize_file_name};
+use crate::{media_type, types::Channel};
+use crate::{mpv_ipc, sql, ytdlp};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use regex::{Captures, Regex};
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::{env::consts::OS, path::PathBuf};
+use tokio::task::JoinHandle;
 
 const ARG_SAVE_POSITION_ON_QUIT: &str = "--save-position-on-quit";
 const ARG_CACHE: &str = "--cache=";
@@ -20,93 +24,250 @@ const ARG_TITLE: &str = "--title=";
 const ARG_MSG_LEVEL: &str = "--msg-level=all=error";
 const ARG_YTDLP_PATH: &str = "--script-opts=ytdl_hook-ytdl_path=";
 const ARG_VOLUME: &str = "--volume=";
+const ARG_INPUT_IPC_SERVER: &str = "--input-ipc-server=";
+const ARG_AUDIO_LANG: &str = "--alang=";
+const ARG_SUBTITLE_LANG: &str = "--slang=";
+const ARG_NO_SUBTITLES: &str = "--sid=no";
+const ARG_HWDEC: &str = "--hwdec";
+const ARG_VO: &str = "--vo";
 const ARG_HTTP_HEADERS: &str = "--http-header-fields=";
 const ARG_USER_AGENT: &str = "--user-agent=";
 const ARG_IGNORE_SSL: &str = "--ytdl-raw-options=no-check-certificates=True";
+const ARG_HTTP_PROXY: &str = "--http-proxy=";
+const ARG_YTDLP_PROXY: &str = "--ytdl-raw-options-append=proxy=";
 const ARG_PREFETCH_PLAYLIST: &str = "--prefetch-playlist=yes";
 const ARG_LOOP_PLAYLIST: &str = "--loop-playlist=inf";
+const ARGS_PIP: [&str; 4] = [
+    "--ontop",
+    "--no-border",
+    "--autofit=384x216",
+    "--geometry=98%:98%",
+];
+const ARG_ONTOP_LEVEL_MACOS: &str = "--ontop-level=system";
 const MPV_BIN_NAME: &str = "mpv";
 const YTDLP_BIN_NAME: &str = "yt-dlp";
 const HTTP_ORIGIN: &str = "origin:";
 const HTTP_REFERRER: &str = "referer:";
-static MPV_PATH: LazyLock<String> = LazyLock::new(|| get_bin(MPV_BIN_NAME));
-static YTDLP_PATH: LazyLock<String> = LazyLock::new(|| find_macos_bin(YTDLP_BIN_NAME));
-
-pub async fn play(channel: Channel, record: bool) -> Result<()> {
-    println!("{} playing", channel.url.as_ref().unwrap());
-    let args = get_play_args(channel, record)?;
-    println!("with args: {:?}", args);
-    let mut cmd = Command::new(MPV_PATH.clone())
-        .args(args)
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    let status = cmd.wait().await?;
-    if !status.success() {
-        let stdout = cmd.stdout.take();
-        if let Some(stdout) = stdout {
-            let mut error: String = "".to_string();
-            let mut lines = BufReader::new(stdout).lines();
-            let mut first = true;
-            while let Some(line) = lines.next_line().await? {
-                error += &line;
-                if !first {
-                    error += "\n"
-                } else {
-                    first = false;
+const MAX_RECORDING_NAME_LEN: usize = 200;
+static PLACEHOLDER_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{(\w+)\}").unwrap());
+static MPV_PATH: LazyLock<PathBuf> = LazyLock::new(|| get_bin(MPV_BIN_NAME));
+static YTDLP_PATH: LazyLock<PathBuf> = LazyLock::new(|| find_macos_bin(YTDLP_BIN_NAME));
+
+pub struct Mpv;
+
+impl Player for Mpv {
+    fn name(&self) -> &'static str {
+        MPV_BIN_NAME
+    }
+
+    fn bin(&self) -> PathBuf {
+        MPV_PATH.clone()
+    }
+
+    fn record_extension(&self) -> &'static str {
+        "mp4"
+    }
+
+    fn get_args(
+        &self,
+        channel: Channel,
+        headers: Option<ChannelHttpHeaders>,
+        settings: Settings,
+        record_path: Option<PathBuf>,
+    ) -> Result<Vec<OsString>> {
+        get_play_args(channel, headers, settings, record_path)
+    }
+
+    fn watch(&self, channel_id: i64) -> Option<JoinHandle<()>> {
+        if channel_id < 0 {
+            return None;
+        }
+        let settings = get_settings().ok()?;
+        let channel = sql::get_channel_by_id(channel_id).ok()?;
+        let live = media_type::is_live(channel.media_type);
+        let remember_volume = settings.remember_channel_volume == Some(true);
+        let mark_watched = settings.auto_mark_watched != Some(false) && !live;
+        let media_title = settings.epg_media_title != Some(false) && live;
+        if !(remember_volume || mark_watched || media_title) {
+            return None;
+        }
+        Some(tokio::spawn(async move {
+            let title = async {
+                if media_title {
+                    mpv_ipc::show_programme_title(channel).await;
+                }
+            };
+            let events = async {
+                if remember_volume || mark_watched {
+                    mpv_ipc::watch(channel_id, remember_volume, mark_watched).await;
                 }
-            }
-            if error != "" {
-                bail!(error);
-            } else {
-                bail!("Mpv encountered an unknown error");
-            }
+            };
+            tokio::join!(title, events);
+        }))
+    }
+
+    fn wait_for_playback(&self, channel_id: i64) -> Option<JoinHandle<Result<()>>> {
+        if channel_id < 0 {
+            return None;
+        }
+        Some(tokio::spawn(mpv_ipc::wait_for_playback(channel_id)))
+    }
+
+    fn cleanup(&self, channel_id: i64) {
+        if channel_id >= 0 {
+            mpv_ipc::cleanup(channel_id);
+        }
+    }
+
+    fn quit(&self, channel_id: i64) -> Option<JoinHandle<Result<()>>> {
+        if channel_id < 0 {
+            return None;
         }
+        Some(tokio::spawn(async move {
+            mpv_ipc::send_command(channel_id, &["quit"]).await
+        }))
+    }
+}
+
+pub fn get_mpv_path() -> PathBuf {
+    MPV_PATH.clone()
+}
+
+pub fn get_ytdlp_path() -> PathBuf {
+    if OS == "macos" {
+        return YTDLP_PATH.clone();
+    }
+    let bin = get_bin(YTDLP_BIN_NAME);
+    if bin.as_os_str() == YTDLP_BIN_NAME {
+        return bin;
     }
-    Ok(())
+    ytdlp::get_updated_path().unwrap_or(bin)
+}
+
+pub async fn play(channel: Channel, record: bool, process: Arc<PlayerProcess>) -> Result<()> {
+    players::play_with(&Mpv, channel, record, None, process).await
 }
 
-fn get_play_args(channel: Channel, record: bool) -> Result<Vec<String>> {
-    let mut args = Vec::new();
-    let settings = get_settings()?;
-    let headers = sql::get_channel_headers_by_id(channel.id.context("no channel id?")?)?;
-    args.push(channel.url.context("no url")?);
-    if channel.media_type != media_type::LIVESTREAM {
-        args.push(ARG_SAVE_POSITION_ON_QUIT.to_string());
+fn get_play_args(
+    channel: Channel,
+    headers: Option<ChannelHttpHeaders>,
+    settings: Settings,
+    record_path: Option<PathBuf>,
+) -> Result<Vec<OsString>> {
+    let mut args: Vec<OsString> = Vec::new();
+    let channel_id = channel.id;
+    args.push(channel.url.context("no url")?.into());
+    if !media_type::is_live(channel.media_type) {
+        args.push(ARG_SAVE_POSITION_ON_QUIT.into());
     }
-    if settings.use_stream_caching == Some(false) {
+    if !settings.use_stream_caching {
         let stream_caching_arg = format!("{ARG_CACHE}{ARG_NO}",);
-        args.push(stream_caching_arg);
+        args.push(stream_caching_arg.into());
     }
-    if record {
-        let record_path = match settings.recording_path {
-            Some(path) => get_path(path),
-            None => get_default_record_path()?,
-        };
-        args.push(format!("{ARG_RECORD}{record_path}"));
+    if let Some(record_path) = record_path {
+        args.push(path_arg(ARG_RECORD, &record_path));
+    }
+    if OS == "macos" && MPV_PATH.as_os_str() != MPV_BIN_NAME {
+        args.push(path_arg(ARG_YTDLP_PATH, &YTDLP_PATH));
+    } else if let Some(path) = ytdlp::get_updated_path() {
+        args.push(path_arg(ARG_YTDLP_PATH, &path));
+    }
+    args.push(format!("{}{}", ARG_TITLE, channel.name).into());
+    args.push(ARG_MSG_LEVEL.into());
+    args.push(ARG_PREFETCH_PLAYLIST.into());
+    args.push(ARG_LOOP_PLAYLIST.into());
+    if let Some(id) = channel_id.filter(|id| *id >= 0) {
+        args.push(format!("{ARG_INPUT_IPC_SERVER}{}", mpv_ipc::get_ipc_path(id)).into());
+    }
+    let channel_volume = match (channel_id, settings.remember_channel_volume) {
+        (Some(id), Some(true)) if id >= 0 => sql::get_channel_volume(id)?,
+        _ => None,
+    };
+    if let Some(volume) = channel_volume.or(settings.volume) {
+        args.push(format!("{ARG_VOLUME}{volume}").into());
+    }
+    if let Some(langs) = get_lang_list(&settings.preferred_audio_lang) {
+        args.push(format!("{ARG_AUDIO_LANG}{langs}").into());
     }
-    if OS == "macos" && *MPV_PATH != MPV_BIN_NAME {
-        args.push(format!("{}{}", ARG_YTDLP_PATH, *YTDLP_PATH));
+    if let Some(langs) = get_lang_list(&settings.preferred_subtitle_lang) {
+        args.push(format!("{ARG_SUBTITLE_LANG}{langs}").into());
     }
-    args.push(format!("{}{}", ARG_TITLE, channel.name));
-    args.push(ARG_MSG_LEVEL.to_string());
-    args.push(ARG_PREFETCH_PLAYLIST.to_string());
-    args.push(ARG_LOOP_PLAYLIST.to_string());
-    if let Some(volume) = settings.volume {
-        args.push(format!("{ARG_VOLUME}{volume}"));
+    if settings.subtitles_enabled == Some(false) {
+        args.push(ARG_NO_SUBTITLES.into());
     }
     set_headers(headers, &mut args);
-    if let Some(mpv_params) = settings.mpv_params {
-        #[cfg(not(target_os = "windows"))]
-        let mut params = shell_words::split(&mpv_params)?;
-        #[cfg(target_os = "windows")]
-        let mut params = winsplit::split(&mpv_params);
-        args.append(&mut params);
+    if let Some(proxy) = &settings.proxy_url {
+        args.extend(get_proxy_args(proxy));
+    }
+    let params = match settings.mpv_params {
+        Some(mpv_params) => split_params(&mpv_params)?,
+        None => Vec::new(),
+    };
+    if let Some(hwdec) = settings.hwdec {
+        if !has_param(&params, ARG_HWDEC) {
+            args.push(format!("{ARG_HWDEC}={hwdec}").into());
+        }
+    }
+    if let Some(vo) = settings.vo {
+        if !has_param(&params, ARG_VO) {
+            args.push(format!("{ARG_VO}={vo}").into());
+        }
     }
+    if settings.pip_mode == Some(true) {
+        args.extend(get_pip_args().into_iter().map(OsString::from));
+    }
+    if channel.media_type == media_type::RADIO {
+        let radio_params = settings
+            .radio_mpv_params
+            .as_deref()
+            .unwrap_or(DEFAULT_RADIO_MPV_PARAMS);
+        args.extend(split_params(radio_params)?.into_iter().map(OsString::from));
+    }
+    // user params come last so they can override anything above
+    args.extend(params.into_iter().map(OsString::from));
     Ok(args)
 }
 
-fn set_headers(headers: Option<ChannelHttpHeaders>, args: &mut Vec<String>) {
+fn split_params(params: &str) -> Result<Vec<String>> {
+    #[cfg(not(target_os = "windows"))]
+    return Ok(shell_words::split(params)?);
+    #[cfg(target_os = "windows")]
+    return Ok(winsplit::split(params));
+}
+
+/// mpv's own http client only speaks to http proxies, yt-dlp also takes socks ones
+fn get_proxy_args(proxy: &str) -> Vec<OsString> {
+    let mut args = Vec::with_capacity(2);
+    if proxy.starts_with("http://") || proxy.starts_with("https://") {
+        args.push(format!("{ARG_HTTP_PROXY}{proxy}").into());
+    }
+    args.push(format!("{ARG_YTDLP_PROXY}{proxy}").into());
+    args
+}
+
+/// On macOS the default ontop level loses against other apps' windows
+fn get_pip_args() -> Vec<&'static str> {
+    let mut args = ARGS_PIP.to_vec();
+    if OS == "macos" {
+        args.push(ARG_ONTOP_LEVEL_MACOS);
+    }
+    args
+}
+
+/// Paths are appended as is, a lossy conversion would break non UTF-8 user folders
+fn path_arg(prefix: &str, path: &Path) -> OsString {
+    let mut arg = OsString::from(prefix);
+    arg.push(path);
+    arg
+}
+
+fn has_param(params: &[String], param: &str) -> bool {
+    params
+        .iter()
+        .any(|p| p == param || p.starts_with(&format!("{param}=")))
+}
+
+fn set_headers(headers: Option<ChannelHttpHeaders>, args: &mut Vec<OsString>) {
     if headers.is_none() {
         return;
     }
@@ -119,27 +280,194 @@ fn set_headers(headers: Option<ChannelHttpHeaders>, args: &mut Vec<String>) {
         headers_vec.push(format!("{HTTP_REFERRER}{referrer}"));
     }
     if let Some(user_agent) = headers.user_agent {
-        args.push(format!("{ARG_USER_AGENT}{user_agent}"));
+        args.push(format!("{ARG_USER_AGENT}{user_agent}").into());
     }
     if let Some(ignore_ssl) = headers.ignore_ssl {
         if ignore_ssl == true {
-            args.push(ARG_IGNORE_SSL.to_string());
+            args.push(ARG_IGNORE_SSL.into());
         }
     }
     if headers_vec.len() > 0 {
         let headers = headers_vec.join(",");
-        args.push(format!("{ARG_HTTP_HEADERS}{headers}"));
+        args.push(format!("{ARG_HTTP_HEADERS}{headers}").into());
     }
 }
 
-fn get_path(path_str: String) -> String {
-    let path = Path::new(&path_str);
-    let path = path.join(get_file_name());
-    return path.to_string_lossy().to_string();
+pub fn get_record_path(
+    settings: &Settings,
+    channel: &Channel,
+    title: Option<&str>,
+    extension: &str,
+) -> Result<PathBuf> {
+    let dir = match &settings.recording_path {
+        Some(path) => path.clone(),
+        None => get_default_record_path()?,
+    };
+    let template = settings
+        .recording_filename_template
+        .as_deref()
+        .unwrap_or(DEFAULT_RECORDING_FILENAME_TEMPLATE);
+    Ok(build_recording_path(
+        &dir,
+        &channel.name,
+        title,
+        template,
+        extension,
+        Local::now(),
+    ))
 }
 
-fn get_file_name() -> String {
-    let current_time = Local::now();
-    let formatted_time = current_time.format("%Y-%m-%d-%H-%M-%S").to_string();
-    format!("{formatted_time}.mp4")
+/// Fills the template placeholders in a single pass so a channel called "{date}" stays as is,
+/// a counter is appended when the file already exists
+pub fn build_recording_path(
+    dir: &Path,
+    channel: &str,
+    title: Option<&str>,
+    template: &str,
+    extension: &str,
+    now: DateTime<Local>,
+) -> PathBuf {
+    let name = PLACEHOLDER_REGEX.replace_all(template, |caps: &Captures| match &caps[1] {
+        "channel" => channel.to_string(),
+        "title" => title.unwrap_or_default().to_string(),
+        "date" => now.format("%Y-%m-%d").to_string(),
+        "time" => now.format("%H-%M-%S").to_string(),
+        _ => caps[0].to_string(),
+    });
+    // an empty {title} would otherwise leave a dangling separator
+    let name = sanitize_file_name(&name, MAX_RECORDING_NAME_LEN)
+        .trim_matches(['-', '_', ' '])
+        .to_string();
+    let name = match name.is_empty() {
+        true => now.format("%Y-%m-%d-%H-%M-%S").to_string(),
+        false => name,
+    };
+    let mut path = dir.join(format!("{name}.{extension}"));
+    let mut counter = 2;
+    while path.exists() {
+        path = dir.join(format!("{name} ({counter}).{extension}"));
+        counter += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod test_mpv {
+    use std::{collections::HashMap, ffi::OsString, fs};
+
+    use chrono::{Local, TimeZone};
+
+    use super::{build_recording_path, get_play_args};
+    use crate::{
+        settings::{MPV_PARAMS, PIP_MODE, PROXY_URL},
+        test_fixtures,
+        types::{Channel, Settings},
+    };
+
+    #[test]
+    fn test_pip_args_before_user_params() {
+        let channel = Channel {
+            url: Some("http://example.com/news.m3u8".to_string()),
+            ..test_fixtures::channel("News")
+        };
+        let settings = Settings::from_map(&HashMap::from([
+            (PIP_MODE.to_string(), "true".to_string()),
+            (MPV_PARAMS.to_string(), "--ontop=no".to_string()),
+        ]));
+        let args = get_play_args(channel, None, settings, None).unwrap();
+        let position = |arg: &str| args.iter().position(|a| a == &OsString::from(arg));
+        assert!(position("--no-border").is_some());
+        assert!(position("--ontop").unwrap() < position("--ontop=no").unwrap());
+        assert_eq!(args.last(), Some(&OsString::from("--ontop=no")));
+    }
+
+    #[test]
+    fn test_proxy_args() {
+        let channel = Channel {
+            url: Some("http://example.com/news.m3u8".to_string()),
+            ..test_fixtures::channel("News")
+        };
+        let args = |proxy: &str| {
+            let settings =
+                Settings::from_map(&HashMap::from([(PROXY_URL.to_string(), proxy.to_string())]));
+            get_play_args(channel.clone(), None, settings, None).unwrap()
+        };
+        let http = args("http://proxy.local:3128");
+        assert!(http.contains(&OsString::from("--http-proxy=http://proxy.local:3128")));
+        assert!(http.contains(&OsString::from(
+            "--ytdl-raw-options-append=proxy=http://proxy.local:3128"
+        )));
+        let socks = args("socks5://10.0.0.1:1080");
+        assert!(!socks
+            .iter()
+            .any(|a| a.to_string_lossy().starts_with("--http-proxy=")));
+        assert!(socks.contains(&OsString::from(
+            "--ytdl-raw-options-append=proxy=socks5://10.0.0.1:1080"
+        )));
+    }
+
+    #[test]
+    fn test_build_recording_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = Local.with_ymd_and_hms(2024, 5, 12, 20, 0, 0).unwrap();
+        let build = |channel: &str, title: Option<&str>, template: &str| {
+            build_recording_path(dir.path(), channel, title, template, "mp4", now)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+        assert_eq!(
+            build("France 24", None, "{channel} - {date} {time}"),
+            "France 24 - 2024-05-12 20-00-00.mp4"
+        );
+        assert_eq!(
+            build("AC/DC Live 🎸", Some("Encore"), "{channel} - {title}"),
+            "AC_DC Live 🎸 - Encore.mp4"
+        );
+        assert_eq!(build("News", None, "{channel} - {title}"), "News.mp4");
+        assert_eq!(
+            build("{date}", None, "{channel} {unknown}"),
+            "{date} {unknown}.mp4"
+        );
+        assert_eq!(build("", None, "{title}"), "2024-05-12-20-00-00.mp4");
+
+        let long = "é".repeat(150);
+        let name = build(&long, None, "{channel}");
+        assert!(name.len() <= 200 + ".mp4".len());
+        assert!(name.starts_with("éé"));
+
+        fs::write(dir.path().join("News.mp4"), "").unwrap();
+        fs::write(dir.path().join("News (2).mp4"), "").unwrap();
+        assert_eq!(build("News", None, "{channel}"), "News (3).mp4");
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "windows")]
+mod test_mpv_windows {
+    use std::{collections::HashMap, ffi::OsString, path::PathBuf};
+
+    use super::get_play_args;
+    use crate::{
+        media_type, test_fixtures,
+        types::{Channel, Settings},
+    };
+
+    #[test]
+    fn test_args_with_spaces_and_non_ascii_paths() {
+        let url = r"C:\Program Files\open-tv\sample file.mkv";
+        let channel = Channel {
+            url: Some(url.to_string()),
+            media_type: media_type::MOVIE,
+            ..test_fixtures::channel("Sample")
+        };
+        let record_path = PathBuf::from(r"C:\Users\Zoë Müller\Videos\open tv\2024-01-01.mp4");
+        let settings = Settings::from_map(&HashMap::new());
+        let args = get_play_args(channel, None, settings, Some(record_path)).unwrap();
+        assert_eq!(args[0], OsString::from(url));
+        assert!(args.contains(&OsString::from(
+            r"--stream-record=C:\Users\Zoë Müller\Videos\open tv\2024-01-01.mp4"
+        )));
+    }
 }
@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::{epg, settings::MAX_VOLUME, sql, types::Channel};
+
+const OBSERVE_VOLUME: &str = "{\"command\":[\"observe_property\",1,\"volume\"]}\n";
+const OBSERVE_PLAYBACK_TIME: &str = "{\"command\":[\"observe_property\",2,\"playback-time\"]}\n";
+const OBSERVE_PERCENT_POS: &str = "{\"command\":[\"observe_property\",3,\"percent-pos\"]}\n";
+const WATCHED_PERCENT: f64 = 90.0;
+const CONNECT_ATTEMPTS: u8 = 20;
+const CONNECT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Deserialize, Debug)]
+struct MpvEvent {
+    event: Option<String>,
+    name: Option<String>,
+    data: Option<serde_json::Value>,
+}
+
+pub fn get_ipc_path(channel_id: i64) -> String {
+    #[cfg(target_os = "windows")]
+    return format!(r"\\.\pipe\open-tv-mpv-{channel_id}");
+    #[cfg(not(target_os = "windows"))]
+    return std::env::temp_dir()
+        .join(format!("open-tv-mpv-{channel_id}.sock"))
+        .to_string_lossy()
+        .to_string();
+}
+
+/// Follows the player until it exits, saving its last volume and marking the channel
+/// as watched once playback goes past 90%
+pub async fn watch(channel_id: i64, remember_volume: bool, mark_watched: bool) {
+    match watch_events(channel_id, remember_volume, mark_watched).await {
+        Ok(Some(volume)) => sql::set_channel_volume(channel_id, volume)
+            .unwrap_or_else(|e| tracing::error!("Failed to save channel volume: {:?}", e)),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to watch mpv: {:?}", e),
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+async fn connect(path: &str) -> std::io::Result<tokio::net::UnixStream> {
+    tokio::net::UnixStream::connect(path).await
+}
+
+#[cfg(target_os = "windows")]
+async fn connect(path: &str) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    tokio::net::windows::named_pipe::ClientOptions::new().open(path)
+}
+
+pub async fn send_command(channel_id: i64, command: &[&str]) -> Result<()> {
+    let mut stream = connect(&get_ipc_path(channel_id)).await?;
+    let command = serde_json::json!({ "command": command }).to_string();
+    stream.write_all(format!("{command}\n").as_bytes()).await?;
+    Ok(())
+}
+
+pub async fn toggle_pause() -> Result<()> {
+    let session = sql::get_open_sessions()?
+        .into_iter()
+        .max_by_key(|session| session.started_at)
+        .context("Nothing is playing")?;
+    send_command(session.channel_id, &["cycle", "pause"]).await
+}
+
+pub async fn toggle_ontop() -> Result<()> {
+    let session = sql::get_open_sessions()?
+        .into_iter()
+        .max_by_key(|session| session.started_at)
+        .context("Nothing is playing")?;
+    send_command(session.channel_id, &["cycle", "ontop"]).await
+}
+
+/// Replaces what the mpv listening on `ipc_channel_id`'s socket plays, the socket keeps
+/// the id of the channel mpv was started with
+pub async fn play_next(ipc_channel_id: i64, url: &str, title: &str) -> Result<()> {
+    let mut stream = connect(&get_ipc_path(ipc_channel_id)).await?;
+    let commands = [
+        serde_json::json!({ "command": ["set_property", "force-media-title", title] }),
+        serde_json::json!({ "command": ["loadfile", url, "replace"] }),
+    ];
+    for command in commands {
+        stream.write_all(format!("{command}\n").as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Resolves once mpv reports a playback position, which only happens after the stream opened
+pub async fn wait_for_playback(channel_id: i64) -> Result<()> {
+    read_playback_start(connect_with_retry(channel_id).await?).await
+}
+
+/// Keeps mpv's media title on the programme airing on the channel. The title is only
+/// rewritten when a programme ends, and the task stops as soon as mpv exits
+pub async fn show_programme_title(channel: Channel) {
+    if let Err(e) = follow_programmes(&channel).await {
+        tracing::warn!("Failed to show the programme in mpv: {:?}", e);
+    }
+}
+
+async fn follow_programmes(channel: &Channel) -> Result<()> {
+    let channel_id = channel.id.context("no channel id")?;
+    let mut now_next = epg::get_now_next(channel).await?;
+    if now_next.current.is_none() && now_next.next.is_none() {
+        return Ok(());
+    }
+    let (reader, mut writer) = tokio::io::split(connect_with_retry(channel_id).await?);
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let title = epg::get_media_title(&channel.name, now_next.current.as_ref());
+        let command =
+            serde_json::json!({ "command": ["set_property", "force-media-title", title] });
+        writer.write_all(format!("{command}\n").as_bytes()).await?;
+        let change = match (&now_next.current, &now_next.next) {
+            (Some(current), _) => current.stop,
+            (None, Some(next)) => next.start,
+            (None, None) => return Ok(()),
+        };
+        let wait = (change - Utc::now().timestamp()).max(1) as u64;
+        let closed = async { while let Ok(Some(_)) = lines.next_line().await {} };
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+            _ = closed => return Ok(()),
+        }
+        now_next = epg::get_now_next(channel).await?;
+    }
+}
+
+async fn connect_with_retry(channel_id: i64) -> Result<impl AsyncRead + AsyncWrite> {
+    let path = get_ipc_path(channel_id);
+    for _ in 0..CONNECT_ATTEMPTS {
+        match connect(&path).await {
+            Ok(stream) => return Ok(stream),
+            Err(_) => tokio::time::sleep(CONNECT_DELAY).await,
+        }
+    }
+    bail!("Could not connect to mpv ipc at {path}")
+}
+
+/// mpv only removes its socket on a clean exit
+pub fn cleanup(channel_id: i64) {
+    #[cfg(not(target_os = "windows"))]
+    let _ = std::fs::remove_file(get_ipc_path(channel_id));
+    #[cfg(target_os = "windows")]
+    let _ = channel_id;
+}
+
+async fn read_playback_start<S: AsyncRead + AsyncWrite>(stream: S) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    writer.write_all(OBSERVE_PLAYBACK_TIME.as_bytes()).await?;
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if is_playback_started(&line) {
+            return Ok(());
+        }
+    }
+    bail!("mpv closed the ipc connection before playback started")
+}
+
+fn is_playback_started(line: &str) -> bool {
+    serde_json::from_str::<MpvEvent>(line).is_ok_and(|event| {
+        event.event.as_deref() == Some("property-change")
+            && event.name.as_deref() == Some("playback-time")
+            && event.data.is_some_and(|data| !data.is_null())
+    })
+}
+
+async fn watch_events(
+    channel_id: i64,
+    remember_volume: bool,
+    mark_watched: bool,
+) -> Result<Option<u8>> {
+    let stream = connect_with_retry(channel_id).await?;
+    read_events(stream, channel_id, remember_volume, mark_watched).await
+}
+
+async fn read_events<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    channel_id: i64,
+    remember_volume: bool,
+    mark_watched: bool,
+) -> Result<Option<u8>> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    if remember_volume {
+        writer.write_all(OBSERVE_VOLUME.as_bytes()).await?;
+    }
+    if mark_watched {
+        writer.write_all(OBSERVE_PERCENT_POS.as_bytes()).await?;
+    }
+    let mut lines = BufReader::new(reader).lines();
+    let mut volume = None;
+    let mut watched = false;
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Some(value) = parse_volume_event(&line) {
+            volume = Some(value);
+        } else if !watched && is_watched_event(&line) {
+            watched = true;
+            sql::set_watched(channel_id, true)
+                .unwrap_or_else(|e| tracing::error!("Failed to mark channel as watched: {:?}", e));
+        }
+    }
+    Ok(volume)
+}
+
+fn parse_property_event(line: &str, name: &str) -> Option<f64> {
+    let event: MpvEvent = serde_json::from_str(line).ok()?;
+    if event.event.as_deref() != Some("property-change") || event.name.as_deref() != Some(name) {
+        return None;
+    }
+    event.data?.as_f64()
+}
+
+fn parse_volume_event(line: &str) -> Option<u8> {
+    let volume = parse_property_event(line, "volume")?;
+    Some(volume.round().clamp(0.0, MAX_VOLUME as f64) as u8)
+}
+
+fn is_watched_event(line: &str) -> bool {
+    parse_property_event(line, "percent-pos").is_some_and(|percent| percent >= WATCHED_PERCENT)
+}
+
+#[cfg(test)]
+mod test_mpv_ipc {
+    use super::{is_playback_started, is_watched_event, parse_volume_event, read_playback_start};
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn test_parse_volume_event() {
+        assert_eq!(
+            parse_volume_event(
+                r#"{"event":"property-change","id":1,"name":"volume","data":72.500000}"#
+            ),
+            Some(73)
+        );
+        assert_eq!(
+            parse_volume_event(r#"{"data":null,"request_id":0,"error":"success"}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_watched_event() {
+        assert!(is_watched_event(
+            r#"{"event":"property-change","id":3,"name":"percent-pos","data":90.4}"#
+        ));
+        assert!(!is_watched_event(
+            r#"{"event":"property-change","id":3,"name":"percent-pos","data":42.0}"#
+        ));
+        assert!(!is_watched_event(
+            r#"{"event":"property-change","id":3,"name":"percent-pos"}"#
+        ));
+        assert!(!is_watched_event(
+            r#"{"event":"property-change","id":1,"name":"volume","data":95.0}"#
+        ));
+    }
+
+    #[test]
+    fn test_is_playback_started() {
+        assert!(!is_playback_started(
+            r#"{"event":"property-change","id":2,"name":"playback-time"}"#
+        ));
+        assert!(!is_playback_started(
+            r#"{"event":"property-change","id":2,"name":"playback-time","data":null}"#
+        ));
+        assert!(is_playback_started(
+            r#"{"event":"property-change","id":2,"name":"playback-time","data":0.033}"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_playback_start() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        server
+            .write_all(b"{\"event\":\"start-file\"}\n{\"event\":\"property-change\",\"name\":\"playback-time\",\"data\":1.2}\n")
+            .await
+            .unwrap();
+        read_playback_start(client).await.unwrap();
+        let (client, server) = tokio::io::duplex(1024);
+        drop(server);
+        assert!(read_playback_start(client).await.is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackErrorKind {
+    Unknown,
+    Forbidden,
+    NotFound,
+    UnrecognizedFormat,
+    Dns,
+    Tls,
+}
+
+/// Messages ffmpeg, libcurl and the platform resolvers print for each failure,
+/// specific enough not to match a url or a stream title
+const PATTERNS: [(&str, PlaybackErrorKind); 16] = [
+    ("http error 403", PlaybackErrorKind::Forbidden),
+    ("403 forbidden", PlaybackErrorKind::Forbidden),
+    ("http error 404", PlaybackErrorKind::NotFound),
+    ("404 not found", PlaybackErrorKind::NotFound),
+    (
+        "failed to recognize file format",
+        PlaybackErrorKind::UnrecognizedFormat,
+    ),
+    ("failed to resolve hostname", PlaybackErrorKind::Dns),
+    ("name or service not known", PlaybackErrorKind::Dns),
+    (
+        "temporary failure in name resolution",
+        PlaybackErrorKind::Dns,
+    ),
+    ("nodename nor servname provided", PlaybackErrorKind::Dns),
+    ("no such host is known", PlaybackErrorKind::Dns),
+    ("certificate verify failed", PlaybackErrorKind::Tls),
+    ("ssl routines", PlaybackErrorKind::Tls),
+    ("tls handshake", PlaybackErrorKind::Tls),
+    ("the certificate is not trusted", PlaybackErrorKind::Tls),
+    ("the certificate has expired", PlaybackErrorKind::Tls),
+    (
+        "unable to get local issuer certificate",
+        PlaybackErrorKind::Tls,
+    ),
+];
+
+#[derive(Debug)]
+pub struct PlaybackError {
+    pub kind: PlaybackErrorKind,
+    pub player: String,
+    /// The last lines the player printed, kept for the log
+    pub output: String,
+}
+
+impl PlaybackError {
+    pub fn new(player: &str, output: String) -> Self {
+        PlaybackError {
+            kind: classify(&output),
+            player: player.to_string(),
+            output,
+        }
+    }
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            PlaybackErrorKind::Forbidden => write!(f, "The provider refused the stream (HTTP 403)"),
+            PlaybackErrorKind::NotFound => write!(f, "The stream does not exist (HTTP 404)"),
+            PlaybackErrorKind::UnrecognizedFormat => {
+                write!(f, "{} could not recognize the stream format", self.player)
+            }
+            PlaybackErrorKind::Dns => write!(f, "The stream host could not be resolved"),
+            PlaybackErrorKind::Tls => write!(f, "The secure connection to the stream failed"),
+            PlaybackErrorKind::Unknown if !self.output.is_empty() => write!(f, "{}", self.output),
+            PlaybackErrorKind::Unknown => {
+                write!(f, "{} encountered an unknown error", self.player)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlaybackError {}
+
+/// The first known pattern wins, so http statuses take precedence over tls failures
+pub fn classify(output: &str) -> PlaybackErrorKind {
+    let output = output.to_lowercase();
+    PATTERNS
+        .iter()
+        .find(|(pattern, _)| output.contains(pattern))
+        .map(|(_, kind)| *kind)
+        .unwrap_or(PlaybackErrorKind::Unknown)
+}
+
+#[cfg(test)]
+mod test_playback_error {
+    use super::{classify, PlaybackError, PlaybackErrorKind};
+
+    #[test]
+    fn test_classify() {
+        let cases = [
+            (
+                "[ffmpeg] https: HTTP error 403 Forbidden",
+                PlaybackErrorKind::Forbidden,
+            ),
+            (
+                "[ffmpeg] http: HTTP error 404 Not Found",
+                PlaybackErrorKind::NotFound,
+            ),
+            (
+                "Failed to recognize file format.",
+                PlaybackErrorKind::UnrecognizedFormat,
+            ),
+            (
+                "[tcp] Failed to resolve hostname example.invalid",
+                PlaybackErrorKind::Dns,
+            ),
+            (
+                "[tls] error:0A000086:SSL routines::certificate verify failed",
+                PlaybackErrorKind::Tls,
+            ),
+            (
+                "Playing: https://tls.example.com/live.m3u8\nExiting... (Errors when loading file)",
+                PlaybackErrorKind::Unknown,
+            ),
+        ];
+        for (output, kind) in cases {
+            assert_eq!(classify(output), kind, "{output}");
+        }
+    }
+
+    #[test]
+    fn test_unknown_error_message() {
+        assert_eq!(
+            PlaybackError::new("mpv", String::new()).to_string(),
+            "mpv encountered an unknown error"
+        );
+        assert_eq!(
+            PlaybackError::new("mpv", "boom".to_string()).to_string(),
+            "boom"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{bail, Context, Result};
+use tauri::State;
+use tempfile::TempPath;
+use tokio::sync::Mutex;
+
+use crate::{
+    m3u, source_type,
+    sql::ImportDedup,
+    types::{AppState, Channel, GroupCount, Source, SourcePreview},
+    xtream::{self, XtreamData},
+};
+
+const SAMPLE_SIZE: usize = 20;
+/// Previews nobody imported are dropped past this many, oldest first
+const MAX_PREPARED: usize = 3;
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// What a preview downloaded, kept so confirming the import doesn't download it again.
+/// A playlist is removed once its preview is imported or dropped
+#[derive(Debug)]
+pub enum PreparedImport {
+    Playlist(TempPath),
+    Xtream(XtreamData),
+}
+
+#[derive(Debug, Default)]
+pub struct PreviewBuilder {
+    channel_count: u64,
+    media_type_counts: HashMap<u8, u64>,
+    groups: BTreeMap<String, u64>,
+    tvg_id_count: u64,
+    sample: Vec<Channel>,
+    dedup: ImportDedup,
+}
+
+impl PreviewBuilder {
+    pub fn add(&mut self, channel: Channel) {
+        if !self.dedup.is_new(&channel) {
+            return;
+        }
+        self.channel_count += 1;
+        *self
+            .media_type_counts
+            .entry(channel.media_type)
+            .or_default() += 1;
+        if let Some(group) = channel.group.as_ref().filter(|g| !g.is_empty()) {
+            *self.groups.entry(group.clone()).or_default() += 1;
+        }
+        if channel.tvg_id.is_some() {
+            self.tvg_id_count += 1;
+        }
+        if self.sample.len() < SAMPLE_SIZE {
+            self.sample.push(channel);
+        }
+    }
+
+    pub fn build(self, token: Option<u64>) -> SourcePreview {
+        SourcePreview {
+            token,
+            channel_count: self.channel_count,
+            media_type_counts: self.media_type_counts,
+            groups: self
+                .groups
+                .into_iter()
+                .map(|(name, count)| GroupCount { name, count })
+                .collect(),
+            tvg_id_count: self.tvg_id_count,
+            sample: self.sample,
+            duplicates: self.dedup.summary.duplicates,
+        }
+    }
+}
+
+pub async fn preview_source(
+    state: State<'_, Mutex<AppState>>,
+    source: Source,
+) -> Result<SourcePreview> {
+    let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    let mut preview = PreviewBuilder::default();
+    let prepared = match source.source_type {
+        source_type::M3U => {
+            let path = source.url.clone().context("no file path found")?;
+            m3u::preview_file(&source, &PathBuf::from(path), &mut preview)?;
+            None
+        }
+        source_type::M3U_LINK => {
+            let path = m3u::download_tmp_m3u8(&source).await?;
+            m3u::preview_file(&source, &path, &mut preview)?;
+            Some(PreparedImport::Playlist(path))
+        }
+        source_type::XTREAM => Some(PreparedImport::Xtream(
+            xtream::preview(source.clone(), &mut preview).await?,
+        )),
+        _ => bail!("this source type can't be previewed"),
+    };
+    let Some(prepared) = prepared else {
+        return Ok(preview.build(None));
+    };
+    let mut state = state.lock().await;
+    while state.prepared_imports.len() >= MAX_PREPARED {
+        let oldest = *state
+            .prepared_imports
+            .keys()
+            .min()
+            .context("no prepared import")?;
+        state.prepared_imports.remove(&oldest);
+    }
+    state.prepared_imports.insert(token, (source, prepared));
+    Ok(preview.build(Some(token)))
+}
+
+/// Hands over what the preview `token` downloaded, once. It is dropped instead when
+/// `source` changed since in a way that downloads something else
+pub async fn take_prepared(
+    state: &State<'_, Mutex<AppState>>,
+    token: Option<u64>,
+    source: &Source,
+) -> Option<PreparedImport> {
+    let (previewed, prepared) = state.lock().await.prepared_imports.remove(&token?)?;
+    is_same_download(&previewed, source).then_some(prepared)
+}
+
+/// Xtream only downloads the media types a source imports, playlists are downloaded whole
+fn is_same_download(previewed: &Source, source: &Source) -> bool {
+    let media_types =
+        |source: &Source| [source.import_live, source.import_vod, source.import_series];
+    previewed.source_type == source.source_type
+        && previewed.url == source.url
+        && previewed.username == source.username
+        && previewed.password == source.password
+        && (source.source_type != source_type::XTREAM
+            || media_types(previewed) == media_types(source))
+}
+
+#[cfg(test)]
+mod test_preview {
+    use super::{is_same_download, PreviewBuilder};
+    use crate::{
+        media_type, source_type, test_fixtures,
+        types::{Channel, Source},
+    };
+
+    fn channel(name: &str, group: Option<&str>, media_type: u8, tvg_id: Option<&str>) -> Channel {
+        Channel {
+            group: group.map(|g| g.to_string()),
+            media_type,
+            tvg_id: tvg_id.map(|id| id.to_string()),
+            ..test_fixtures::channel(name)
+        }
+    }
+
+    #[test]
+    fn test_preview_builder() {
+        let mut builder = PreviewBuilder::default();
+        builder.add(channel(
+            "TF1",
+            Some("France"),
+            media_type::LIVESTREAM,
+            Some("tf1.fr"),
+        ));
+        builder.add(channel("M6", Some("France"), media_type::LIVESTREAM, None));
+        builder.add(channel("Dune", Some("Films"), media_type::MOVIE, None));
+        builder.add(channel("Misc", None, media_type::LIVESTREAM, None));
+        builder.add(channel("M6", Some("France"), media_type::LIVESTREAM, None));
+        let preview = builder.build(Some(4));
+        assert_eq!(preview.token, Some(4));
+        assert_eq!(preview.channel_count, 4);
+        assert_eq!(preview.media_type_counts[&media_type::LIVESTREAM], 3);
+        assert_eq!(preview.media_type_counts[&media_type::MOVIE], 1);
+        let groups: Vec<(&str, u64)> = preview
+            .groups
+            .iter()
+            .map(|g| (g.name.as_str(), g.count))
+            .collect();
+        assert_eq!(groups, vec![("Films", 1), ("France", 2)]);
+        assert_eq!(preview.tvg_id_count, 1);
+        assert_eq!(preview.sample.len(), 4);
+        assert_eq!(preview.duplicates, 1);
+    }
+
+    #[test]
+    fn test_preview_is_bound_to_its_source() {
+        let previewed = Source {
+            url: Some("http://provider.tv/player_api.php".to_string()),
+            username: Some("fred".to_string()),
+            password: Some("secret".to_string()),
+            ..test_fixtures::source("preview", source_type::XTREAM)
+        };
+        let renamed = Source {
+            name: "renamed".to_string(),
+            ..previewed.clone()
+        };
+        assert!(is_same_download(&previewed, &renamed));
+        let other_account = Source {
+            username: Some("barney".to_string()),
+            ..previewed.clone()
+        };
+        assert!(!is_same_download(&previewed, &other_account));
+        let without_series = Source {
+            import_series: false,
+            ..previewed.clone()
+        };
+        assert!(!is_same_download(&previewed, &without_series));
+        let playlist = |source: &Source| Source {
+            source_type: source_type::M3U_LINK,
+            ..source.clone()
+        };
+        assert!(is_same_download(
+            &playlist(&previewed),
+            &playlist(&without_series)
+        ));
+    }
+}
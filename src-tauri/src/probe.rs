@@ -0,0 +1,182 @@
+use std::{sync::LazyLock, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use tauri::State;
+use tokio::{sync::Mutex, time::Instant};
+use url::Url;
+
+use crate::{
+    relay::is_hls,
+    sql,
+    tasks::CancelToken,
+    types::{AppState, ChannelHttpHeaders, HlsVariant, StreamProbe},
+    utils::{build_channel_request, get_playable_url},
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+const DOWNLOAD_DURATION: Duration = Duration::from_secs(5);
+const MAX_DOWNLOAD_BYTES: usize = 8 * 1024 * 1024;
+const CACHE_SECONDS: i64 = 3600;
+
+static BANDWIDTH_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[:,]BANDWIDTH=(?P<bandwidth>\d+)"#).unwrap());
+static RESOLUTION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"RESOLUTION=(?P<resolution>\d+x\d+)"#).unwrap());
+
+pub async fn probe_stream(
+    state: State<'_, Mutex<AppState>>,
+    channel_id: i64,
+) -> Result<StreamProbe> {
+    if let Some(probe) = sql::get_stream_probe(channel_id, CACHE_SECONDS)? {
+        return Ok(probe);
+    }
+    let stop = CancelToken::default();
+    state.lock().await.refreshes.probe_stop = stop.clone();
+    let channel = sql::get_channel_by_id(channel_id)?;
+    let url = get_playable_url(&channel)?;
+    let headers = sql::get_channel_headers_by_id(channel_id)?;
+    let probe = tokio::select! {
+        result = tokio::time::timeout(PROBE_TIMEOUT, probe(channel_id, url, headers)) => {
+            result.context("Probing the stream took too long")??
+        }
+        _ = stop.cancelled() => bail!("Probing was cancelled"),
+    };
+    sql::set_stream_probe(&probe)?;
+    Ok(probe)
+}
+
+pub async fn cancel_probe(state: State<'_, Mutex<AppState>>) -> Result<()> {
+    state.lock().await.refreshes.probe_stop.cancel();
+    Ok(())
+}
+
+async fn probe(
+    channel_id: i64,
+    url: String,
+    headers: Option<ChannelHttpHeaders>,
+) -> Result<StreamProbe> {
+    let start = Instant::now();
+    let response = build_channel_request(&url, headers.clone())?
+        .send()
+        .await?
+        .error_for_status()?;
+    let latency_ms = start.elapsed().as_millis() as u64;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.to_string());
+    let mut variants = Vec::new();
+    let (container, response) = if is_hls(response.url(), content_type.as_deref()) {
+        let base = response.url().clone();
+        let playlist = response.text().await?;
+        variants = parse_master_playlist(&playlist);
+        let segment = get_first_segment(&playlist, &base, headers.clone()).await?;
+        let response = build_channel_request(&segment, headers)?
+            .send()
+            .await?
+            .error_for_status()?;
+        ("hls".to_string(), response)
+    } else {
+        (
+            get_container(response.url(), content_type.as_deref()),
+            response,
+        )
+    };
+    Ok(StreamProbe {
+        channel_id,
+        latency_ms,
+        throughput_kbps: measure_throughput(response).await?,
+        container,
+        variants,
+        probed_at: chrono::Utc::now().timestamp(),
+    })
+}
+
+async fn measure_throughput(mut response: reqwest::Response) -> Result<u64> {
+    let start = Instant::now();
+    let mut downloaded: usize = 0;
+    while start.elapsed() < DOWNLOAD_DURATION && downloaded < MAX_DOWNLOAD_BYTES {
+        match response.chunk().await? {
+            Some(chunk) => downloaded += chunk.len(),
+            None => break,
+        }
+    }
+    let seconds = start.elapsed().as_secs_f64().max(0.001);
+    Ok((downloaded as f64 * 8.0 / 1000.0 / seconds) as u64)
+}
+
+async fn get_first_segment(
+    playlist: &str,
+    base: &Url,
+    headers: Option<ChannelHttpHeaders>,
+) -> Result<String> {
+    let first = get_first_uri(playlist, base).context("Empty HLS playlist")?;
+    if !playlist.contains("#EXT-X-STREAM-INF") {
+        return Ok(first);
+    }
+    let response = build_channel_request(&first, headers)?
+        .send()
+        .await?
+        .error_for_status()?;
+    let base = response.url().clone();
+    let media_playlist = response.text().await?;
+    get_first_uri(&media_playlist, &base).context("Empty HLS media playlist")
+}
+
+fn get_first_uri(playlist: &str, base: &Url) -> Option<String> {
+    playlist
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .and_then(|line| base.join(line).ok())
+        .map(|url| url.to_string())
+}
+
+fn parse_master_playlist(playlist: &str) -> Vec<HlsVariant> {
+    playlist
+        .lines()
+        .filter(|line| line.starts_with("#EXT-X-STREAM-INF"))
+        .map(|line| HlsVariant {
+            bandwidth: BANDWIDTH_REGEX
+                .captures(line)
+                .and_then(|c| c["bandwidth"].parse().ok()),
+            resolution: RESOLUTION_REGEX
+                .captures(line)
+                .map(|c| c["resolution"].to_string()),
+        })
+        .collect()
+}
+
+fn get_container(url: &Url, content_type: Option<&str>) -> String {
+    let path = url.path().to_lowercase();
+    let content_type = content_type.unwrap_or_default().to_lowercase();
+    if path.ends_with(".ts") || content_type.contains("mp2t") {
+        "mpegts".to_string()
+    } else if path.ends_with(".mp4") || content_type.contains("mp4") {
+        "mp4".to_string()
+    } else if path.ends_with(".mkv") || content_type.contains("matroska") {
+        "mkv".to_string()
+    } else if !content_type.is_empty() {
+        content_type
+    } else {
+        "unknown".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test_probe {
+    use super::parse_master_playlist;
+
+    #[test]
+    fn test_parse_master_playlist() {
+        let playlist = "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=1280000,AVERAGE-BANDWIDTH=1000000,RESOLUTION=1280x720\nhd.m3u8\n#EXT-X-STREAM-INF:BANDWIDTH=6000000,RESOLUTION=1920x1080\nfhd.m3u8";
+        let variants = parse_master_playlist(playlist);
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].bandwidth, Some(1280000));
+        assert_eq!(variants[0].resolution, Some("1280x720".to_string()));
+        assert_eq!(variants[1].bandwidth, Some(6000000));
+        assert_eq!(variants[1].resolution, Some("1920x1080".to_string()));
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+use crate::events::{self, RecordingStateChanged};
+
+const SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct RecordingStopped {
+    pub reason: String,
+}
+
+impl fmt::Display for RecordingStopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recording stopped: {}", self.reason)
+    }
+}
+
+impl std::error::Error for RecordingStopped {}
+
+/// Describes why a recording can't continue in `dir`, a floor of 0 disables the check.
+/// A folder whose free space can't be read is never considered full
+pub fn get_low_space_reason(dir: &Path, min_free_mb: u32) -> Option<String> {
+    if min_free_mb == 0 {
+        return None;
+    }
+    let available = match fs2::available_space(dir) {
+        Ok(available) => available,
+        Err(e) => {
+            tracing::warn!("Failed to read free space of {}: {e}", dir.display());
+            return None;
+        }
+    };
+    if available >= min_free_mb as u64 * BYTES_PER_MB {
+        return None;
+    }
+    Some(format!(
+        "only {} MB left in {}, at least {min_free_mb} MB are required",
+        available / BYTES_PER_MB,
+        dir.display()
+    ))
+}
+
+/// Resolves with the reason once the recording has to stop, either because the disk
+/// is almost full or because it ran for `max_minutes`
+pub async fn limit_reached(dir: &Path, min_free_mb: u32, max_minutes: Option<u16>) -> String {
+    let max_minutes = max_minutes.filter(|minutes| *minutes > 0);
+    let deadline =
+        max_minutes.map(|minutes| Instant::now() + Duration::from_secs(minutes as u64 * 60));
+    loop {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return format!(
+                "reached the {} minutes limit",
+                max_minutes.unwrap_or_default()
+            );
+        }
+        if let Some(reason) = get_low_space_reason(dir, min_free_mb) {
+            return reason;
+        }
+        let wait = deadline
+            .map(|d| d.saturating_duration_since(Instant::now()))
+            .map_or(SPACE_CHECK_INTERVAL, |left| left.min(SPACE_CHECK_INTERVAL));
+        tokio::time::sleep(wait).await;
+    }
+}
+
+pub fn emit_state(
+    app: &AppHandle,
+    operation_id: u64,
+    channel_name: &str,
+    recording: bool,
+    reason: Option<String>,
+) {
+    let state = RecordingStateChanged {
+        channel_name: channel_name.to_string(),
+        recording,
+        reason,
+    };
+    events::emit(app, events::RECORDING_STATE_CHANGED, operation_id, state);
+}
+
+/// Reports the end of a recording, one cut short by a limit or a failure also raises a notification
+pub fn notify_ended(
+    app: &AppHandle,
+    operation_id: u64,
+    channel_name: &str,
+    reason: Option<String>,
+) {
+    if let Some(reason) = reason.as_deref() {
+        notify_stopped(app, channel_name, reason);
+    }
+    emit_state(app, operation_id, channel_name, false, reason);
+}
+
+fn notify_stopped(app: &AppHandle, channel_name: &str, reason: &str) {
+    let result = app
+        .notification()
+        .builder()
+        .title(format!("Recording of {channel_name} stopped"))
+        .body(reason)
+        .show();
+    if let Err(e) = result {
+        tracing::error!("Failed to show recording notification: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod test_recording {
+    use super::{get_low_space_reason, limit_reached};
+
+    #[test]
+    fn test_get_low_space_reason() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(get_low_space_reason(dir.path(), 0), None);
+        assert_eq!(get_low_space_reason(dir.path(), 1), None);
+        assert!(get_low_space_reason(dir.path(), u32::MAX).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_limit_reached_on_low_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let reason = limit_reached(dir.path(), u32::MAX, Some(60)).await;
+        assert!(reason.starts_with("only"), "{reason}");
+    }
+}
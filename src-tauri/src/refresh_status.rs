@@ -0,0 +1,2 @@
+pub const SUCCESS: u8 = 0;
+pub const FAILED: u8 = 1;
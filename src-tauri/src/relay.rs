@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::LazyLock,
+};
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use regex::{Captures, Regex};
+use ring::{hmac, rand::SystemRandom};
+use tauri::State;
+use tokio::{
+    net::UdpSocket,
+    sync::{oneshot, Mutex, OwnedSemaphorePermit},
+};
+use url::Url;
+use warp::{
+    filters::BoxedFilter,
+    http::{header::CONTENT_TYPE, Response, StatusCode},
+    hyper::Body,
+    Filter,
+};
+
+use crate::{
+    sql,
+    types::{AppState, RelayHandle},
+    utils::{build_channel_request, get_playable_url},
+};
+
+const HLS_CONTENT_TYPE: &str = "application/vnd.apple.mpegurl";
+static URI_ATTRIBUTE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"URI="(?P<uri>[^"]+)""#).unwrap());
+/// Signs the urls rewritten playlists point to, the proxy route only fetches those.
+/// Generated for each run, so a url stops working with the app that handed it out
+static SIGNING_KEY: LazyLock<hmac::Key> =
+    LazyLock::new(|| hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new()).unwrap());
+
+pub async fn get_relay_url(state: State<'_, Mutex<AppState>>, channel_id: i64) -> Result<String> {
+    let mut state = state.lock().await;
+    let port = match &state.playback.relay {
+        Some(relay) => relay.port,
+        None => {
+            let relay = serve(IpAddr::V4(Ipv4Addr::LOCALHOST), false)?;
+            let port = relay.port;
+            state.playback.relay = Some(relay);
+            port
+        }
+    };
+    Ok(format!("http://127.0.0.1:{port}/stream/{channel_id}"))
+}
+
+/// The url a device on the network streams a channel from. That relay listens on the
+/// address reaching `device` and only serves the urls it signed
+pub async fn get_cast_url(
+    state: &State<'_, Mutex<AppState>>,
+    channel_id: i64,
+    device: IpAddr,
+) -> Result<String> {
+    let ip = get_local_ip_towards(device).await?;
+    let mut state = state.lock().await;
+    let port = match &state.playback.cast_relay {
+        Some(relay) if relay.ip == ip => relay.port,
+        _ => {
+            if let Some(relay) = state.playback.cast_relay.take() {
+                let _ = relay.stop.send(true);
+            }
+            let relay = serve(ip, true)?;
+            let port = relay.port;
+            state.playback.cast_relay = Some(relay);
+            port
+        }
+    };
+    Ok(format!(
+        "http://{}/stream/{channel_id}?sig={}",
+        SocketAddr::new(ip, port),
+        sign(channel_id, STREAM_SIGNATURE)
+    ))
+}
+
+/// What the channel itself is signed as, playlist urls are never empty
+const STREAM_SIGNATURE: &str = "";
+
+fn serve(ip: IpAddr, signed: bool) -> Result<RelayHandle> {
+    let stream = warp::path!("stream" / i64)
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(
+            move |channel_id, query: HashMap<String, String>| async move {
+                let signature = query.get("sig").map(String::as_str).unwrap_or_default();
+                if signed && !is_signed(channel_id, STREAM_SIGNATURE, signature) {
+                    return Ok(error_response(StatusCode::FORBIDDEN, "Unsigned url"));
+                }
+                handle(channel_id, None, None).await
+            },
+        );
+    let (stop, rx) = oneshot::channel::<bool>();
+    let (addr, server) = warp::serve(stream.or(proxy_route(|_| true)))
+        .try_bind_with_graceful_shutdown((ip, 0), async {
+            rx.await.ok();
+        })?;
+    tokio::spawn(server);
+    Ok(RelayHandle {
+        ip,
+        port: addr.port(),
+        stop,
+    })
+}
+
+/// The local address the system routes to `device` from, nothing is sent
+async fn get_local_ip_towards(device: IpAddr) -> Result<IpAddr> {
+    let any: IpAddr = match device {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => std::net::Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let socket = UdpSocket::bind((any, 0)).await?;
+    socket.connect((device, 9)).await?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// The route the urls of rewritten HLS playlists point to, for the channels `allowed` accepts.
+/// Urls the relay didn't sign for that channel are refused
+pub fn proxy_route(
+    allowed: impl Fn(i64) -> bool + Clone + Send + Sync + 'static,
+) -> BoxedFilter<(Response<Body>,)> {
+    warp::path!("proxy" / i64)
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(move |channel_id, query: HashMap<String, String>| {
+            let allowed = allowed(channel_id);
+            async move {
+                if !allowed {
+                    return Ok(error_response(StatusCode::NOT_FOUND, "Unknown channel"));
+                }
+                let (Some(url), Some(signature)) = (query.get("url"), query.get("sig")) else {
+                    return Ok(error_response(StatusCode::FORBIDDEN, "Unsigned url"));
+                };
+                if !is_signed(channel_id, url, signature) {
+                    return Ok(error_response(StatusCode::FORBIDDEN, "Unsigned url"));
+                }
+                handle(channel_id, Some(url.clone()), None).await
+            }
+        })
+        .boxed()
+}
+
+/// Streams a channel, or `url` for the parts of its HLS playlists. The `permit` is held
+/// until the stream ends
+pub async fn handle(
+    channel_id: i64,
+    url: Option<String>,
+    permit: Option<OwnedSemaphorePermit>,
+) -> Result<Response<Body>, Infallible> {
+    Ok(relay(channel_id, url, permit).await.unwrap_or_else(|e| {
+        tracing::error!("{:?}", e);
+        error_response(StatusCode::BAD_GATEWAY, &e.to_string())
+    }))
+}
+
+pub fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(message.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+async fn relay(
+    channel_id: i64,
+    url: Option<String>,
+    permit: Option<OwnedSemaphorePermit>,
+) -> Result<Response<Body>> {
+    let headers = sql::get_channel_headers_by_id(channel_id)?;
+    let url = match url {
+        Some(url) => url,
+        None => get_playable_url(&sql::get_channel_by_id(channel_id)?)?,
+    };
+    let mut response = build_channel_request(&url, headers)?.send().await?;
+    let status = response.status().as_u16();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.to_string());
+    if is_hls(response.url(), content_type.as_deref()) {
+        let base = response.url().clone();
+        let playlist = rewrite_playlist(&response.text().await?, &base, channel_id);
+        return Ok(Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, HLS_CONTENT_TYPE)
+            .body(Body::from(playlist))?);
+    }
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let _permit = permit;
+        while let Ok(Some(chunk)) = response.chunk().await {
+            if sender.send_data(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut builder = Response::builder().status(status);
+    if let Some(content_type) = content_type {
+        builder = builder.header(CONTENT_TYPE, content_type);
+    }
+    Ok(builder.body(body)?)
+}
+
+pub fn is_hls(url: &Url, content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|c| c.to_lowercase().contains("mpegurl"))
+        || url.path().to_lowercase().ends_with(".m3u8")
+}
+
+fn rewrite_playlist(playlist: &str, base: &Url, channel_id: i64) -> String {
+    playlist
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                line.to_string()
+            } else if trimmed.starts_with('#') {
+                URI_ATTRIBUTE_REGEX
+                    .replace_all(line, |caps: &Captures| {
+                        format!(r#"URI="{}""#, to_proxy_url(&caps["uri"], base, channel_id))
+                    })
+                    .to_string()
+            } else {
+                to_proxy_url(trimmed, base, channel_id)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn to_proxy_url(target: &str, base: &Url, channel_id: i64) -> String {
+    let absolute = base
+        .join(target)
+        .map(|u| u.to_string())
+        .unwrap_or(target.to_string());
+    let encoded: String = url::form_urlencoded::byte_serialize(absolute.as_bytes()).collect();
+    format!(
+        "/proxy/{channel_id}?url={encoded}&sig={}",
+        sign(channel_id, &absolute)
+    )
+}
+
+fn sign(channel_id: i64, url: &str) -> String {
+    let tag = hmac::sign(&SIGNING_KEY, format!("{channel_id} {url}").as_bytes());
+    URL_SAFE_NO_PAD.encode(tag.as_ref())
+}
+
+fn is_signed(channel_id: i64, url: &str, signature: &str) -> bool {
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+    hmac::verify(
+        &SIGNING_KEY,
+        format!("{channel_id} {url}").as_bytes(),
+        &signature,
+    )
+    .is_ok()
+}
+
+#[cfg(test)]
+mod test_relay {
+    use url::Url;
+
+    use super::{is_signed, rewrite_playlist, sign};
+
+    #[test]
+    fn test_rewrite_playlist() {
+        let base = Url::parse("http://myurl.local/live/1234/index.m3u8").unwrap();
+        let playlist = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n#EXTINF:5.0,\nsegment1.ts\n\nhttp://other.local/segment2.ts";
+        let rewritten = rewrite_playlist(playlist, &base, 7);
+        assert_eq!(
+            rewritten,
+            format!(
+                "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"/proxy/7?url=http%3A%2F%2Fmyurl.local%2Flive%2F1234%2Fkey.bin&sig={}\"\n#EXTINF:5.0,\n/proxy/7?url=http%3A%2F%2Fmyurl.local%2Flive%2F1234%2Fsegment1.ts&sig={}\n\n/proxy/7?url=http%3A%2F%2Fother.local%2Fsegment2.ts&sig={}",
+                sign(7, "http://myurl.local/live/1234/key.bin"),
+                sign(7, "http://myurl.local/live/1234/segment1.ts"),
+                sign(7, "http://other.local/segment2.ts")
+            )
+        );
+    }
+
+    #[test]
+    fn test_rewrite_playlist_ipv6() {
+        let base = Url::parse("http://[2a02::1]:8080/live/index.m3u8?token=a").unwrap();
+        let rewritten = rewrite_playlist("#EXTINF:5.0,\nsegment1.ts", &base, 7);
+        assert_eq!(
+            rewritten,
+            format!(
+                "#EXTINF:5.0,\n/proxy/7?url=http%3A%2F%2F%5B2a02%3A%3A1%5D%3A8080%2Flive%2Fsegment1.ts&sig={}",
+                sign(7, "http://[2a02::1]:8080/live/segment1.ts")
+            )
+        );
+    }
+
+    #[test]
+    fn test_only_signed_urls_are_relayed() {
+        let url = "http://myurl.local/live/1234/segment1.ts";
+        let signature = sign(7, url);
+        assert!(is_signed(7, url, &signature));
+        assert!(!is_signed(8, url, &signature));
+        assert!(!is_signed(
+            7,
+            "http://169.254.169.254/latest/meta-data",
+            &signature
+        ));
+        assert!(!is_signed(7, url, "not a signature"));
+    }
+}
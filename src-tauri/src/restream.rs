@@ -1,6 +1,7 @@
 use std::{
     path::{Path, PathBuf},
     process::{Child, Command, Stdio},
+    sync::Arc,
     time::Duration,
 };
 
@@ -18,11 +19,12 @@ use tokio::{
 };
 
 use crate::{
-    mpv,
+    events, mpv, proxy,
     settings::get_settings,
     sql,
-    types::{AppState, Channel, NetworkInfo},
-    utils::get_bin,
+    tasks::CancelToken,
+    types::{AppState, Channel, NetworkInfo, PlayerProcess},
+    utils::{get_bin, get_playable_url},
 };
 
 const WAN_IP_API: &str = "https://api.ipify.org";
@@ -58,7 +60,7 @@ fn start_ffmpeg_listening(channel: Channel, restream_dir: PathBuf) -> Result<Chi
     command.creation_flags(CREATE_NO_WINDOW);
     let child = command
         .arg("-i")
-        .arg(channel.url.context("no channel url")?)
+        .arg(get_playable_url(&channel)?)
         .arg("-c")
         .arg("copy")
         .arg("-f")
@@ -104,14 +106,14 @@ pub async fn start_restream(
     app: AppHandle,
     channel: Channel,
 ) -> Result<()> {
-    let stop = state.lock().await.restream_stop_signal.clone();
-    stop.store(false, std::sync::atomic::Ordering::Relaxed);
+    let stop = CancelToken::default();
+    state.lock().await.playback.restream_stop = stop.clone();
     let restream_dir = get_restream_folder()?;
     delete_old_segments(&restream_dir).await?;
     let mut ffmpeg_child = start_ffmpeg_listening(channel, restream_dir.clone())?;
     let (web_server_tx, web_server_handle) = start_web_server(restream_dir, port).await?;
-    let _ = app.emit("restream_started", true);
-    while !stop.load(std::sync::atomic::Ordering::Relaxed)
+    let _ = app.emit(events::RESTREAM_STARTED, true);
+    while !stop.is_cancelled()
         && ffmpeg_child
             .try_wait()
             .map(|option| option.is_none())
@@ -128,10 +130,7 @@ pub async fn start_restream(
 }
 
 pub async fn stop_restream(state: State<'_, Mutex<AppState>>) -> Result<()> {
-    let state = state.lock().await;
-    state
-        .restream_stop_signal
-        .store(true, std::sync::atomic::Ordering::Relaxed);
+    state.lock().await.playback.restream_stop.cancel();
     Ok(())
 }
 
@@ -158,11 +157,12 @@ async fn delete_old_segments(dir: &Path) -> Result<()> {
     Ok(())
 }
 
-pub async fn watch_self(port: u16) -> Result<()> {
+pub async fn watch_self(port: u16, process: Arc<PlayerProcess>) -> Result<()> {
     let channel = Channel {
         url: Some(format!("http://127.0.0.1:{port}/stream.m3u8").to_string()),
         name: "Local livestream".to_string(),
         favorite: false,
+        watched: false,
         group: None,
         group_id: None,
         id: Some(-1),
@@ -171,8 +171,11 @@ pub async fn watch_self(port: u16) -> Result<()> {
         series_id: None,
         source_id: None,
         stream_id: None,
+        tvg_id: None,
+        media_types: None,
+        catchup: None,
     };
-    mpv::play(channel, false).await
+    mpv::play(channel, false, process).await
 }
 
 pub fn share_restream(address: String, channel: Channel) -> Result<()> {
@@ -188,7 +191,11 @@ pub fn share_restream(address: String, channel: Channel) -> Result<()> {
             series_id: None,
             group_id: None,
             favorite: false,
+            watched: false,
             stream_id: None,
+            tvg_id: None,
+            media_types: None,
+            catchup: None,
         },
         Some(format!("RST - {}", channel.id.context("no id")?)),
     )
@@ -214,6 +221,11 @@ fn get_ips(port: u16) -> Result<Vec<String>> {
 async fn get_wan_ip(port: u16) -> Result<String> {
     Ok(format!(
         "http://{}:{port}/stream.m3u8",
-        reqwest::get(WAN_IP_API).await?.text().await?
+        proxy::get_client()?
+            .get(WAN_IP_API)
+            .send()
+            .await?
+            .text()
+            .await?
     ))
 }
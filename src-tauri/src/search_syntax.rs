@@ -0,0 +1,179 @@
+use std::fmt;
+
+use crate::utils::{fold_search_name, like_literal};
+
+/// Bounds the statement a search turns into. Terms are escaped so each pattern holds at
+/// most two wildcards and stays linear to match
+const MAX_QUERY_CHARS: usize = 200;
+const MAX_TERMS: usize = 16;
+
+/// LIKE patterns over folded names: every `include` must match and no `exclude` may.
+/// `escaped` patterns are meant for `ESCAPE '\'`
+#[derive(Debug, PartialEq, Default)]
+pub struct SearchTerms {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub escaped: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidQuery {
+    /// Counted in characters from 0
+    pub position: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for InvalidQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid search at character {}: {}",
+            self.position + 1,
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for InvalidQuery {}
+
+/// Asked for by the frontend, or by starting the query with a `/`
+pub fn is_advanced(query: &str, flagged: bool) -> bool {
+    flagged || query.trim_start().starts_with('/')
+}
+
+/// Every term must be in the name, `-term` must not, `^term` starts it, `term$` ends it
+/// and `"a phrase"` keeps its spaces, e.g. `^uk| -vip "main event"`
+pub fn parse(query: &str) -> Result<SearchTerms, InvalidQuery> {
+    let chars: Vec<char> = query.chars().collect();
+    if chars.len() > MAX_QUERY_CHARS {
+        return Err(InvalidQuery {
+            position: MAX_QUERY_CHARS,
+            reason: "the search is too long",
+        });
+    }
+    let mut terms = SearchTerms {
+        escaped: true,
+        ..Default::default()
+    };
+    let skip_spaces = |mut i: usize| {
+        while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+            i += 1;
+        }
+        i
+    };
+    let mut i = skip_spaces(0);
+    // the leading / only switches the mode
+    if chars.get(i) == Some(&'/') {
+        i += 1;
+    }
+    loop {
+        i = skip_spaces(i);
+        if i == chars.len() {
+            break;
+        }
+        let start = i;
+        if terms.include.len() + terms.exclude.len() == MAX_TERMS {
+            return Err(InvalidQuery {
+                position: start,
+                reason: "the search has too many terms",
+            });
+        }
+        let negated = chars[i] == '-';
+        if negated {
+            i += 1;
+        }
+        let starts = chars.get(i) == Some(&'^');
+        if starts {
+            i += 1;
+        }
+        let mut text = String::new();
+        let mut ends = false;
+        if chars.get(i) == Some(&'"') {
+            let quote = i;
+            i += 1;
+            while chars.get(i).is_some_and(|c| *c != '"') {
+                text.push(chars[i]);
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(InvalidQuery {
+                    position: quote,
+                    reason: "the quote is never closed",
+                });
+            }
+            i += 1;
+            if chars.get(i) == Some(&'$') {
+                ends = true;
+                i += 1;
+            }
+        } else {
+            while chars
+                .get(i)
+                .is_some_and(|c| !c.is_whitespace() && *c != '"')
+            {
+                text.push(chars[i]);
+                i += 1;
+            }
+            ends = text.len() > 1 && text.ends_with('$');
+            if ends {
+                text.pop();
+            }
+        }
+        if chars.get(i).is_some_and(|c| !c.is_whitespace()) {
+            return Err(InvalidQuery {
+                position: i,
+                reason: "terms must be separated by spaces",
+            });
+        }
+        let text = fold_search_name(&text);
+        if text.trim().is_empty() {
+            return Err(InvalidQuery {
+                position: start,
+                reason: "the term is empty",
+            });
+        }
+        let pattern = format!(
+            "{}{}{}",
+            if starts { "" } else { "%" },
+            like_literal(&text),
+            if ends { "" } else { "%" }
+        );
+        match negated {
+            true => terms.exclude.push(pattern),
+            false => terms.include.push(pattern),
+        }
+    }
+    Ok(terms)
+}
+
+#[cfg(test)]
+mod test_search_syntax {
+    use super::{is_advanced, parse, InvalidQuery, MAX_TERMS};
+
+    #[test]
+    fn test_parse() {
+        let terms = parse(r#"/^UK| -vip "Main Event" 100%$"#).unwrap();
+        assert_eq!(terms.include, ["uk|%", "%main event%", "%100\\%"]);
+        assert_eq!(terms.exclude, ["%vip%"]);
+        assert!(terms.escaped);
+        assert!(parse(" / ").unwrap().include.is_empty());
+        assert!(is_advanced(" /uk", false));
+        assert!(!is_advanced("uk", false));
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        let error = |query: &str| parse(query).unwrap_err();
+        assert_eq!(
+            error(r#"uk "main event"#),
+            InvalidQuery {
+                position: 3,
+                reason: "the quote is never closed",
+            }
+        );
+        assert_eq!(error(r#""uk"vip"#).position, 4);
+        assert_eq!(error("uk - vip").position, 3);
+        assert_eq!(error(&"a ".repeat(MAX_TERMS + 1)).position, MAX_TERMS * 2);
+        assert_eq!(error(&"a".repeat(201)).position, 200);
+    }
+}
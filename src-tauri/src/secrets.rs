@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, OnceLock},
+};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::sql;
+
+const SERVICE: &str = "open-tv";
+const KEYRING_PREFIX: &str = "keyring:";
+const ENCRYPTED_PREFIX: &str = "enc:";
+const APP_KEY_FILE: &str = "secret.key";
+const APP_KEY_LEN: usize = 32;
+pub const STORAGE_KEYRING: &str = "keyring";
+pub const STORAGE_ENCRYPTED: &str = "encrypted";
+static KEYRING_AVAILABLE: OnceLock<bool> = OnceLock::new();
+/// Keyring lookups can prompt or go through dbus, each password is read once per run
+static KEYRING_CACHE: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
+
+/// Stores a source password and returns the reference to keep in the sources table
+pub fn store(source_id: i64, secret: &str) -> Result<String> {
+    if keyring_available() {
+        let user = get_keyring_user(source_id);
+        match keyring::Entry::new(SERVICE, &user).and_then(|e| e.set_password(secret)) {
+            Ok(_) => {
+                KEYRING_CACHE
+                    .lock()
+                    .unwrap()
+                    .insert(user.clone(), secret.to_string());
+                return Ok(format!("{KEYRING_PREFIX}{user}"));
+            }
+            Err(e) => {
+                tracing::warn!("Failed to store password in keyring, encrypting it instead: {e}")
+            }
+        }
+    }
+    Ok(format!("{ENCRYPTED_PREFIX}{}", encrypt(secret)?))
+}
+
+/// Resolves a reference from the sources table, values that predate references are returned as is
+pub fn resolve(reference: &str) -> Result<String> {
+    if let Some(user) = reference.strip_prefix(KEYRING_PREFIX) {
+        if let Some(secret) = KEYRING_CACHE.lock().unwrap().get(user) {
+            return Ok(secret.clone());
+        }
+        let secret = keyring::Entry::new(SERVICE, user)?
+            .get_password()
+            .with_context(|| format!("Failed to read {user} from keyring"))?;
+        KEYRING_CACHE
+            .lock()
+            .unwrap()
+            .insert(user.to_string(), secret.clone());
+        return Ok(secret);
+    }
+    if let Some(blob) = reference.strip_prefix(ENCRYPTED_PREFIX) {
+        return decrypt(blob);
+    }
+    Ok(reference.to_string())
+}
+
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with(KEYRING_PREFIX) || value.starts_with(ENCRYPTED_PREFIX)
+}
+
+pub fn delete(source_id: i64) -> Result<()> {
+    if !keyring_available() {
+        return Ok(());
+    }
+    let user = get_keyring_user(source_id);
+    KEYRING_CACHE.lock().unwrap().remove(&user);
+    match keyring::Entry::new(SERVICE, &user)?.delete_credential() {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn get_storage() -> &'static str {
+    if keyring_available() {
+        STORAGE_KEYRING
+    } else {
+        STORAGE_ENCRYPTED
+    }
+}
+
+fn get_keyring_user(source_id: i64) -> String {
+    format!("source-{source_id}")
+}
+
+fn keyring_available() -> bool {
+    if cfg!(test) {
+        return false;
+    }
+    *KEYRING_AVAILABLE.get_or_init(|| {
+        let available = keyring::Entry::new(SERVICE, "probe")
+            .and_then(|e| e.get_password())
+            .map_or_else(|e| matches!(e, keyring::Error::NoEntry), |_| true);
+        if !available {
+            tracing::warn!(
+                "No keyring available, source passwords will be encrypted with the app key"
+            );
+        }
+        available
+    })
+}
+
+fn encrypt(secret: &str) -> Result<String> {
+    let key = get_app_key()?;
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow!("Failed to generate nonce"))?;
+    let mut data = secret.as_bytes().to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Failed to encrypt password"))?;
+    let mut blob = nonce.to_vec();
+    blob.append(&mut data);
+    Ok(BASE64_STANDARD.encode(blob))
+}
+
+fn decrypt(blob: &str) -> Result<String> {
+    let key = get_app_key()?;
+    let mut blob = BASE64_STANDARD.decode(blob)?;
+    if blob.len() < NONCE_LEN {
+        return Err(anyhow!("Encrypted password is too short"));
+    }
+    let mut data = blob.split_off(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(&blob)
+        .map_err(|_| anyhow!("Invalid nonce in encrypted password"))?;
+    let plain = key
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| anyhow!("Failed to decrypt password, was the app key replaced?"))?;
+    Ok(String::from_utf8(plain.to_vec())?)
+}
+
+/// The app key is kept out of the data directory, so a copy of the database and
+/// its folder does not carry the key to its passwords along
+fn get_app_key() -> Result<LessSafeKey> {
+    let path = get_app_key_path()?;
+    move_legacy_app_key(&path)?;
+    let bytes = if path.exists() {
+        fs::read(&path)?
+    } else {
+        let mut bytes = vec![0u8; APP_KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow!("Failed to generate app key"))?;
+        write_app_key(&path, &bytes)?;
+        bytes
+    };
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes)
+        .map_err(|_| anyhow!("Invalid app key in {}", path.to_string_lossy()))?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn get_app_key_path() -> Result<PathBuf> {
+    // tests keep their key in their own temporary folder
+    if cfg!(test) {
+        return Ok(get_legacy_app_key_path()?
+            .with_file_name("key")
+            .join(APP_KEY_FILE));
+    }
+    let mut path = directories::ProjectDirs::from("dev", "fredol", "open-tv")
+        .context("can't find project folder")?
+        .preference_dir()
+        .to_owned();
+    path.push(APP_KEY_FILE);
+    Ok(path)
+}
+
+/// Earlier versions kept the app key next to the database
+fn get_legacy_app_key_path() -> Result<PathBuf> {
+    Ok(PathBuf::from(sql::get_db_path()?).with_file_name(APP_KEY_FILE))
+}
+
+fn move_legacy_app_key(path: &Path) -> Result<()> {
+    let legacy = get_legacy_app_key_path()?;
+    if !legacy.exists() {
+        return Ok(());
+    }
+    if !path.exists() {
+        write_app_key(path, &fs::read(&legacy)?)?;
+    }
+    fs::remove_file(&legacy)?;
+    Ok(())
+}
+
+fn write_app_key(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(path)?, bytes)
+        .with_context(|| format!("Failed to write app key to {}", path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod test_secrets {
+    use std::path::PathBuf;
+
+    use super::{get_app_key_path, is_reference, resolve, store, APP_KEY_FILE};
+    use crate::sql::{get_db_path, init_test_db};
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let _db = init_test_db();
+        let reference = store(1, "hunter2").unwrap();
+        assert!(is_reference(&reference));
+        assert!(!reference.contains("hunter2"));
+        assert_eq!(resolve(&reference).unwrap(), "hunter2");
+        assert_eq!(resolve("plain").unwrap(), "plain");
+    }
+
+    #[test]
+    fn test_legacy_app_key_is_moved() {
+        let _db = init_test_db();
+        let reference = store(1, "hunter2").unwrap();
+        let key_path = get_app_key_path().unwrap();
+        let legacy = PathBuf::from(get_db_path().unwrap()).with_file_name(APP_KEY_FILE);
+        std::fs::rename(&key_path, &legacy).unwrap();
+        assert_eq!(resolve(&reference).unwrap(), "hunter2");
+        assert!(key_path.exists());
+        assert!(!legacy.exists());
+    }
+}
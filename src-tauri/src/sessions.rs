@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use anyhow::Result;
+use tauri::State;
+use tokio::sync::Mutex;
+
+use crate::{
+    sql,
+    types::{AppState, Channel},
+};
+
+pub fn recover_interrupted_sessions() -> Result<Option<i64>> {
+    let sessions = sql::get_open_sessions()?;
+    for session in sessions.iter() {
+        if let Some(path) = &session.record_path {
+            clean_orphaned_recording(Path::new(path));
+        }
+    }
+    sql::end_open_sessions(true)?;
+    Ok(sessions
+        .iter()
+        .max_by_key(|session| session.started_at)
+        .map(|session| session.channel_id))
+}
+
+fn clean_orphaned_recording(path: &Path) {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.len() == 0 => {
+            let _ = std::fs::remove_file(path).inspect_err(|e| tracing::warn!("{:?}", e));
+        }
+        Ok(_) => tracing::warn!(
+            "Recording {} was interrupted and may be incomplete",
+            path.to_string_lossy()
+        ),
+        Err(_) => {}
+    }
+}
+
+pub async fn get_resume_candidate(state: State<'_, Mutex<AppState>>) -> Result<Option<Channel>> {
+    let channel_id = state.lock().await.resume_candidate.take();
+    match channel_id {
+        Some(id) => Ok(sql::get_channel_by_id(id).ok()),
+        None => Ok(None),
+    }
+}
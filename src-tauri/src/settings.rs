@@ -1,9 +1,13 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, env::consts::OS, fmt::Display, path::PathBuf, str::FromStr};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use directories::UserDirs;
 
-use crate::{sql, types::Settings};
+use crate::{
+    gamepad, hotkeys, players, proxy, sql,
+    types::{Settings, SettingsFieldError},
+    view_type,
+};
 
 pub const MPV_PARAMS: &str = "mpvParams";
 pub const USE_STREAM_CACHING: &str = "useStreamingCaching";
@@ -12,55 +16,572 @@ pub const DEFAULT_VIEW: &str = "defaultView";
 pub const VOLUME: &str = "volume";
 pub const REFRESH_ON_START: &str = "refreshOnStart";
 pub const RESTREAM_PORT: &str = "restreamPort";
+pub const NEW_CHANNELS_DAYS: &str = "newChannelsDays";
+pub const DEFAULT_NEW_CHANNELS_DAYS: u16 = 7;
+pub const PREFERRED_PLAYER: &str = "preferredPlayer";
+pub const HEALTH_CHECK_CONCURRENCY: &str = "healthCheckConcurrency";
+pub const DEFAULT_HEALTH_CHECK_CONCURRENCY: u8 = 10;
+pub const REFRESH_INTERVAL_HOURS: &str = "refreshIntervalHours";
+pub const PAGE_SIZE: &str = "pageSize";
+pub const DEFAULT_PAGE_SIZE: u8 = 36;
+pub const MAX_VOLUME: u8 = 130;
+pub const REMEMBER_CHANNEL_VOLUME: &str = "rememberChannelVolume";
+pub const PREFERRED_AUDIO_LANG: &str = "preferredAudioLang";
+pub const PREFERRED_SUBTITLE_LANG: &str = "preferredSubtitleLang";
+pub const SUBTITLES_ENABLED: &str = "subtitlesEnabled";
+pub const HWDEC: &str = "hwdec";
+pub const VO: &str = "vo";
+pub const YTDLP_AUTO_UPDATE: &str = "ytdlpAutoUpdate";
+pub const HOTKEY_SHOW_WINDOW: &str = "hotkeyShowWindow";
+pub const HOTKEY_PLAY_PAUSE: &str = "hotkeyPlayPause";
+pub const GAMEPAD_ENABLED: &str = "gamepadEnabled";
+pub const GAMEPAD_MAPPING: &str = "gamepadMapping";
+pub const FOLDER_EXTENSIONS: &str = "folderExtensions";
+pub const PLAYER_STARTUP_TIMEOUT: &str = "playerStartupTimeout";
+pub const DEFAULT_PLAYER_STARTUP_TIMEOUT: u16 = 30;
+pub const SINGLE_INSTANCE_PLAYBACK: &str = "singleInstancePlayback";
+pub const RECORDING_FILENAME_TEMPLATE: &str = "recordingFilenameTemplate";
+pub const DEFAULT_RECORDING_FILENAME_TEMPLATE: &str = "{channel} - {date} {time}";
+pub const MIN_FREE_SPACE_MB: &str = "minFreeSpaceMb";
+pub const DEFAULT_MIN_FREE_SPACE_MB: u32 = 1024;
+pub const MAX_RECORDING_MINUTES: &str = "maxRecordingMinutes";
+pub const PIP_MODE: &str = "pipMode";
+pub const RADIO_MPV_PARAMS: &str = "radioMpvParams";
+pub const DEFAULT_RADIO_MPV_PARAMS: &str = "--force-window=no --no-video";
+pub const AUTO_MARK_WATCHED: &str = "autoMarkWatched";
+pub const EPG_MEDIA_TITLE: &str = "epgMediaTitle";
+pub const EPG_RETENTION_DAYS: &str = "epgRetentionDays";
+pub const DEFAULT_EPG_RETENTION_DAYS: u16 = 2;
+pub const EPG_FUTURE_DAYS: &str = "epgFutureDays";
+pub const DEFAULT_EPG_FUTURE_DAYS: u16 = 7;
+pub const XTREAM_REQUESTS_PER_SECOND: &str = "xtreamRequestsPerSecond";
+pub const DEFAULT_XTREAM_REQUESTS_PER_SECOND: u8 = 4;
+pub const TRACK_WATCH_STATS: &str = "trackWatchStats";
+pub const HDHOMERUN_ENABLED: &str = "hdhomerunEnabled";
+pub const HDHOMERUN_PORT: &str = "hdhomerunPort";
+pub const DEFAULT_HDHOMERUN_PORT: u16 = 5004;
+pub const HDHOMERUN_LAN: &str = "hdhomerunLan";
+pub const HDHOMERUN_PLAYLIST_ID: &str = "hdhomerunPlaylistId";
+pub const HDHOMERUN_MAX_STREAMS: &str = "hdhomerunMaxStreams";
+pub const DEFAULT_HDHOMERUN_MAX_STREAMS: u8 = 1;
+pub const AUTO_BACKUP: &str = "autoBackup";
+pub const AUTO_BACKUP_VALUES: [&str; 3] = ["off", "daily", "weekly"];
+pub const AUTO_BACKUP_PATH: &str = "autoBackupPath";
+pub const AUTO_BACKUP_COUNT: &str = "autoBackupCount";
+pub const DEFAULT_AUTO_BACKUP_COUNT: u8 = 5;
+pub const PROXY_URL: &str = "proxyUrl";
+pub const USE_SYSTEM_PROXY: &str = "useSystemProxy";
+pub const CONTROL_API_ENABLED: &str = "controlApiEnabled";
+pub const CONTROL_API_PORT: &str = "controlApiPort";
+pub const DEFAULT_CONTROL_API_PORT: u16 = 5005;
+pub const CONTROL_API_LAN: &str = "controlApiLan";
+pub const HWDEC_VALUES: [&str; 6] = [
+    "off",
+    "auto",
+    "auto-safe",
+    "vaapi",
+    "videotoolbox",
+    "d3d11va",
+];
+pub const VO_VALUES: [&str; 7] = [
+    "gpu",
+    "gpu-next",
+    "x11",
+    "xv",
+    "dmabuf-wayland",
+    "direct3d",
+    "sdl",
+];
 
-pub fn get_settings() -> Result<Settings> {
-    let map = sql::get_settings()?;
-    let settings = Settings {
-        mpv_params: map.get(MPV_PARAMS).map(|s| s.to_string()),
-        recording_path: map.get(RECORDING_PATH).map(|s| s.to_string()),
-        use_stream_caching: map.get(USE_STREAM_CACHING).and_then(|s| s.parse().ok()),
-        default_view: map.get(DEFAULT_VIEW).and_then(|s| s.parse().ok()),
-        volume: map.get(VOLUME).and_then(|s| s.parse().ok()),
-        refresh_on_start: map.get(REFRESH_ON_START).and_then(|s| s.parse().ok()),
-        restream_port: map.get(RESTREAM_PORT).and_then(|s| s.parse().ok()),
-    };
-    Ok(settings)
-}
-
-pub fn update_settings(settings: Settings) -> Result<()> {
-    let mut map: HashMap<String, String> = HashMap::with_capacity(3);
-    if let Some(mpv_params) = settings.mpv_params {
-        map.insert(MPV_PARAMS.to_string(), mpv_params);
+impl Settings {
+    pub fn from_map(map: &HashMap<String, String>) -> Settings {
+        Settings {
+            mpv_params: map.get(MPV_PARAMS).map(|s| s.to_string()),
+            recording_path: map.get(RECORDING_PATH).and_then(|s| normalize_path(s)),
+            use_stream_caching: get(map, USE_STREAM_CACHING).unwrap_or(true),
+            default_view: get(map, DEFAULT_VIEW),
+            volume: get::<u8>(map, VOLUME).map(|v| v.min(MAX_VOLUME)),
+            refresh_on_start: get(map, REFRESH_ON_START),
+            restream_port: get(map, RESTREAM_PORT),
+            new_channels_days: get(map, NEW_CHANNELS_DAYS),
+            preferred_player: get(map, PREFERRED_PLAYER),
+            health_check_concurrency: get(map, HEALTH_CHECK_CONCURRENCY),
+            refresh_interval_hours: get(map, REFRESH_INTERVAL_HOURS),
+            page_size: get(map, PAGE_SIZE),
+            remember_channel_volume: get(map, REMEMBER_CHANNEL_VOLUME),
+            preferred_audio_lang: map.get(PREFERRED_AUDIO_LANG).map(|s| s.to_string()),
+            preferred_subtitle_lang: map.get(PREFERRED_SUBTITLE_LANG).map(|s| s.to_string()),
+            subtitles_enabled: get(map, SUBTITLES_ENABLED),
+            hwdec: map
+                .get(HWDEC)
+                .map(|s| s.to_string())
+                .filter(|s| !s.is_empty()),
+            vo: map.get(VO).map(|s| s.to_string()).filter(|s| !s.is_empty()),
+            ytdlp_auto_update: get(map, YTDLP_AUTO_UPDATE),
+            hotkey_show_window: map.get(HOTKEY_SHOW_WINDOW).map(|s| s.to_string()),
+            hotkey_play_pause: map.get(HOTKEY_PLAY_PAUSE).map(|s| s.to_string()),
+            gamepad_enabled: get(map, GAMEPAD_ENABLED),
+            gamepad_mapping: map
+                .get(GAMEPAD_MAPPING)
+                .map(|s| s.to_string())
+                .filter(|s| !s.trim().is_empty()),
+            folder_extensions: map
+                .get(FOLDER_EXTENSIONS)
+                .map(|s| s.to_string())
+                .filter(|s| !s.trim().is_empty()),
+            player_startup_timeout: get(map, PLAYER_STARTUP_TIMEOUT),
+            single_instance_playback: get(map, SINGLE_INSTANCE_PLAYBACK),
+            recording_filename_template: map
+                .get(RECORDING_FILENAME_TEMPLATE)
+                .map(|s| s.to_string())
+                .filter(|s| !s.trim().is_empty()),
+            min_free_space_mb: get(map, MIN_FREE_SPACE_MB),
+            max_recording_minutes: get(map, MAX_RECORDING_MINUTES),
+            pip_mode: get(map, PIP_MODE),
+            radio_mpv_params: map.get(RADIO_MPV_PARAMS).map(|s| s.to_string()),
+            auto_mark_watched: get(map, AUTO_MARK_WATCHED),
+            epg_media_title: get(map, EPG_MEDIA_TITLE),
+            epg_retention_days: get(map, EPG_RETENTION_DAYS),
+            epg_future_days: get(map, EPG_FUTURE_DAYS),
+            xtream_requests_per_second: get(map, XTREAM_REQUESTS_PER_SECOND),
+            track_watch_stats: get(map, TRACK_WATCH_STATS),
+            hdhomerun_enabled: get(map, HDHOMERUN_ENABLED),
+            hdhomerun_port: get(map, HDHOMERUN_PORT),
+            hdhomerun_lan: get(map, HDHOMERUN_LAN),
+            hdhomerun_playlist_id: get(map, HDHOMERUN_PLAYLIST_ID),
+            hdhomerun_max_streams: get(map, HDHOMERUN_MAX_STREAMS),
+            auto_backup: map
+                .get(AUTO_BACKUP)
+                .map(|s| s.to_string())
+                .filter(|s| !s.is_empty()),
+            auto_backup_path: map.get(AUTO_BACKUP_PATH).and_then(|s| normalize_path(s)),
+            auto_backup_count: get(map, AUTO_BACKUP_COUNT),
+            proxy_url: map
+                .get(PROXY_URL)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty()),
+            use_system_proxy: get(map, USE_SYSTEM_PROXY),
+            control_api_enabled: get(map, CONTROL_API_ENABLED),
+            control_api_port: get(map, CONTROL_API_PORT),
+            control_api_lan: get(map, CONTROL_API_LAN),
+        }
     }
-    if let Some(recording_path) = settings.recording_path {
-        map.insert(RECORDING_PATH.to_string(), recording_path);
-    }
-    if let Some(use_stream_caching) = settings.use_stream_caching {
+
+    pub fn to_map(self) -> HashMap<String, String> {
+        let mut map: HashMap<String, String> = HashMap::new();
+        set(&mut map, MPV_PARAMS, self.mpv_params);
+        set(
+            &mut map,
+            RECORDING_PATH,
+            self.recording_path
+                .map(|path| path.to_string_lossy().to_string()),
+        );
+        set(&mut map, USE_STREAM_CACHING, Some(self.use_stream_caching));
+        set(&mut map, DEFAULT_VIEW, self.default_view);
+        set(&mut map, VOLUME, self.volume);
+        set(&mut map, REFRESH_ON_START, self.refresh_on_start);
+        set(&mut map, RESTREAM_PORT, self.restream_port);
+        set(&mut map, NEW_CHANNELS_DAYS, self.new_channels_days);
+        set(&mut map, PREFERRED_PLAYER, self.preferred_player);
+        set(
+            &mut map,
+            HEALTH_CHECK_CONCURRENCY,
+            self.health_check_concurrency,
+        );
+        set(
+            &mut map,
+            REFRESH_INTERVAL_HOURS,
+            self.refresh_interval_hours,
+        );
+        set(&mut map, PAGE_SIZE, self.page_size);
+        set(
+            &mut map,
+            REMEMBER_CHANNEL_VOLUME,
+            self.remember_channel_volume,
+        );
+        set(&mut map, PREFERRED_AUDIO_LANG, self.preferred_audio_lang);
+        set(
+            &mut map,
+            PREFERRED_SUBTITLE_LANG,
+            self.preferred_subtitle_lang,
+        );
+        set(&mut map, SUBTITLES_ENABLED, self.subtitles_enabled);
+        set(&mut map, HWDEC, self.hwdec);
+        set(&mut map, VO, self.vo);
+        set(&mut map, YTDLP_AUTO_UPDATE, self.ytdlp_auto_update);
+        set(&mut map, HOTKEY_SHOW_WINDOW, self.hotkey_show_window);
+        set(&mut map, HOTKEY_PLAY_PAUSE, self.hotkey_play_pause);
+        set(&mut map, GAMEPAD_ENABLED, self.gamepad_enabled);
+        set(&mut map, GAMEPAD_MAPPING, self.gamepad_mapping);
+        set(&mut map, FOLDER_EXTENSIONS, self.folder_extensions);
+        set(
+            &mut map,
+            PLAYER_STARTUP_TIMEOUT,
+            self.player_startup_timeout,
+        );
+        set(
+            &mut map,
+            SINGLE_INSTANCE_PLAYBACK,
+            self.single_instance_playback,
+        );
+        set(
+            &mut map,
+            RECORDING_FILENAME_TEMPLATE,
+            self.recording_filename_template,
+        );
+        set(&mut map, MIN_FREE_SPACE_MB, self.min_free_space_mb);
+        set(&mut map, MAX_RECORDING_MINUTES, self.max_recording_minutes);
+        set(&mut map, PIP_MODE, self.pip_mode);
+        set(&mut map, RADIO_MPV_PARAMS, self.radio_mpv_params);
+        set(&mut map, AUTO_MARK_WATCHED, self.auto_mark_watched);
+        set(&mut map, EPG_MEDIA_TITLE, self.epg_media_title);
+        set(&mut map, EPG_RETENTION_DAYS, self.epg_retention_days);
+        set(&mut map, EPG_FUTURE_DAYS, self.epg_future_days);
+        set(
+            &mut map,
+            XTREAM_REQUESTS_PER_SECOND,
+            self.xtream_requests_per_second,
+        );
+        set(&mut map, TRACK_WATCH_STATS, self.track_watch_stats);
+        set(&mut map, HDHOMERUN_ENABLED, self.hdhomerun_enabled);
+        set(&mut map, HDHOMERUN_PORT, self.hdhomerun_port);
+        set(&mut map, HDHOMERUN_LAN, self.hdhomerun_lan);
+        // no playlist is the favorites, it has to replace the playlist chosen before
         map.insert(
-            USE_STREAM_CACHING.to_string(),
-            use_stream_caching.to_string(),
+            HDHOMERUN_PLAYLIST_ID.to_string(),
+            self.hdhomerun_playlist_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+        );
+        set(&mut map, HDHOMERUN_MAX_STREAMS, self.hdhomerun_max_streams);
+        set(&mut map, AUTO_BACKUP, self.auto_backup);
+        set(
+            &mut map,
+            AUTO_BACKUP_PATH,
+            self.auto_backup_path
+                .map(|path| path.to_string_lossy().to_string()),
         );
+        set(&mut map, AUTO_BACKUP_COUNT, self.auto_backup_count);
+        set(&mut map, PROXY_URL, self.proxy_url);
+        set(&mut map, USE_SYSTEM_PROXY, self.use_system_proxy);
+        set(&mut map, CONTROL_API_ENABLED, self.control_api_enabled);
+        set(&mut map, CONTROL_API_PORT, self.control_api_port);
+        set(&mut map, CONTROL_API_LAN, self.control_api_lan);
+        map
     }
-    if let Some(default_view) = settings.default_view {
-        map.insert(DEFAULT_VIEW.to_string(), default_view.to_string());
+
+    /// Field errors of these settings, the recording path is only checked when it differs
+    /// from the `saved` one so a removed folder does not block saving everything else
+    pub fn validate(&self, saved: &Settings) -> Vec<SettingsFieldError> {
+        let mut errors = Vec::new();
+        let mut error = |field: &str, message: &str| {
+            errors.push(SettingsFieldError {
+                field: field.to_string(),
+                message: message.to_string(),
+            })
+        };
+        if self.volume.is_some_and(|v| v > MAX_VOLUME) {
+            error("volume", "Volume must be between 0 and 130");
+        }
+        if self.recording_path != saved.recording_path
+            && self.recording_path.as_ref().is_some_and(|p| !p.is_dir())
+        {
+            error("recording_path", "Recording path does not exist");
+        }
+        #[cfg(not(target_os = "windows"))]
+        if self
+            .mpv_params
+            .as_ref()
+            .is_some_and(|p| shell_words::split(p).is_err())
+        {
+            error("mpv_params", "Mpv params could not be parsed");
+        }
+        if self.default_view.is_some_and(|v| v > view_type::NEW) {
+            error("default_view", "Invalid default view");
+        }
+        if self.restream_port == Some(0) {
+            error("restream_port", "Port must be greater than 0");
+        }
+        if self
+            .auto_backup
+            .as_deref()
+            .is_some_and(|b| !AUTO_BACKUP_VALUES.contains(&b))
+        {
+            error("auto_backup", "Invalid backup frequency");
+        }
+        if self.auto_backup_path.as_ref().is_some_and(|p| !p.is_dir()) {
+            error("auto_backup_path", "Backup folder does not exist");
+        }
+        if self.auto_backup_count == Some(0) {
+            error("auto_backup_count", "At least one backup must be kept");
+        }
+        if self
+            .proxy_url
+            .as_deref()
+            .is_some_and(|p| !p.trim().is_empty() && proxy::validate(p.trim()).is_err())
+        {
+            error(
+                "proxy_url",
+                "Use an http, https or socks5 url like http://host:port",
+            );
+        }
+        if self.hdhomerun_port == Some(0) {
+            error("hdhomerun_port", "Port must be greater than 0");
+        }
+        if self.hdhomerun_max_streams == Some(0) {
+            error("hdhomerun_max_streams", "At least one stream is needed");
+        }
+        if self.control_api_port == Some(0) {
+            error("control_api_port", "Port must be greater than 0");
+        }
+        if self.control_api_enabled == Some(true)
+            && self.hdhomerun_enabled == Some(true)
+            && self.control_api_port.unwrap_or(DEFAULT_CONTROL_API_PORT)
+                == self.hdhomerun_port.unwrap_or(DEFAULT_HDHOMERUN_PORT)
+        {
+            error(
+                "control_api_port",
+                "The HDHomeRun tuner already uses this port",
+            );
+        }
+        if self.new_channels_days == Some(0) {
+            error("new_channels_days", "Must be at least one day");
+        }
+        if self.epg_future_days == Some(0) {
+            error("epg_future_days", "Must be at least one day");
+        }
+        if self
+            .preferred_player
+            .is_some_and(|p| p != players::MPV && p != players::VLC)
+        {
+            error("preferred_player", "Invalid player");
+        }
+        if self.xtream_requests_per_second == Some(0) {
+            error(
+                "xtream_requests_per_second",
+                "At least one request per second is needed",
+            );
+        }
+        if self.health_check_concurrency == Some(0) {
+            error("health_check_concurrency", "Concurrency must be at least 1");
+        }
+        if self.page_size.is_some_and(|p| p < 12) {
+            error("page_size", "Page size must be at least 12");
+        }
+        if self
+            .hwdec
+            .as_deref()
+            .is_some_and(|h| !h.is_empty() && !HWDEC_VALUES.contains(&h))
+        {
+            error("hwdec", "Invalid hardware decoding value");
+        }
+        if self
+            .vo
+            .as_deref()
+            .is_some_and(|v| !v.is_empty() && !VO_VALUES.contains(&v))
+        {
+            error("vo", "Invalid video output");
+        }
+        if !hotkeys::is_valid_accelerator(&self.hotkey_show_window) {
+            error("hotkey_show_window", "Invalid shortcut");
+        }
+        if !hotkeys::is_valid_accelerator(&self.hotkey_play_pause) {
+            error("hotkey_play_pause", "Invalid shortcut");
+        }
+        if self
+            .gamepad_mapping
+            .as_deref()
+            .is_some_and(|m| gamepad::parse_mapping(m).is_err())
+        {
+            error(
+                "gamepad_mapping",
+                "Use button=action pairs separated by commas",
+            );
+        }
+        if !is_valid_lang_list(&self.preferred_audio_lang) {
+            error(
+                "preferred_audio_lang",
+                "Use ISO 639 codes separated by commas",
+            );
+        }
+        if !is_valid_lang_list(&self.preferred_subtitle_lang) {
+            error(
+                "preferred_subtitle_lang",
+                "Use ISO 639 codes separated by commas",
+            );
+        }
+        errors
     }
-    if let Some(volume) = settings.volume {
-        map.insert(VOLUME.to_string(), volume.to_string());
+}
+
+fn is_valid_lang_list(langs: &Option<String>) -> bool {
+    match langs.as_deref().map(|l| l.trim()) {
+        None | Some("") => true,
+        Some(langs) => langs
+            .split(",")
+            .map(|lang| lang.trim())
+            .filter(|lang| !lang.is_empty())
+            .all(|lang| {
+                (2..=3).contains(&lang.len()) && lang.chars().all(|c| c.is_ascii_alphabetic())
+            }),
     }
-    if let Some(refresh_on_start) = settings.refresh_on_start {
-        map.insert(REFRESH_ON_START.to_string(), refresh_on_start.to_string());
+}
+
+pub fn get_lang_list(langs: &Option<String>) -> Option<String> {
+    langs
+        .as_deref()
+        .map(|l| {
+            l.split(",")
+                .map(|lang| lang.trim())
+                .filter(|lang| !lang.is_empty())
+                .collect::<Vec<&str>>()
+                .join(",")
+        })
+        .filter(|l| !l.is_empty())
+}
+
+fn get<T: FromStr>(map: &HashMap<String, String>, key: &str) -> Option<T> {
+    map.get(key).and_then(|s| s.parse().ok())
+}
+
+fn set<T: Display>(map: &mut HashMap<String, String>, key: &str, value: Option<T>) {
+    if let Some(value) = value {
+        map.insert(key.to_string(), value.to_string());
     }
-    if let Some(port) = settings.restream_port {
-        map.insert(RESTREAM_PORT.to_string(), port.to_string());
+}
+
+pub fn get_settings() -> Result<Settings> {
+    Ok(Settings::from_map(&sql::get_settings()?))
+}
+
+pub fn update_settings(settings: Settings) -> Result<()> {
+    let errors = settings.validate(&get_settings()?);
+    if !errors.is_empty() {
+        bail!(errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect::<Vec<String>>()
+            .join("\n"));
     }
-    sql::update_settings(map)?;
+    sql::update_settings(settings.to_map())?;
     Ok(())
 }
 
-pub fn get_default_record_path() -> Result<String> {
+pub fn apply_platform_defaults() -> Result<()> {
+    let map = sql::get_settings()?;
+    if map.contains_key(HWDEC) {
+        return Ok(());
+    }
+    let hwdec = match OS {
+        "macos" => "videotoolbox",
+        "windows" => "d3d11va",
+        _ => "auto-safe",
+    };
+    sql::update_settings(HashMap::from([(HWDEC.to_string(), hwdec.to_string())]))
+}
+
+pub fn get_page_size() -> Result<u8> {
+    Ok(get_settings()?.page_size.unwrap_or(DEFAULT_PAGE_SIZE))
+}
+
+pub fn get_default_record_path() -> Result<PathBuf> {
     let user_dirs = UserDirs::new().context("Failed to get user dirs")?;
     let mut path = user_dirs.video_dir().context("No videos dir")?.to_owned();
     path.push("open-tv");
     std::fs::create_dir_all(&path)?;
-    Ok(path.to_string_lossy().to_string())
+    Ok(path)
+}
+
+/// Paths pasted from a file manager often keep their quotes or a trailing separator
+fn normalize_path(path: &str) -> Option<PathBuf> {
+    let path = path.trim().trim_matches(['"', '\'']).trim();
+    let trimmed = path.trim_end_matches(['\\', '/']);
+    // roots like / or C:\ need their separator
+    let path = match trimmed.is_empty() || trimmed.ends_with(':') {
+        true => path,
+        false => trimmed,
+    };
+    if path.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod test_settings {
+    use std::collections::HashMap;
+
+    use crate::types::Settings;
+
+    use super::{
+        get_lang_list, normalize_path, HDHOMERUN_PLAYLIST_ID, MAX_VOLUME, RECORDING_PATH,
+        USE_STREAM_CACHING, VOLUME,
+    };
+
+    #[test]
+    fn test_settings_round_trip() {
+        let mut map: HashMap<String, String> = HashMap::new();
+        map.insert(VOLUME.to_string(), "200".to_string());
+        map.insert(USE_STREAM_CACHING.to_string(), "false".to_string());
+        let settings = Settings::from_map(&map);
+        assert_eq!(settings.volume, Some(MAX_VOLUME));
+        assert_eq!(settings.use_stream_caching, false);
+        assert!(settings
+            .validate(&Settings::from_map(&HashMap::new()))
+            .is_empty());
+        let map = settings.to_map();
+        assert_eq!(map.get(VOLUME), Some(&MAX_VOLUME.to_string()));
+        assert_eq!(map.get(USE_STREAM_CACHING), Some(&"false".to_string()));
+        assert_eq!(map.get(HDHOMERUN_PLAYLIST_ID), Some(&String::new()));
+        assert_eq!(Settings::from_map(&map).hdhomerun_playlist_id, None);
+    }
+
+    #[test]
+    fn test_lang_list() {
+        let mut settings = Settings::from_map(&HashMap::new());
+        settings.preferred_audio_lang = Some(" fre, eng ,".to_string());
+        settings.preferred_subtitle_lang = Some("english".to_string());
+        let errors = settings.validate(&Settings::from_map(&HashMap::new()));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "preferred_subtitle_lang");
+        assert_eq!(
+            get_lang_list(&settings.preferred_audio_lang),
+            Some("fre,eng".to_string())
+        );
+        assert_eq!(get_lang_list(&Some(" ".to_string())), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut settings = Settings::from_map(&HashMap::new());
+        settings.volume = Some(150);
+        settings.recording_path = Some("/this/path/does/not/exist".into());
+        let errors = settings.validate(&Settings::from_map(&HashMap::new()));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].field, "volume");
+        assert_eq!(errors[1].field, "recording_path");
+        let saved = settings.clone();
+        settings.volume = Some(100);
+        assert!(settings.validate(&saved).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(r#" "C:\Users\Zoë\Videos\" "#),
+            Some(r"C:\Users\Zoë\Videos".into())
+        );
+        assert_eq!(
+            normalize_path("/home/zoe/Videos/"),
+            Some("/home/zoe/Videos".into())
+        );
+        assert_eq!(normalize_path("'/'"), Some("/".into()));
+        assert_eq!(normalize_path(r"C:\"), Some(r"C:\".into()));
+        assert_eq!(normalize_path(" \"\" "), None);
+        let map = HashMap::from([(RECORDING_PATH.to_string(), "'/tmp/rec/'".to_string())]);
+        assert_eq!(
+            Settings::from_map(&map).recording_path,
+            Some("/tmp/rec".into())
+        );
+    }
 }
@@ -2,18 +2,22 @@ use crate::types::CustomChannel;
 use crate::types::ExportedGroup;
 use crate::types::ExportedSource;
 use crate::types::Group;
+use crate::types::ImportSummary;
 use crate::types::Source;
+use crate::types::{SourceList, SourceListEntry, SourceListImport};
 use crate::utils::sanitize;
-use crate::{sql, types::Channel};
+use crate::{enigma2, media_type, source_type, sql, types::Channel};
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use directories::UserDirs;
 use serde::Serialize;
+use std::path::Path;
 
 const CHANNEL_SHARE_EXTENSION: &str = ".otv";
 const GROUP_SHARE_EXTENSION: &str = ".otvg";
 const PLAYLIST_SHARE_EXTENSION: &str = ".otvp";
+const SOURCE_LIST_VERSION: u8 = 1;
 
 pub fn share_custom_channel(channel: Channel, filename: Option<String>) -> Result<()> {
     let channel = get_custom_channel(channel)?;
@@ -77,16 +81,34 @@ pub fn share_custom_source(mut source: Source) -> Result<()> {
     Ok(())
 }
 
-pub fn import(path: String, source_id: Option<i64>, name_override: Option<String>) -> Result<()> {
+pub fn import(
+    path: String,
+    source_id: Option<i64>,
+    name_override: Option<String>,
+) -> Result<ImportSummary> {
     let data = std::fs::read_to_string(&path)?;
+    let imported = |channels: usize| ImportSummary {
+        channels,
+        ..Default::default()
+    };
     match path
         .split(".")
         .last()
         .context("Invalid path, no extension")?
     {
-        "otv" => import_channel(data, source_id.context("No source id")?, name_override),
-        "otvg" => import_group(data, source_id.context("No source id")?, name_override),
-        "otvp" => import_playlist(data, name_override),
+        "otv" => import_channel(data, source_id.context("No source id")?, name_override)
+            .map(|_| imported(1)),
+        "otvg" => {
+            import_group(data, source_id.context("No source id")?, name_override).map(imported)
+        }
+        "otvp" => import_playlist(data, name_override).map(imported),
+        extension @ ("tv" | "radio") => import_bouquet(
+            data,
+            &path,
+            extension == "radio",
+            source_id.context("No source id")?,
+            name_override,
+        ),
         _ => Err(anyhow::anyhow!("Invalid path")),
     }
 }
@@ -108,7 +130,7 @@ fn import_channel(data: String, source_id: i64, name_override: Option<String>) -
     Ok(())
 }
 
-fn import_group(data: String, source_id: i64, name_override: Option<String>) -> Result<()> {
+fn import_group(data: String, source_id: i64, name_override: Option<String>) -> Result<usize> {
     let mut data: ExportedGroup = serde_json::from_str(&data)?;
     if let Some(name) = name_override {
         data.group.name = name;
@@ -116,6 +138,7 @@ fn import_group(data: String, source_id: i64, name_override: Option<String>) ->
     if sql::group_exists(&data.group.name, source_id)? {
         bail!("Duplicate exists");
     }
+    let count = data.channels.len();
     sql::do_tx(|tx| {
         data.group.source_id = Some(source_id);
         let group_id = sql::add_custom_group(&tx, data.group)?;
@@ -126,10 +149,10 @@ fn import_group(data: String, source_id: i64, name_override: Option<String>) ->
         }
         Ok(())
     })?;
-    Ok(())
+    Ok(count)
 }
 
-fn import_playlist(data: String, name_override: Option<String>) -> Result<()> {
+fn import_playlist(data: String, name_override: Option<String>) -> Result<usize> {
     let mut data: ExportedSource = serde_json::from_str(&data)?;
     if let Some(name) = name_override {
         data.source.name = name;
@@ -137,6 +160,12 @@ fn import_playlist(data: String, name_override: Option<String>) -> Result<()> {
     if sql::source_name_exists(&data.source.name)? {
         bail!("Duplicate exists");
     }
+    let count = data.channels.len()
+        + data
+            .groups
+            .iter()
+            .map(|group| group.channels.len())
+            .sum::<usize>();
     sql::do_tx(|tx| {
         let source_id = sql::create_or_find_source_by_name(tx, &data.source)?;
         for mut group in data.groups {
@@ -154,5 +183,210 @@ fn import_playlist(data: String, name_override: Option<String>) -> Result<()> {
         }
         Ok(())
     })?;
-    Ok(())
+    Ok(count)
+}
+
+/// Adds an Enigma2 bouquet to a custom source as a group of its own
+fn import_bouquet(
+    data: String,
+    path: &str,
+    radio: bool,
+    source_id: i64,
+    name_override: Option<String>,
+) -> Result<ImportSummary> {
+    let bouquet = enigma2::parse_bouquet(&data);
+    let name = name_override.or(bouquet.name).unwrap_or_else(|| {
+        let file_name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        enigma2::name_from_file_name(&file_name)
+    });
+    if sql::group_exists(&name, source_id)? {
+        bail!("Duplicate exists");
+    }
+    let mut dedup = sql::ImportDedup::default();
+    sql::do_tx(|tx| {
+        let group_id = sql::add_custom_group(
+            tx,
+            Group {
+                id: None,
+                name,
+                image: None,
+                source_id: Some(source_id),
+            },
+        )?;
+        for entry in bouquet.entries {
+            let channel = Channel {
+                id: None,
+                name: entry.name,
+                url: Some(entry.url),
+                group: None,
+                group_id: Some(group_id),
+                image: None,
+                media_type: match radio {
+                    true => media_type::RADIO,
+                    false => media_type::LIVESTREAM,
+                },
+                source_id: Some(source_id),
+                series_id: None,
+                favorite: false,
+                watched: false,
+                stream_id: None,
+                tvg_id: None,
+                media_types: None,
+                catchup: None,
+            };
+            if dedup.is_new(&channel) {
+                sql::add_custom_channel(
+                    tx,
+                    CustomChannel {
+                        data: channel,
+                        headers: None,
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    })?;
+    dedup.summary.skipped = bouquet.skipped;
+    Ok(dedup.summary)
+}
+
+/// Writes every source except custom ones, which only exist through their channels
+pub fn export_sources(path: String, include_credentials: bool) -> Result<()> {
+    let sources = sql::get_sources()?
+        .into_iter()
+        .filter(|source| source.source_type != source_type::CUSTOM)
+        .map(|source| -> Result<SourceListEntry> {
+            let group_filter = match source.id {
+                Some(id) => Some(sql::get_source_group_filter(id)?).filter(|f| !f.is_empty()),
+                None => None,
+            };
+            Ok(SourceListEntry {
+                name: source.name,
+                source_type: source.source_type,
+                url: source.url,
+                username: source.username.filter(|_| include_credentials),
+                password: source.password.filter(|_| include_credentials),
+                use_tvg_id: source.use_tvg_id,
+                import_live: source.import_live,
+                import_vod: source.import_vod,
+                import_series: source.import_series,
+                group_filter,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    serialize_to_file(
+        SourceList {
+            version: SOURCE_LIST_VERSION,
+            sources,
+        },
+        path,
+    )
+}
+
+/// Creates the sources of a list disabled, so nothing is downloaded until they
+/// are refreshed. Sources whose name is already taken are skipped
+pub fn import_sources(path: String) -> Result<SourceListImport> {
+    let list: SourceList = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    if list.version > SOURCE_LIST_VERSION {
+        bail!(
+            "This source list was made by a newer version of Open TV (version {}, this version supports {})",
+            list.version,
+            SOURCE_LIST_VERSION
+        );
+    }
+    let mut summary = SourceListImport::default();
+    let mut to_import = Vec::new();
+    for entry in list.sources {
+        let duplicate = to_import
+            .iter()
+            .any(|source: &Source| source.name == entry.name);
+        if entry.source_type == source_type::CUSTOM
+            || duplicate
+            || sql::source_name_exists(&entry.name)?
+        {
+            summary.skipped.push(entry.name);
+            continue;
+        }
+        summary.imported.push(entry.name.clone());
+        to_import.push(Source {
+            id: None,
+            name: entry.name,
+            url: entry.url,
+            url_origin: None,
+            username: entry.username,
+            password: entry.password,
+            source_type: entry.source_type,
+            use_tvg_id: entry.use_tvg_id,
+            enabled: false,
+            last_refresh_at: None,
+            last_refresh_status: None,
+            last_refresh_error: None,
+            channel_count: None,
+            import_live: entry.import_live,
+            import_vod: entry.import_vod,
+            import_series: entry.import_series,
+            group_filter: entry.group_filter,
+        });
+    }
+    sql::do_tx(|tx| {
+        for source in to_import.iter() {
+            sql::create_or_find_source_by_name(tx, source)?;
+        }
+        Ok(())
+    })?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test_share {
+    use super::{export_sources, import_sources};
+    use crate::{source_type, sql, test_fixtures, types::Source};
+
+    fn source(name: &str) -> Source {
+        Source {
+            url: Some(format!("https://{name}.local")),
+            username: Some("fred".to_string()),
+            import_vod: false,
+            group_filter: Some(vec!["News".to_string()]),
+            ..test_fixtures::source(name, source_type::XTREAM)
+        }
+    }
+
+    #[test]
+    fn test_source_list_round_trip() {
+        let _db = sql::init_test_db();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("sources.json")
+            .to_string_lossy()
+            .to_string();
+        sql::do_tx(|tx| {
+            sql::create_or_find_source_by_name(tx, &source("first"))?;
+            sql::create_or_find_source_by_name(tx, &source("second"))
+        })
+        .unwrap();
+        export_sources(path.clone(), false).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("fred"));
+
+        sql::delete_source(sql::get_sources().unwrap()[0].id.unwrap()).unwrap();
+        let summary = import_sources(path).unwrap();
+        assert_eq!(summary.imported, ["first"]);
+        assert_eq!(summary.skipped, ["second"]);
+        let imported = sql::get_sources()
+            .unwrap()
+            .into_iter()
+            .find(|source| source.name == "first")
+            .unwrap();
+        assert!(!imported.enabled);
+        assert!(!imported.import_vod);
+        assert_eq!(imported.username, None);
+        assert_eq!(
+            sql::get_source_group_filter(imported.id.unwrap()).unwrap(),
+            ["News"]
+        );
+    }
 }
@@ -0,0 +1,67 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Mutex;
+
+use crate::{
+    events, players, sql,
+    types::{AppState, PlayerProcess},
+};
+
+const WARNING_BEFORE: Duration = Duration::from_secs(60);
+
+/// Replaces any running timer and returns when it will fire as a unix timestamp.
+/// Setting it again is how the frontend extends it
+pub async fn set(
+    state: &Mutex<AppState>,
+    app: AppHandle,
+    minutes: u16,
+    stop_recording: bool,
+) -> Result<i64> {
+    if minutes == 0 {
+        bail!("The sleep timer needs at least one minute");
+    }
+    let duration = Duration::from_secs(minutes as u64 * 60);
+    let ends_at = Utc::now().timestamp() + duration.as_secs() as i64;
+    let process = state.lock().await.playback.player.clone();
+    let timer =
+        tauri::async_runtime::spawn(run(app, process.clone(), duration, ends_at, stop_recording));
+    if let Some(previous) = process.sleep_timer.lock().unwrap().replace(timer) {
+        previous.abort();
+    }
+    Ok(ends_at)
+}
+
+pub fn cancel(process: &PlayerProcess) {
+    if let Some(timer) = process.sleep_timer.lock().unwrap().take() {
+        timer.abort();
+    }
+}
+
+async fn run(
+    app: AppHandle,
+    process: Arc<PlayerProcess>,
+    duration: Duration,
+    ends_at: i64,
+    stop_recording: bool,
+) {
+    tokio::time::sleep(duration.saturating_sub(WARNING_BEFORE)).await;
+    let _ = app.emit(events::SLEEP_TIMER_WARNING, ends_at);
+    tokio::time::sleep(duration.min(WARNING_BEFORE)).await;
+    let _ = app.emit(events::SLEEP_TIMER_ENDED, ());
+    if !stop_recording && is_recording() {
+        tracing::info!("Sleep timer ended but a recording is running, leaving it alone");
+        return;
+    }
+    if let Err(e) = players::stop_playback(&process) {
+        tracing::info!("Sleep timer ended: {e}");
+    }
+}
+
+fn is_recording() -> bool {
+    sql::get_open_sessions()
+        .map(|sessions| sessions.iter().any(|session| session.record_path.is_some()))
+        .unwrap_or(false)
+}
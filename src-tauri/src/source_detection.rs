@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::Value;
+use url::Url;
+
+use crate::{proxy, source_type, types::Source};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(8);
+
+#[derive(Debug, PartialEq)]
+enum Guess {
+    Xtream,
+    M3uLink,
+    /// Xtream portals also serve their lists as playlists, only asking them tells
+    MaybeXtream,
+}
+
+/// Suggests the type and fields of a pasted url. Playlist urls of Xtream portals are
+/// confirmed with `player_api.php` first, `None` when the url says nothing either way
+pub async fn detect_source_type(url: &str) -> Result<Option<Source>> {
+    let url = url.trim();
+    let url = match url.contains("://") {
+        true => url.to_string(),
+        false => format!("http://{url}"),
+    };
+    let Ok(url) = Url::parse(&url) else {
+        return Ok(None);
+    };
+    if (url.scheme() != "http" && url.scheme() != "https") || url.host().is_none() {
+        return Ok(None);
+    }
+    let (username, password) = get_credentials(&url);
+    let source = match guess_source_type(&url) {
+        Some(Guess::Xtream) => xtream_source(&url, username, password)?,
+        Some(Guess::MaybeXtream) => match (username, password) {
+            (Some(username), Some(password))
+                if is_xtream_portal(&url, &username, &password).await =>
+            {
+                xtream_source(&url, Some(username), Some(password))?
+            }
+            _ => suggested_source(source_type::M3U_LINK, url.to_string(), None, None),
+        },
+        Some(Guess::M3uLink) => {
+            suggested_source(source_type::M3U_LINK, url.to_string(), None, None)
+        }
+        None => return Ok(None),
+    };
+    Ok(Some(source))
+}
+
+fn guess_source_type(url: &Url) -> Option<Guess> {
+    let path = url.path().to_lowercase();
+    if path.ends_with("/player_api.php") {
+        return Some(Guess::Xtream);
+    }
+    if path.ends_with("/get.php") {
+        return Some(Guess::MaybeXtream);
+    }
+    if path.ends_with(".m3u") || path.ends_with(".m3u8") {
+        return Some(Guess::M3uLink);
+    }
+    match get_credentials(url) {
+        (Some(_), Some(_)) => Some(Guess::MaybeXtream),
+        _ => None,
+    }
+}
+
+fn get_credentials(url: &Url) -> (Option<String>, Option<String>) {
+    let get = |name: &str| {
+        url.query_pairs()
+            .find(|(key, value)| key == name && !value.is_empty())
+            .map(|(_, value)| value.into_owned())
+    };
+    (get("username"), get("password"))
+}
+
+/// `player_api.php` next to the pasted url, without its query
+fn get_xtream_url(url: &Url) -> Result<Url> {
+    let mut url = url.join("player_api.php")?;
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url)
+}
+
+fn xtream_source(url: &Url, username: Option<String>, password: Option<String>) -> Result<Source> {
+    Ok(suggested_source(
+        source_type::XTREAM,
+        get_xtream_url(url)?.to_string(),
+        username,
+        password,
+    ))
+}
+
+/// Portals answer `player_api.php` with the account, even when it has expired
+async fn is_xtream_portal(url: &Url, username: &str, password: &str) -> bool {
+    let result = async {
+        let mut api = get_xtream_url(url)?;
+        api.query_pairs_mut()
+            .append_pair("username", username)
+            .append_pair("password", password);
+        let client = proxy::client_builder()?.timeout(PROBE_TIMEOUT).build()?;
+        let account: Value = client
+            .get(api)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        anyhow::Ok(account.get("user_info").is_some_and(Value::is_object))
+    }
+    .await;
+    result.unwrap_or_else(|e| {
+        tracing::debug!(
+            "{} is not an Xtream portal: {:?}",
+            url.origin().ascii_serialization(),
+            e
+        );
+        false
+    })
+}
+
+fn suggested_source(
+    source_type: u8,
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+) -> Source {
+    Source {
+        id: None,
+        name: String::new(),
+        url: Some(url),
+        url_origin: None,
+        username,
+        password,
+        source_type,
+        use_tvg_id: None,
+        enabled: true,
+        last_refresh_at: None,
+        last_refresh_status: None,
+        last_refresh_error: None,
+        channel_count: None,
+        import_live: true,
+        import_vod: true,
+        import_series: true,
+        group_filter: None,
+    }
+}
+
+#[cfg(test)]
+mod test_source_detection {
+    use url::Url;
+
+    use super::{get_credentials, get_xtream_url, guess_source_type, Guess};
+
+    #[test]
+    fn test_guess_source_type() {
+        let guess = |url: &str| guess_source_type(&Url::parse(url).unwrap());
+        assert_eq!(
+            guess("http://provider.tv:8080/player_api.php?username=fred&password=secret"),
+            Some(Guess::Xtream)
+        );
+        assert_eq!(
+            guess("http://provider.tv:8080/GET.php?username=fred&password=secret&type=m3u_plus"),
+            Some(Guess::MaybeXtream)
+        );
+        assert_eq!(
+            guess("http://provider.tv/list?username=fred&password=secret"),
+            Some(Guess::MaybeXtream)
+        );
+        assert_eq!(
+            guess("https://iptv-org.github.io/iptv/index.m3u?username=fred&password=secret"),
+            Some(Guess::M3uLink)
+        );
+        assert_eq!(guess("http://provider.tv/list?username=fred"), None);
+        assert_eq!(guess("http://provider.tv/"), None);
+    }
+
+    #[test]
+    fn test_get_xtream_url() {
+        let url = Url::parse(
+            "http://provider.tv:8080/portal/get.php?username=fred&password=p%40ss&type=m3u_plus",
+        )
+        .unwrap();
+        assert_eq!(
+            get_xtream_url(&url).unwrap().as_str(),
+            "http://provider.tv:8080/portal/player_api.php"
+        );
+        assert_eq!(
+            get_credentials(&url),
+            (Some("fred".to_string()), Some("p@ss".to_string()))
+        );
+    }
+}
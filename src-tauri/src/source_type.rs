@@ -2,3 +2,6 @@ pub const M3U: u8 = 0;
 pub const M3U_LINK: u8 = 1;
 pub const XTREAM: u8 = 2;
 pub const CUSTOM: u8 = 3;
+// 4 is taken by custom imports in the frontend
+pub const FOLDER: u8 = 5;
+pub const TVHEADEND: u8 = 6;
@@ -1,43 +1,196 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+    sync::{Mutex, MutexGuard, RwLock, TryLockError},
+    time::{Duration, Instant},
+};
 
-use crate::log::log;
 use crate::types::{
-    CustomChannel, CustomChannelExtraData, EPGNotify, ExportedGroup, Group, IdName,
+    AppliedFilterPreset, ChannelNote, CustomChannel, CustomChannelExtraData, DbInfo, DeleteImpact,
+    DuplicatedGroup, EPGNotify, EpgGridRow, EpgRefreshResult, EpgSource, ExportedGroup,
+    FilterPreset, Group, GroupItem, GroupMerge, GroupMergeRule, IdName, ImportSummary,
+    PendingImport, PlaybackSession, Playlist, Programme, ProgrammeSearchResult, RefreshLogEntry,
+    SearchPage, SeriesSummary, SourceStats, StreamProbe, TableCheck, Tag, WatchSession,
 };
 use crate::{
-    media_type, source_type,
-    types::{Channel, ChannelHttpHeaders, Filters, Source},
-    view_type,
+    health, media_type, refresh_status,
+    search_syntax::{self, SearchTerms},
+    secrets, source_type,
+    types::{Channel, ChannelHttpHeaders, Filters, ImportCheckpoint, Source},
+    utils::{channel_name_key, fold_search_name, like_literal},
+    view_type, xtream,
 };
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use directories::ProjectDirs;
 use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, params_from_iter, OptionalExtension, Row, Transaction};
+use rusqlite::{
+    params, params_from_iter, Connection, ErrorCode, OptionalExtension, Row, Transaction,
+    TransactionBehavior,
+};
 use rusqlite_migration::{Migrations, M};
 
-const PAGE_SIZE: u8 = 36;
-static CONN: LazyLock<Pool<SqliteConnectionManager>> = LazyLock::new(|| create_connection_pool());
+const DEFAULT_SNOOZE_MINUTES: u16 = 10;
+const REFRESH_LOG_SIZE: u32 = 20;
+const REMINDER_SEARCH_WINDOW: i64 = 12 * 3600;
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+/// How long a writer waits on another one before failing with "database is locked"
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+const POOL_TIMEOUT: Duration = Duration::from_secs(30);
+pub const INSERT_BATCH_SIZE: usize = 5000;
+/// Stays below the 999 bound parameters allowed by older SQLite builds
+const MAX_BOUND_IDS: usize = 900;
+pub const DELETED_ROWS_SINCE_VACUUM: &str = "deletedRowsSinceVacuum";
+pub const LAST_BACKUP_AT: &str = "lastBackupAt";
+pub const LAST_CHANNELS: &str = "lastChannels";
+pub const CONTROL_API_TOKEN: &str = "controlApiToken";
+static DB: RwLock<Option<Database>> = RwLock::new(None);
+/// Imports hold their transaction for the whole source, so concurrent refreshes
+/// download in parallel but take turns writing
+static IMPORT_LOCK: Mutex<()> = Mutex::new(());
+#[cfg(test)]
+thread_local! {
+    static TEST_DB: std::cell::RefCell<Option<Database>> = const { std::cell::RefCell::new(None) };
+}
+
+#[derive(Clone)]
+struct Database {
+    path: String,
+    pool: Pool<SqliteConnectionManager>,
+}
+
+/// Opens the connection pool, must be called once at startup before any query.
+/// A corrupt database is moved aside so the app starts over with an empty one,
+/// the path it was moved to is returned
+pub fn init_db(path: String) -> Result<Option<String>> {
+    let moved = match is_corrupt(&path)? {
+        true => Some(move_corrupt_database(&path)?),
+        false => None,
+    };
+    let mut db = DB.write().unwrap();
+    if db.is_some() {
+        bail!("Database already initialized");
+    }
+    *db = Some(open_database(path)?);
+    Ok(moved)
+}
+
+/// Copies the database to `path` and sends every following query there. Imports are
+/// held off during the copy, the old file is left untouched
+pub fn move_database(path: &str) -> Result<()> {
+    let _import = lock_import();
+    let sql = get_conn()?;
+    sql.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    sql.execute("VACUUM INTO ?", params![path])?;
+    let moved = open_database(path.to_string())?;
+    {
+        let copy = moved.pool.get()?;
+        let check: String = copy.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+        if check != "ok" || get_schema_version(&copy)? != get_schema_version(&sql)? {
+            bail!("The copy at {path} could not be verified");
+        }
+    }
+    #[cfg(test)]
+    if TEST_DB.with(|db| db.borrow().is_some()) {
+        TEST_DB.with(|db| *db.borrow_mut() = Some(moved));
+        return Ok(());
+    }
+    *DB.write().unwrap() = Some(moved);
+    Ok(())
+}
+
+fn is_corrupt(path: &str) -> Result<bool> {
+    if !Path::new(path).exists() {
+        return Ok(false);
+    }
+    let sql = Connection::open(path)?;
+    match sql.query_row("PRAGMA integrity_check", [], |row| row.get::<_, String>(0)) {
+        Ok(result) if result == "ok" => Ok(false),
+        Ok(result) => {
+            tracing::error!("Database integrity check failed: {result}");
+            Ok(true)
+        }
+        Err(rusqlite::Error::SqliteFailure(e, message))
+            if matches!(e.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) =>
+        {
+            tracing::error!("Database integrity check failed: {:?}", message);
+            Ok(true)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Keeps the corrupt file and its journals next to the new database, timestamped
+fn move_corrupt_database(path: &str) -> Result<String> {
+    let suffix = format!("corrupt-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    let moved = format!("{path}.{suffix}");
+    std::fs::rename(path, &moved)?;
+    for journal in ["-wal", "-shm"] {
+        let file = format!("{path}{journal}");
+        if Path::new(&file).exists() {
+            std::fs::rename(&file, format!("{moved}{journal}"))?;
+        }
+    }
+    Ok(moved)
+}
+
+fn get_db() -> Result<Database> {
+    #[cfg(test)]
+    if let Some(db) = TEST_DB.with(|db| db.borrow().clone()) {
+        return Ok(db);
+    }
+    DB.read()
+        .unwrap()
+        .clone()
+        .context("Database is not initialized")
+}
+
+pub fn get_db_path() -> Result<String> {
+    Ok(get_db()?.path)
+}
 
 pub fn get_conn() -> Result<PooledConnection<SqliteConnectionManager>> {
-    CONN.try_get().context("No sqlite conns available")
+    get_db()?.pool.get().context("No sqlite conns available")
+}
+
+fn open_database(path: String) -> Result<Database> {
+    let manager = SqliteConnectionManager::file(&path).with_init(|c| {
+        c.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        // WAL lets searches read while an import is writing
+        c.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        c.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(())
+    });
+    let pool = r2d2::Pool::builder()
+        .max_size(20)
+        .connection_timeout(POOL_TIMEOUT)
+        .build(manager)?;
+    Ok(Database { path, pool })
 }
 
-fn create_connection_pool() -> Pool<SqliteConnectionManager> {
-    let manager = SqliteConnectionManager::file(get_and_create_sqlite_db_path());
-    r2d2::Pool::builder().max_size(20).build(manager).unwrap()
+/// Points the current test thread at a fresh database in a temporary directory,
+/// which is deleted when the returned guard is dropped
+#[cfg(test)]
+pub fn init_test_db() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("db.sqlite").to_string_lossy().to_string();
+    let db = open_database(path).unwrap();
+    TEST_DB.with(|test_db| *test_db.borrow_mut() = Some(db));
+    create_or_initialize_db().unwrap();
+    dir
 }
 
-fn get_and_create_sqlite_db_path() -> String {
+pub fn get_and_create_sqlite_db_path() -> Result<String> {
     let mut path = ProjectDirs::from("dev", "fredol", "open-tv")
-        .unwrap()
+        .context("can't find project folder")?
         .data_dir()
         .to_owned();
     if !path.exists() {
-        std::fs::create_dir_all(&path).unwrap();
+        std::fs::create_dir_all(&path)?;
     }
     path.push("db.sqlite");
-    return path.to_string_lossy().to_string();
+    Ok(path.to_string_lossy().to_string())
 }
 
 fn create_structure() -> Result<()> {
@@ -116,21 +269,79 @@ fn structure_exists() -> Result<bool> {
 }
 
 pub fn delete_database() -> Result<()> {
-    std::fs::remove_file(get_and_create_sqlite_db_path())?;
+    let path = get_db()?.path;
+    std::fs::remove_file(&path)?;
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{path}{suffix}"));
+    }
     std::process::exit(0);
 }
 
+#[derive(Debug)]
+pub struct NewerDatabase {
+    pub found: usize,
+    pub expected: usize,
+}
+
+impl fmt::Display for NewerDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "This database was created by a newer version of Open TV (schema {}, this version supports {}). Please upgrade Open TV or restore a backup",
+            self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for NewerDatabase {}
+
 pub fn create_or_initialize_db() -> Result<()> {
-    if !structure_exists()? {
+    let existed = structure_exists()?;
+    if !existed {
         create_structure()?;
     }
-    apply_migrations()?;
+    apply_migrations(existed)?;
+    backfill_search_names()?;
+    migrate_plaintext_passwords()?;
+    template_xtream_urls()?;
     Ok(())
 }
 
-fn apply_migrations() -> Result<()> {
+fn get_schema_version(sql: &Connection) -> Result<usize> {
+    let version: usize = sql.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version)
+}
+
+pub fn get_expected_schema_version() -> usize {
+    get_migrations().len()
+}
+
+fn apply_migrations(existed: bool) -> Result<()> {
     let mut sql = get_conn()?;
-    let migrations = Migrations::new(vec![
+    let migrations = get_migrations();
+    let expected = migrations.len();
+    let found = get_schema_version(&sql)?;
+    if found > expected {
+        return Err(NewerDatabase { found, expected }.into());
+    }
+    if existed && found < expected {
+        backup_before_migration(&sql, found)?;
+    }
+    Migrations::new(migrations).to_latest(&mut sql)?;
+    Ok(())
+}
+
+fn backup_before_migration(sql: &Connection, version: usize) -> Result<()> {
+    let path = format!("{}.v{version}.bak", get_db()?.path);
+    if std::path::Path::new(&path).exists() {
+        std::fs::remove_file(&path)?;
+    }
+    sql.execute("VACUUM INTO ?", params![path])?;
+    Ok(())
+}
+
+fn get_migrations() -> Vec<M<'static>> {
+    vec![
         M::up(
             r#"
                 DROP INDEX IF EXISTS channels_unique;
@@ -163,1016 +374,7379 @@ fn apply_migrations() -> Result<()> {
                 CREATE UNIQUE INDEX IF NOT EXISTS index_epg_epg_id on epg(epg_id);
             "#,
         ),
-    ]);
-    migrations.to_latest(&mut sql)?;
-    Ok(())
-}
-
-pub fn drop_db() -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute_batch(
-        "DROP TABLE channels; DROP TABLE groups; DROP TABLE sources; DROP TABLE settings;",
-    )?;
-    Ok(())
-}
-
-pub fn create_or_find_source_by_name(tx: &Transaction, source: &Source) -> Result<i64> {
-    let id: Option<i64> = tx
-        .query_row(
-            "SELECT id FROM sources WHERE name = ?1",
-            params![source.name],
-            |r| r.get(0),
-        )
-        .optional()?;
-    if let Some(id) = id {
-        return Ok(id);
-    }
-    tx.execute(
-    "INSERT INTO sources (name, source_type, url, username, password, use_tvg_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-    params![source.name, source.source_type.clone() as u8, source.url, source.username, source.password, source.use_tvg_id],
-    )?;
-    Ok(tx.last_insert_rowid())
-}
-
-pub fn insert_channel(tx: &Transaction, channel: Channel) -> Result<()> {
-    tx.execute(
-        r#"
-INSERT INTO channels (name, group_id, image, url, source_id, media_type, series_id, favorite, stream_id)
-VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-ON CONFLICT (name, url, source_id)
-DO UPDATE SET
-    stream_id = excluded.stream_id,
-    image = excluded.image,
-    series_id = excluded.series_id;
-"#,
-        params![
-            channel.name,
-            channel.group_id,
-            channel.image,
-            channel.url,
-            channel.source_id,
-            channel.media_type as u8,
-            channel.series_id,
-            channel.favorite,
-            channel.stream_id
-        ],
-    )?;
-    Ok(())
-}
-
-pub fn insert_channel_headers(tx: &Transaction, headers: ChannelHttpHeaders) -> Result<()> {
-    tx.execute(
-        r#"
-INSERT OR IGNORE INTO channel_http_headers (channel_id, referrer, user_agent, http_origin, ignore_ssl)
-VALUES (?, ?, ?, ?, ?);
-"#,
-        params![
-            headers.channel_id,
-            headers.referrer,
-            headers.user_agent,
-            headers.http_origin,
-            headers.ignore_ssl
-        ],
-    )?;
-    Ok(())
-}
-
-fn get_or_insert_group(
-    tx: &Transaction,
-    group: &str,
-    image: &Option<String>,
-    source_id: &i64,
-) -> Result<i64> {
-    let rows_changed = tx.execute(
-        r#"
-        INSERT OR IGNORE INTO groups (name, image, source_id)
-        VALUES (?1, ?2, ?3);
-        "#,
-        params![group, &image, source_id],
-    )?;
-    if rows_changed == 0 {
-        return Ok(tx.query_row(
-            "SELECT id FROM groups WHERE name = ? and source_id = ?",
-            params![group, source_id],
-            |row| row.get::<_, i64>("id"),
-        )?);
-    }
-    Ok(tx.last_insert_rowid())
-}
-
-pub fn set_channel_group_id(
-    groups: &mut HashMap<String, i64>,
-    channel: &mut Channel,
-    tx: &Transaction,
-    source_id: &i64,
-) -> Result<()> {
-    if channel.group.is_none() {
-        return Ok(());
-    }
-    if !groups.contains_key(channel.group.as_ref().unwrap()) {
-        let id = get_or_insert_group(
-            tx,
-            channel.group.as_ref().unwrap(),
-            &channel.image,
-            source_id,
-        )?;
-        groups.insert(channel.group.clone().unwrap(), id);
-        channel.group_id = Some(id);
-    } else {
-        channel.group_id = groups
-            .get(channel.group.as_ref().unwrap())
-            .map(|x| x.to_owned());
-    }
-    Ok(())
-}
-
-pub fn get_channel_headers_by_id(id: i64) -> Result<Option<ChannelHttpHeaders>> {
-    let sql = get_conn()?;
-    let headers = sql
-        .query_row(
-            "SELECT * FROM channel_http_headers WHERE channel_id = ?",
-            params![id],
-            row_to_channel_headers,
-        )
-        .optional()?;
-    Ok(headers)
-}
-
-fn row_to_channel_headers(row: &Row) -> Result<ChannelHttpHeaders, rusqlite::Error> {
-    Ok(ChannelHttpHeaders {
-        id: row.get("id")?,
-        channel_id: row.get("channel_id")?,
-        http_origin: row.get("http_origin")?,
-        referrer: row.get("referrer")?,
-        user_agent: row.get("user_agent")?,
-        ignore_ssl: row.get("ignore_ssl")?,
-    })
-}
-
-pub fn get_settings() -> Result<HashMap<String, String>> {
-    let sql = get_conn()?;
-    let map = sql
-        .prepare("SELECT key, value FROM Settings")?
-        .query_map([], |row| {
-            let key: String = row.get(0)?;
-            let value: String = row.get(1)?;
-            Ok((key, value))
-        })?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(map)
-}
-
-pub fn update_settings(map: HashMap<String, String>) -> Result<()> {
-    let mut sql: PooledConnection<SqliteConnectionManager> = get_conn()?;
-    let tx = sql.transaction()?;
-    for (key, value) in map {
-        tx.execute(
+        M::up(
             r#"
-            INSERT INTO Settings (key, value)
-            VALUES (?1, ?2)
-            ON CONFLICT(key) DO UPDATE SET value = ?2
+                ALTER TABLE channels ADD COLUMN added_at integer;
+                CREATE INDEX IF NOT EXISTS index_channel_added_at ON channels(added_at);
             "#,
-            params![key, value],
-        )?;
-    }
-    tx.commit()?;
-    Ok(())
-}
-
-pub fn search(filters: Filters) -> Result<Vec<Channel>> {
-    if filters.view_type == view_type::CATEGORIES
-        && filters.group_id.is_none()
-        && filters.series_id.is_none()
-    {
-        return search_group(filters);
-    }
-    let sql = get_conn()?;
-    let offset: u16 = filters.page as u16 * PAGE_SIZE as u16 - PAGE_SIZE as u16;
-    let media_types = match filters.series_id.is_some() {
-        true => vec![1],
-        false => filters.media_types.clone().unwrap(),
-    };
-    let query = filters.query.unwrap_or("".to_string());
-    let keywords: Vec<String> = match filters.use_keywords {
-        true => query
-            .split(" ")
-            .map(|f| format!("%{f}%").to_string())
-            .collect(),
-        false => vec![format!("%{query}%")],
-    };
-    let mut sql_query = format!(
-        r#"
-        SELECT * FROM CHANNELS
-        WHERE ({})
-        AND media_type IN ({})
-		AND source_id IN ({})
-        AND url IS NOT NULL"#,
-        get_keywords_sql(keywords.len()),
-        generate_placeholders(media_types.len()),
-        generate_placeholders(filters.source_ids.len()),
-    );
-    let mut baked_params = 2;
-    if filters.view_type == view_type::FAVORITES && filters.series_id.is_none() {
-        sql_query += "\nAND favorite = 1";
-    }
-    if filters.series_id.is_some() {
-        sql_query += &format!("\nAND series_id = ?");
-        baked_params += 1;
-    } else if filters.group_id.is_some() {
-        sql_query += &format!("\nAND group_id = ?");
-        baked_params += 1;
-    }
-    sql_query += "\nLIMIT ?, ?";
-    let mut params: Vec<&dyn rusqlite::ToSql> = Vec::with_capacity(
-        baked_params + media_types.len() + filters.source_ids.len() + keywords.len(),
-    );
-    params.extend(to_to_sql(&keywords));
-    params.extend(to_to_sql(&media_types));
-    params.extend(to_to_sql(&filters.source_ids));
-    if let Some(ref series_id) = filters.series_id {
-        params.push(series_id);
-    } else if let Some(ref group) = filters.group_id {
-        params.push(group);
-    }
-    params.push(&offset);
-    params.push(&PAGE_SIZE);
-    let channels: Vec<Channel> = sql
-        .prepare(&sql_query)?
-        .query_map(params_from_iter(params), row_to_channel)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(channels)
-}
-
-fn to_to_sql<T: rusqlite::ToSql>(values: &[T]) -> Vec<&dyn rusqlite::ToSql> {
-    values.iter().map(|x| x as &dyn rusqlite::ToSql).collect()
-}
-
-fn get_keywords_sql(size: usize) -> String {
-    std::iter::repeat("name LIKE ?")
-        .take(size)
-        .collect::<Vec<_>>()
-        .join(" AND ")
-}
-
-fn generate_placeholders(size: usize) -> String {
-    std::iter::repeat("?")
-        .take(size)
-        .collect::<Vec<_>>()
-        .join(",")
-}
-
-pub fn series_has_episodes(series_id: u64, source_id: i64) -> Result<bool> {
-    let sql = get_conn()?;
-    let series_exists = sql
-        .query_row(
+        ),
+        M::up(
             r#"
-      SELECT 1
-      FROM channels
-      WHERE series_id = ? AND source_id = ?
-      LIMIT 1
-    "#,
-            params![series_id, source_id],
-            |row| row.get::<_, u8>(0),
-        )
-        .optional()?
-        .is_some();
-    Ok(series_exists)
+                ALTER TABLE groups ADD COLUMN favorite integer DEFAULT 0;
+                CREATE INDEX IF NOT EXISTS index_group_favorite ON groups(favorite);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN preferred_player integer;
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN last_check_status integer;
+                ALTER TABLE channels ADD COLUMN last_checked_at integer;
+                CREATE INDEX IF NOT EXISTS index_channel_last_check_status ON channels(last_check_status);
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE IF NOT EXISTS "stream_probe" (
+                  "channel_id" INTEGER PRIMARY KEY,
+                  "latency_ms" integer,
+                  "throughput_kbps" integer,
+                  "container" varchar(100),
+                  "variants" text,
+                  "probed_at" integer,
+                  FOREIGN KEY (channel_id) REFERENCES channels(id) ON DELETE CASCADE
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE IF NOT EXISTS "channel_volume" (
+                  "channel_id" INTEGER PRIMARY KEY,
+                  "volume" integer,
+                  FOREIGN KEY (channel_id) REFERENCES channels(id) ON DELETE CASCADE
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE IF NOT EXISTS "playback_sessions" (
+                  "id" INTEGER PRIMARY KEY,
+                  "channel_id" integer NOT NULL,
+                  "started_at" integer NOT NULL,
+                  "ended_at" integer,
+                  "record_path" text,
+                  "interrupted" integer DEFAULT 0
+                );
+                CREATE INDEX IF NOT EXISTS index_playback_sessions_ended_at ON playback_sessions(ended_at);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE epg ADD COLUMN channel_id integer;
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN tvg_id varchar(100);
+                CREATE INDEX IF NOT EXISTS index_channel_tvg_id ON channels(tvg_id);
+                ALTER TABLE epg ADD COLUMN tvg_id varchar(100);
+                ALTER TABLE epg ADD COLUMN source_id integer;
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE epg ADD COLUMN repeat integer;
+                ALTER TABLE epg ADD COLUMN snooze_minutes integer;
+                ALTER TABLE epg ADD COLUMN snoozed_until integer;
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN catchup integer;
+                CREATE INDEX IF NOT EXISTS index_playback_sessions_channel_id ON playback_sessions(channel_id);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN search_name varchar(100);
+                CREATE INDEX IF NOT EXISTS index_channel_search_name ON channels(search_name);
+                ALTER TABLE groups ADD COLUMN search_name varchar(100);
+                CREATE INDEX IF NOT EXISTS index_group_search_name ON groups(search_name);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE sources ADD COLUMN last_refresh_at integer;
+                ALTER TABLE sources ADD COLUMN last_refresh_status integer;
+                ALTER TABLE sources ADD COLUMN last_refresh_error text;
+                ALTER TABLE sources ADD COLUMN channel_count integer;
+                CREATE TABLE refresh_log (
+                    "id" INTEGER PRIMARY KEY,
+                    "source_id" integer NOT NULL,
+                    "timestamp" integer NOT NULL,
+                    "status" integer NOT NULL,
+                    "error" text,
+                    "channel_count" integer NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS index_refresh_log_source_id ON refresh_log(source_id);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE groups ADD COLUMN image_auto integer DEFAULT 1;
+                UPDATE groups SET image_auto = 1;
+                CREATE TABLE group_image_overrides (
+                    "source_id" integer NOT NULL,
+                    "name" varchar(100) NOT NULL,
+                    "image" varchar(500) NOT NULL,
+                    PRIMARY KEY (source_id, name)
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE channel_overrides (
+                    "source_id" integer NOT NULL,
+                    "channel_name" varchar(100) NOT NULL,
+                    "group_name" varchar(100) NOT NULL,
+                    PRIMARY KEY (source_id, channel_name)
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE playlists (
+                    "id" INTEGER PRIMARY KEY,
+                    "name" varchar(100) NOT NULL UNIQUE
+                );
+                CREATE TABLE playlist_channels (
+                    "id" INTEGER PRIMARY KEY,
+                    "playlist_id" integer NOT NULL,
+                    "channel_id" integer,
+                    "name" varchar(100) NOT NULL,
+                    "url" varchar(500),
+                    "source_id" integer NOT NULL,
+                    "position" integer NOT NULL,
+                    FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE
+                );
+                CREATE UNIQUE INDEX index_playlist_channels_unique ON playlist_channels(playlist_id, name, url, source_id);
+                CREATE INDEX index_playlist_channels_channel_id ON playlist_channels(channel_id);
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE filter_presets (
+                    "id" INTEGER PRIMARY KEY,
+                    "name" varchar(100) NOT NULL UNIQUE,
+                    "filters" text NOT NULL
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN position integer;
+                UPDATE channels SET position = id
+                WHERE source_id IN (SELECT id FROM sources WHERE source_type = 3);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE sources ADD COLUMN import_live integer NOT NULL DEFAULT 1;
+                ALTER TABLE sources ADD COLUMN import_vod integer NOT NULL DEFAULT 1;
+                ALTER TABLE sources ADD COLUMN import_series integer NOT NULL DEFAULT 1;
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE source_group_filters (
+                    "id" INTEGER PRIMARY KEY,
+                    "source_id" integer NOT NULL,
+                    "name" varchar(100) NOT NULL
+                );
+                CREATE UNIQUE INDEX index_source_group_filters_unique ON source_group_filters(source_id, name);
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE channel_media_type_overrides (
+                    "source_id" integer NOT NULL,
+                    "channel_name" varchar(100) NOT NULL,
+                    "media_type" integer NOT NULL,
+                    PRIMARY KEY (source_id, channel_name)
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN watched_at integer;
+                CREATE TABLE watched_channels (
+                    "source_id" integer NOT NULL,
+                    "url" varchar(500) NOT NULL,
+                    "watched_at" integer NOT NULL,
+                    PRIMARY KEY (source_id, url)
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE orphaned_favorites (
+                    "channel_id" integer PRIMARY KEY,
+                    "source_id" integer NOT NULL,
+                    "orphaned_at" integer NOT NULL
+                );
+                CREATE TABLE parked_favorites (
+                    id integer PRIMARY KEY,
+                    source_id integer,
+                    name varchar(100),
+                    url varchar(500),
+                    tvg_id varchar(100),
+                    group_name varchar(100)
+                );
+                CREATE INDEX index_parked_favorites_source_id ON parked_favorites(source_id);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE playback_sessions ADD COLUMN transient integer DEFAULT 0;
+                ALTER TABLE playback_sessions ADD COLUMN name text;
+                ALTER TABLE playback_sessions ADD COLUMN url text;
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE sources ADD COLUMN epg_url varchar(500);
+                CREATE TABLE epg_sources (
+                    "id" INTEGER PRIMARY KEY,
+                    "name" varchar(100) NOT NULL,
+                    "url" varchar(500) NOT NULL,
+                    "source_id" integer UNIQUE,
+                    "last_refresh_at" integer
+                );
+                CREATE TABLE programmes (
+                    "id" INTEGER PRIMARY KEY,
+                    "epg_source_id" integer NOT NULL,
+                    "tvg_id" varchar(100) NOT NULL,
+                    "title" text NOT NULL,
+                    "description" text,
+                    "start" integer NOT NULL,
+                    "stop" integer NOT NULL
+                );
+                CREATE INDEX index_programmes_tvg_id ON programmes(tvg_id, start);
+                CREATE INDEX index_programmes_epg_source_id ON programmes(epg_source_id);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE epg_sources ADD COLUMN timezone varchar(64);
+            "#,
+        ),
+        M::up(
+            r#"
+                UPDATE sources SET name = name || ' (' || id || ')'
+                WHERE EXISTS (
+                    SELECT 1 FROM sources s
+                    WHERE s.name = sources.name COLLATE NOCASE AND s.id < sources.id
+                );
+                DROP INDEX index_source_name;
+                CREATE UNIQUE INDEX index_source_name ON sources(name COLLATE NOCASE);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE groups ADD COLUMN media_types integer NOT NULL DEFAULT 0;
+                UPDATE groups SET media_types = COALESCE((
+                    SELECT SUM(DISTINCT 1 << media_type) FROM channels WHERE group_id = groups.id
+                ), 0);
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE fetched_series (
+                    "source_id" integer NOT NULL,
+                    "series_id" integer NOT NULL,
+                    PRIMARY KEY (source_id, series_id)
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE channel_notes (
+                    "channel_id" integer PRIMARY KEY,
+                    "note" text NOT NULL,
+                    "updated_at" integer NOT NULL
+                );
+                CREATE TABLE tags (
+                    "id" integer PRIMARY KEY,
+                    "name" varchar(100) NOT NULL
+                );
+                CREATE UNIQUE INDEX index_tag_name ON tags(name COLLATE NOCASE);
+                CREATE TABLE channel_tags (
+                    "channel_id" integer NOT NULL,
+                    "tag_id" integer NOT NULL,
+                    PRIMARY KEY (channel_id, tag_id)
+                );
+                CREATE INDEX index_channel_tags_tag_id ON channel_tags(tag_id);
+                ALTER TABLE parked_favorites ADD COLUMN favorite integer;
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE watch_sessions (
+                    "id" integer PRIMARY KEY,
+                    "channel_id" integer NOT NULL,
+                    "name" text NOT NULL,
+                    "media_type" integer,
+                    "started_at" integer NOT NULL,
+                    "ended_at" integer NOT NULL
+                );
+                CREATE INDEX index_watch_sessions_ended_at ON watch_sessions(ended_at);
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channels ADD COLUMN favorite_position integer;
+                ALTER TABLE parked_favorites ADD COLUMN favorite_position integer;
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE INDEX index_channel_source_media_type ON channels(source_id, media_type, group_id);
+                DROP INDEX IF EXISTS index_channel_source_id;
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE pending_imports (
+                    source_id integer PRIMARY KEY,
+                    started_at integer NOT NULL,
+                    new_source integer NOT NULL,
+                    checkpoint text
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE group_merge_rules (
+                    id integer PRIMARY KEY,
+                    source_id integer NOT NULL,
+                    pattern varchar(100) NOT NULL,
+                    target_source_id integer NOT NULL,
+                    target_name varchar(100) NOT NULL
+                );
+                CREATE UNIQUE INDEX index_group_merge_rules_pattern ON group_merge_rules(source_id, pattern);
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE staged_channels (
+                    id integer PRIMARY KEY,
+                    source_id integer NOT NULL,
+                    name varchar(100) NOT NULL,
+                    url varchar(500),
+                    channel text NOT NULL,
+                    headers text
+                );
+                CREATE INDEX index_staged_channels_source_id ON staged_channels(source_id);
+            "#,
+        ),
+        M::up(
+            r#"
+                CREATE TABLE channel_player_overrides (
+                    "source_id" integer NOT NULL,
+                    "channel_name" varchar(100) NOT NULL,
+                    "url" varchar(500) NOT NULL,
+                    "player" integer NOT NULL,
+                    PRIMARY KEY (source_id, channel_name, url)
+                );
+                INSERT OR IGNORE INTO channel_player_overrides (source_id, channel_name, url, player)
+                SELECT source_id, name, IFNULL(url, ''), preferred_player
+                FROM channels
+                WHERE preferred_player IS NOT NULL AND source_id IS NOT NULL;
+            "#,
+        ),
+        M::up(
+            r#"
+                DROP TABLE IF EXISTS stream_probe;
+                CREATE TABLE stream_probe (
+                    "source_id" integer NOT NULL,
+                    "url" varchar(500) NOT NULL,
+                    "latency_ms" integer,
+                    "throughput_kbps" integer,
+                    "container" varchar(100),
+                    "variants" text,
+                    "probed_at" integer,
+                    PRIMARY KEY (source_id, url)
+                );
+            "#,
+        ),
+        M::up(
+            r#"
+                ALTER TABLE channel_volume RENAME TO channel_volume_by_id;
+                CREATE TABLE channel_volume (
+                    "source_id" integer NOT NULL,
+                    "channel_name" varchar(100) NOT NULL,
+                    "url" varchar(500) NOT NULL,
+                    "volume" integer NOT NULL,
+                    PRIMARY KEY (source_id, channel_name, url)
+                );
+                INSERT OR IGNORE INTO channel_volume (source_id, channel_name, url, volume)
+                SELECT c.source_id, c.name, IFNULL(c.url, ''), v.volume
+                FROM channel_volume_by_id v
+                JOIN channels c ON c.id = v.channel_id
+                WHERE c.source_id IS NOT NULL AND v.volume IS NOT NULL;
+                DROP TABLE channel_volume_by_id;
+            "#,
+        ),
+    ]
+}
+
+pub fn get_db_info() -> Result<DbInfo> {
+    let path = get_db()?.path;
+    let sql = get_conn()?;
+    let mut row_counts = HashMap::new();
+    for table in [
+        "sources",
+        "channels",
+        "groups",
+        "channel_http_headers",
+        "epg",
+        "epg_sources",
+        "programmes",
+        "playback_sessions",
+        "settings",
+    ] {
+        let count: u64 = sql.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+            row.get(0)
+        })?;
+        row_counts.insert(table.to_string(), count);
+    }
+    Ok(DbInfo {
+        schema_version: get_schema_version(&sql)?,
+        expected_schema_version: get_expected_schema_version(),
+        file_size: std::fs::metadata(&path)?.len(),
+        path,
+        row_counts,
+        secret_storage: secrets::get_storage().to_string(),
+        last_backup_at: get_last_backup_at()?,
+    })
+}
+
+/// Moves passwords saved before secrets were stored outside the database
+fn migrate_plaintext_passwords() -> Result<()> {
+    let sql = get_conn()?;
+    let passwords: Vec<(i64, String)> = sql
+        .prepare("SELECT id, password FROM sources WHERE password IS NOT NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, password) in passwords {
+        if secrets::is_reference(&password) {
+            continue;
+        }
+        sql.execute(
+            "UPDATE sources SET password = ? WHERE id = ?",
+            params![secrets::store(id, &password)?, id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Xtream channels saved with full urls, credentials included, get the templates refreshes
+/// now store. Watched marks and playlists follow since they are keyed by url
+fn template_xtream_urls() -> Result<()> {
+    let mut sql = get_conn()?;
+    let rows: Vec<(i64, i64, String)> = sql
+        .prepare(
+            r#"
+            SELECT id, source_id, url FROM channels
+            WHERE source_id IN (SELECT id FROM sources WHERE source_type = ?)
+            AND url NOT LIKE 'xtream:%'
+        "#,
+        )?
+        .query_map(params![source_type::XTREAM], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let rows: Vec<(i64, i64, String, String)> = rows
+        .into_iter()
+        .filter_map(|(id, source_id, url)| {
+            let template = xtream::to_url_template(&url)?;
+            Some((id, source_id, url, template))
+        })
+        .collect();
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let tx = sql.transaction()?;
+    {
+        let mut channels = tx.prepare("UPDATE OR IGNORE channels SET url = ? WHERE id = ?")?;
+        let mut watched = tx.prepare(
+            "UPDATE OR IGNORE watched_channels SET url = ?1 WHERE source_id = ?2 AND url = ?3",
+        )?;
+        let mut playlists = tx.prepare(
+            "UPDATE OR IGNORE playlist_channels SET url = ?1 WHERE source_id = ?2 AND url = ?3",
+        )?;
+        let mut players = tx.prepare(
+            "UPDATE OR IGNORE channel_player_overrides SET url = ?1 WHERE source_id = ?2 AND url = ?3",
+        )?;
+        let mut volumes = tx.prepare(
+            "UPDATE OR IGNORE channel_volume SET url = ?1 WHERE source_id = ?2 AND url = ?3",
+        )?;
+        for (id, source_id, url, template) in rows {
+            channels.execute(params![template, id])?;
+            watched.execute(params![template, source_id, url])?;
+            playlists.execute(params![template, source_id, url])?;
+            players.execute(params![template, source_id, url])?;
+            volumes.execute(params![template, source_id, url])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Fills search_name for rows that predate the column
+fn backfill_search_names() -> Result<()> {
+    let mut sql = get_conn()?;
+    for table in ["channels", "groups"] {
+        let rows: Vec<(i64, String)> = sql
+            .prepare(&format!(
+                "SELECT id, name FROM {table} WHERE search_name IS NULL AND name IS NOT NULL"
+            ))?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        if rows.is_empty() {
+            continue;
+        }
+        let tx = sql.transaction()?;
+        {
+            let mut stmt =
+                tx.prepare(&format!("UPDATE {table} SET search_name = ? WHERE id = ?"))?;
+            for (id, name) in rows {
+                stmt.execute(params![fold_search_name(&name), id])?;
+            }
+        }
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+pub fn drop_db() -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute_batch(
+        "DROP TABLE channels; DROP TABLE groups; DROP TABLE sources; DROP TABLE settings;",
+    )?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct SourceAlreadyExists {
+    pub name: String,
+}
+
+impl fmt::Display for SourceAlreadyExists {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "A source named {} already exists", self.name)
+    }
+}
+
+impl std::error::Error for SourceAlreadyExists {}
+
+/// Creates a source the user asked for. Names are unique regardless of case
+pub fn create_source(tx: &Transaction, source: &Source) -> Result<i64> {
+    insert_source(tx, source)?.ok_or_else(|| {
+        SourceAlreadyExists {
+            name: source.name.clone(),
+        }
+        .into()
+    })
+}
+
+pub fn create_or_find_source_by_name(tx: &Transaction, source: &Source) -> Result<i64> {
+    if let Some(id) = insert_source(tx, source)? {
+        return Ok(id);
+    }
+    Ok(tx.query_row(
+        "SELECT id FROM sources WHERE name = ?1 COLLATE NOCASE",
+        params![source.name],
+        |r| r.get(0),
+    )?)
+}
+
+/// None when a source with the same name already exists
+fn insert_source(tx: &Transaction, source: &Source) -> Result<Option<i64>> {
+    let inserted = tx.execute(
+    "INSERT INTO sources (name, source_type, url, username, use_tvg_id, import_live, import_vod, import_series, enabled) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9) ON CONFLICT DO NOTHING",
+    params![source.name, source.source_type.clone() as u8, source.url, source.username, source.use_tvg_id, source.import_live, source.import_vod, source.import_series, source.enabled],
+    )?;
+    if inserted == 0 {
+        return Ok(None);
+    }
+    let id = tx.last_insert_rowid();
+    if let Some(groups) = &source.group_filter {
+        set_group_filter(tx, id, groups)?;
+    }
+    if let Some(password) = &source.password {
+        tx.execute(
+            "UPDATE sources SET password = ? WHERE id = ?",
+            params![secrets::store(id, password)?, id],
+        )?;
+    }
+    Ok(Some(id))
+}
+
+const INSERT_CHANNEL: &str = r#"
+INSERT INTO channels (name, group_id, image, url, source_id, media_type, series_id, favorite, stream_id, tvg_id, catchup, search_name, added_at)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, strftime('%s', 'now'))
+ON CONFLICT (name, url, source_id)
+DO UPDATE SET
+    search_name = excluded.search_name,
+    stream_id = excluded.stream_id,
+    image = excluded.image,
+    series_id = excluded.series_id,
+    tvg_id = excluded.tvg_id,
+    catchup = excluded.catchup
+RETURNING id;
+"#;
+
+pub fn insert_channel(tx: &Transaction, channel: Channel) -> Result<i64> {
+    let search_name = fold_search_name(&channel.name);
+    let id = tx.prepare_cached(INSERT_CHANNEL)?.query_row(
+        params_from_iter(channel_params(&channel, &search_name)),
+        |row| row.get::<_, i64>(0),
+    )?;
+    Ok(id)
+}
+
+/// Inserts channels with a single prepared statement and returns the row id of each
+/// channel, or None if that row failed
+#[tracing::instrument(level = "debug", skip_all, fields(count = channels.len()))]
+pub fn insert_channels_batch(tx: &Transaction, channels: &[Channel]) -> Result<Vec<Option<i64>>> {
+    let mut stmt = tx.prepare_cached(INSERT_CHANNEL)?;
+    let ids = channels
+        .iter()
+        .map(|channel| {
+            let search_name = fold_search_name(&channel.name);
+            stmt.query_row(
+                params_from_iter(channel_params(channel, &search_name)),
+                |row| row.get(0),
+            )
+            .map_err(|e| tracing::warn!("Failed to insert channel {}: {:?}", channel.name, e))
+            .ok()
+        })
+        .collect();
+    Ok(ids)
+}
+
+/// Collapses the rows of one import sharing a name and url, the unique index would
+/// silently ignore them and leave the counts off
+#[derive(Debug, Default)]
+pub struct ImportDedup {
+    seen: HashSet<(String, String)>,
+    pub summary: ImportSummary,
+}
+
+impl ImportDedup {
+    /// Knows the channels a resumed import already staged without counting them again
+    pub fn resume(tx: &Transaction, source_id: i64) -> Result<Self> {
+        let seen = tx
+            .prepare("SELECT name, COALESCE(url, '') FROM staged_channels WHERE source_id = ?")?
+            .query_map([source_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(ImportDedup {
+            seen,
+            summary: ImportSummary::default(),
+        })
+    }
+
+    pub fn is_new(&mut self, channel: &Channel) -> bool {
+        let key = (
+            channel.name.clone(),
+            channel.url.clone().unwrap_or_default(),
+        );
+        if self.seen.insert(key) {
+            self.summary.channels += 1;
+            true
+        } else {
+            self.summary.duplicates += 1;
+            false
+        }
+    }
+}
+
+fn channel_params<'a>(
+    channel: &'a Channel,
+    search_name: &'a String,
+) -> [&'a dyn rusqlite::ToSql; 12] {
+    [
+        &channel.name,
+        &channel.group_id,
+        &channel.image,
+        &channel.url,
+        &channel.source_id,
+        &channel.media_type,
+        &channel.series_id,
+        &channel.favorite,
+        &channel.stream_id,
+        &channel.tvg_id,
+        &channel.catchup,
+        search_name,
+    ]
+}
+
+/// Commits the channels staged so far along with their checkpoint and opens a new
+/// transaction on the same connection, the source itself is untouched until `swap_in_staged`
+pub fn commit_batch(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("COMMIT; BEGIN IMMEDIATE;")?;
+    Ok(())
+}
+
+/// Serializes the write phase of imports, hold it for as long as the import transaction.
+/// Waiting for it blocks the thread, async code only takes it inside [`spawn_blocking`]
+pub fn lock_import() -> MutexGuard<'static, ()> {
+    IMPORT_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The import lock when no import is writing, for work that would rather wait for a
+/// later turn than hold one up
+pub fn try_lock_import() -> Option<MutexGuard<'static, ()>> {
+    match IMPORT_LOCK.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Runs database work that may wait for the import lock on a blocking thread, tests
+/// carry their database along
+pub async fn spawn_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    #[cfg(test)]
+    let test_db = TEST_DB.with(|db| db.borrow().clone());
+    tokio::task::spawn_blocking(move || {
+        #[cfg(test)]
+        TEST_DB.with(|db| *db.borrow_mut() = test_db);
+        work()
+    })
+    .await?
+}
+
+/// Takes the write lock upfront, a deferred transaction can't upgrade once another
+/// writer committed after its first read and fails without waiting
+pub fn import_transaction(conn: &mut Connection) -> Result<Transaction<'_>> {
+    Ok(conn.transaction_with_behavior(TransactionBehavior::Immediate)?)
+}
+
+pub fn set_bulk_import(conn: &Connection, enabled: bool) -> Result<()> {
+    let temp_store = match enabled {
+        true => "MEMORY",
+        false => "DEFAULT",
+    };
+    conn.pragma_update(None, "temp_store", temp_store)?;
+    Ok(())
+}
+
+pub fn insert_channel_headers(tx: &Transaction, headers: ChannelHttpHeaders) -> Result<()> {
+    tx.prepare_cached(
+        r#"
+INSERT OR IGNORE INTO channel_http_headers (channel_id, referrer, user_agent, http_origin, ignore_ssl)
+VALUES (?, ?, ?, ?, ?);
+"#,
+    )?
+    .execute(params![
+            headers.channel_id,
+            headers.referrer,
+            headers.user_agent,
+            headers.http_origin,
+            headers.ignore_ssl
+        ])?;
+    Ok(())
+}
+
+fn get_or_insert_group(
+    tx: &Transaction,
+    group: &str,
+    image: &Option<String>,
+    source_id: &i64,
+) -> Result<i64> {
+    let rows_changed = tx.execute(
+        r#"
+        INSERT OR IGNORE INTO groups (name, image, source_id, search_name)
+        VALUES (?1, ?2, ?3, ?4);
+        "#,
+        params![group, &image, source_id, fold_search_name(group)],
+    )?;
+    if rows_changed == 0 {
+        return Ok(tx.query_row(
+            "SELECT id FROM groups WHERE name = ? and source_id = ?",
+            params![group, source_id],
+            |row| row.get::<_, i64>("id"),
+        )?);
+    }
+    Ok(tx.last_insert_rowid())
+}
+
+pub fn set_channel_group_id(
+    groups: &mut HashMap<String, i64>,
+    channel: &mut Channel,
+    tx: &Transaction,
+    source_id: &i64,
+) -> Result<()> {
+    if channel.group.is_none() {
+        return Ok(());
+    }
+    if !groups.contains_key(channel.group.as_ref().unwrap()) {
+        let id = get_or_insert_group(
+            tx,
+            channel.group.as_ref().unwrap(),
+            &channel.image,
+            source_id,
+        )?;
+        groups.insert(channel.group.clone().unwrap(), id);
+        channel.group_id = Some(id);
+    } else {
+        channel.group_id = groups
+            .get(channel.group.as_ref().unwrap())
+            .map(|x| x.to_owned());
+    }
+    Ok(())
+}
+
+/// Creates the source, or empties it for a refresh, and keeps the import pending until
+/// `finish_import` so one cut short by a crash is noticed at the next start
+pub fn begin_import(tx: &Transaction, source: &mut Source, wipe: bool) -> Result<i64> {
+    let source_id = match wipe {
+        true => {
+            let id = source.id.context("no source id")?;
+            self::wipe(tx, id)?;
+            id
+        }
+        false => {
+            let id = create_source(tx, source)?;
+            source.id = Some(id);
+            id
+        }
+    };
+    add_pending_import(tx, source_id, !wipe)?;
+    Ok(source_id)
+}
+
+/// Starts an import that stages its channels in batches, the source keeps its channels
+/// until `swap_in_staged` replaces them at the end. A new source is created right away, or
+/// reused when its first import is started over
+pub fn begin_staged_import(tx: &Transaction, source: &mut Source, wipe: bool) -> Result<i64> {
+    let source_id = match source.id {
+        Some(id) => id,
+        None => {
+            let id = create_source(tx, source)?;
+            source.id = Some(id);
+            id
+        }
+    };
+    tx.execute(
+        "DELETE FROM staged_channels WHERE source_id = ?",
+        params![source_id],
+    )?;
+    add_pending_import(tx, source_id, !wipe)?;
+    Ok(source_id)
+}
+
+fn add_pending_import(tx: &Transaction, source_id: i64, new_source: bool) -> Result<()> {
+    tx.execute(
+        r#"
+        INSERT INTO pending_imports (source_id, started_at, new_source)
+        VALUES (?1, strftime('%s', 'now'), ?2)
+        ON CONFLICT (source_id) DO UPDATE SET started_at = excluded.started_at, checkpoint = NULL
+    "#,
+        params![source_id, new_source],
+    )?;
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(count = channels.len()))]
+pub fn stage_channels(
+    tx: &Transaction,
+    source_id: i64,
+    channels: &[(Channel, Option<ChannelHttpHeaders>)],
+) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO staged_channels (source_id, name, url, channel, headers) VALUES (?, ?, ?, ?, ?)",
+    )?;
+    for (channel, headers) in channels {
+        stmt.execute(params![
+            source_id,
+            channel.name,
+            channel.url,
+            serde_json::to_string(channel)?,
+            headers.as_ref().map(serde_json::to_string).transpose()?
+        ])?;
+    }
+    Ok(())
+}
+
+/// Replaces the channels of the source with the staged ones, in the transaction that
+/// finishes the import so an interrupted one never leaves the source half emptied
+pub fn swap_in_staged(tx: &Transaction, source_id: i64, wipe: bool) -> Result<()> {
+    if wipe {
+        self::wipe(tx, source_id)?;
+    }
+    let staged: Vec<(String, Option<String>)> = tx
+        .prepare("SELECT channel, headers FROM staged_channels WHERE source_id = ? ORDER BY id")?
+        .query_map(params![source_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut groups = HashMap::new();
+    for batch in staged.chunks(INSERT_BATCH_SIZE) {
+        let mut channels = Vec::with_capacity(batch.len());
+        let mut headers = Vec::with_capacity(batch.len());
+        for (channel, channel_headers) in batch {
+            let mut channel: Channel = serde_json::from_str(channel)?;
+            set_channel_group_id(&mut groups, &mut channel, tx, &source_id).unwrap_or_else(|e| {
+                tracing::warn!(
+                    "Failed to set group id for channel: {}, Error: {:?}",
+                    channel.name,
+                    e
+                )
+            });
+            channels.push(channel);
+            headers.push(
+                channel_headers
+                    .as_deref()
+                    .map(serde_json::from_str::<ChannelHttpHeaders>)
+                    .transpose()?,
+            );
+        }
+        let ids = insert_channels_batch(tx, &channels)?;
+        for (id, headers) in ids.into_iter().zip(headers) {
+            if let (Some(id), Some(mut headers)) = (id, headers) {
+                headers.channel_id = Some(id);
+                insert_channel_headers(tx, headers)?;
+            }
+        }
+    }
+    tx.execute(
+        "DELETE FROM staged_channels WHERE source_id = ?",
+        params![source_id],
+    )?;
+    if wipe {
+        restore_added_at(tx, source_id)?;
+    }
+    Ok(())
+}
+
+/// Saved along with the batch it follows, a resumed import starts right after it
+pub fn set_import_checkpoint(
+    tx: &Transaction,
+    source_id: i64,
+    checkpoint: &ImportCheckpoint,
+) -> Result<()> {
+    tx.execute(
+        "UPDATE pending_imports SET checkpoint = ? WHERE source_id = ?",
+        params![serde_json::to_string(checkpoint)?, source_id],
+    )?;
+    Ok(())
+}
+
+pub fn get_pending_imports() -> Result<Vec<PendingImport>> {
+    let sql = get_conn()?;
+    let pending = sql
+        .prepare(
+            r#"
+            SELECT p.source_id, s.name, p.started_at, p.new_source, p.checkpoint
+            FROM pending_imports p
+            JOIN sources s ON s.id = p.source_id
+        "#,
+        )?
+        .query_map([], |row| {
+            Ok(PendingImport {
+                source_id: row.get(0)?,
+                name: row.get(1)?,
+                started_at: row.get(2)?,
+                new_source: row.get(3)?,
+                // an unreadable checkpoint only costs the resume, the import starts over
+                checkpoint: row
+                    .get::<_, Option<String>>(4)?
+                    .and_then(|checkpoint| serde_json::from_str(&checkpoint).ok()),
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(pending)
+}
+
+pub fn get_pending_import(source_id: i64) -> Result<Option<PendingImport>> {
+    Ok(get_pending_imports()?
+        .into_iter()
+        .find(|pending| pending.source_id == source_id))
+}
+
+/// Reapplies user changes that would otherwise be lost when a source is refreshed
+pub fn finish_import(tx: &Transaction, source_id: i64) -> Result<()> {
+    tx.execute(
+        "DELETE FROM pending_imports WHERE source_id = ?",
+        params![source_id],
+    )?;
+    apply_group_merge_rules(tx, source_id)?;
+    rematch_favorites(tx, source_id)?;
+    purge_skipped_media_types(tx, source_id)?;
+    purge_detached_notes_and_tags(tx)?;
+    apply_channel_overrides(tx, source_id)?;
+    apply_media_type_overrides(tx, source_id)?;
+    apply_player_overrides(tx, source_id)?;
+    tag_group_media_types(tx, source_id)?;
+    apply_watched(tx, source_id, None)?;
+    pick_group_images(tx, source_id)?;
+    relink_playlist_channels(tx, source_id)?;
+    Ok(())
+}
+
+/// Groups keep the media types of their channels so a mixed group opens with everything it holds
+fn tag_group_media_types(tx: &Transaction, source_id: i64) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE groups
+        SET media_types = COALESCE((
+            SELECT SUM(DISTINCT 1 << media_type) FROM channels WHERE group_id = groups.id
+        ), 0)
+        WHERE source_id = ?
+    "#,
+        params![source_id],
+    )?;
+    Ok(())
+}
+
+/// Same as `tag_group_media_types` for a single group, which may hold other sources' channels
+fn tag_group_media_types_by_id(tx: &Transaction, group_id: i64) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE groups
+        SET media_types = COALESCE((
+            SELECT SUM(DISTINCT 1 << media_type) FROM channels WHERE group_id = groups.id
+        ), 0)
+        WHERE id = ?
+    "#,
+        params![group_id],
+    )?;
+    Ok(())
+}
+
+/// Removes channels of media types the source no longer imports, favorites included since
+/// the user opted out of that whole media type
+fn purge_skipped_media_types(tx: &Transaction, source_id: i64) -> Result<()> {
+    let deleted = tx.execute(
+        r#"
+        DELETE FROM channels
+        WHERE source_id = ?1
+        AND media_type IN (
+            SELECT ?2 FROM sources WHERE id = ?1 AND import_live = 0
+            UNION SELECT ?3 FROM sources WHERE id = ?1 AND import_vod = 0
+            UNION SELECT ?4 FROM sources WHERE id = ?1 AND import_series = 0
+            UNION SELECT ?5 FROM sources WHERE id = ?1 AND import_live = 0
+        )
+    "#,
+        params![
+            source_id,
+            media_type::LIVESTREAM,
+            media_type::MOVIE,
+            media_type::SERIE,
+            media_type::RADIO
+        ],
+    )?;
+    if deleted > 0 {
+        track_deleted_rows(tx, deleted)?;
+        delete_empty_groups(tx, source_id)?;
+    }
+    Ok(())
+}
+
+/// Reapplies user-set group images, then picks a representative image for every other group
+fn pick_group_images(tx: &Transaction, source_id: i64) -> Result<()> {
+    let start = Instant::now();
+    tx.execute(
+        r#"
+        UPDATE groups
+        SET image = o.image, image_auto = 0
+        FROM group_image_overrides o
+        WHERE o.source_id = groups.source_id
+        AND o.name = groups.name
+        AND groups.source_id = ?
+    "#,
+        params![source_id],
+    )?;
+    let mut images = Vec::new();
+    {
+        let mut stmt = tx.prepare(
+            r#"
+            SELECT g.id, g.search_name, c.search_name, c.image
+            FROM groups g
+            JOIN channels c ON c.group_id = g.id
+            WHERE g.source_id = ?
+            AND g.image_auto = 1
+            AND c.image IS NOT NULL
+            ORDER BY g.id, c.id
+        "#,
+        )?;
+        let mut rows = stmt.query(params![source_id])?;
+        let mut current: Option<(i64, String)> = None;
+        let mut candidates: Vec<(String, String)> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let group_id: i64 = row.get(0)?;
+            if current.as_ref().is_some_and(|(id, _)| *id != group_id) {
+                let (id, name) = current.take().unwrap();
+                images.push((id, pick_group_image(&name, &candidates)));
+                candidates.clear();
+            }
+            if current.is_none() {
+                current = Some((
+                    group_id,
+                    row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                ));
+            }
+            candidates.push((
+                row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                row.get(3)?,
+            ));
+        }
+        if let Some((id, name)) = current {
+            images.push((id, pick_group_image(&name, &candidates)));
+        }
+    }
+    let mut stmt = tx.prepare("UPDATE groups SET image = ? WHERE id = ?")?;
+    for (id, image) in images.iter() {
+        stmt.execute(params![image, id])?;
+    }
+    tracing::debug!(
+        "Picked images for {} groups in {:?}",
+        images.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+/// Prefers a channel named after the group, otherwise a logo from the most common domain
+fn pick_group_image(group_name: &str, candidates: &[(String, String)]) -> Option<String> {
+    if !group_name.is_empty() {
+        if let Some((_, image)) = candidates
+            .iter()
+            .find(|(name, _)| name.contains(group_name))
+        {
+            return Some(image.clone());
+        }
+    }
+    let domains: Vec<Option<String>> = candidates
+        .iter()
+        .map(|(_, image)| {
+            url::Url::parse(image)
+                .ok()
+                .and_then(|url| url.host_str().map(|host| host.to_string()))
+        })
+        .collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for domain in domains.iter().flatten() {
+        *counts.entry(domain).or_default() += 1;
+    }
+    let best = counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .map(|(domain, _)| domain);
+    candidates
+        .iter()
+        .zip(domains.iter())
+        .find(|(_, domain)| domain.as_deref() == best)
+        .map(|((_, image), _)| image.clone())
+}
+
+pub fn set_group_image(group_id: i64, image: Option<String>) -> Result<()> {
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    let (name, source_id): (String, i64) = tx.query_row(
+        "SELECT name, source_id FROM groups WHERE id = ?",
+        params![group_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    match image {
+        Some(image) => {
+            tx.execute(
+                r#"
+                INSERT INTO group_image_overrides (source_id, name, image)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (source_id, name) DO UPDATE SET image = excluded.image
+            "#,
+                params![source_id, name, image],
+            )?;
+        }
+        None => {
+            tx.execute(
+                "DELETE FROM group_image_overrides WHERE source_id = ? AND name = ?",
+                params![source_id, name],
+            )?;
+            tx.execute(
+                "UPDATE groups SET image_auto = 1 WHERE id = ?",
+                params![group_id],
+            )?;
+        }
+    }
+    pick_group_images(&tx, source_id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Moves a channel to another group of its source, the move is reapplied after every refresh until cleared
+pub fn set_channel_group(channel_id: i64, group_id: Option<i64>) -> Result<()> {
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    let (name, source_id): (String, i64) = tx.query_row(
+        "SELECT name, source_id FROM channels WHERE id = ?",
+        params![channel_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    match group_id {
+        Some(group_id) => {
+            let (group_name, group_source_id): (String, i64) = tx
+                .query_row(
+                    "SELECT name, source_id FROM groups WHERE id = ?",
+                    params![group_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?
+                .context("Group does not exist")?;
+            if group_source_id != source_id {
+                bail!("Channels can only be moved to a group of the same source");
+            }
+            tx.execute(
+                r#"
+                INSERT INTO channel_overrides (source_id, channel_name, group_name)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (source_id, channel_name) DO UPDATE SET group_name = excluded.group_name
+            "#,
+                params![source_id, name, group_name],
+            )?;
+            tx.execute(
+                "UPDATE channels SET group_id = ? WHERE id = ?",
+                params![group_id, channel_id],
+            )?;
+        }
+        None => {
+            tx.execute(
+                "DELETE FROM channel_overrides WHERE source_id = ? AND channel_name = ?",
+                params![source_id, name],
+            )?;
+        }
+    }
+    tag_group_media_types(&tx, source_id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Moves the channels of `group_ids` into `target_id` and deletes the emptied groups. A rule
+/// keeps them merged when their source is refreshed, by `pattern` where `*` is any text, or
+/// by the name of each group. Groups of another source need `cross_source`, and a `dry_run`
+/// only counts what the merge would move
+pub fn merge_groups(
+    target_id: i64,
+    group_ids: Vec<i64>,
+    cross_source: bool,
+    dry_run: bool,
+    pattern: Option<String>,
+) -> Result<GroupMerge> {
+    let pattern = pattern
+        .map(|pattern| pattern.trim().to_string())
+        .filter(|pattern| !pattern.is_empty());
+    let _import = lock_import();
+    do_tx(|tx| merge_groups_tx(tx, target_id, group_ids, cross_source, dry_run, pattern))
+}
+
+fn merge_groups_tx(
+    tx: &Transaction,
+    target_id: i64,
+    group_ids: Vec<i64>,
+    cross_source: bool,
+    dry_run: bool,
+    pattern: Option<String>,
+) -> Result<GroupMerge> {
+    let (target_name, target_source_id): (String, i64) = tx
+        .query_row(
+            "SELECT name, source_id FROM groups WHERE id = ?",
+            params![target_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .context("The target category no longer exists")?;
+    let mut merged: Vec<(i64, String, i64)> = Vec::new();
+    for id in group_ids.into_iter().filter(|id| *id != target_id) {
+        let group: (i64, String, i64) = tx
+            .query_row(
+                "SELECT id, name, source_id FROM groups WHERE id = ?",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?
+            .context("A category to merge no longer exists")?;
+        if group.2 != target_source_id && !cross_source {
+            bail!("Categories of different sources can only be merged when allowed explicitly");
+        }
+        if let Some(pattern) = &pattern {
+            if !matches_merge_pattern(tx, pattern, &group.1)? {
+                bail!("{pattern} does not match the category {}", group.1);
+            }
+        }
+        if !merged.contains(&group) {
+            merged.push(group);
+        }
+    }
+    if merged.is_empty() {
+        bail!("Pick at least one category to merge into {target_name}");
+    }
+    let ids = serde_json::to_string(&merged.iter().map(|g| g.0).collect::<Vec<_>>())?;
+    let channels: u64 = tx.query_row(
+        "SELECT COUNT(*) FROM channels WHERE group_id IN (SELECT value FROM json_each(?))",
+        params![ids],
+        |row| row.get(0),
+    )?;
+    let current: u64 = tx.query_row(
+        "SELECT COUNT(*) FROM channels WHERE group_id = ?",
+        params![target_id],
+        |row| row.get(0),
+    )?;
+    let merge = GroupMerge {
+        channels,
+        total_channels: current + channels,
+        groups: merged.len() as u64,
+    };
+    if dry_run {
+        return Ok(merge);
+    }
+    tx.execute(
+        r#"
+        UPDATE groups
+        SET favorite = 1
+        WHERE id = ?1
+        AND EXISTS (SELECT 1 FROM groups g WHERE g.favorite = 1 AND g.id IN (SELECT value FROM json_each(?2)))
+    "#,
+        params![target_id, ids],
+    )?;
+    tx.execute(
+        "UPDATE channels SET group_id = ?1 WHERE group_id IN (SELECT value FROM json_each(?2))",
+        params![target_id, ids],
+    )?;
+    for (id, name, source_id) in merged {
+        tx.execute(
+            r#"
+            INSERT INTO group_merge_rules (source_id, pattern, target_source_id, target_name)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (source_id, pattern) DO UPDATE
+            SET target_source_id = excluded.target_source_id, target_name = excluded.target_name
+        "#,
+            params![
+                source_id,
+                pattern.as_deref().unwrap_or(&name),
+                target_source_id,
+                target_name
+            ],
+        )?;
+        // groups merged into this one earlier follow it
+        tx.execute(
+            "UPDATE group_merge_rules SET target_source_id = ?1, target_name = ?2 WHERE target_source_id = ?3 AND target_name = ?4",
+            params![target_source_id, target_name, source_id, name],
+        )?;
+        if source_id == target_source_id {
+            tx.execute(
+                "UPDATE channel_overrides SET group_name = ?1 WHERE source_id = ?2 AND group_name = ?3",
+                params![target_name, source_id, name],
+            )?;
+        }
+        tx.execute(
+            "DELETE FROM group_image_overrides WHERE source_id = ? AND name = ?",
+            params![source_id, name],
+        )?;
+        tx.execute("DELETE FROM groups WHERE id = ?", params![id])?;
+    }
+    tag_group_media_types_by_id(tx, target_id)?;
+    Ok(merge)
+}
+
+/// `*` is any text, everything else matches itself
+fn to_like_pattern(pattern: &str) -> String {
+    like_literal(pattern).replace('*', "%")
+}
+
+fn matches_merge_pattern(tx: &Transaction, pattern: &str, name: &str) -> Result<bool> {
+    Ok(tx.query_row(
+        r#"SELECT ?1 LIKE ?2 ESCAPE '\'"#,
+        params![name, to_like_pattern(pattern)],
+        |row| row.get(0),
+    )?)
+}
+
+/// The merge rules of a source, with those of other sources merging into its groups
+pub fn get_group_merge_rules(source_id: i64) -> Result<Vec<GroupMergeRule>> {
+    let sql = get_conn()?;
+    let rules = sql
+        .prepare(
+            r#"
+            SELECT id, source_id, pattern, target_source_id, target_name
+            FROM group_merge_rules
+            WHERE source_id = ?1 OR target_source_id = ?1
+            ORDER BY target_name, pattern
+        "#,
+        )?
+        .query_map(params![source_id], |row| {
+            Ok(GroupMergeRule {
+                id: row.get(0)?,
+                source_id: row.get(1)?,
+                pattern: row.get(2)?,
+                target_source_id: row.get(3)?,
+                target_name: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(rules)
+}
+
+/// Stops a merge from being reapplied, the split groups come back with the next refresh
+pub fn delete_group_merge_rule(id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute("DELETE FROM group_merge_rules WHERE id = ?", params![id])?;
+    Ok(())
+}
+
+/// Runs before the other reapplied changes so favorites are matched in the merged groups
+fn apply_group_merge_rules(tx: &Transaction, source_id: i64) -> Result<()> {
+    let rules: Vec<(String, i64, String)> = tx
+        .prepare(
+            "SELECT pattern, target_source_id, target_name FROM group_merge_rules WHERE source_id = ?",
+        )?
+        .query_map(params![source_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    for (pattern, target_source_id, target_name) in rules {
+        let merged: Vec<i64> = tx
+            .prepare(
+                r#"
+                SELECT id FROM groups
+                WHERE source_id = ?1
+                AND name LIKE ?2 ESCAPE '\'
+                AND NOT (source_id = ?3 AND name = ?4)
+            "#,
+            )?
+            .query_map(
+                params![
+                    source_id,
+                    to_like_pattern(&pattern),
+                    target_source_id,
+                    target_name
+                ],
+                |row| row.get(0),
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+        if merged.is_empty() {
+            continue;
+        }
+        let target_id = get_or_insert_group(tx, &target_name, &None, &target_source_id)?;
+        for id in merged {
+            tx.execute(
+                "UPDATE channels SET group_id = ? WHERE group_id = ?",
+                params![target_id, id],
+            )?;
+            tx.execute(
+                "UPDATE groups SET favorite = 1 WHERE id = ? AND (SELECT favorite FROM groups WHERE id = ?) = 1",
+                params![target_id, id],
+            )?;
+            tx.execute("DELETE FROM groups WHERE id = ?", params![id])?;
+        }
+        tag_group_media_types_by_id(tx, target_id)?;
+    }
+    Ok(())
+}
+
+fn apply_channel_overrides(tx: &Transaction, source_id: i64) -> Result<()> {
+    let overrides: Vec<(String, String)> = tx
+        .prepare("SELECT channel_name, group_name FROM channel_overrides WHERE source_id = ?")?
+        .query_map(params![source_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut groups: HashMap<String, i64> = HashMap::new();
+    for (channel_name, group_name) in overrides {
+        let group_id = match groups.get(&group_name) {
+            Some(id) => *id,
+            None => {
+                let id = get_or_insert_group(tx, &group_name, &None, &source_id)?;
+                groups.insert(group_name, id);
+                id
+            }
+        };
+        tx.execute(
+            "UPDATE channels SET group_id = ? WHERE source_id = ? AND name = ?",
+            params![group_id, source_id, channel_name],
+        )?;
+    }
+    Ok(())
+}
+
+/// Switches a live channel between radio and video, reapplied after every refresh until cleared
+pub fn set_channel_media_type(channel_id: i64, media_type: Option<u8>) -> Result<()> {
+    if media_type.is_some_and(|media_type| !media_type::is_live(media_type)) {
+        bail!("Only livestreams can be switched between radio and video");
+    }
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    let (name, source_id, current): (String, i64, u8) = tx.query_row(
+        "SELECT name, source_id, media_type FROM channels WHERE id = ?",
+        params![channel_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    if !media_type::is_live(current) {
+        bail!("Only livestreams can be switched between radio and video");
+    }
+    match media_type {
+        Some(media_type) => {
+            tx.execute(
+                r#"
+                INSERT INTO channel_media_type_overrides (source_id, channel_name, media_type)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (source_id, channel_name) DO UPDATE SET media_type = excluded.media_type
+            "#,
+                params![source_id, name, media_type],
+            )?;
+            tx.execute(
+                "UPDATE channels SET media_type = ? WHERE id = ?",
+                params![media_type, channel_id],
+            )?;
+        }
+        None => {
+            tx.execute(
+                "DELETE FROM channel_media_type_overrides WHERE source_id = ? AND channel_name = ?",
+                params![source_id, name],
+            )?;
+        }
+    }
+    tag_group_media_types(&tx, source_id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn apply_media_type_overrides(tx: &Transaction, source_id: i64) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE channels
+        SET media_type = o.media_type
+        FROM channel_media_type_overrides o
+        WHERE o.source_id = channels.source_id
+        AND o.channel_name = channels.name
+        AND channels.source_id = ?1
+        AND channels.media_type IN (?2, ?3)
+    "#,
+        params![source_id, media_type::LIVESTREAM, media_type::RADIO],
+    )?;
+    Ok(())
+}
+
+/// Watched marks are kept by url in their own table so they survive source refreshes
+/// and episodes being loaded again
+pub fn set_watched(channel_id: i64, watched: bool) -> Result<()> {
+    do_tx(|tx| mark_watched(tx, "id = ?1", &[&channel_id], watched))
+}
+
+/// Series ids come from the provider, so they only identify a series within its source
+pub fn set_series_watched(series_id: i64, source_id: i64, watched: bool) -> Result<()> {
+    do_tx(|tx| {
+        mark_watched(
+            tx,
+            "series_id = ?1 AND source_id = ?2",
+            &[&series_id, &source_id],
+            watched,
+        )
+    })
+}
+
+fn mark_watched(
+    tx: &Transaction,
+    condition: &str,
+    ids: &[&dyn rusqlite::ToSql],
+    watched: bool,
+) -> Result<()> {
+    if watched {
+        tx.execute(
+            &format!(
+                r#"
+                INSERT INTO watched_channels (source_id, url, watched_at)
+                SELECT source_id, url, strftime('%s', 'now')
+                FROM channels
+                WHERE {condition} AND url IS NOT NULL AND watched_at IS NULL
+                ON CONFLICT (source_id, url) DO UPDATE SET watched_at = excluded.watched_at
+            "#
+            ),
+            ids,
+        )?;
+        tx.execute(
+            &format!(
+                "UPDATE channels SET watched_at = strftime('%s', 'now') WHERE {condition} AND watched_at IS NULL"
+            ),
+            ids,
+        )?;
+    } else {
+        tx.execute(
+            &format!(
+                r#"
+                DELETE FROM watched_channels
+                WHERE (source_id, url) IN (SELECT source_id, url FROM channels WHERE {condition})
+            "#
+            ),
+            ids,
+        )?;
+        tx.execute(
+            &format!("UPDATE channels SET watched_at = NULL WHERE {condition}"),
+            ids,
+        )?;
+    }
+    Ok(())
+}
+
+/// Restores the watched marks of a source, or only of one of its series when given
+pub fn apply_watched(tx: &Transaction, source_id: i64, series_id: Option<u64>) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE channels
+        SET watched_at = w.watched_at
+        FROM watched_channels w
+        WHERE w.source_id = channels.source_id
+        AND w.url = channels.url
+        AND channels.source_id = ?1
+        AND (?2 IS NULL OR channels.series_id = ?2)
+    "#,
+        params![source_id, series_id],
+    )?;
+    Ok(())
+}
+
+pub fn create_playlist(name: String) -> Result<i64> {
+    let sql = get_conn()?;
+    sql.execute("INSERT INTO playlists (name) VALUES (?)", params![name])?;
+    Ok(sql.last_insert_rowid())
+}
+
+pub fn rename_playlist(id: i64, name: String) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        "UPDATE playlists SET name = ? WHERE id = ?",
+        params![name, id],
+    )?;
+    Ok(())
+}
+
+pub fn delete_playlist(id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        "DELETE FROM playlist_channels WHERE playlist_id = ?",
+        params![id],
+    )?;
+    sql.execute("DELETE FROM playlists WHERE id = ?", params![id])?;
+    Ok(())
+}
+
+pub fn get_playlists() -> Result<Vec<Playlist>> {
+    let sql = get_conn()?;
+    let playlists: Vec<Playlist> = sql
+        .prepare(
+            r#"
+        SELECT p.id, p.name, COUNT(pc.channel_id) AS channel_count
+        FROM playlists p
+        LEFT JOIN playlist_channels pc ON pc.playlist_id = p.id
+        GROUP BY p.id
+        ORDER BY p.name
+    "#,
+        )?
+        .query_map(
+            [],
+            logged("playlists", |row| {
+                Ok(Playlist {
+                    id: row.get("id")?,
+                    name: row.get("name")?,
+                    channel_count: row.get("channel_count")?,
+                })
+            }),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(playlists)
+}
+
+pub fn add_to_playlist(playlist_id: i64, channel_id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        INSERT OR IGNORE INTO playlist_channels (playlist_id, channel_id, name, url, source_id, position)
+        SELECT ?1, id, name, url, source_id,
+            (SELECT COALESCE(MAX(position), 0) + 1 FROM playlist_channels WHERE playlist_id = ?1)
+        FROM channels
+        WHERE id = ?2
+    "#,
+        params![playlist_id, channel_id],
+    )?;
+    Ok(())
+}
+
+pub fn remove_from_playlist(playlist_id: i64, channel_id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        "DELETE FROM playlist_channels WHERE playlist_id = ? AND channel_id = ?",
+        params![playlist_id, channel_id],
+    )?;
+    Ok(())
+}
+
+/// Members are ordered as in `channel_ids`, members missing from it are kept after them
+pub fn reorder_playlist(playlist_id: i64, channel_ids: Vec<i64>) -> Result<()> {
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    let offset = channel_ids.len() as i64;
+    tx.execute(
+        "UPDATE playlist_channels SET position = position + ? WHERE playlist_id = ?",
+        params![offset, playlist_id],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "UPDATE playlist_channels SET position = ? WHERE playlist_id = ? AND channel_id = ?",
+        )?;
+        for (position, channel_id) in channel_ids.iter().enumerate() {
+            stmt.execute(params![position as i64, playlist_id, channel_id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Channel ids change when a source is wiped, members are found again by name, url and source
+fn relink_playlist_channels(tx: &Transaction, source_id: i64) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE playlist_channels
+        SET channel_id = (
+            SELECT c.id
+            FROM channels c
+            WHERE c.name = playlist_channels.name
+            AND c.url IS playlist_channels.url
+            AND c.source_id = playlist_channels.source_id
+        )
+        WHERE source_id = ?
+    "#,
+        params![source_id],
+    )?;
+    Ok(())
+}
+
+/// Saves a preset, replacing the filters of an existing preset with the same name
+pub fn save_filter_preset(name: String, filters: Filters) -> Result<i64> {
+    let sql = get_conn()?;
+    let id = sql.query_row(
+        r#"
+        INSERT INTO filter_presets (name, filters)
+        VALUES (?, ?)
+        ON CONFLICT (name) DO UPDATE SET filters = excluded.filters
+        RETURNING id
+    "#,
+        params![name, serde_json::to_string(&filters)?],
+        |row| row.get(0),
+    )?;
+    Ok(id)
+}
+
+pub fn list_filter_presets() -> Result<Vec<FilterPreset>> {
+    let sql = get_conn()?;
+    let presets: Vec<(i64, String, String)> = sql
+        .prepare("SELECT id, name, filters FROM filter_presets ORDER BY name")?
+        .query_map(
+            [],
+            logged("filter_presets", |row| {
+                Ok((row.get("id")?, row.get("name")?, row.get("filters")?))
+            }),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    presets
+        .into_iter()
+        .map(|(id, name, filters)| {
+            Ok(FilterPreset {
+                id,
+                name,
+                filters: serde_json::from_str(&filters)?,
+            })
+        })
+        .collect()
+}
+
+/// Returns the filters of a preset without the sources that were deleted since it was saved
+pub fn apply_filter_preset(id: i64) -> Result<AppliedFilterPreset> {
+    let sql = get_conn()?;
+    let filters: String = sql.query_row(
+        "SELECT filters FROM filter_presets WHERE id = ?",
+        params![id],
+        |row| row.get(0),
+    )?;
+    let mut filters: Filters = serde_json::from_str(&filters)?;
+    let existing: Vec<i64> = sql
+        .prepare("SELECT id FROM sources")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let count = filters.source_ids.len();
+    filters.source_ids.retain(|id| existing.contains(id));
+    let missing_sources = filters.source_ids.len() != count;
+    if missing_sources {
+        tracing::warn!("Filter preset {id} references sources that no longer exist");
+    }
+    Ok(AppliedFilterPreset {
+        filters,
+        missing_sources,
+    })
+}
+
+pub fn delete_filter_preset(id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute("DELETE FROM filter_presets WHERE id = ?", params![id])?;
+    Ok(())
+}
+
+pub fn get_channel_headers_by_id(id: i64) -> Result<Option<ChannelHttpHeaders>> {
+    let sql = get_conn()?;
+    let headers = sql
+        .query_row(
+            "SELECT * FROM channel_http_headers WHERE channel_id = ?",
+            params![id],
+            row_to_channel_headers,
+        )
+        .optional()?;
+    Ok(headers)
+}
+
+pub fn get_last_watched(channel_id: i64) -> Result<Option<i64>> {
+    let sql = get_conn()?;
+    let last_watched = sql.query_row(
+        "SELECT MAX(started_at) FROM playback_sessions WHERE channel_id = ?",
+        params![channel_id],
+        |row| row.get::<_, Option<i64>>(0),
+    )?;
+    Ok(last_watched)
+}
+
+pub fn get_channel_player(id: i64) -> Result<Option<u8>> {
+    let sql = get_conn()?;
+    let player = sql
+        .query_row(
+            "SELECT preferred_player FROM channels WHERE id = ?",
+            params![id],
+            |row| row.get::<_, Option<u8>>(0),
+        )
+        .optional()?;
+    Ok(player.flatten())
+}
+
+/// Kept by name and url in `channel_player_overrides` and reapplied after every refresh
+pub fn set_channel_player(id: i64, player: Option<u8>) -> Result<()> {
+    do_tx(|tx| {
+        match player {
+            Some(player) => tx.execute(
+                r#"
+                INSERT INTO channel_player_overrides (source_id, channel_name, url, player)
+                SELECT source_id, name, IFNULL(url, ''), ?2 FROM channels WHERE id = ?1
+                ON CONFLICT (source_id, channel_name, url) DO UPDATE SET player = excluded.player
+            "#,
+                params![id, player],
+            )?,
+            None => tx.execute(
+                r#"
+                DELETE FROM channel_player_overrides
+                WHERE (source_id, channel_name, url) IN (
+                    SELECT source_id, name, IFNULL(url, '') FROM channels WHERE id = ?
+                )
+            "#,
+                params![id],
+            )?,
+        };
+        tx.execute(
+            "UPDATE channels SET preferred_player = ? WHERE id = ?",
+            params![player, id],
+        )?;
+        Ok(())
+    })
+}
+
+fn apply_player_overrides(tx: &Transaction, source_id: i64) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE channels
+        SET preferred_player = o.player
+        FROM channel_player_overrides o
+        WHERE o.source_id = channels.source_id
+        AND o.channel_name = channels.name
+        AND o.url = IFNULL(channels.url, '')
+        AND channels.source_id = ?1
+    "#,
+        params![source_id],
+    )?;
+    Ok(())
+}
+
+/// Volumes are kept by name and url so they follow the channel through refreshes
+pub fn get_channel_volume(channel_id: i64) -> Result<Option<u8>> {
+    let sql = get_conn()?;
+    let volume = sql
+        .query_row(
+            r#"
+            SELECT v.volume
+            FROM channel_volume v
+            JOIN channels c
+            ON c.source_id = v.source_id AND c.name = v.channel_name AND IFNULL(c.url, '') = v.url
+            WHERE c.id = ?
+        "#,
+            params![channel_id],
+            |row| row.get::<_, u8>(0),
+        )
+        .optional()?;
+    Ok(volume)
+}
+
+pub fn set_channel_volume(channel_id: i64, volume: u8) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        INSERT OR REPLACE INTO channel_volume (source_id, channel_name, url, volume)
+        SELECT source_id, name, IFNULL(url, ''), ?2 FROM channels WHERE id = ?1
+    "#,
+        params![channel_id, volume],
+    )?;
+    Ok(())
+}
+
+pub fn reset_channel_volumes() -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute("DELETE FROM channel_volume", [])?;
+    Ok(())
+}
+
+/// `transient` is the channel of a play that isn't in the database, kept so the
+/// history can still show what was watched
+pub fn start_session(
+    channel_id: i64,
+    record_path: Option<&str>,
+    transient: Option<&Channel>,
+) -> Result<i64> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        INSERT INTO playback_sessions (channel_id, started_at, record_path, transient, name, url)
+        VALUES (?, strftime('%s', 'now'), ?, ?, ?, ?)
+        "#,
+        params![
+            channel_id,
+            record_path,
+            transient.is_some(),
+            transient.map(|channel| &channel.name),
+            transient.and_then(|channel| channel.url.as_ref()),
+        ],
+    )?;
+    Ok(sql.last_insert_rowid())
+}
+
+pub fn end_session(id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        "UPDATE playback_sessions SET ended_at = strftime('%s', 'now') WHERE id = ?",
+        params![id],
+    )?;
+    Ok(())
+}
+
+pub fn end_open_sessions(interrupted: bool) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        UPDATE playback_sessions
+        SET ended_at = strftime('%s', 'now'), interrupted = ?
+        WHERE ended_at IS NULL
+        "#,
+        params![interrupted],
+    )?;
+    Ok(())
+}
+
+/// Keeps the name and media type along with the session so the statistics outlive the
+/// channel. Sessions shorter than `min_seconds` are left out
+pub fn record_watch_session(session_id: i64, min_seconds: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        INSERT INTO watch_sessions (channel_id, name, media_type, started_at, ended_at)
+        SELECT s.channel_id, COALESCE(c.name, s.name), c.media_type, s.started_at, s.ended_at
+        FROM playback_sessions s
+        LEFT JOIN channels c ON c.id = s.channel_id AND COALESCE(s.transient, 0) = 0
+        WHERE s.id = ?
+        AND s.ended_at - s.started_at >= ?
+        AND COALESCE(c.name, s.name) IS NOT NULL
+        "#,
+        params![session_id, min_seconds],
+    )?;
+    Ok(())
+}
+
+/// Sessions overlapping the window, oldest first
+pub fn get_watch_sessions(from: i64, to: i64) -> Result<Vec<WatchSession>> {
+    let sql = get_conn()?;
+    let sessions = sql
+        .prepare(
+            r#"
+            SELECT channel_id, name, media_type, started_at, ended_at
+            FROM watch_sessions
+            WHERE ended_at > ? AND started_at < ?
+            ORDER BY started_at
+            "#,
+        )?
+        .query_map(params![from, to], |row| {
+            Ok(WatchSession {
+                channel_id: row.get(0)?,
+                name: row.get(1)?,
+                media_type: row.get(2)?,
+                started_at: row.get(3)?,
+                ended_at: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(sessions)
+}
+
+pub fn clear_watch_sessions() -> Result<()> {
+    let sql = get_conn()?;
+    let deleted = sql.execute("DELETE FROM watch_sessions", [])?;
+    track_deleted_rows(&sql, deleted)?;
+    Ok(())
+}
+
+pub fn get_open_sessions() -> Result<Vec<PlaybackSession>> {
+    let sql = get_conn()?;
+    let sessions: Vec<PlaybackSession> = sql
+        .prepare("SELECT * FROM playback_sessions WHERE ended_at IS NULL")?
+        .query_map(
+            [],
+            logged("playback_sessions", |row| {
+                Ok(PlaybackSession {
+                    id: row.get("id")?,
+                    channel_id: row.get("channel_id")?,
+                    started_at: row.get("started_at")?,
+                    record_path: row.get("record_path")?,
+                })
+            }),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(sessions)
+}
+
+fn row_to_channel_headers(row: &Row) -> Result<ChannelHttpHeaders, rusqlite::Error> {
+    Ok(ChannelHttpHeaders {
+        id: row.get("id")?,
+        channel_id: row.get("channel_id")?,
+        http_origin: row.get("http_origin")?,
+        referrer: row.get("referrer")?,
+        user_agent: row.get("user_agent")?,
+        ignore_ssl: row.get("ignore_ssl")?,
+    })
+}
+
+pub fn get_settings() -> Result<HashMap<String, String>> {
+    let sql = get_conn()?;
+    let map: HashMap<String, String> = sql
+        .prepare("SELECT key, value FROM Settings")?
+        .query_map([], |row| {
+            let key: String = row.get(0)?;
+            let value: String = row.get(1)?;
+            Ok((key, value))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(map)
+}
+
+pub fn update_settings(map: HashMap<String, String>) -> Result<()> {
+    let mut sql: PooledConnection<SqliteConnectionManager> = get_conn()?;
+    let tx = sql.transaction()?;
+    for (key, value) in map {
+        tx.execute(
+            r#"
+            INSERT INTO Settings (key, value)
+            VALUES (?1, ?2)
+            ON CONFLICT(key) DO UPDATE SET value = ?2
+            "#,
+            params![key, value],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn is_group_search(filters: &Filters) -> bool {
+    let favorite_groups = filters.view_type == view_type::FAVORITES
+        && filters
+            .media_types
+            .as_ref()
+            .is_some_and(|types| types.contains(&media_type::GROUP));
+    (filters.view_type == view_type::CATEGORIES || favorite_groups)
+        && filters.group_id.is_none()
+        && filters.group_ids.as_ref().map_or(true, Vec::is_empty)
+        && filters.series_id.is_none()
+}
+
+pub fn search(filters: Filters) -> Result<Vec<Channel>> {
+    Ok(search_page(filters)?.channels)
+}
+
+/// Searches channels and returns the cursor of the next page when the listing is keyset paginated
+#[tracing::instrument(level = "debug", skip_all, fields(view_type = filters.view_type, page = filters.page))]
+pub fn search_page(mut filters: Filters) -> Result<SearchPage> {
+    retain_enabled_sources(&mut filters)?;
+    if is_group_search(&filters) {
+        return Ok(SearchPage {
+            channels: search_group(filters)?,
+            after_id: None,
+            after_name: None,
+        });
+    }
+    if filters.media_types.is_none() {
+        filters.media_types = Some(get_default_media_types(&filters)?);
+    }
+    let sql = get_conn()?;
+    let page_size = crate::settings::get_page_size()?;
+    let custom_order = is_custom_listing(&sql, &filters)?;
+    let channels = search_channels(
+        &sql,
+        &filters,
+        page_size,
+        get_new_channels_cutoff()?,
+        custom_order,
+    )?;
+    tracing::debug!("search returned {} channels", channels.len());
+    let cursor = channels
+        .last()
+        .filter(|_| uses_keyset(&filters, custom_order) && channels.len() == page_size as usize)
+        .map(|last| (last.id, last.name.clone()));
+    Ok(SearchPage {
+        after_id: cursor.as_ref().and_then(|(id, _)| *id),
+        after_name: cursor.map(|(_, name)| name),
+        channels,
+    })
+}
+
+/// Disabled sources stay hidden even when the frontend still has them selected
+fn retain_enabled_sources(filters: &mut Filters) -> Result<()> {
+    if filters.source_ids.is_empty() {
+        return Ok(());
+    }
+    let enabled: HashSet<i64> = get_conn()?
+        .prepare_cached("SELECT id FROM sources WHERE enabled = 1")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    filters.source_ids.retain(|id| enabled.contains(id));
+    Ok(())
+}
+
+/// Without a media filter a group shows the types it holds, anything else shows every type
+fn get_default_media_types(filters: &Filters) -> Result<Vec<u8>> {
+    let group_ids = get_group_ids(filters);
+    if group_ids.is_empty() {
+        return Ok(media_type::CHANNEL_TYPES.to_vec());
+    }
+    let masks: Vec<u8> = get_conn()?
+        .prepare_cached(
+            "SELECT media_types FROM groups WHERE id IN (SELECT value FROM json_each(?))",
+        )?
+        .query_map(params![serde_json::to_string(&group_ids)?], |row| {
+            row.get(0)
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let media_types = media_type::from_mask(masks.into_iter().fold(0, |all, mask| all | mask));
+    Ok(match media_types.is_empty() {
+        true => media_type::CHANNEL_TYPES.to_vec(),
+        false => media_types,
+    })
+}
+
+/// Listings with their own order (new, favorites, playlists, custom channels, episodes) keep page numbers,
+/// everything else is ordered by (name, id) so a cursor stays stable while rows get inserted
+fn uses_keyset(filters: &Filters, custom_order: bool) -> bool {
+    filters.series_id.is_none()
+        && filters.view_type != view_type::NEW
+        && filters.view_type != view_type::FAVORITES
+        && filters.view_type != view_type::PLAYLIST
+        && !custom_order
+}
+
+/// Custom channels keep their user-defined order unless a text query ranks them
+fn is_custom_listing(sql: &Connection, filters: &Filters) -> Result<bool> {
+    if filters
+        .query
+        .as_deref()
+        .is_some_and(|q| !q.trim().is_empty())
+        || filters.source_ids.is_empty()
+    {
+        return Ok(false);
+    }
+    let non_custom: u64 = sql.query_row(
+        r#"
+        SELECT COUNT(*) FROM sources
+        WHERE id IN (SELECT value FROM json_each(?))
+        AND source_type != ?
+    "#,
+        params![
+            serde_json::to_string(&filters.source_ids)?,
+            source_type::CUSTOM
+        ],
+        |row| row.get(0),
+    )?;
+    Ok(non_custom == 0)
+}
+
+fn search_channels(
+    sql: &Connection,
+    filters: &Filters,
+    page_size: u8,
+    added_since: i64,
+    custom_order: bool,
+) -> Result<Vec<Channel>> {
+    let offset: u32 = match get_search_cursor(filters, custom_order) {
+        Some(_) => 0,
+        None => filters.page as u32 * page_size as u32 - page_size as u32,
+    };
+    let media_types = match filters.series_id.is_some() {
+        true => vec![media_type::MOVIE],
+        false => filters.media_types.clone().unwrap(),
+    };
+    let conditions = ChannelConditions::new(filters, &media_types, added_since)?;
+    let (sql_query, params) =
+        get_channel_search_query(filters, &conditions, custom_order, &offset, &page_size);
+    let mut stmt = sql.prepare_cached(&sql_query)?;
+    let channels: Vec<Channel> = match filters.include_group_names {
+        true => stmt
+            .query_map(
+                params_from_iter(params),
+                logged("channels", row_to_channel_with_group),
+            )?
+            .collect::<rusqlite::Result<_>>()?,
+        false => stmt
+            .query_map(params_from_iter(params), logged("channels", row_to_channel))?
+            .collect::<rusqlite::Result<_>>()?,
+    };
+    Ok(channels)
+}
+
+fn get_search_cursor(filters: &Filters, custom_order: bool) -> Option<(&String, &i64)> {
+    match (&filters.after_name, &filters.after_id) {
+        (Some(name), Some(id)) if uses_keyset(filters, custom_order) => Some((name, id)),
+        _ => None,
+    }
+}
+
+fn get_channel_search_query<'a>(
+    filters: &'a Filters,
+    conditions: &'a ChannelConditions,
+    custom_order: bool,
+    offset: &'a u32,
+    page_size: &'a u8,
+) -> (String, Vec<&'a dyn rusqlite::ToSql>) {
+    let (where_clause, mut params) = conditions.to_sql();
+    // A correlated lookup rather than a join keeps the unqualified columns of the conditions unambiguous
+    let columns = match filters.include_group_names {
+        true => "*, (SELECT name FROM groups WHERE groups.id = channels.group_id) AS group_name",
+        false => "*",
+    };
+    let mut sql_query = format!("SELECT {columns} FROM channels\nWHERE {where_clause}");
+    if let Some((name, id)) = get_search_cursor(filters, custom_order) {
+        sql_query += "\nAND (name, id) > (?, ?)";
+        params.push(name);
+        params.push(id);
+    }
+    if filters.view_type == view_type::NEW && filters.series_id.is_none() {
+        sql_query += "\nORDER BY added_at DESC";
+    } else if let Some(ref playlist_id) = conditions.playlist_id {
+        sql_query += "\nORDER BY (SELECT position FROM playlist_channels WHERE playlist_id = ? AND channel_id = channels.id)";
+        params.push(playlist_id);
+    } else if conditions.favorites {
+        sql_query += "\nORDER BY favorite_position IS NULL, favorite_position, name, id";
+    } else if custom_order {
+        sql_query += "\nORDER BY position, id";
+    } else if uses_keyset(filters, custom_order) {
+        sql_query += "\nORDER BY name, id";
+    }
+    sql_query += "\nLIMIT ?, ?";
+    params.push(offset);
+    params.push(page_size);
+    (sql_query, params)
+}
+
+/// Values bound by the WHERE clause shared by channel searches and counts, so a tab
+/// count always agrees with what the tab lists
+struct ChannelConditions {
+    keywords: Option<String>,
+    excluded: Option<String>,
+    escaped: bool,
+    media_types: String,
+    source_ids: String,
+    group_ids: Option<String>,
+    series_id: Option<i64>,
+    playlist_id: Option<i64>,
+    added_since: Option<i64>,
+    favorites: bool,
+    exclude_dead: bool,
+    hide_watched: bool,
+    tag: Option<String>,
+}
+
+impl ChannelConditions {
+    fn new(filters: &Filters, media_types: &[u8], added_since: i64) -> Result<Self> {
+        let has_query = filters
+            .query
+            .as_deref()
+            .is_some_and(|query| !query.trim().is_empty());
+        let group_ids = get_group_ids(filters);
+        let episodes = filters.series_id.is_some();
+        let terms = match has_query {
+            true => get_search_terms(filters)?,
+            false => SearchTerms::default(),
+        };
+        let to_list = |patterns: &Vec<String>| match patterns.is_empty() {
+            true => Ok(None),
+            false => serde_json::to_string(patterns).map(Some),
+        };
+        Ok(ChannelConditions {
+            keywords: to_list(&terms.include)?,
+            excluded: to_list(&terms.exclude)?,
+            escaped: terms.escaped,
+            media_types: serde_json::to_string(media_types)?,
+            source_ids: serde_json::to_string(&filters.source_ids)?,
+            group_ids: match group_ids.is_empty() || episodes {
+                true => None,
+                false => Some(serde_json::to_string(&group_ids)?),
+            },
+            series_id: filters.series_id,
+            playlist_id: filters
+                .playlist_id
+                .filter(|_| filters.view_type == view_type::PLAYLIST && !episodes),
+            added_since: Some(added_since)
+                .filter(|_| filters.view_type == view_type::NEW && !episodes),
+            favorites: filters.view_type == view_type::FAVORITES && !episodes,
+            exclude_dead: filters.exclude_dead == Some(true),
+            hide_watched: filters.hide_watched && episodes,
+            tag: filters
+                .tag
+                .as_ref()
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty()),
+        })
+    }
+
+    // IN lists are bound as json arrays so each filter combination has a single
+    // statement shape that prepare_cached can reuse
+    fn to_sql(&self) -> (String, Vec<&dyn rusqlite::ToSql>) {
+        let mut sql = r#"media_type IN (SELECT value FROM json_each(?))
+        AND source_id IN (SELECT value FROM json_each(?))
+        AND url IS NOT NULL"#
+            .to_string();
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&self.media_types, &self.source_ids];
+        let escape = match self.escaped {
+            true => " ESCAPE '\\'",
+            false => "",
+        };
+        if let Some(ref keywords) = self.keywords {
+            sql += &format!("\nAND NOT EXISTS (SELECT 1 FROM json_each(?) WHERE channels.search_name NOT LIKE json_each.value{escape})");
+            params.push(keywords);
+        }
+        if let Some(ref excluded) = self.excluded {
+            sql += &format!("\nAND NOT EXISTS (SELECT 1 FROM json_each(?) WHERE channels.search_name LIKE json_each.value{escape})");
+            params.push(excluded);
+        }
+        if self.favorites {
+            sql += "\nAND favorite = 1";
+        }
+        if self.hide_watched {
+            sql += "\nAND watched_at IS NULL";
+        }
+        if self.exclude_dead {
+            sql += &format!(
+                "\nAND (last_check_status IS NULL OR last_check_status != {})",
+                health::STATUS_DEAD
+            );
+        }
+        if let Some(ref playlist_id) = self.playlist_id {
+            sql += "\nAND id IN (SELECT channel_id FROM playlist_channels WHERE playlist_id = ?)";
+            params.push(playlist_id);
+        }
+        if let Some(ref tag) = self.tag {
+            sql += "\nAND id IN (SELECT ct.channel_id FROM channel_tags ct JOIN tags t ON t.id = ct.tag_id WHERE t.name = ? COLLATE NOCASE)";
+            params.push(tag);
+        }
+        if let Some(ref series_id) = self.series_id {
+            sql += "\nAND series_id = ?";
+            params.push(series_id);
+        } else if let Some(ref group_ids) = self.group_ids {
+            sql += "\nAND group_id IN (SELECT value FROM json_each(?))";
+            params.push(group_ids);
+        }
+        if let Some(ref added_since) = self.added_since {
+            sql += "\nAND added_at >= ?";
+            params.push(added_since);
+        }
+        (sql, params)
+    }
+}
+
+/// Counts the channels of each media type matching the filters, regardless of the
+/// selected media types. Without sources the totals of every enabled source are returned
+pub fn get_media_type_counts(mut filters: Filters) -> Result<HashMap<u8, u64>> {
+    let sql = get_conn()?;
+    if filters.source_ids.is_empty() {
+        filters.source_ids = sql
+            .prepare("SELECT id FROM sources WHERE enabled = 1")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+    }
+    let media_types = [
+        media_type::LIVESTREAM,
+        media_type::MOVIE,
+        media_type::SERIE,
+        media_type::RADIO,
+    ];
+    let conditions = ChannelConditions::new(&filters, &media_types, get_new_channels_cutoff()?)?;
+    let (where_clause, params) = conditions.to_sql();
+    let counts = sql
+        .prepare_cached(&format!(
+            "SELECT media_type, COUNT(*) FROM channels\nWHERE {where_clause}\nGROUP BY media_type"
+        ))?
+        .query_map(params_from_iter(params), |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<rusqlite::Result<HashMap<u8, u64>>>()?;
+    Ok(counts)
+}
+
+/// The advanced syntax rejects a malformed query with an `InvalidQuery`
+fn get_search_terms(filters: &Filters) -> Result<SearchTerms> {
+    let query = filters.query.as_deref().unwrap_or("");
+    if search_syntax::is_advanced(query, filters.advanced_query) {
+        return Ok(search_syntax::parse(query)?);
+    }
+    Ok(SearchTerms {
+        include: get_keywords(filters),
+        ..Default::default()
+    })
+}
+
+fn get_keywords(filters: &Filters) -> Vec<String> {
+    let query = fold_search_name(filters.query.as_deref().unwrap_or(""));
+    match filters.use_keywords {
+        true => query
+            .split(" ")
+            .map(|f| format!("%{f}%").to_string())
+            .collect(),
+        false => vec![format!("%{query}%")],
+    }
+}
+
+fn get_new_channels_cutoff() -> Result<i64> {
+    let days = crate::settings::get_settings()?
+        .new_channels_days
+        .unwrap_or(crate::settings::DEFAULT_NEW_CHANNELS_DAYS);
+    Ok(chrono::Utc::now().timestamp() - days as i64 * 86400)
+}
+
+pub fn get_new_channel_count(since: i64) -> Result<u64> {
+    let sql = get_conn()?;
+    let count = sql.query_row(
+        r#"
+        SELECT COUNT(*)
+        FROM channels
+        WHERE added_at >= ?
+        AND source_id IN (SELECT id FROM sources WHERE enabled = 1)
+    "#,
+        params![since],
+        |row| row.get::<_, u64>(0),
+    )?;
+    Ok(count)
+}
+
+fn get_group_ids(filters: &Filters) -> Vec<i64> {
+    let mut group_ids = filters.group_ids.clone().unwrap_or_default();
+    if let Some(group_id) = filters.group_id {
+        if !group_ids.contains(&group_id) {
+            group_ids.push(group_id);
+        }
+    }
+    group_ids
+}
+
+/// Series of a source whose episodes were never fetched, so a prefetch stopped by the
+/// provider picks up where it left
+pub fn get_unfetched_series(source_id: i64) -> Result<Vec<u64>> {
+    let sql = get_conn()?;
+    let series = sql
+        .prepare(
+            r#"
+            SELECT CAST(c.url AS INTEGER) AS series_id
+            FROM channels c
+            WHERE c.source_id = ?1
+            AND c.media_type = ?2
+            AND c.series_id IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM fetched_series f
+                WHERE f.source_id = ?1 AND f.series_id = CAST(c.url AS INTEGER)
+            )
+            AND NOT EXISTS (
+                SELECT 1 FROM channels e
+                WHERE e.source_id = ?1 AND e.series_id = CAST(c.url AS INTEGER)
+            )
+            ORDER BY c.id
+        "#,
+        )?
+        .query_map(params![source_id, media_type::SERIE], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(series)
+}
+
+pub fn mark_series_fetched(tx: &Transaction, source_id: i64, series_id: u64) -> Result<()> {
+    tx.execute(
+        "INSERT OR IGNORE INTO fetched_series (source_id, series_id) VALUES (?, ?)",
+        params![source_id, series_id],
+    )?;
+    Ok(())
+}
+
+/// The loaded episodes of a series, in season and episode order
+pub fn get_series_episodes(series_id: u64, source_id: i64) -> Result<Vec<Channel>> {
+    let sql = get_conn()?;
+    let episodes = sql
+        .prepare("SELECT * FROM channels WHERE series_id = ? AND source_id = ? ORDER BY id")?
+        .query_map(params![series_id, source_id], |row| {
+            let mut channel = row_to_channel(row)?;
+            channel.series_id = Some(series_id);
+            Ok(channel)
+        })?
+        .collect::<rusqlite::Result<Vec<Channel>>>()?;
+    Ok(episodes)
+}
+
+pub fn series_has_episodes(series_id: u64, source_id: i64) -> Result<bool> {
+    let summary = get_series_summary(&[series_id as i64], source_id)?;
+    Ok(summary.first().is_some_and(|s| s.episode_count > 0))
+}
+
+/// Episode counts and latest episode insertion of each series, in the order requested.
+/// Series whose episodes were never loaded come back with a count of 0
+pub fn get_series_summary(series_ids: &[i64], source_id: i64) -> Result<Vec<SeriesSummary>> {
+    let sql = get_conn()?;
+    let mut found: HashMap<i64, (u32, Option<i64>)> = HashMap::with_capacity(series_ids.len());
+    for chunk in series_ids.chunks(MAX_BOUND_IDS) {
+        let query = format!(
+            "SELECT series_id, COUNT(*), MAX(added_at) FROM channels WHERE series_id IN ({}) AND source_id = ? GROUP BY series_id",
+            vec!["?"; chunk.len()].join(", ")
+        );
+        let mut params: Vec<&dyn rusqlite::ToSql> =
+            chunk.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
+        params.push(&source_id);
+        let mut stmt = sql.prepare(&query)?;
+        let rows = stmt.query_map(params_from_iter(params), |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })?;
+        for row in rows {
+            let (series_id, summary) = row?;
+            found.insert(series_id, summary);
+        }
+    }
+    Ok(series_ids
+        .iter()
+        .map(|series_id| {
+            let (episode_count, last_added_at) = found.get(series_id).cloned().unwrap_or_default();
+            SeriesSummary {
+                series_id: *series_id,
+                episode_count,
+                last_added_at,
+            }
+        })
+        .collect())
+}
+
+fn to_sql_like(query: Option<String>) -> String {
+    query.map(|x| format!("%{x}%")).unwrap_or("%".to_string())
+}
+
+/// Groups are still listed as channels by `search` until the frontend moves to `search_groups`
+pub fn search_group(filters: Filters) -> Result<Vec<Channel>> {
+    Ok(search_groups(filters)?
+        .into_iter()
+        .map(group_to_channel)
+        .collect())
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(view_type = filters.view_type, page = filters.page))]
+pub fn search_groups(mut filters: Filters) -> Result<Vec<GroupItem>> {
+    retain_enabled_sources(&mut filters)?;
+    let sql = get_conn()?;
+    let page_size = crate::settings::get_page_size()?;
+    let offset: u32 = filters.page as u32 * page_size as u32 - page_size as u32;
+    let terms = get_search_terms(&filters)?;
+    let keywords = serde_json::to_string(&terms.include)?;
+    let excluded = serde_json::to_string(&terms.exclude)?;
+    let source_ids = serde_json::to_string(&filters.source_ids)?;
+    let favorites_only = match filters.view_type == view_type::FAVORITES {
+        true => "AND groups.favorite = 1",
+        false => "",
+    };
+    let escape = match terms.escaped {
+        true => "ESCAPE '\\'",
+        false => "",
+    };
+    let sql_query = format!(
+        r#"
+        SELECT groups.*, sources.name AS source_name, COALESCE(counts.channel_count, 0) AS channel_count
+        FROM groups
+        JOIN sources ON sources.id = groups.source_id
+        LEFT JOIN (
+            SELECT group_id, COUNT(*) AS channel_count
+            FROM channels
+            WHERE source_id IN (SELECT value FROM json_each(?2))
+            AND url IS NOT NULL
+            GROUP BY group_id
+        ) counts ON counts.group_id = groups.id
+        WHERE NOT EXISTS (SELECT 1 FROM json_each(?1) WHERE groups.search_name NOT LIKE json_each.value {escape})
+        AND NOT EXISTS (SELECT 1 FROM json_each(?5) WHERE groups.search_name LIKE json_each.value {escape})
+        AND groups.source_id IN (SELECT value FROM json_each(?2))
+        {favorites_only}
+        ORDER BY groups.favorite DESC
+        LIMIT ?3, ?4
+    "#
+    );
+    let groups: Vec<GroupItem> = sql
+        .prepare_cached(&sql_query)?
+        .query_map(
+            params![keywords, source_ids, offset, page_size, excluded],
+            logged("groups", row_to_group),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    tracing::debug!("group search returned {} groups", groups.len());
+    Ok(groups)
+}
+
+/// Logs the identifier of a row that fails to map, so malformed rows surface as errors
+/// instead of silently disappearing from results
+fn logged<T>(
+    table: &'static str,
+    map: impl Fn(&Row) -> rusqlite::Result<T>,
+) -> impl Fn(&Row) -> rusqlite::Result<T> {
+    move |row| {
+        map(row).inspect_err(|e| {
+            let id = row
+                .get::<_, rusqlite::types::Value>("id")
+                .or_else(|_| row.get::<_, rusqlite::types::Value>(0))
+                .ok();
+            tracing::error!("Failed to read {table} row {:?}: {:?}", id, e);
+        })
+    }
+}
+
+fn row_to_group(row: &Row) -> std::result::Result<GroupItem, rusqlite::Error> {
+    Ok(GroupItem {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        image: row.get("image")?,
+        source_id: row.get("source_id")?,
+        source_name: row.get("source_name")?,
+        channel_count: row.get("channel_count")?,
+        media_types: row.get("media_types")?,
+        favorite: row.get::<_, Option<bool>>("favorite")?.unwrap_or(false),
+    })
+}
+
+fn group_to_channel(group: GroupItem) -> Channel {
+    Channel {
+        id: Some(group.id),
+        name: group.name,
+        group: None,
+        image: group.image,
+        media_type: media_type::GROUP,
+        url: None,
+        series_id: None,
+        group_id: None,
+        favorite: group.favorite,
+        source_id: Some(group.source_id),
+        stream_id: None,
+        tvg_id: None,
+        media_types: Some(group.media_types),
+        catchup: None,
+        watched: false,
+    }
+}
+
+fn row_to_channel(row: &Row) -> std::result::Result<Channel, rusqlite::Error> {
+    let channel = Channel {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        group_id: row.get("group_id")?,
+        image: row.get("image")?,
+        media_type: row.get("media_type")?,
+        source_id: row.get("source_id")?,
+        url: row.get("url")?,
+        favorite: row.get("favorite")?,
+        series_id: None,
+        group: None,
+        stream_id: row.get("stream_id")?,
+        tvg_id: row.get("tvg_id")?,
+        media_types: None,
+        catchup: row.get("catchup")?,
+        watched: row.get::<_, Option<i64>>("watched_at")?.is_some(),
+    };
+    Ok(channel)
+}
+
+fn row_to_channel_with_group(row: &Row) -> std::result::Result<Channel, rusqlite::Error> {
+    let mut channel = row_to_channel(row)?;
+    channel.group = row.get("group_name")?;
+    Ok(channel)
+}
+
+pub fn get_channel_by_id(id: i64) -> Result<Channel> {
+    let sql = get_conn()?;
+    Ok(sql.query_row(
+        "SELECT * FROM channels WHERE id = ?",
+        params![id],
+        row_to_channel,
+    )?)
+}
+
+pub fn find_channel_by_name(name: &str) -> Result<Option<Channel>> {
+    let sql = get_conn()?;
+    let query = |condition: &str| {
+        format!(
+            r#"
+            SELECT * FROM channels
+            WHERE {condition}
+            AND url IS NOT NULL
+            AND source_id IN (SELECT id FROM sources WHERE enabled = 1)
+            ORDER BY length(name)
+            LIMIT 1
+            "#
+        )
+    };
+    let exact = sql
+        .query_row(
+            &query("name = ? COLLATE NOCASE"),
+            params![name],
+            row_to_channel,
+        )
+        .optional()?;
+    if exact.is_some() {
+        return Ok(exact);
+    }
+    let fuzzy = sql
+        .query_row(
+            &query("name LIKE ?"),
+            params![format!("%{name}%")],
+            row_to_channel,
+        )
+        .optional()?;
+    Ok(fuzzy)
+}
+
+pub fn get_channels_by_source(source_id: i64, media_type: u8) -> Result<Vec<Channel>> {
+    let sql = get_conn()?;
+    let channels: Vec<Channel> = sql
+        .prepare("SELECT * FROM channels WHERE source_id = ? AND media_type = ?")?
+        .query_map(
+            params![source_id, media_type],
+            logged("channels", row_to_channel),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(channels)
+}
+
+pub fn set_check_statuses(tx: &Transaction, statuses: &[(i64, u8)]) -> Result<()> {
+    let mut stmt = tx.prepare(
+        r#"
+        UPDATE channels
+        SET last_check_status = ?, last_checked_at = strftime('%s', 'now')
+        WHERE id = ?
+    "#,
+    )?;
+    for (id, status) in statuses {
+        stmt.execute(params![status, id])?;
+    }
+    Ok(())
+}
+
+pub fn delete_dead_channels(source_id: i64) -> Result<usize> {
+    let sql = get_conn()?;
+    let count = sql.execute(
+        r#"
+        DELETE FROM channels
+        WHERE source_id = ?
+        AND last_check_status = ?
+        AND favorite = 0
+    "#,
+        params![source_id, health::STATUS_DEAD],
+    )?;
+    Ok(count)
+}
+
+pub fn delete_channels_by_source(tx: &Transaction, source_id: i64) -> Result<()> {
+    let deleted = tx.execute(
+        r#"
+        DELETE FROM channels
+        WHERE source_id = ?
+        AND favorite = 0;
+    "#,
+        params![source_id.to_string()],
+    )?;
+    track_deleted_rows(tx, deleted)?;
+    Ok(())
+}
+
+/// Only the listed groups get imported on the next refresh, an empty list imports everything
+pub fn set_source_group_filter(source_id: i64, groups: Vec<String>) -> Result<()> {
+    do_tx(|tx| set_group_filter(tx, source_id, &groups))
+}
+
+fn set_group_filter(tx: &Transaction, source_id: i64, groups: &[String]) -> Result<()> {
+    tx.execute(
+        "DELETE FROM source_group_filters WHERE source_id = ?",
+        params![source_id],
+    )?;
+    let mut stmt =
+        tx.prepare("INSERT OR IGNORE INTO source_group_filters (source_id, name) VALUES (?, ?)")?;
+    for group in groups {
+        stmt.execute(params![source_id, group.trim()])?;
+    }
+    Ok(())
+}
+
+pub fn get_source_group_filter(source_id: i64) -> Result<Vec<String>> {
+    let sql = get_conn()?;
+    let groups = sql
+        .prepare("SELECT name FROM source_group_filters WHERE source_id = ? ORDER BY name")?
+        .query_map(params![source_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(groups)
+}
+
+/// Returns None when the source imports every group
+pub fn get_group_filter(tx: &Transaction, source_id: i64) -> Result<Option<HashSet<String>>> {
+    let groups = tx
+        .prepare("SELECT name FROM source_group_filters WHERE source_id = ?")?
+        .query_map(params![source_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<HashSet<String>>>()?;
+    Ok(Some(groups).filter(|groups| !groups.is_empty()))
+}
+
+pub fn get_channel_urls_by_source(tx: &Transaction, source_id: i64) -> Result<HashSet<String>> {
+    let mut stmt =
+        tx.prepare("SELECT url FROM channels WHERE source_id = ? AND url IS NOT NULL")?;
+    let urls = stmt
+        .query_map(params![source_id], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<HashSet<String>>>()?;
+    Ok(urls)
+}
+
+pub fn delete_channels_by_urls(tx: &Transaction, source_id: i64, urls: &[String]) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        r#"
+        DELETE FROM channels
+        WHERE source_id = ?
+        AND url = ?
+        AND favorite = 0
+    "#,
+    )?;
+    let mut deleted = 0;
+    for url in urls {
+        deleted += stmt.execute(params![source_id, url])?;
+    }
+    track_deleted_rows(tx, deleted)?;
+    Ok(())
+}
+
+pub fn delete_empty_groups(tx: &Transaction, source_id: i64) -> Result<()> {
+    let deleted = tx.execute(
+        r#"
+        DELETE FROM groups
+        WHERE source_id = ?
+        AND favorite = 0
+        AND NOT EXISTS (SELECT 1 FROM channels WHERE group_id = groups.id)
+    "#,
+        params![source_id],
+    )?;
+    track_deleted_rows(tx, deleted)?;
+    Ok(())
+}
+
+pub fn delete_groups_by_source(tx: &Transaction, source_id: i64) -> Result<()> {
+    let deleted = tx.execute(
+        r#"
+        DELETE FROM groups
+        WHERE source_id = ?1
+        AND favorite = 0
+        AND NOT EXISTS (
+            SELECT 1
+            FROM channels
+            WHERE group_id = groups.id
+            AND (favorite = 1 OR source_id != ?1)
+        )
+    "#,
+        params![source_id],
+    )?;
+    track_deleted_rows(tx, deleted)?;
+    Ok(())
+}
+
+/// Predicates of the rows removed along with a source or a group, shared by the deletes
+/// and `get_delete_impact` so the confirmation counts match what actually goes away
+struct DeleteScope {
+    channels: &'static str,
+    groups: &'static str,
+    headers: &'static str,
+    playlist_channels: &'static str,
+    reminders: &'static str,
+}
+
+const SOURCE_SCOPE: DeleteScope = DeleteScope {
+    channels: "source_id = ?1",
+    groups: "source_id = ?1",
+    headers: "channel_id IN (SELECT id FROM channels WHERE source_id = ?1)",
+    playlist_channels: "source_id = ?1",
+    reminders: "source_id = ?1",
+};
+
+const GROUP_SCOPE: DeleteScope = DeleteScope {
+    channels: "group_id = ?1",
+    groups: "id = ?1",
+    headers: "channel_id IN (SELECT id FROM channels WHERE group_id = ?1)",
+    playlist_channels: "channel_id IN (SELECT id FROM channels WHERE group_id = ?1)",
+    reminders: "channel_id IN (SELECT id FROM channels WHERE group_id = ?1)",
+};
+
+impl DeleteScope {
+    /// Dependent rows go first since their predicates look up the channels
+    fn delete(&self, sql: &Connection, id: i64) -> Result<usize> {
+        let mut deleted = 0;
+        for (table, predicate) in [
+            ("channel_http_headers", self.headers),
+            // notes and tags hang off the same channels as the headers
+            ("channel_notes", self.headers),
+            ("channel_tags", self.headers),
+            ("playlist_channels", self.playlist_channels),
+            ("epg", self.reminders),
+            ("channels", self.channels),
+            ("groups", self.groups),
+        ] {
+            deleted += sql.execute(
+                &format!("DELETE FROM {table} WHERE {predicate}"),
+                params![id],
+            )?;
+        }
+        Ok(deleted)
+    }
+
+    fn impact(&self, sql: &Connection, id: i64) -> Result<DeleteImpact> {
+        let count = |table: &str, predicate: &str| -> Result<u64> {
+            Ok(sql.query_row(
+                &format!("SELECT COUNT(*) FROM {table} WHERE {predicate}"),
+                params![id],
+                |row| row.get(0),
+            )?)
+        };
+        Ok(DeleteImpact {
+            channels: count("channels", self.channels)?,
+            groups: count("groups", self.groups)?,
+            favorites: count("channels", &format!("favorite = 1 AND {}", self.channels))?
+                + count("groups", &format!("favorite = 1 AND {}", self.groups))?,
+            headers: count("channel_http_headers", self.headers)?,
+            playlist_channels: count("playlist_channels", self.playlist_channels)?,
+            reminders: count("epg", self.reminders)?,
+        })
+    }
+}
+
+pub fn get_source_delete_impact(id: i64) -> Result<DeleteImpact> {
+    SOURCE_SCOPE.impact(&get_conn()?, id)
+}
+
+/// What deleting the group along with its channels would remove
+pub fn get_group_delete_impact(id: i64) -> Result<DeleteImpact> {
+    GROUP_SCOPE.impact(&get_conn()?, id)
+}
+
+#[derive(Debug)]
+pub struct GroupNotEmpty {
+    pub channels: u64,
+}
+
+impl fmt::Display for GroupNotEmpty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "This category still has {} channels, move or delete them first",
+            self.channels
+        )
+    }
+}
+
+impl std::error::Error for GroupNotEmpty {}
+
+pub fn delete_source(id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    // channels of other sources merged into its groups go back to having none
+    sql.execute(
+        "UPDATE channels SET group_id = NULL WHERE source_id != ?1 AND group_id IN (SELECT id FROM groups WHERE source_id = ?1)",
+        params![id],
+    )?;
+    let mut deleted = SOURCE_SCOPE.delete(&sql, id)?;
+    deleted += sql.execute("DELETE FROM refresh_log WHERE source_id = ?", params![id])?;
+    deleted += sql.execute(
+        "DELETE FROM group_image_overrides WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM channel_overrides WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM channel_media_type_overrides WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM channel_player_overrides WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute("DELETE FROM stream_probe WHERE source_id = ?", params![id])?;
+    deleted += sql.execute(
+        "DELETE FROM channel_volume WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM watched_channels WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM orphaned_favorites WHERE source_id = ?",
+        params![id],
+    )?;
+    // favorites parked by an interrupted refresh are detached from the source
+    deleted += sql.execute(
+        "DELETE FROM channels WHERE id IN (SELECT id FROM parked_favorites WHERE source_id = ?)",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM parked_favorites WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM source_group_filters WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM programmes WHERE epg_source_id IN (SELECT id FROM epg_sources WHERE source_id = ?)",
+        params![id],
+    )?;
+    deleted += sql.execute("DELETE FROM epg_sources WHERE source_id = ?", params![id])?;
+    deleted += sql.execute(
+        "DELETE FROM fetched_series WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM pending_imports WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM staged_channels WHERE source_id = ?",
+        params![id],
+    )?;
+    deleted += sql.execute(
+        "DELETE FROM group_merge_rules WHERE source_id = ?1 OR target_source_id = ?1",
+        params![id],
+    )?;
+    track_deleted_rows(&sql, deleted)?;
+    let count = sql.execute(
+        r#"
+        DELETE FROM sources
+        WHERE id = ?;
+    "#,
+        params![id],
+    )?;
+    if count != 1 {
+        return Err(anyhow!("No sources were deleted"));
+    }
+    secrets::delete(id)?;
+    Ok(())
+}
+
+fn track_deleted_rows(sql: &Connection, deleted: usize) -> Result<()> {
+    if deleted == 0 {
+        return Ok(());
+    }
+    sql.execute(
+        r#"
+        INSERT INTO settings (key, value)
+        VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET value = CAST(value AS INTEGER) + ?2
+    "#,
+        params![DELETED_ROWS_SINCE_VACUUM, deleted],
+    )?;
+    Ok(())
+}
+
+pub fn get_deleted_rows_since_vacuum() -> Result<u64> {
+    let sql = get_conn()?;
+    let deleted: Option<u64> = sql
+        .query_row(
+            "SELECT CAST(value AS INTEGER) FROM settings WHERE key = ?",
+            params![DELETED_ROWS_SINCE_VACUUM],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(deleted.unwrap_or(0))
+}
+
+/// Copies the database to `path` unless an import is writing, returns whether it was copied.
+/// Passwords are copied as the keyring references or encrypted values the sources table holds
+pub fn backup_to(path: &str) -> Result<bool> {
+    let Some(_import) = try_lock_import() else {
+        return Ok(false);
+    };
+    let sql = get_conn()?;
+    sql.execute("VACUUM INTO ?", params![path])?;
+    sql.execute(
+        r#"
+        INSERT INTO settings (key, value)
+        VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET value = ?2
+    "#,
+        params![LAST_BACKUP_AT, chrono::Utc::now().timestamp()],
+    )?;
+    Ok(true)
+}
+
+pub fn get_last_backup_at() -> Result<Option<i64>> {
+    let sql = get_conn()?;
+    Ok(sql
+        .query_row(
+            "SELECT CAST(value AS INTEGER) FROM settings WHERE key = ?",
+            params![LAST_BACKUP_AT],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+/// Most recent first, stored as a comma separated list
+pub fn set_last_channels(channel_ids: &[i64]) -> Result<()> {
+    let value = channel_ids
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        INSERT INTO settings (key, value)
+        VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET value = ?2
+    "#,
+        params![LAST_CHANNELS, value],
+    )?;
+    Ok(())
+}
+
+pub fn set_control_api_token(token: &str) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        INSERT INTO settings (key, value)
+        VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET value = ?2
+    "#,
+        params![CONTROL_API_TOKEN, token],
+    )?;
+    Ok(())
+}
+
+pub fn get_control_api_token() -> Result<Option<String>> {
+    let sql = get_conn()?;
+    Ok(sql
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?",
+            params![CONTROL_API_TOKEN],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+pub fn get_last_channels() -> Result<Vec<i64>> {
+    let sql = get_conn()?;
+    let value: Option<String> = sql
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?",
+            params![LAST_CHANNELS],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.parse().ok())
+        .collect())
+}
+
+pub fn get_db_file_size() -> Result<u64> {
+    Ok(std::fs::metadata(get_db()?.path)?.len())
+}
+
+pub fn compact() -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute_batch("VACUUM; ANALYZE; PRAGMA optimize;")?;
+    sql.execute(
+        "DELETE FROM settings WHERE key = ?",
+        params![DELETED_ROWS_SINCE_VACUUM],
+    )?;
+    Ok(())
+}
+
+pub fn get_channel_count_by_source(id: i64) -> Result<u64> {
+    let sql = get_conn()?;
+    let count = sql.query_row(
+        "SELECT COUNT(*) FROM channels WHERE source_id = ?",
+        params![id],
+        |row| row.get::<_, u64>(0),
+    )?;
+    Ok(count)
+}
+
+pub fn source_name_exists(name: &str) -> Result<bool> {
+    let sql = get_conn()?;
+    Ok(sql
+        .query_row(
+            r#"
+    SELECT 1
+    FROM sources
+    WHERE name = ?1 COLLATE NOCASE
+    "#,
+            [name],
+            |row| row.get::<_, u8>(0),
+        )
+        .optional()?
+        .is_some())
+}
+
+/// A channel leaving the favorites loses its place among them
+pub fn favorite_channel(channel_id: i64, favorite: bool) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        UPDATE channels
+        SET favorite = ?1,
+        favorite_position = CASE WHEN ?1 THEN favorite_position END
+        WHERE id = ?2
+    "#,
+        params![favorite, channel_id],
+    )?;
+    Ok(())
+}
+
+/// Pins favorites in the given order, the ones left out follow by name. Ids that
+/// are no longer favorites are skipped
+pub fn reorder_favorites(ordered_ids: Vec<i64>) -> Result<()> {
+    do_tx(|tx| {
+        tx.execute(
+            "UPDATE channels SET favorite_position = NULL WHERE favorite_position IS NOT NULL",
+            [],
+        )?;
+        let mut stmt =
+            tx.prepare("UPDATE channels SET favorite_position = ? WHERE id = ? AND favorite = 1")?;
+        let mut position = 0;
+        for id in ordered_ids {
+            if stmt.execute(params![position + 1, id])? > 0 {
+                position += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn favorite_group(group_id: i64, favorite: bool) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        UPDATE groups
+        SET favorite = ?1
+        WHERE id = ?2
+    "#,
+        params![favorite, group_id],
+    )?;
+    Ok(())
+}
+
+/// Saves the note of a channel, an empty note removes it
+pub fn set_channel_note(channel_id: i64, note: String) -> Result<()> {
+    let sql = get_conn()?;
+    let note = note.trim();
+    if note.is_empty() {
+        sql.execute(
+            "DELETE FROM channel_notes WHERE channel_id = ?",
+            params![channel_id],
+        )?;
+        return Ok(());
+    }
+    sql.execute(
+        r#"
+        INSERT INTO channel_notes (channel_id, note, updated_at)
+        VALUES (?1, ?2, strftime('%s', 'now'))
+        ON CONFLICT (channel_id) DO UPDATE SET note = excluded.note, updated_at = excluded.updated_at
+    "#,
+        params![channel_id, note],
+    )?;
+    Ok(())
+}
+
+pub fn get_channel_note(channel_id: i64) -> Result<Option<ChannelNote>> {
+    let sql = get_conn()?;
+    Ok(sql
+        .query_row(
+            "SELECT note, updated_at FROM channel_notes WHERE channel_id = ?",
+            params![channel_id],
+            |row| {
+                Ok(ChannelNote {
+                    note: row.get(0)?,
+                    updated_at: row.get(1)?,
+                })
+            },
+        )
+        .optional()?)
+}
+
+pub fn create_tag(name: String) -> Result<i64> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("Tag name can't be empty");
+    }
+    let sql = get_conn()?;
+    let id = sql.query_row(
+        r#"
+        INSERT INTO tags (name)
+        VALUES (?)
+        ON CONFLICT DO UPDATE SET name = name
+        RETURNING id
+    "#,
+        params![name],
+        |row| row.get(0),
+    )?;
+    Ok(id)
+}
+
+pub fn rename_tag(id: i64, name: String) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("Tag name can't be empty");
+    }
+    let sql = get_conn()?;
+    sql.execute("UPDATE tags SET name = ? WHERE id = ?", params![name, id])?;
+    Ok(())
+}
+
+/// Deletes a tag along with its assignments
+pub fn delete_tag(id: i64) -> Result<()> {
+    do_tx(|tx| {
+        tx.execute("DELETE FROM channel_tags WHERE tag_id = ?", params![id])?;
+        tx.execute("DELETE FROM tags WHERE id = ?", params![id])?;
+        Ok(())
+    })
+}
+
+pub fn get_tags() -> Result<Vec<Tag>> {
+    let sql = get_conn()?;
+    let tags = sql
+        .prepare(
+            r#"
+            SELECT t.id, t.name, COUNT(ct.channel_id)
+            FROM tags t
+            LEFT JOIN channel_tags ct ON ct.tag_id = t.id
+            GROUP BY t.id
+            ORDER BY t.name COLLATE NOCASE
+        "#,
+        )?
+        .query_map([], |row| {
+            Ok(Tag {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                channel_count: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(tags)
+}
+
+pub fn tag_channel(channel_id: i64, tag_id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        "INSERT OR IGNORE INTO channel_tags (channel_id, tag_id) VALUES (?, ?)",
+        params![channel_id, tag_id],
+    )?;
+    Ok(())
+}
+
+pub fn untag_channel(channel_id: i64, tag_id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        "DELETE FROM channel_tags WHERE channel_id = ? AND tag_id = ?",
+        params![channel_id, tag_id],
+    )?;
+    Ok(())
+}
+
+pub fn get_channel_tags(channel_id: i64) -> Result<Vec<String>> {
+    let sql = get_conn()?;
+    let tags = sql
+        .prepare(
+            r#"
+            SELECT t.name
+            FROM channel_tags ct
+            JOIN tags t ON t.id = ct.tag_id
+            WHERE ct.channel_id = ?
+            ORDER BY t.name COLLATE NOCASE
+        "#,
+        )?
+        .query_map(params![channel_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(tags)
+}
+
+pub fn get_sources() -> Result<Vec<Source>> {
+    let sql = get_conn()?;
+    let sources: Vec<Source> = sql
+        .prepare("SELECT * FROM sources")?
+        .query_map([], logged("sources", row_to_source))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(sources)
+}
+
+/// Every source with its channels and groups counted by media type. A group counts
+/// for each media type among its channels
+pub fn get_sources_with_stats() -> Result<Vec<SourceStats>> {
+    let sql = get_conn()?;
+    let mut stmt = sql.prepare(
+        r#"
+        SELECT s.*, c.media_type AS stat_media_type, c.channels AS stat_channels, c.groups AS stat_groups
+        FROM sources s
+        LEFT JOIN (
+            SELECT source_id, media_type, COUNT(*) AS channels, COUNT(DISTINCT group_id) AS groups
+            FROM channels
+            GROUP BY source_id, media_type
+        ) c ON c.source_id = s.id
+        ORDER BY s.id
+    "#,
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut sources: Vec<SourceStats> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get("id")?;
+        if sources.last().and_then(|s| s.source.id) != Some(id) {
+            sources.push(SourceStats {
+                source: row_to_source(row)?,
+                channels: HashMap::new(),
+                groups: HashMap::new(),
+            });
+        }
+        if let Some(media_type) = row.get::<_, Option<u8>>("stat_media_type")? {
+            let stats = sources.last_mut().unwrap();
+            stats.channels.insert(media_type, row.get("stat_channels")?);
+            stats.groups.insert(media_type, row.get("stat_groups")?);
+        }
+    }
+    Ok(sources)
+}
+
+pub fn get_enabled_sources() -> Result<Vec<Source>> {
+    let sql = get_conn()?;
+    let sources: Vec<Source> = sql
+        .prepare("SELECT * FROM sources WHERE enabled = 1")?
+        .query_map([], logged("sources", row_to_source))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(sources)
+}
+
+pub fn verify_database() -> Result<Vec<TableCheck>> {
+    let sql = get_conn()?;
+    let media_types = [
+        media_type::LIVESTREAM,
+        media_type::MOVIE,
+        media_type::SERIE,
+        media_type::GROUP,
+        media_type::RADIO,
+    ]
+    .map(|t| t.to_string())
+    .join(", ");
+    let checks = [
+        ("sources", "name IS NULL OR source_type IS NULL OR enabled IS NULL".to_string()),
+        (
+            "channels",
+            format!(
+                "name IS NULL OR favorite IS NULL OR media_type IS NULL OR media_type NOT IN ({media_types})
+                OR source_id IS NULL OR source_id NOT IN (SELECT id FROM sources)"
+            ),
+        ),
+        (
+            "groups",
+            "name IS NULL OR favorite IS NULL OR source_id IS NULL OR source_id NOT IN (SELECT id FROM sources)"
+                .to_string(),
+        ),
+        (
+            "epg",
+            "epg_id IS NULL OR channel_name IS NULL OR title IS NULL OR start_timestamp IS NULL"
+                .to_string(),
+        ),
+    ];
+    let mut results = Vec::with_capacity(checks.len());
+    for (table, invalid) in checks {
+        let (total, invalid): (u64, u64) = sql.query_row(
+            &format!("SELECT COUNT(*), COALESCE(SUM({invalid}), 0) FROM {table}"),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        if invalid > 0 {
+            tracing::warn!("Found {invalid} invalid rows out of {total} in {table}");
+        }
+        results.push(TableCheck {
+            table: table.to_string(),
+            total,
+            invalid,
+        });
+    }
+    Ok(results)
+}
+
+fn row_to_source(row: &Row) -> std::result::Result<Source, rusqlite::Error> {
+    Ok(Source {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        username: row.get("username")?,
+        password: row
+            .get::<_, Option<String>>("password")?
+            .map(|password| secrets::resolve(&password))
+            .transpose()
+            .map_err(|e| rusqlite::Error::UserFunctionError(e.into()))?,
+        url: row.get("url")?,
+        source_type: row.get("source_type")?,
+        url_origin: None,
+        enabled: row.get("enabled")?,
+        use_tvg_id: row.get("use_tvg_id")?,
+        last_refresh_at: row.get("last_refresh_at")?,
+        last_refresh_status: row.get("last_refresh_status")?,
+        last_refresh_error: row.get("last_refresh_error")?,
+        channel_count: row.get("channel_count")?,
+        import_live: row.get("import_live")?,
+        import_vod: row.get("import_vod")?,
+        import_series: row.get("import_series")?,
+        group_filter: None,
+    })
+}
+
+/// Identifies the channels of a source across a refresh, which recreates every row
+pub fn get_source_channel_keys(source_id: i64) -> Result<HashSet<String>> {
+    let sql = get_conn()?;
+    let keys = sql
+        .prepare("SELECT name || char(0) || COALESCE(url, '') FROM channels WHERE source_id = ?")?
+        .query_map([source_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<HashSet<String>>>()?;
+    Ok(keys)
+}
+
+/// Saves the outcome of a refresh on the source and in its refresh log, keeping the last attempts only
+pub fn record_refresh(source_id: i64, error: Option<String>) -> Result<()> {
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    let status = match error {
+        Some(_) => refresh_status::FAILED,
+        None => refresh_status::SUCCESS,
+    };
+    let channel_count: u64 = tx.query_row(
+        "SELECT COUNT(*) FROM channels WHERE source_id = ?",
+        [source_id],
+        |row| row.get(0),
+    )?;
+    tx.execute(
+        r#"
+        UPDATE sources
+        SET last_refresh_at = strftime('%s', 'now'), last_refresh_status = ?, last_refresh_error = ?, channel_count = ?
+        WHERE id = ?
+    "#,
+        params![status, error, channel_count, source_id],
+    )?;
+    tx.execute(
+        r#"
+        INSERT INTO refresh_log (source_id, timestamp, status, error, channel_count)
+        VALUES (?, strftime('%s', 'now'), ?, ?, ?)
+    "#,
+        params![source_id, status, error, channel_count],
+    )?;
+    tx.execute(
+        r#"
+        DELETE FROM refresh_log
+        WHERE source_id = ?1
+        AND id NOT IN (SELECT id FROM refresh_log WHERE source_id = ?1 ORDER BY id DESC LIMIT ?2)
+    "#,
+        params![source_id, REFRESH_LOG_SIZE],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn get_epg_sources() -> Result<Vec<EpgSource>> {
+    let sql = get_conn()?;
+    let sources = sql
+        .prepare("SELECT * FROM epg_sources ORDER BY source_id IS NOT NULL, name")?
+        .query_map([], logged("epg_sources", row_to_epg_source))?
+        .collect::<rusqlite::Result<Vec<EpgSource>>>()?;
+    Ok(sources)
+}
+
+pub fn get_epg_source(id: i64) -> Result<EpgSource> {
+    let sql = get_conn()?;
+    Ok(sql.query_row(
+        "SELECT * FROM epg_sources WHERE id = ?",
+        params![id],
+        row_to_epg_source,
+    )?)
+}
+
+fn row_to_epg_source(row: &Row) -> rusqlite::Result<EpgSource> {
+    Ok(EpgSource {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        url: row.get("url")?,
+        source_id: row.get("source_id")?,
+        last_refresh_at: row.get("last_refresh_at")?,
+        timezone: row.get("timezone")?,
+    })
+}
+
+/// The timezone used for the times of the feed that have no offset, None is UTC
+pub fn set_epg_source_timezone(id: i64, timezone: Option<&str>) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        "UPDATE epg_sources SET timezone = ? WHERE id = ?",
+        params![timezone, id],
+    )?;
+    Ok(())
+}
+
+/// Adds a feed that isn't tied to a source, its programmes are used for any channel
+/// with a matching tvg-id
+pub fn add_epg_source(name: &str, url: &str) -> Result<i64> {
+    let sql = get_conn()?;
+    sql.execute(
+        "INSERT INTO epg_sources (name, url) VALUES (?, ?)",
+        params![name, url],
+    )?;
+    Ok(sql.last_insert_rowid())
+}
+
+pub fn remove_epg_source(id: i64) -> Result<()> {
+    do_tx(|tx| {
+        let deleted = tx.execute("DELETE FROM programmes WHERE epg_source_id = ?", [id])?;
+        tx.execute("DELETE FROM epg_sources WHERE id = ?", [id])?;
+        track_deleted_rows(tx, deleted)
+    })
+}
+
+/// The feed of a source is kept as an epg source linked to it, None removes it
+pub fn set_source_epg_url(source_id: i64, url: Option<String>) -> Result<Option<i64>> {
+    let url = url
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+    do_tx(|tx| {
+        tx.execute(
+            "UPDATE sources SET epg_url = ? WHERE id = ?",
+            params![url, source_id],
+        )?;
+        let Some(url) = url else {
+            let deleted = tx.execute(
+                "DELETE FROM programmes WHERE epg_source_id IN (SELECT id FROM epg_sources WHERE source_id = ?)",
+                [source_id],
+            )?;
+            tx.execute("DELETE FROM epg_sources WHERE source_id = ?", [source_id])?;
+            track_deleted_rows(tx, deleted)?;
+            return Ok(None);
+        };
+        let id = tx.query_row(
+            r#"
+            INSERT INTO epg_sources (name, url, source_id)
+            SELECT name, ?, id FROM sources WHERE id = ?
+            ON CONFLICT (source_id) DO UPDATE SET url = excluded.url
+            RETURNING id
+            "#,
+            params![url, source_id],
+            |row| row.get(0),
+        )?;
+        Ok(Some(id))
+    })
+}
+
+pub fn delete_programmes(tx: &Transaction, epg_source_id: i64) -> Result<()> {
+    let deleted = tx.execute(
+        "DELETE FROM programmes WHERE epg_source_id = ?",
+        [epg_source_id],
+    )?;
+    track_deleted_rows(tx, deleted)
+}
+
+pub fn insert_programme(tx: &Transaction, epg_source_id: i64, programme: &Programme) -> Result<()> {
+    tx.prepare_cached(
+        r#"
+        INSERT INTO programmes (epg_source_id, tvg_id, title, description, start, stop)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )?
+    .execute(params![
+        epg_source_id,
+        programme.tvg_id,
+        programme.title,
+        programme.description,
+        programme.start,
+        programme.stop
+    ])?;
+    Ok(())
+}
+
+/// Stamps the refresh and counts the channels the feed has programmes for. The feed
+/// of a source only counts that source's channels
+pub fn finish_epg_refresh(tx: &Transaction, epg_source_id: i64) -> Result<EpgRefreshResult> {
+    tx.execute(
+        "UPDATE epg_sources SET last_refresh_at = strftime('%s', 'now') WHERE id = ?",
+        [epg_source_id],
+    )?;
+    let programmes = tx.query_row(
+        "SELECT COUNT(*) FROM programmes WHERE epg_source_id = ?",
+        [epg_source_id],
+        |row| row.get(0),
+    )?;
+    let channels = tx.query_row(
+        r#"
+        SELECT COUNT(*) FROM channels
+        WHERE tvg_id IN (SELECT tvg_id FROM programmes WHERE epg_source_id = ?1)
+        AND source_id = COALESCE((SELECT source_id FROM epg_sources WHERE id = ?1), source_id)
+        "#,
+        [epg_source_id],
+        |row| row.get(0),
+    )?;
+    Ok(EpgRefreshResult {
+        epg_source_id,
+        programmes,
+        channels,
+        pruned: 0,
+    })
+}
+
+/// Drops the programmes of every feed that ended before `before` or start after `after`
+pub fn delete_programmes_outside(tx: &Transaction, before: i64, after: i64) -> Result<u64> {
+    let deleted = tx.execute(
+        "DELETE FROM programmes WHERE stop < ?1 OR start > ?2",
+        [before, after],
+    )?;
+    track_deleted_rows(tx, deleted)?;
+    Ok(deleted as u64)
+}
+
+/// Programmes of a channel overlapping `from..to`, all taken from a single feed:
+/// the feed of the channel's source first, then standalone feeds, then any other
+pub fn get_programmes(channel_id: i64, from: i64, to: i64) -> Result<Vec<Programme>> {
+    let sql = get_conn()?;
+    let channel = get_channel_by_id(channel_id)?;
+    let Some(tvg_id) = channel.tvg_id else {
+        return Ok(Vec::new());
+    };
+    query_programmes(&sql, &tvg_id, channel.source_id, from, to)
+}
+
+/// Programmes overlapping the window for each channel, in the order requested.
+/// Channels that no longer exist are left out
+pub fn get_epg_grid(channel_ids: &[i64], from: i64, to: i64) -> Result<Vec<EpgGridRow>> {
+    let sql = get_conn()?;
+    let mut rows = Vec::with_capacity(channel_ids.len());
+    for &channel_id in channel_ids {
+        let channel = sql
+            .prepare_cached("SELECT name, tvg_id, source_id FROM channels WHERE id = ?")?
+            .query_row([channel_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                ))
+            })
+            .optional()?;
+        let Some((name, tvg_id, source_id)) = channel else {
+            continue;
+        };
+        let programmes = match tvg_id {
+            Some(tvg_id) => query_programmes(&sql, &tvg_id, source_id, from, to)?,
+            None => Vec::new(),
+        };
+        rows.push(EpgGridRow {
+            channel_id,
+            name,
+            programmes,
+        });
+    }
+    Ok(rows)
+}
+
+const PROGRAMMES_QUERY: &str = r#"
+        SELECT * FROM programmes
+        WHERE tvg_id = ?1 AND stop > ?3 AND start < ?4
+        AND epg_source_id = (
+            SELECT e.id FROM epg_sources e
+            WHERE EXISTS (SELECT 1 FROM programmes p WHERE p.epg_source_id = e.id AND p.tvg_id = ?1)
+            ORDER BY e.source_id IS ?2 DESC, e.source_id IS NULL DESC, e.id
+            LIMIT 1
+        )
+        ORDER BY start
+        "#;
+
+/// A channel only shows the programmes of one feed: its own source's first, then
+/// standalone feeds, then any other feed knowing the tvg id
+fn query_programmes(
+    sql: &Connection,
+    tvg_id: &str,
+    source_id: Option<i64>,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Programme>> {
+    let programmes = sql
+        .prepare_cached(PROGRAMMES_QUERY)?
+        .query_map(
+            params![tvg_id, source_id, from, to],
+            logged("programmes", row_to_programme),
+        )?
+        .collect::<rusqlite::Result<Vec<Programme>>>()?;
+    Ok(programmes)
+}
+
+/// Programmes whose title or description contains `query`, on the live and radio channels
+/// of `source_ids`, soonest first. A programme shows once per channel airing it
+pub fn search_epg(
+    query: &str,
+    source_ids: &[i64],
+    from: i64,
+    to: i64,
+    page: u8,
+) -> Result<Vec<ProgrammeSearchResult>> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = get_conn()?;
+    let page_size = crate::settings::get_page_size()?;
+    let offset: u32 = page.max(1) as u32 * page_size as u32 - page_size as u32;
+    let results = sql
+        .prepare_cached(
+            r#"
+            SELECT p.*, c.id AS channel_id, c.name AS channel_name, c.image AS channel_image,
+                c.source_id AS channel_source_id, c.media_type AS channel_media_type, c.catchup
+            FROM programmes p
+            JOIN channels c ON c.tvg_id = p.tvg_id
+            WHERE (p.title LIKE ?1 OR p.description LIKE ?1)
+            AND p.stop > ?3 AND p.start < ?4
+            AND c.source_id IN (SELECT value FROM json_each(?2))
+            AND c.media_type IN (?5, ?6)
+            AND p.epg_source_id = (
+                SELECT e.id FROM epg_sources e
+                WHERE EXISTS (SELECT 1 FROM programmes x WHERE x.epg_source_id = e.id AND x.tvg_id = p.tvg_id)
+                ORDER BY e.source_id IS c.source_id DESC, e.source_id IS NULL DESC, e.id
+                LIMIT 1
+            )
+            ORDER BY p.start, c.name, c.id
+            LIMIT ?7, ?8
+            "#,
+        )?
+        .query_map(
+            params![
+                to_sql_like(Some(query.to_string())),
+                serde_json::to_string(source_ids)?,
+                from,
+                to,
+                media_type::LIVESTREAM,
+                media_type::RADIO,
+                offset,
+                page_size
+            ],
+            logged("programmes", row_to_programme_search_result),
+        )?
+        .collect::<rusqlite::Result<Vec<ProgrammeSearchResult>>>()?;
+    Ok(results)
+}
+
+fn row_to_programme_search_result(row: &Row) -> rusqlite::Result<ProgrammeSearchResult> {
+    Ok(ProgrammeSearchResult {
+        programme: row_to_programme(row)?,
+        channel_id: row.get("channel_id")?,
+        channel_name: row.get("channel_name")?,
+        channel_image: row.get("channel_image")?,
+        source_id: row.get("channel_source_id")?,
+        media_type: row.get("channel_media_type")?,
+        catchup: row.get::<_, Option<bool>>("catchup")?.unwrap_or(false),
+    })
+}
+
+fn row_to_programme(row: &Row) -> rusqlite::Result<Programme> {
+    Ok(Programme {
+        tvg_id: row.get("tvg_id")?,
+        title: row.get("title")?,
+        description: row.get("description")?,
+        start: row.get("start")?,
+        stop: row.get("stop")?,
+    })
+}
+
+pub fn get_refresh_log(source_id: i64) -> Result<Vec<RefreshLogEntry>> {
+    let sql = get_conn()?;
+    let entries: Vec<RefreshLogEntry> = sql
+        .prepare(
+            r#"
+        SELECT timestamp, status, error, channel_count
+        FROM refresh_log
+        WHERE source_id = ?
+        ORDER BY id DESC
+    "#,
+        )?
+        .query_map(
+            [source_id],
+            logged("refresh_log", |row| {
+                Ok(RefreshLogEntry {
+                    timestamp: row.get("timestamp")?,
+                    status: row.get("status")?,
+                    error: row.get("error")?,
+                    channel_count: row.get("channel_count")?,
+                })
+            }),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(entries)
+}
+
+pub fn get_source_from_id(source_id: i64) -> Result<Source> {
+    let sql = get_conn()?;
+    Ok(sql.query_row(
+        r#"
+    SELECT * FROM sources where id = ?"#,
+        [source_id],
+        row_to_source,
+    )?)
+}
+
+/// Unknown sources count as enabled, their reminders fall back to the missing channel notice
+pub fn is_source_enabled(source_id: i64) -> Result<bool> {
+    let sql = get_conn()?;
+    let enabled = sql
+        .query_row(
+            "SELECT enabled FROM sources WHERE id = ?",
+            params![source_id],
+            |row| row.get::<_, bool>(0),
+        )
+        .optional()?;
+    Ok(enabled.unwrap_or(true))
+}
+
+pub fn set_source_enabled(value: bool, source_id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        UPDATE sources
+        SET enabled = ?
+        WHERE id = ?
+    "#,
+        params![value, source_id],
+    )?;
+    Ok(())
+}
+
+pub fn add_custom_channel(tx: &Transaction, channel: CustomChannel) -> Result<()> {
+    tx.execute(
+        "UPDATE groups SET media_types = media_types | ? WHERE id = ?",
+        params![
+            media_type::to_mask(&[channel.data.media_type]),
+            channel.data.group_id
+        ],
+    )?;
+    let id = insert_channel(tx, channel.data)?;
+    append_custom_position(tx, id)?;
+    if let Some(mut headers) = channel.headers {
+        if channel_headers_empty(&headers) {
+            return Ok(());
+        }
+        headers.channel_id = Some(id);
+        insert_channel_headers(tx, headers)?;
+    }
+    Ok(())
+}
+
+/// Places a custom channel without a position after the others of its group
+fn append_custom_position(tx: &Transaction, id: i64) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE channels
+        SET position = (
+            SELECT COALESCE(MAX(c.position), 0) + 1
+            FROM channels c
+            WHERE c.source_id = channels.source_id
+            AND c.group_id IS channels.group_id
+        )
+        WHERE id = ? AND position IS NULL
+    "#,
+        params![id],
+    )?;
+    Ok(())
+}
+
+fn channel_headers_empty(headers: &ChannelHttpHeaders) -> bool {
+    return headers.ignore_ssl.is_none()
+        && headers.http_origin.is_none()
+        && headers.referrer.is_none()
+        && headers.user_agent.is_none();
+}
+
+pub fn get_custom_source(name: String) -> Source {
+    Source {
+        id: None,
+        name: name.to_string(),
+        enabled: true,
+        username: None,
+        password: None,
+        source_type: source_type::CUSTOM,
+        url: None,
+        url_origin: None,
+        last_refresh_at: None,
+        last_refresh_status: None,
+        last_refresh_error: None,
+        channel_count: None,
+        import_live: true,
+        import_vod: true,
+        import_series: true,
+        group_filter: None,
+        use_tvg_id: None,
+    }
+}
+
+pub fn edit_custom_channel(channel: CustomChannel) -> Result<()> {
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    match edit_custom_channel_tx(channel, &tx) {
+        Ok(_) => {
+            tx.commit()?;
+            Ok(())
+        }
+        Err(e) => {
+            tx.rollback().unwrap_or_else(|e| tracing::error!("{:?}", e));
+            return Err(e);
+        }
+    }
+}
+
+fn edit_custom_channel_tx(channel: CustomChannel, tx: &Transaction) -> Result<()> {
+    tx.execute(
+        r#"
+        UPDATE channels
+        SET name = ?, image = ?, url = ?, media_type = ?, group_id = ?, search_name = ?
+        WHERE id = ?
+    "#,
+        params![
+            channel.data.name,
+            channel.data.image,
+            channel.data.url,
+            channel.data.media_type,
+            channel.data.group_id,
+            fold_search_name(&channel.data.name),
+            channel.data.id
+        ],
+    )?;
+    let source_id: i64 = tx.query_row(
+        "SELECT source_id FROM channels WHERE id = ?",
+        params![channel.data.id],
+        |row| row.get(0),
+    )?;
+    tag_group_media_types(tx, source_id)?;
+    if let Some(mut headers) = channel.headers {
+        headers.channel_id = channel.data.id;
+        tx.execute(
+            r#"
+            INSERT INTO channel_http_headers (referrer, user_agent, http_origin, ignore_ssl, channel_id)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(channel_id) DO UPDATE SET
+                referrer = ?1,
+                user_agent = ?2,
+                http_origin = ?3,
+                ignore_ssl = ?4
+        "#,
+            params![
+                headers.referrer,
+                headers.user_agent,
+                headers.http_origin,
+                headers.ignore_ssl,
+                headers.channel_id
+            ],
+        )?;
+    } else {
+        tx.execute(
+            "DELETE FROM channel_http_headers WHERE channel_id = ?",
+            params![channel.data.id],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn delete_custom_channel(id: i64) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute("DELETE FROM channels WHERE id = ?", params![id])?;
+    Ok(())
 }
 
-fn to_sql_like(query: Option<String>) -> String {
-    query.map(|x| format!("%{x}%")).unwrap_or("%".to_string())
+pub fn duplicate_custom_channel(id: i64) -> Result<i64> {
+    do_tx(|tx| {
+        let group_id: Option<i64> = tx.query_row(
+            "SELECT group_id FROM channels WHERE id = ?",
+            params![id],
+            |row| row.get(0),
+        )?;
+        copy_custom_channel(tx, id, group_id)
+    })
 }
 
-pub fn search_group(filters: Filters) -> Result<Vec<Channel>> {
+pub fn duplicate_custom_group(group_id: i64) -> Result<DuplicatedGroup> {
+    do_tx(|tx| {
+        let (name, image, source_id): (String, Option<String>, i64) = tx.query_row(
+            "SELECT name, image, source_id FROM groups WHERE id = ?",
+            params![group_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        ensure_custom_source(tx, source_id)?;
+        let copy_name = get_copy_name(&name, |copy_name| {
+            Ok(tx
+                .query_row(
+                    "SELECT 1 FROM groups WHERE name = ? AND source_id = ?",
+                    params![copy_name, source_id],
+                    |row| row.get::<_, u8>(0),
+                )
+                .optional()?
+                .is_some())
+        })?;
+        let id = add_custom_group(
+            tx,
+            Group {
+                id: None,
+                name: copy_name,
+                image,
+                source_id: Some(source_id),
+            },
+        )?;
+        let channel_ids: Vec<i64> = tx
+            .prepare("SELECT id FROM channels WHERE group_id = ? ORDER BY position, id")?
+            .query_map(params![group_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        let channel_ids = channel_ids
+            .into_iter()
+            .map(|channel_id| copy_custom_channel(tx, channel_id, Some(id)))
+            .collect::<Result<Vec<i64>>>()?;
+        Ok(DuplicatedGroup { id, channel_ids })
+    })
+}
+
+/// Copies a custom channel and its headers under a name that doesn't collide with the unique index
+fn copy_custom_channel(tx: &Transaction, id: i64, group_id: Option<i64>) -> Result<i64> {
+    let (name, url, source_id): (String, Option<String>, i64) = tx.query_row(
+        "SELECT name, url, source_id FROM channels WHERE id = ?",
+        params![id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    ensure_custom_source(tx, source_id)?;
+    let copy_name = get_copy_name(&name, |copy_name| {
+        Ok(tx
+            .query_row(
+                "SELECT 1 FROM channels WHERE name = ? AND url IS ? AND source_id = ?",
+                params![copy_name, url, source_id],
+                |row| row.get::<_, u8>(0),
+            )
+            .optional()?
+            .is_some())
+    })?;
+    tx.execute(
+        r#"
+        INSERT INTO channels (name, search_name, group_id, image, url, source_id, media_type, favorite, stream_id, tvg_id, catchup, added_at)
+        SELECT ?, ?, ?, image, url, source_id, media_type, 0, stream_id, tvg_id, catchup, strftime('%s', 'now')
+        FROM channels
+        WHERE id = ?
+    "#,
+        params![copy_name, fold_search_name(&copy_name), group_id, id],
+    )?;
+    let new_id = tx.last_insert_rowid();
+    append_custom_position(tx, new_id)?;
+    tx.execute(
+        r#"
+        INSERT INTO channel_http_headers (channel_id, referrer, user_agent, http_origin, ignore_ssl)
+        SELECT ?, referrer, user_agent, http_origin, ignore_ssl
+        FROM channel_http_headers
+        WHERE channel_id = ?
+    "#,
+        params![new_id, id],
+    )?;
+    Ok(new_id)
+}
+
+fn get_copy_name(name: &str, exists: impl Fn(&str) -> Result<bool>) -> Result<String> {
+    let mut copy_name = format!("{name} (copy)");
+    let mut attempt = 1;
+    while exists(&copy_name)? {
+        attempt += 1;
+        copy_name = format!("{name} (copy {attempt})");
+    }
+    Ok(copy_name)
+}
+
+fn ensure_custom_source(tx: &Transaction, source_id: i64) -> Result<()> {
+    let source_type: u8 = tx.query_row(
+        "SELECT source_type FROM sources WHERE id = ?",
+        params![source_id],
+        |row| row.get(0),
+    )?;
+    if source_type != source_type::CUSTOM {
+        bail!("Only channels and groups of custom sources can be duplicated");
+    }
+    Ok(())
+}
+
+pub fn group_exists(name: &str, source_id: i64) -> Result<bool> {
+    let sql = get_conn()?;
+    Ok(sql
+        .query_row(
+            r#"
+            SELECT 1
+            FROM groups
+            WHERE name = ? AND source_id = ?
+        "#,
+            params![name, source_id],
+            |row| row.get::<_, u8>(0),
+        )
+        .optional()?
+        .is_some())
+}
+
+pub fn channel_exists(name: &str, url: &str, source_id: i64) -> Result<bool> {
+    let sql = get_conn()?;
+    Ok(sql
+        .query_row(
+            r#"
+            SELECT 1
+            FROM channels
+            WHERE name = ? AND source_id = ? AND url = ?
+        "#,
+            params![name, source_id, url],
+            |row| row.get::<_, u8>(0),
+        )
+        .optional()?
+        .is_some())
+}
+
+pub fn add_custom_group(tx: &Transaction, group: Group) -> Result<i64> {
+    tx.execute(
+        r#"
+        INSERT INTO groups (name, image, source_id, search_name)
+        VALUES (?, ?, ?, ?)
+    "#,
+        params!(
+            group.name,
+            group.image,
+            group.source_id,
+            fold_search_name(&group.name)
+        ),
+    )?;
+    Ok(tx.last_insert_rowid())
+}
+
+pub fn group_auto_complete(query: Option<String>, source_id: i64) -> Result<Vec<IdName>> {
+    let sql = get_conn()?;
+    let groups: Vec<IdName> = sql
+        .prepare(
+            r#"
+        SELECT id, name
+        FROM groups
+        WHERE search_name LIKE ?
+        AND source_id = ?
+    "#,
+        )?
+        .query_map(
+            params![
+                to_sql_like(query.map(|query| fold_search_name(&query))),
+                source_id
+            ],
+            logged("groups", row_to_id_name),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(groups)
+}
+
+pub fn channel_auto_complete(
+    query: String,
+    source_ids: Vec<i64>,
+    media_types: Vec<u8>,
+    limit: u16,
+) -> Result<Vec<IdName>> {
+    let sql = get_conn()?;
+    auto_complete_channels(&sql, &query, &source_ids, &media_types, limit)
+}
+
+const AUTO_COMPLETE_PREFIX_QUERY: &str = r#"
+        SELECT id, name
+        FROM channels
+        WHERE search_name >= ?1 AND search_name < ?2
+        AND +source_id IN (SELECT value FROM json_each(?3))
+        AND +media_type IN (SELECT value FROM json_each(?4))
+        AND url IS NOT NULL
+        ORDER BY favorite DESC, search_name
+        LIMIT ?5
+    "#;
+
+/// Prefix matches come first and are read from the search_name index, the substring scan
+/// only runs when they don't fill the limit
+fn auto_complete_channels(
+    sql: &Connection,
+    query: &str,
+    source_ids: &[i64],
+    media_types: &[u8],
+    limit: u16,
+) -> Result<Vec<IdName>> {
+    let query = fold_search_name(query.trim());
+    if query.is_empty() || limit == 0 {
+        return Ok(Vec::new());
+    }
+    let upper = format!("{query}{}", char::MAX);
+    let source_ids = serde_json::to_string(source_ids)?;
+    let media_types = serde_json::to_string(media_types)?;
+    let mut suggestions: Vec<IdName> = sql
+        .prepare_cached(AUTO_COMPLETE_PREFIX_QUERY)?
+        .query_map(
+            params![query, upper, source_ids, media_types, limit],
+            logged("channels", row_to_id_name),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    let remaining = limit as usize - suggestions.len();
+    if remaining == 0 {
+        return Ok(suggestions);
+    }
+    let mut substring: Vec<IdName> = sql
+        .prepare_cached(
+            r#"
+        SELECT id, name
+        FROM channels
+        WHERE search_name LIKE ?1
+        AND NOT (search_name >= ?2 AND search_name < ?3)
+        AND source_id IN (SELECT value FROM json_each(?4))
+        AND media_type IN (SELECT value FROM json_each(?5))
+        AND url IS NOT NULL
+        ORDER BY favorite DESC, search_name
+        LIMIT ?6
+    "#,
+        )?
+        .query_map(
+            params![
+                format!("%{query}%"),
+                query,
+                upper,
+                source_ids,
+                media_types,
+                remaining
+            ],
+            logged("channels", row_to_id_name),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    suggestions.append(&mut substring);
+    Ok(suggestions)
+}
+
+fn row_to_id_name(row: &Row) -> Result<IdName, rusqlite::Error> {
+    Ok(IdName {
+        id: row.get("id")?,
+        name: row.get("name")?,
+    })
+}
+
+pub fn edit_custom_group(group: Group) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute(
+        r#"
+        UPDATE groups
+        SET name = ?, image = ?, search_name = ?
+        WHERE id = ?
+    "#,
+        params![
+            group.name,
+            group.image,
+            fold_search_name(&group.name),
+            group.id
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_group_by_id(id: i64) -> Result<Option<Group>> {
+    let sql = get_conn()?;
+    let group: Option<Group> = sql
+        .query_row(
+            "SELECT * FROM groups WHERE id = ?",
+            params![id],
+            row_to_custom_group,
+        )
+        .optional()?;
+    Ok(group)
+}
+
+fn row_to_custom_group(row: &Row) -> Result<Group, rusqlite::Error> {
+    Ok(Group {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        image: row.get("image")?,
+        source_id: row.get("source_id")?,
+    })
+}
+
+pub fn get_custom_channel_extra_data(
+    id: i64,
+    group_id: Option<i64>,
+) -> Result<CustomChannelExtraData> {
+    Ok(CustomChannelExtraData {
+        headers: get_channel_headers_by_id(id)?,
+        group: match group_id {
+            None => None,
+            Some(group) => get_group_by_id(group)?,
+        },
+    })
+}
+
+/// A group with channels is only deleted when they move to `new_id` or go with it on `cascade`
+pub fn delete_custom_group(
+    id: i64,
+    new_id: Option<i64>,
+    do_channels_update: bool,
+    cascade: bool,
+) -> Result<()> {
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    if do_channels_update {
+        tx.execute(
+            r#"
+        UPDATE channels
+        SET group_id = ?
+        WHERE group_id = ?
+    "#,
+            params![new_id, id],
+        )?;
+    } else if !cascade {
+        let channels: u64 = tx.query_row(
+            "SELECT COUNT(*) FROM channels WHERE group_id = ?",
+            params![id],
+            |row| row.get(0),
+        )?;
+        if channels > 0 {
+            return Err(GroupNotEmpty { channels }.into());
+        }
+    }
+    let deleted = GROUP_SCOPE.delete(&tx, id)?;
+    track_deleted_rows(&tx, deleted)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Positions are rewritten from 1 in the given order, channels missing from `ordered_ids` keep theirs
+pub fn reorder_custom_channels(group_id: Option<i64>, ordered_ids: Vec<i64>) -> Result<()> {
+    do_tx(|tx| {
+        let mut stmt =
+            tx.prepare("UPDATE channels SET position = ? WHERE id = ? AND group_id IS ?")?;
+        for (position, id) in ordered_ids.iter().enumerate() {
+            stmt.execute(params![position as i64 + 1, id, group_id])?;
+        }
+        Ok(())
+    })
+}
+
+pub fn group_not_empty(id: i64) -> Result<bool> {
+    let sql = get_conn()?;
+    Ok(sql
+        .query_row(
+            r#"
+                SELECT 1
+                FROM channels
+                WHERE group_id = ?
+            "#,
+            params![id],
+            |row| row.get::<_, u8>(0),
+        )
+        .optional()?
+        .is_some())
+}
+
+pub fn get_custom_channels(group_id: Option<i64>, source_id: i64) -> Result<Vec<CustomChannel>> {
+    let sql = get_conn()?;
+    let mut sql_query = r#"
+        SELECT c.name, c.image, c.url, c.media_type, ch.referrer, ch.user_agent, ch.http_origin, ch.ignore_ssl
+        FROM channels c
+        LEFT JOIN channel_http_headers ch on ch.channel_id = c.id
+        WHERE source_id = ?
+    "#.to_string();
+    let mut params: Vec<i64> = Vec::with_capacity(2);
+    params.push(source_id);
+    if let Some(id) = group_id {
+        sql_query.push_str("\nAND group_id = ?");
+        params.push(id);
+    } else {
+        sql_query.push_str("\nAND group_id IS NULL");
+    }
+    sql_query.push_str("\nORDER BY c.position, c.id");
+    let result: Vec<CustomChannel> = sql
+        .prepare(&sql_query)?
+        .query_map(
+            params_from_iter(params),
+            logged("channels", row_to_custom_channel),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(result)
+}
+
+fn row_to_custom_channel(row: &Row) -> Result<CustomChannel, rusqlite::Error> {
+    Ok(CustomChannel {
+        data: Channel {
+            name: row.get("name")?,
+            image: row.get("image")?,
+            url: row.get("url")?,
+            media_type: row.get("media_type")?,
+            favorite: false,
+            watched: false,
+            group_id: None,
+            group: None,
+            id: None,
+            series_id: None,
+            source_id: None,
+            stream_id: None,
+            tvg_id: None,
+            media_types: None,
+            catchup: None,
+        },
+        headers: Some(ChannelHttpHeaders {
+            http_origin: row.get("http_origin")?,
+            ignore_ssl: row.get("ignore_ssl")?,
+            referrer: row.get("referrer")?,
+            user_agent: row.get("user_agent")?,
+            channel_id: None,
+            id: None,
+        }),
+    })
+}
+
+fn get_groups_by_source_id(id: i64) -> Result<Vec<Group>> {
     let sql = get_conn()?;
-    let offset = filters.page * PAGE_SIZE - PAGE_SIZE;
-    let query = filters.query.unwrap_or("".to_string());
-    let keywords: Vec<String> = match filters.use_keywords {
-        true => query
-            .split(" ")
-            .map(|f| format!("%{f}%").to_string())
-            .collect(),
-        false => vec![format!("%{query}%")],
-    };
-    let mut params: Vec<&dyn rusqlite::ToSql> = Vec::with_capacity(2 + filters.source_ids.len());
-    let sql_query = format!(
-        r#"
+    let result: Vec<Group> = sql
+        .prepare(
+            r#"
         SELECT *
         FROM groups
-        WHERE ({})
-        AND source_id in ({})
-        LIMIT ?, ?
+        WHERE source_id = ?
     "#,
-        get_keywords_sql(keywords.len()),
-        generate_placeholders(filters.source_ids.len())
-    );
-    params.extend(to_to_sql(&keywords));
-    params.extend(to_to_sql(&filters.source_ids));
-    params.push(&offset);
-    params.push(&PAGE_SIZE);
-    let channels: Vec<Channel> = sql
-        .prepare(&sql_query)?
-        .query_map(params_from_iter(params), row_to_group)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(channels)
+        )?
+        .query_map(params![id], logged("groups", row_to_custom_group))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(result)
 }
 
-fn row_to_group(row: &Row) -> std::result::Result<Channel, rusqlite::Error> {
-    let channel = Channel {
-        id: row.get("id")?,
-        name: row.get("name")?,
-        group: None,
-        image: row.get("image")?,
-        media_type: media_type::GROUP,
-        url: None,
-        series_id: None,
-        group_id: None,
-        favorite: false,
-        source_id: row.get("source_id")?,
-        stream_id: None,
-    };
-    Ok(channel)
+pub fn get_custom_groups(source_id: i64) -> Result<Vec<ExportedGroup>> {
+    let groups = get_groups_by_source_id(source_id)?;
+    let mut export: Vec<ExportedGroup> = Vec::new();
+    for group in groups {
+        export.push(ExportedGroup {
+            group: Group {
+                name: group.name,
+                image: group.image,
+                source_id: None,
+                id: None,
+            },
+            channels: get_custom_channels(group.id, source_id)?,
+        });
+    }
+    Ok(export)
 }
 
-fn row_to_channel(row: &Row) -> std::result::Result<Channel, rusqlite::Error> {
-    let channel = Channel {
-        id: row.get("id")?,
-        name: row.get("name")?,
-        group_id: row.get("group_id")?,
-        image: row.get("image")?,
-        media_type: row.get("media_type")?,
-        source_id: row.get("source_id")?,
-        url: row.get("url")?,
-        favorite: row.get("favorite")?,
-        series_id: None,
-        group: None,
-        stream_id: row.get("stream_id")?,
+pub fn do_tx<F, T>(f: F) -> Result<T>
+where
+    F: FnOnce(&Transaction) -> Result<T>,
+{
+    let mut sql = get_conn()?;
+    let tx = sql.transaction()?;
+    let result = f(&tx)?;
+    tx.commit()?;
+    Ok(result)
+}
+
+pub fn update_source(source: Source) -> Result<()> {
+    let sql = get_conn()?;
+    let password = match (&source.password, source.id) {
+        (Some(password), Some(id)) => Some(secrets::store(id, password)?),
+        _ => None,
     };
-    Ok(channel)
+    sql.execute(
+        r#"
+        UPDATE sources
+        SET username = ?, password = ?, url = ?, use_tvg_id = ?, import_live = ?, import_vod = ?, import_series = ?
+        WHERE id = ?"#,
+        params![
+            source.username,
+            password,
+            source.url,
+            source.use_tvg_id,
+            source.import_live,
+            source.import_vod,
+            source.import_series,
+            source.id
+        ],
+    )?;
+    Ok(())
 }
 
-pub fn delete_channels_by_source(tx: &Transaction, source_id: i64) -> Result<()> {
+pub fn wipe(tx: &Transaction, id: i64) -> Result<()> {
+    snapshot_added_at(tx, id)?;
+    park_favorites(tx, id)?;
+    delete_channels_by_source(tx, id)?;
     tx.execute(
+        "DELETE FROM fetched_series WHERE source_id = ?",
+        params![id],
+    )?;
+    delete_groups_by_source(tx, id)?;
+    Ok(())
+}
+
+fn snapshot_added_at(tx: &Transaction, source_id: i64) -> Result<()> {
+    tx.execute_batch(
         r#"
-        DELETE FROM channels
+        CREATE TEMP TABLE IF NOT EXISTS previous_channels (
+            name varchar(100),
+            url varchar(500),
+            added_at integer
+        );
+        DELETE FROM previous_channels;
+        CREATE INDEX IF NOT EXISTS temp.index_previous_channels ON previous_channels(name, url);
+    "#,
+    )?;
+    tx.execute(
+        r#"
+        INSERT INTO previous_channels (name, url, added_at)
+        SELECT name, url, added_at
+        FROM channels
         WHERE source_id = ?
-        AND favorite = 0;
     "#,
-        params![source_id.to_string()],
+        params![source_id],
     )?;
     Ok(())
 }
 
-pub fn delete_groups_by_source(tx: &Transaction, source_id: i64) -> Result<()> {
+pub fn restore_added_at(tx: &Transaction, source_id: i64) -> Result<()> {
     tx.execute(
         r#"
-        DELETE FROM groups
+        UPDATE channels
+        SET added_at = (
+            SELECT p.added_at
+            FROM previous_channels p
+            WHERE p.name = channels.name AND p.url = channels.url
+        )
         WHERE source_id = ?
-        AND ID not in (
-            SELECT group_id
-            FROM channels
-            WHERE favorite = 1
+        AND EXISTS (
+            SELECT 1
+            FROM previous_channels p
+            WHERE p.name = channels.name AND p.url = channels.url
         )
     "#,
-        params!(source_id),
+        params![source_id],
     )?;
+    tx.execute_batch("DROP TABLE IF EXISTS temp.previous_channels")?;
     Ok(())
 }
 
-pub fn delete_source(id: i64) -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute(
-        r#"
-        DELETE FROM channels
-        WHERE source_id = ?;
-    "#,
-        params![id],
-    )?;
-    sql.execute(
+/// Detaches the favorites, and the channels carrying a note or tags, from their source for
+/// the length of a refresh so the import inserts fresh rows for them, which
+/// `rematch_favorites` then looks up. Episodes are loaded separately and keep their rows
+/// They are parked in a table of their own rather than a temporary one so an interrupted
+/// refresh still brings them back, the ones it parked stay until the next refresh ends
+fn park_favorites(tx: &Transaction, source_id: i64) -> Result<()> {
+    tx.execute(
         r#"
-        DELETE FROM groups
-        WHERE source_id = ?;
+        INSERT OR IGNORE INTO parked_favorites (id, source_id, name, url, tvg_id, group_name, favorite, favorite_position)
+        SELECT c.id, c.source_id, c.name, c.url, c.tvg_id, g.name, c.favorite, c.favorite_position
+        FROM channels c
+        LEFT JOIN groups g ON g.id = c.group_id
+        WHERE c.source_id = ?
+        AND (
+            c.favorite = 1
+            OR c.id IN (SELECT channel_id FROM channel_notes)
+            OR c.id IN (SELECT channel_id FROM channel_tags)
+        )
+        AND c.series_id IS NULL
     "#,
-        params![id],
+        params![source_id],
     )?;
-    let count = sql.execute(
-        r#"
-        DELETE FROM sources
-        WHERE id = ?;
-    "#,
-        params![id],
+    tx.execute(
+        "UPDATE channels SET source_id = NULL WHERE id IN (SELECT id FROM parked_favorites WHERE source_id = ?)",
+        params![source_id],
     )?;
-    if count != 1 {
-        return Err(anyhow!("No sources were deleted"));
+    Ok(())
+}
+
+/// Carries each favorite over to its row in the refreshed source, found by name and url,
+/// then url alone, then tvg-id, then a similar name in the same group. Notes and tags move
+/// along. Favorites without a successor are kept and reported by `get_orphaned_favorites`,
+/// other parked channels are dropped like any channel the source no longer has
+fn rematch_favorites(tx: &Transaction, source_id: i64) -> Result<()> {
+    let parked: Vec<(
+        i64,
+        String,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        bool,
+        Option<i64>,
+    )> = tx
+        .prepare(
+            "SELECT id, name, url, tvg_id, group_name, favorite, favorite_position FROM parked_favorites WHERE source_id = ?",
+        )?
+        .query_map(params![source_id], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get::<_, Option<bool>>(5)?.unwrap_or(false),
+                row.get(6)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, name, url, tvg_id, group_name, favorite, favorite_position) in parked {
+        let successor = find_favorite_successor(
+            tx,
+            source_id,
+            &name,
+            url.as_deref(),
+            tvg_id.as_deref(),
+            group_name.as_deref(),
+        )?;
+        match successor {
+            Some(successor) => {
+                tx.execute(
+                    "UPDATE channels SET favorite = MAX(favorite, ?), favorite_position = COALESCE(favorite_position, ?) WHERE id = ?",
+                    params![favorite, favorite_position, successor],
+                )?;
+                move_notes_and_tags(tx, id, successor)?;
+                tx.execute(
+                    "DELETE FROM channel_http_headers WHERE channel_id = ?",
+                    params![id],
+                )?;
+                tx.execute("DELETE FROM channels WHERE id = ?", params![id])?;
+                tx.execute(
+                    "DELETE FROM orphaned_favorites WHERE channel_id = ?",
+                    params![id],
+                )?;
+            }
+            None if !favorite => {
+                tx.execute(
+                    "DELETE FROM channel_http_headers WHERE channel_id = ?",
+                    params![id],
+                )?;
+                tx.execute("DELETE FROM channels WHERE id = ?", params![id])?;
+            }
+            None => {
+                tx.execute(
+                    "UPDATE channels SET source_id = ? WHERE id = ?",
+                    params![source_id, id],
+                )?;
+                tx.execute(
+                    r#"
+                    INSERT INTO orphaned_favorites (channel_id, source_id, orphaned_at)
+                    VALUES (?, ?, strftime('%s', 'now'))
+                    ON CONFLICT (channel_id) DO NOTHING
+                "#,
+                    params![id, source_id],
+                )?;
+            }
+        }
     }
+    tx.execute(
+        "DELETE FROM parked_favorites WHERE source_id = ?",
+        params![source_id],
+    )?;
     Ok(())
 }
 
-pub fn get_channel_count_by_source(id: i64) -> Result<u64> {
-    let sql = get_conn()?;
-    let count = sql.query_row(
-        "SELECT COUNT(*) FROM channels WHERE source_id = ?",
-        params![id],
-        |row| row.get::<_, u64>(0),
+fn move_notes_and_tags(tx: &Transaction, from: i64, to: i64) -> Result<()> {
+    tx.execute(
+        "UPDATE OR IGNORE channel_tags SET channel_id = ?1 WHERE channel_id = ?2",
+        params![to, from],
     )?;
-    Ok(count)
+    tx.execute(
+        "UPDATE OR REPLACE channel_notes SET channel_id = ?1 WHERE channel_id = ?2",
+        params![to, from],
+    )?;
+    Ok(())
 }
 
-pub fn source_name_exists(name: &str) -> Result<bool> {
+/// Notes and tags of channels deleted by an import, episodes included
+fn purge_detached_notes_and_tags(tx: &Transaction) -> Result<()> {
+    tx.execute(
+        "DELETE FROM channel_notes WHERE channel_id NOT IN (SELECT id FROM channels)",
+        [],
+    )?;
+    tx.execute(
+        "DELETE FROM channel_tags WHERE channel_id NOT IN (SELECT id FROM channels)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn find_favorite_successor(
+    tx: &Transaction,
+    source_id: i64,
+    name: &str,
+    url: Option<&str>,
+    tvg_id: Option<&str>,
+    group_name: Option<&str>,
+) -> Result<Option<i64>> {
+    let find = |condition: &str, params: &[&dyn rusqlite::ToSql]| -> Result<Option<i64>> {
+        let query = format!(
+            "SELECT id FROM channels WHERE source_id = ? AND favorite = 0 AND series_id IS NULL AND {condition} ORDER BY id LIMIT 1"
+        );
+        let mut all: Vec<&dyn rusqlite::ToSql> = vec![&source_id];
+        all.extend_from_slice(params);
+        Ok(tx
+            .query_row(&query, params_from_iter(all), |row| row.get(0))
+            .optional()?)
+    };
+    if let Some(id) = find("name = ? AND url IS ?", &[&name, &url])? {
+        return Ok(Some(id));
+    }
+    if let Some(url) = url {
+        if let Some(id) = find("url = ?", &[&url])? {
+            return Ok(Some(id));
+        }
+    }
+    if let Some(tvg_id) = tvg_id.filter(|tvg_id| !tvg_id.trim().is_empty()) {
+        if let Some(id) = find("tvg_id = ?", &[&tvg_id])? {
+            return Ok(Some(id));
+        }
+    }
+    let candidates: Vec<(i64, String)> = tx
+        .prepare(
+            r#"
+            SELECT c.id, c.name
+            FROM channels c
+            LEFT JOIN groups g ON g.id = c.group_id
+            WHERE c.source_id = ?
+            AND c.favorite = 0
+            AND c.series_id IS NULL
+            AND g.name IS ?
+        "#,
+        )?
+        .query_map(params![source_id, group_name], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(find_similar_name(name, &candidates))
+}
+
+/// Names match when they only differ by punctuation and quality markers, or when one
+/// extends the other with extra words. The closest length wins
+fn find_similar_name(name: &str, candidates: &[(i64, String)]) -> Option<i64> {
+    let key = channel_name_key(name);
+    if key.is_empty() {
+        return None;
+    }
+    candidates
+        .iter()
+        .map(|(id, candidate)| (id, channel_name_key(candidate)))
+        .filter(|(_, candidate)| {
+            *candidate == key
+                || candidate.starts_with(&format!("{key} "))
+                || key.starts_with(&format!("{candidate} "))
+        })
+        .min_by_key(|(_, candidate)| candidate.len().abs_diff(key.len()))
+        .map(|(id, _)| *id)
+}
+
+pub fn get_orphaned_favorites() -> Result<Vec<Channel>> {
     let sql = get_conn()?;
-    Ok(sql
+    let channels = sql
+        .prepare(
+            r#"
+            SELECT c.*
+            FROM orphaned_favorites o
+            JOIN channels c ON c.id = o.channel_id
+            WHERE c.favorite = 1
+            ORDER BY o.orphaned_at DESC, c.name
+        "#,
+        )?
+        .query_map([], logged("channels", row_to_channel))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(channels)
+}
+
+/// Live and radio channels of a playlist in its order, or the favorites in theirs
+/// without one. Channels of disabled sources are left out
+pub fn get_lineup_channels(playlist_id: Option<i64>) -> Result<Vec<Channel>> {
+    let sql = get_conn()?;
+    let query = match playlist_id {
+        Some(_) => {
+            r#"
+            SELECT c.*
+            FROM playlist_channels pc
+            JOIN channels c ON c.id = pc.channel_id
+            WHERE pc.playlist_id = ?1
+            AND c.media_type IN (?2, ?3)
+            AND c.source_id IN (SELECT id FROM sources WHERE enabled = 1)
+            ORDER BY pc.position
+        "#
+        }
+        None => {
+            r#"
+            SELECT c.*
+            FROM channels c
+            WHERE c.favorite = 1
+            AND ?1 IS NULL
+            AND c.media_type IN (?2, ?3)
+            AND c.source_id IN (SELECT id FROM sources WHERE enabled = 1)
+            ORDER BY c.favorite_position IS NULL, c.favorite_position, c.name
+        "#
+        }
+    };
+    let channels = sql
+        .prepare(query)?
+        .query_map(
+            params![playlist_id, media_type::LIVESTREAM, media_type::RADIO],
+            logged("channels", row_to_channel),
+        )?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(channels)
+}
+
+/// Probes are kept by the url of the channel so they outlive the channel ids of a refresh
+pub fn get_stream_probe(channel_id: i64, max_age: i64) -> Result<Option<StreamProbe>> {
+    let sql = get_conn()?;
+    let probe = sql
         .query_row(
             r#"
-    SELECT 1
-    FROM sources
-    WHERE name = ?1
-    "#,
-            [name],
-            |row| row.get::<_, u8>(0),
+            SELECT p.*, c.id AS channel_id
+            FROM stream_probe p
+            JOIN channels c ON c.source_id = p.source_id AND c.url = p.url
+            WHERE c.id = ?
+            AND p.probed_at >= strftime('%s', 'now') - ?
+        "#,
+            params![channel_id, max_age],
+            row_to_stream_probe,
         )
-        .optional()?
-        .is_some())
+        .optional()?;
+    Ok(probe)
 }
 
-pub fn favorite_channel(channel_id: i64, favorite: bool) -> Result<()> {
+fn row_to_stream_probe(row: &Row) -> Result<StreamProbe, rusqlite::Error> {
+    let variants: Option<String> = row.get("variants")?;
+    Ok(StreamProbe {
+        channel_id: row.get("channel_id")?,
+        latency_ms: row.get("latency_ms")?,
+        throughput_kbps: row.get("throughput_kbps")?,
+        container: row.get("container")?,
+        variants: variants
+            .and_then(|v| serde_json::from_str(&v).ok())
+            .unwrap_or_default(),
+        probed_at: row.get("probed_at")?,
+    })
+}
+
+pub fn set_stream_probe(probe: &StreamProbe) -> Result<()> {
     let sql = get_conn()?;
     sql.execute(
         r#"
-        UPDATE channels
-        SET favorite = ?1
-        WHERE id = ?2
+        INSERT OR REPLACE INTO stream_probe (source_id, url, latency_ms, throughput_kbps, container, variants, probed_at)
+        SELECT source_id, url, ?2, ?3, ?4, ?5, ?6 FROM channels WHERE id = ?1 AND url IS NOT NULL
     "#,
-        params![favorite, channel_id],
+        params![
+            probe.channel_id,
+            probe.latency_ms,
+            probe.throughput_kbps,
+            probe.container,
+            serde_json::to_string(&probe.variants)?,
+            probe.probed_at
+        ],
     )?;
     Ok(())
 }
 
-pub fn get_sources() -> Result<Vec<Source>> {
-    let sql = get_conn()?;
-    let sources: Vec<Source> = sql
-        .prepare("SELECT * FROM sources")?
-        .query_map([], row_to_source)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(sources)
-}
-
-pub fn get_enabled_sources() -> Result<Vec<Source>> {
+/// Drops reminders that are over. One-off reminders covered by an XMLTV feed follow
+/// their programme when the feed moved it, and are dropped when it was removed.
+/// Returns how many reminders were dropped
+pub fn clean_epgs() -> Result<usize> {
     let sql = get_conn()?;
-    let sources: Vec<Source> = sql
-        .prepare("SELECT * FROM sources WHERE enabled = 1")?
-        .query_map([], row_to_source)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(sources)
-}
-
-fn row_to_source(row: &Row) -> std::result::Result<Source, rusqlite::Error> {
-    Ok(Source {
-        id: row.get("id")?,
-        name: row.get("name")?,
-        username: row.get("username")?,
-        password: row.get("password")?,
-        url: row.get("url")?,
-        source_type: row.get("source_type")?,
-        url_origin: None,
-        enabled: row.get("enabled")?,
-        use_tvg_id: row.get("use_tvg_id")?,
-    })
+    let mut removed = sql.execute(
+        "DELETE FROM epg WHERE COALESCE(snoozed_until, start_timestamp) < strftime('%s', 'now')",
+        [],
+    )?;
+    let reminders = sql
+        .prepare(
+            r#"
+            SELECT * FROM epg
+            WHERE tvg_id IS NOT NULL AND COALESCE(repeat, 0) = 0 AND snoozed_until IS NULL
+            "#,
+        )?
+        .query_map([], logged("epg", row_to_epg))?
+        .collect::<rusqlite::Result<Vec<EPGNotify>>>()?;
+    let now = chrono::Utc::now().timestamp();
+    for reminder in reminders {
+        let Some(tvg_id) = reminder.tvg_id.as_deref() else {
+            continue;
+        };
+        let start = reminder.start_timestamp;
+        let around = query_programmes(
+            &sql,
+            tvg_id,
+            reminder.source_id,
+            start - REMINDER_SEARCH_WINDOW,
+            start + REMINDER_SEARCH_WINDOW,
+        )?;
+        // Reminders made from the provider's short EPG may not be in any feed
+        if !around.iter().any(|p| p.start <= start && p.stop > start) {
+            continue;
+        }
+        let programme = around
+            .iter()
+            .filter(|p| p.title == reminder.title)
+            .min_by_key(|p| (p.start - start).abs());
+        match programme {
+            Some(programme) if programme.start == start => {}
+            Some(programme) if programme.start > now => {
+                sql.execute(
+                    "UPDATE epg SET start_timestamp = ? WHERE epg_id = ?",
+                    params![programme.start, reminder.epg_id],
+                )?;
+            }
+            _ => {
+                removed +=
+                    sql.execute("DELETE FROM epg WHERE epg_id = ?", params![reminder.epg_id])?;
+            }
+        }
+    }
+    Ok(removed)
 }
 
-pub fn get_source_from_id(source_id: i64) -> Result<Source> {
+pub fn add_epg(epg: EPGNotify) -> Result<()> {
     let sql = get_conn()?;
-    Ok(sql.query_row(
+    sql.execute(
         r#"
-    SELECT * FROM sources where id = ?"#,
-        [source_id],
-        row_to_source,
-    )?)
+        INSERT OR REPLACE INTO epg (epg_id, channel_id, tvg_id, source_id, channel_name, title,
+            start_timestamp, repeat, snooze_minutes, snoozed_until)
+        VALUES (?,?,?,?,?,?,?,?,?,?)
+        "#,
+        params![
+            epg.epg_id,
+            epg.channel_id,
+            epg.tvg_id,
+            epg.source_id,
+            epg.channel_name,
+            epg.title,
+            epg.start_timestamp,
+            epg.repeat,
+            epg.snooze_minutes,
+            epg.snoozed_until
+        ],
+    )?;
+    Ok(())
 }
 
-pub fn set_source_enabled(value: bool, source_id: i64) -> Result<()> {
+pub fn snooze_epg(epg_id: String, minutes: Option<u16>) -> Result<()> {
     let sql = get_conn()?;
     sql.execute(
         r#"
-        UPDATE sources
-        SET enabled = ?
-        WHERE id = ?
-    "#,
-        params![value, source_id],
+        UPDATE epg
+        SET snoozed_until = strftime('%s', 'now') + 60 * COALESCE(?, snooze_minutes, ?)
+        WHERE epg_id = ?
+        "#,
+        params![minutes, DEFAULT_SNOOZE_MINUTES, epg_id],
     )?;
     Ok(())
 }
 
-pub fn add_custom_channel(tx: &Transaction, channel: CustomChannel) -> Result<()> {
-    insert_channel(tx, channel.data)?;
-    if let Some(mut headers) = channel.headers {
-        if channel_headers_empty(&headers) {
-            return Ok(());
-        }
-        headers.channel_id = Some(tx.last_insert_rowid());
-        insert_channel_headers(tx, headers)?;
-    }
+pub fn remove_epg(epg_id: String) -> Result<()> {
+    let sql = get_conn()?;
+    sql.execute("DELETE FROM epg WHERE epg_id = ?", params![epg_id])?;
     Ok(())
 }
 
-fn channel_headers_empty(headers: &ChannelHttpHeaders) -> bool {
-    return headers.ignore_ssl.is_none()
-        && headers.http_origin.is_none()
-        && headers.referrer.is_none()
-        && headers.user_agent.is_none();
+pub fn get_epgs() -> Result<Vec<EPGNotify>> {
+    let sql = get_conn()?;
+    let epgs: Vec<EPGNotify> = sql
+        .prepare("SELECT * FROM epg")?
+        .query_map(params![], logged("epg", row_to_epg))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(epgs)
 }
 
-pub fn get_custom_source(name: String) -> Source {
-    Source {
-        id: None,
-        name: name.to_string(),
-        enabled: true,
-        username: None,
-        password: None,
-        source_type: source_type::CUSTOM,
-        url: None,
-        url_origin: None,
-        use_tvg_id: None,
+pub fn resolve_epg_channel(epg: &EPGNotify) -> Result<Option<Channel>> {
+    let sql = get_conn()?;
+    let query = |condition: &str| {
+        format!(
+            r#"
+            SELECT * FROM channels
+            WHERE {condition}
+            AND (?2 IS NULL OR source_id = ?2)
+            AND url IS NOT NULL
+            LIMIT 1
+            "#
+        )
+    };
+    if let Some(tvg_id) = &epg.tvg_id {
+        let channel = sql
+            .query_row(
+                &query("tvg_id = ?1"),
+                params![tvg_id, epg.source_id],
+                row_to_channel,
+            )
+            .optional()?;
+        if channel.is_some() {
+            return Ok(channel);
+        }
     }
+    let channel = sql
+        .query_row(
+            &query("name = ?1"),
+            params![epg.channel_name, epg.source_id],
+            row_to_channel,
+        )
+        .optional()?;
+    Ok(channel)
 }
 
-pub fn edit_custom_channel(channel: CustomChannel) -> Result<()> {
-    let mut sql = get_conn()?;
-    let tx = sql.transaction()?;
-    match edit_custom_channel_tx(channel, &tx) {
-        Ok(_) => {
-            tx.commit()?;
-            Ok(())
+pub fn get_epg_ids() -> Result<Vec<String>> {
+    let sql = get_conn()?;
+    let epgs: Vec<String> = sql
+        .prepare("SELECT epg_id FROM epg")?
+        .query_map(params![], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(epgs)
+}
+
+fn row_to_epg(row: &Row) -> Result<EPGNotify, rusqlite::Error> {
+    Ok(EPGNotify {
+        epg_id: row.get("epg_id")?,
+        channel_id: row.get("channel_id")?,
+        tvg_id: row.get("tvg_id")?,
+        source_id: row.get("source_id")?,
+        repeat: row.get("repeat")?,
+        snooze_minutes: row.get("snooze_minutes")?,
+        snoozed_until: row.get("snoozed_until")?,
+        channel_name: row.get("channel_name")?,
+        start_timestamp: row.get("start_timestamp")?,
+        title: row.get("title")?,
+    })
+}
+
+#[cfg(test)]
+mod test_sql {
+    use std::{
+        collections::{HashMap, HashSet},
+        time::Duration,
+    };
+
+    use rusqlite::{params, Connection};
+
+    use crate::{
+        media_type, refresh_status,
+        search_syntax::InvalidQuery,
+        settings::{RECORDING_PATH, USE_STREAM_CACHING},
+        source_type,
+        sql::{create_structure, drop_db, structure_exists},
+        types::{
+            Channel, ChannelHttpHeaders, CustomChannel, DeleteImpact, EPGNotify, Filters, Group,
+            GroupMerge, Programme, Source, StreamProbe,
+        },
+        view_type,
+    };
+
+    use super::{
+        add_custom_channel, add_custom_group, add_epg, add_epg_source, add_to_playlist,
+        apply_channel_overrides, apply_filter_preset, apply_watched, auto_complete_channels,
+        backup_to, begin_import, begin_staged_import, clean_epgs, commit_batch, compact,
+        create_or_find_source_by_name, create_or_initialize_db, create_playlist, create_source,
+        create_tag, delete_custom_group, delete_filter_preset, delete_group_merge_rule,
+        delete_source, delete_tag, do_tx, duplicate_custom_channel, duplicate_custom_group,
+        favorite_channel, favorite_group, finish_epg_refresh, finish_import, fold_search_name,
+        get_channel_by_id, get_channel_headers_by_id, get_channel_note, get_channel_player,
+        get_channel_search_query, get_channel_tags, get_channel_volume, get_conn,
+        get_custom_channels, get_custom_source, get_db_info, get_db_path,
+        get_deleted_rows_since_vacuum, get_epg_grid, get_epgs, get_expected_schema_version,
+        get_group_by_id, get_group_delete_impact, get_group_merge_rules, get_last_backup_at,
+        get_media_type_counts, get_or_insert_group, get_orphaned_favorites, get_pending_imports,
+        get_playlists, get_refresh_log, get_series_episodes, get_series_summary, get_settings,
+        get_source_delete_impact, get_source_from_id, get_sources, get_sources_with_stats,
+        get_stream_probe, get_tags, import_transaction, init_test_db, insert_channel,
+        insert_channel_headers, insert_channels_batch, insert_programme, is_corrupt,
+        list_filter_presets, lock_import, merge_groups, migrate_plaintext_passwords,
+        move_corrupt_database, move_database, open_database, pick_group_image, pick_group_images,
+        record_refresh, reorder_custom_channels, reorder_favorites, reorder_playlist,
+        save_filter_preset, search, search_channels, search_epg, search_groups,
+        series_has_episodes, set_bulk_import, set_channel_group, set_channel_group_id,
+        set_channel_media_type, set_channel_note, set_channel_player, set_channel_volume,
+        set_group_image, set_series_watched, set_source_enabled, set_stream_probe, set_watched,
+        source_name_exists, stage_channels, swap_in_staged, tag_channel, template_xtream_urls,
+        update_settings, update_source, wipe, ChannelConditions, GroupNotEmpty, NewerDatabase,
+        SourceAlreadyExists, AUTO_COMPLETE_PREFIX_QUERY, INSERT_BATCH_SIZE, PROGRAMMES_QUERY,
+        TEST_DB,
+    };
+
+    fn fixture_channel(name: &str, group: &str, media_type: u8, source_id: i64) -> Channel {
+        Channel {
+            id: None,
+            name: name.to_string(),
+            url: Some(format!("http://example.com/{}", name.replace(' ', "_"))),
+            group: Some(group.to_string()),
+            image: None,
+            media_type,
+            source_id: Some(source_id),
+            series_id: None,
+            group_id: None,
+            favorite: false,
+            watched: false,
+            stream_id: None,
+            tvg_id: None,
+            media_types: None,
+            catchup: None,
         }
-        Err(e) => {
-            tx.rollback().unwrap_or_else(|e| log(format!("{:?}", e)));
-            return Err(e);
+    }
+
+    fn seed_fixtures() -> i64 {
+        do_tx(|tx| {
+            let source_id = create_or_find_source_by_name(tx, &test_source("fixture"))?;
+            let mut groups = HashMap::new();
+            let mut channels = vec![
+                fixture_channel("France 24", "News", media_type::LIVESTREAM, source_id),
+                fixture_channel("BBC One", "News", media_type::LIVESTREAM, source_id),
+                fixture_channel("TF1 France", "Général", media_type::LIVESTREAM, source_id),
+                fixture_channel("Amélie", "Films", media_type::MOVIE, source_id),
+                fixture_channel("Fracture", "Films", media_type::MOVIE, source_id),
+            ];
+            for channel in channels.iter_mut() {
+                set_channel_group_id(&mut groups, channel, tx, &source_id)?;
+            }
+            insert_channels_batch(tx, &channels)?;
+            Ok(source_id)
+        })
+        .unwrap()
+    }
+
+    fn fixture_filters(query: &str, view_type: u8, source_id: i64) -> Filters {
+        Filters {
+            media_types: Some(vec![media_type::LIVESTREAM, media_type::MOVIE]),
+            page: 1,
+            query: Some(query.to_string()),
+            source_ids: vec![source_id],
+            view_type,
+            group_id: None,
+            group_ids: None,
+            series_id: None,
+            use_keywords: false,
+            exclude_dead: None,
+            playlist_id: None,
+            after_id: None,
+            after_name: None,
+            include_group_names: false,
+            hide_watched: false,
+            tag: None,
+            advanced_query: false,
         }
     }
-}
 
-fn edit_custom_channel_tx(channel: CustomChannel, tx: &Transaction) -> Result<()> {
-    tx.execute(
-        r#"
-        UPDATE channels
-        SET name = ?, image = ?, url = ?, media_type = ?, group_id = ?
-        WHERE id = ?
-    "#,
-        params![
-            channel.data.name,
-            channel.data.image,
-            channel.data.url,
-            channel.data.media_type,
-            channel.data.group_id,
-            channel.data.id
-        ],
-    )?;
-    if let Some(mut headers) = channel.headers {
-        headers.channel_id = channel.data.id;
-        tx.execute(
-            r#"
-            INSERT INTO channel_http_headers (referrer, user_agent, http_origin, ignore_ssl, channel_id)
-            VALUES (?1, ?2, ?3, ?4, ?5)
-            ON CONFLICT(channel_id) DO UPDATE SET
-                referrer = ?1,
-                user_agent = ?2,
-                http_origin = ?3,
-                ignore_ssl = ?4
-        "#,
-            params![
-                headers.referrer,
-                headers.user_agent,
-                headers.http_origin,
-                headers.ignore_ssl,
-                headers.channel_id
-            ],
-        )?;
-    } else {
-        tx.execute(
-            "DELETE FROM channel_http_headers WHERE channel_id = ?",
-            params![channel.data.id],
-        )?;
+    fn names(channels: Vec<Channel>) -> Vec<String> {
+        let mut names: Vec<String> = channels.into_iter().map(|c| c.name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_structure_exists() {
+        let _db = init_test_db();
+        assert_eq!(structure_exists().unwrap(), true);
+        drop_db().unwrap();
+        assert_eq!(structure_exists().unwrap(), false);
+        create_structure().unwrap();
+        assert_eq!(structure_exists().unwrap(), true);
+    }
+
+    #[test]
+    fn test_newer_database_is_rejected() {
+        let _db = init_test_db();
+        let expected = get_expected_schema_version();
+        get_conn()
+            .unwrap()
+            .pragma_update(None, "user_version", expected + 1)
+            .unwrap();
+        let error = create_or_initialize_db().unwrap_err();
+        let error = error.downcast_ref::<NewerDatabase>().unwrap();
+        assert_eq!(error.found, expected + 1);
+        assert_eq!(error.expected, expected);
+        assert_eq!(get_db_info().unwrap().schema_version, expected + 1);
+    }
+
+    #[test]
+    fn test_update_settings() {
+        let _db = init_test_db();
+        let mut map: HashMap<String, String> = HashMap::with_capacity(3);
+        map.insert(USE_STREAM_CACHING.to_string(), true.to_string());
+        map.insert(RECORDING_PATH.to_string(), "somePath".to_string());
+        update_settings(map.clone()).unwrap();
+        update_settings(map).unwrap();
+        let settings = get_settings().unwrap();
+        assert_eq!(settings.get(USE_STREAM_CACHING).unwrap(), "true");
+        assert_eq!(settings.get(RECORDING_PATH).unwrap(), "somePath");
+    }
+
+    #[test]
+    fn test_search() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let results = search(fixture_filters("Fra", view_type::ALL, source_id)).unwrap();
+        assert_eq!(names(results), vec!["Fracture", "France 24", "TF1 France"]);
+        let results = search(Filters {
+            media_types: Some(vec![media_type::MOVIE]),
+            ..fixture_filters("Fra", view_type::ALL, source_id)
+        })
+        .unwrap();
+        assert_eq!(names(results), vec!["Fracture"]);
+        let results = search(Filters {
+            use_keywords: true,
+            ..fixture_filters("fra 24", view_type::ALL, source_id)
+        })
+        .unwrap();
+        assert_eq!(names(results), vec!["France 24"]);
+        let results = search(fixture_filters("Fra", view_type::ALL, source_id + 1)).unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_advanced_search() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let search_names =
+            |query: &str| names(search(fixture_filters(query, view_type::ALL, source_id)).unwrap());
+        assert_eq!(search_names("/fra -24"), vec!["Fracture", "TF1 France"]);
+        assert_eq!(search_names("/^fra"), vec!["Fracture", "France 24"]);
+        assert_eq!(search_names(r#"/"tf1 fr" france$"#), vec!["TF1 France"]);
+        assert_eq!(search_names("/fra_"), Vec::<String>::new());
+        let results = search(Filters {
+            advanced_query: true,
+            ..fixture_filters("-france", view_type::ALL, source_id)
+        })
+        .unwrap();
+        assert_eq!(names(results), vec!["Amélie", "BBC One", "Fracture"]);
+        let groups = search_groups(Filters {
+            advanced_query: true,
+            ..fixture_filters("-news -films", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        assert_eq!(groups.len(), 1);
+        let error = search(fixture_filters(r#"/fra "24"#, view_type::ALL, source_id)).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<InvalidQuery>().map(|e| e.position),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn test_keyset_pagination_with_concurrent_inserts() {
+        let _db = init_test_db();
+        let source_id = do_tx(|tx| create_or_find_source_by_name(tx, &test_source("big"))).unwrap();
+        let insert = |names: Vec<String>| {
+            do_tx(|tx| {
+                let channels: Vec<Channel> = names
+                    .iter()
+                    .map(|name| fixture_channel(name, "VOD", media_type::MOVIE, source_id))
+                    .collect();
+                insert_channels_batch(tx, &channels)?;
+                Ok(())
+            })
+            .unwrap()
+        };
+        insert(
+            (0..50_000)
+                .map(|i| format!("Movie {:05}", i * 7 % 50_000))
+                .collect(),
+        );
+        let filters = fixture_filters("", view_type::ALL, source_id);
+        let page_size = 250;
+        let conn = get_conn().unwrap();
+        let mut seen = HashSet::new();
+        let mut cursor: Option<(i64, String)> = None;
+        for page in 0.. {
+            let channels = search_channels(
+                &conn,
+                &Filters {
+                    after_id: cursor.as_ref().map(|(id, _)| *id),
+                    after_name: cursor.as_ref().map(|(_, name)| name.clone()),
+                    ..filters.clone()
+                },
+                page_size,
+                0,
+                false,
+            )
+            .unwrap();
+            for channel in &channels {
+                assert!(
+                    seen.insert(channel.id.unwrap()),
+                    "{} returned twice",
+                    channel.name
+                );
+            }
+            if channels.len() < page_size as usize {
+                break;
+            }
+            let last = channels.last().unwrap();
+            cursor = Some((last.id.unwrap(), last.name.clone()));
+            // rows sorting before and after the cursor land between page fetches
+            insert(vec![
+                format!("Movie {:05} new", page * 131 % 50_000),
+                format!("A new {page}"),
+            ]);
+        }
+        let original: HashSet<i64> = conn
+            .prepare("SELECT id FROM channels WHERE name NOT LIKE '%new%'")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(original.len(), 50_000);
+        assert!(original.is_subset(&seen));
+    }
+
+    #[test]
+    fn test_search_includes_group_names() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        do_tx(|tx| {
+            let ungrouped = Channel {
+                group: None,
+                ..fixture_channel("Fra Loose", "", media_type::MOVIE, source_id)
+            };
+            let episode = Channel {
+                series_id: Some(7),
+                ..fixture_channel("Fra Episode", "", media_type::MOVIE, source_id)
+            };
+            insert_channels_batch(tx, &[ungrouped, episode])?;
+            Ok(())
+        })
+        .unwrap();
+        let filters = Filters {
+            include_group_names: true,
+            ..fixture_filters("fra", view_type::ALL, source_id)
+        };
+        let conn = get_conn().unwrap();
+        let first = search_channels(&conn, &filters, 2, 0, false).unwrap();
+        let last = first.last().unwrap();
+        let second = search_channels(
+            &conn,
+            &Filters {
+                after_id: last.id,
+                after_name: Some(last.name.clone()),
+                ..filters.clone()
+            },
+            2,
+            0,
+            false,
+        )
+        .unwrap();
+        let groups: Vec<(String, Option<String>)> = first
+            .into_iter()
+            .chain(second)
+            .map(|c| (c.name, c.group))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("Fra Episode".to_string(), None),
+                ("Fra Loose".to_string(), None),
+                ("Fracture".to_string(), Some("Films".to_string())),
+                ("France 24".to_string(), Some("News".to_string())),
+            ]
+        );
+
+        let bbc = search(fixture_filters("BBC", view_type::ALL, source_id)).unwrap();
+        assert_eq!(bbc[0].group, None);
+        favorite_channel(bbc[0].id.unwrap(), true).unwrap();
+        let favorites = search(Filters {
+            include_group_names: true,
+            ..fixture_filters("", view_type::FAVORITES, source_id)
+        })
+        .unwrap();
+        assert_eq!(favorites.len(), 1);
+        assert_eq!(favorites[0].id, bbc[0].id);
+        assert_eq!(favorites[0].group.as_deref(), Some("News"));
+        let episodes = search(Filters {
+            query: None,
+            series_id: Some(7),
+            ..filters.clone()
+        })
+        .unwrap();
+        assert_eq!(episodes.len(), 1);
+        assert_eq!(episodes[0].group, None);
+    }
+
+    #[test]
+    fn test_series_summary() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        do_tx(|tx| {
+            let episodes: Vec<Channel> = [(7, "S01E01"), (7, "S01E02"), (8, "Pilot")]
+                .into_iter()
+                .map(|(series_id, name)| Channel {
+                    series_id: Some(series_id),
+                    ..fixture_channel(name, "", media_type::MOVIE, source_id)
+                })
+                .collect();
+            insert_channels_batch(tx, &episodes)?;
+            Ok(())
+        })
+        .unwrap();
+        let mut ids: Vec<i64> = (1000..3000).collect();
+        ids.extend([8, 7, 9]);
+        let summary = get_series_summary(&ids, source_id).unwrap();
+        assert_eq!(summary.len(), ids.len());
+        let tail: Vec<(i64, u32)> = summary[2000..]
+            .iter()
+            .map(|s| (s.series_id, s.episode_count))
+            .collect();
+        assert_eq!(tail, vec![(8, 1), (7, 2), (9, 0)]);
+        assert!(summary[2001].last_added_at.is_some());
+        assert_eq!(summary[2002].last_added_at, None);
+        assert_eq!(
+            get_series_summary(&[7], source_id + 1).unwrap()[0].episode_count,
+            0
+        );
+        assert!(series_has_episodes(7, source_id).unwrap());
+        assert!(!series_has_episodes(7, source_id + 1).unwrap());
+        assert!(!series_has_episodes(9, source_id).unwrap());
+        assert_eq!(
+            names(get_series_episodes(7, source_id).unwrap()),
+            vec!["S01E01", "S01E02"]
+        );
+        assert!(get_series_episodes(7, source_id + 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_watched_survives_refresh() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let insert_episodes = || {
+            do_tx(|tx| {
+                let episodes: Vec<Channel> = ["S01E01", "S01E02"]
+                    .into_iter()
+                    .map(|name| Channel {
+                        series_id: Some(7),
+                        ..fixture_channel(name, "", media_type::MOVIE, source_id)
+                    })
+                    .collect();
+                insert_channels_batch(tx, &episodes)?;
+                apply_watched(tx, source_id, Some(7))
+            })
+            .unwrap()
+        };
+        insert_episodes();
+        let unwatched = || {
+            let episodes = search(Filters {
+                series_id: Some(7),
+                hide_watched: true,
+                ..fixture_filters("", view_type::ALL, source_id)
+            })
+            .unwrap();
+            names(episodes)
+        };
+        set_series_watched(7, source_id + 1, true).unwrap();
+        assert_eq!(unwatched(), vec!["S01E01", "S01E02"]);
+        set_series_watched(7, source_id, true).unwrap();
+        assert!(unwatched().is_empty());
+        let first = search(fixture_filters("S01E01", view_type::ALL, source_id)).unwrap();
+        assert!(first[0].watched);
+        set_watched(first[0].id.unwrap(), false).unwrap();
+        assert_eq!(unwatched(), vec!["S01E01"]);
+
+        do_tx(|tx| {
+            wipe(tx, source_id)?;
+            finish_import(tx, source_id)
+        })
+        .unwrap();
+        insert_episodes();
+        assert_eq!(unwatched(), vec!["S01E01"]);
+        let all = search(Filters {
+            series_id: Some(7),
+            ..fixture_filters("", view_type::ALL, source_id)
+        })
+        .unwrap();
+        assert_eq!(all.len(), 2);
+    }
+
+    #[test]
+    fn test_favorites_follow_renamed_channels() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let id_of = |name: &str| {
+            search(fixture_filters(name, view_type::ALL, source_id)).unwrap()[0]
+                .id
+                .unwrap()
+        };
+        for name in ["BBC One", "France 24", "TF1 France", "Fracture"] {
+            favorite_channel(id_of(name), true).unwrap();
+        }
+        do_tx(|tx| {
+            wipe(tx, source_id)?;
+            let mut groups = HashMap::new();
+            let mut channels = vec![
+                // renamed, same url
+                Channel {
+                    url: Some("http://example.com/BBC_One".to_string()),
+                    ..fixture_channel("BBC One FHD", "News", media_type::LIVESTREAM, source_id)
+                },
+                // same name, new url
+                Channel {
+                    url: Some("http://example.com/v2/france24".to_string()),
+                    ..fixture_channel("France 24", "News", media_type::LIVESTREAM, source_id)
+                },
+                // renamed and moved
+                fixture_channel(
+                    "TF1 France HD",
+                    "Général",
+                    media_type::LIVESTREAM,
+                    source_id,
+                ),
+                fixture_channel("Amélie", "Films", media_type::MOVIE, source_id),
+            ];
+            for channel in channels.iter_mut() {
+                set_channel_group_id(&mut groups, channel, tx, &source_id)?;
+            }
+            insert_channels_batch(tx, &channels)?;
+            finish_import(tx, source_id)
+        })
+        .unwrap();
+        let favorites = search(Filters {
+            query: None,
+            ..fixture_filters("", view_type::FAVORITES, source_id)
+        })
+        .unwrap();
+        assert_eq!(
+            names(favorites),
+            vec!["BBC One FHD", "Fracture", "France 24", "TF1 France HD"]
+        );
+        let orphaned = get_orphaned_favorites().unwrap();
+        assert_eq!(names(orphaned), vec!["Fracture"]);
+        let rows: u32 = get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM channels WHERE source_id IS ?",
+                params![source_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(rows, 5);
+    }
+
+    #[test]
+    fn test_notes_and_tags_follow_refresh() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let id_of = |name: &str| {
+            search(fixture_filters(name, view_type::ALL, source_id)).unwrap()[0]
+                .id
+                .unwrap()
+        };
+        let uhd = create_tag("4K".to_string()).unwrap();
+        let unstable = create_tag("unstable".to_string()).unwrap();
+        assert_eq!(create_tag(" 4k ".to_string()).unwrap(), uhd);
+        tag_channel(id_of("BBC One"), uhd).unwrap();
+        tag_channel(id_of("BBC One"), unstable).unwrap();
+        tag_channel(id_of("Fracture"), unstable).unwrap();
+        set_channel_note(id_of("BBC One"), "use backup after 22:00".to_string()).unwrap();
+        do_tx(|tx| {
+            wipe(tx, source_id)?;
+            let mut groups = HashMap::new();
+            let mut channels = vec![Channel {
+                url: Some("http://example.com/BBC_One".to_string()),
+                ..fixture_channel("BBC One FHD", "News", media_type::LIVESTREAM, source_id)
+            }];
+            for channel in channels.iter_mut() {
+                set_channel_group_id(&mut groups, channel, tx, &source_id)?;
+            }
+            insert_channels_batch(tx, &channels)?;
+            finish_import(tx, source_id)
+        })
+        .unwrap();
+        let bbc = id_of("BBC One FHD");
+        assert_eq!(get_channel_tags(bbc).unwrap(), ["4K", "unstable"]);
+        assert_eq!(
+            get_channel_note(bbc).unwrap().unwrap().note,
+            "use backup after 22:00"
+        );
+        assert!(!get_channel_by_id(bbc).unwrap().favorite);
+        let tagged = search(Filters {
+            query: None,
+            tag: Some("UNSTABLE".to_string()),
+            ..fixture_filters("", view_type::ALL, source_id)
+        })
+        .unwrap();
+        assert_eq!(names(tagged), vec!["BBC One FHD"]);
+        let counts: Vec<(String, u64)> = get_tags()
+            .unwrap()
+            .into_iter()
+            .map(|tag| (tag.name, tag.channel_count))
+            .collect();
+        assert_eq!(counts, [("4K".to_string(), 1), ("unstable".to_string(), 1)]);
+        delete_tag(unstable).unwrap();
+        assert_eq!(get_channel_tags(bbc).unwrap(), ["4K"]);
+        set_channel_note(bbc, " ".to_string()).unwrap();
+        assert!(get_channel_note(bbc).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_delete_impact_matches_delete() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let id_of = |name: &str| {
+            search(fixture_filters(name, view_type::ALL, source_id)).unwrap()[0]
+                .id
+                .unwrap()
+        };
+        let bbc = id_of("BBC One");
+        favorite_channel(bbc, true).unwrap();
+        let playlist_id = create_playlist("Morning".to_string()).unwrap();
+        add_to_playlist(playlist_id, bbc).unwrap();
+        do_tx(|tx| {
+            insert_channel_headers(
+                tx,
+                ChannelHttpHeaders {
+                    channel_id: Some(id_of("France 24")),
+                    referrer: Some("https://example.com".to_string()),
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap();
+        let news: i64 = get_conn()
+            .unwrap()
+            .query_row("SELECT id FROM groups WHERE name = 'News'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+
+        let group_impact = get_group_delete_impact(news).unwrap();
+        assert_eq!(
+            group_impact,
+            DeleteImpact {
+                channels: 2,
+                groups: 1,
+                favorites: 1,
+                headers: 1,
+                playlist_channels: 1,
+                reminders: 0,
+            }
+        );
+        let error = delete_custom_group(news, None, false, false).unwrap_err();
+        assert_eq!(error.downcast_ref::<GroupNotEmpty>().unwrap().channels, 2);
+
+        let source_impact = get_source_delete_impact(source_id).unwrap();
+        assert_eq!(source_impact.channels, 5);
+        assert_eq!(source_impact.groups, 3);
+        delete_custom_group(news, None, false, true).unwrap();
+        assert_eq!(
+            get_group_delete_impact(news).unwrap(),
+            DeleteImpact::default()
+        );
+        delete_source(source_id).unwrap();
+        assert_eq!(
+            get_source_delete_impact(source_id).unwrap(),
+            DeleteImpact::default()
+        );
+    }
+
+    #[test]
+    fn test_media_type_counts() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let counts = get_media_type_counts(Filters {
+            source_ids: vec![],
+            ..fixture_filters("", view_type::ALL, source_id)
+        })
+        .unwrap();
+        assert_eq!(
+            counts,
+            HashMap::from([(media_type::LIVESTREAM, 3), (media_type::MOVIE, 2)])
+        );
+        let filters = fixture_filters("fra", view_type::ALL, source_id);
+        let counts = get_media_type_counts(filters.clone()).unwrap();
+        for media_type in [media_type::LIVESTREAM, media_type::MOVIE] {
+            let listed = search(Filters {
+                media_types: Some(vec![media_type]),
+                ..filters.clone()
+            })
+            .unwrap();
+            assert_eq!(counts[&media_type], listed.len() as u64);
+        }
+        assert_eq!(counts[&media_type::LIVESTREAM], 2);
     }
-    Ok(())
-}
 
-pub fn delete_custom_channel(id: i64) -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute("DELETE FROM channels WHERE id = ?", params![id])?;
-    Ok(())
-}
+    #[test]
+    fn test_search_group() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let results = search(Filters {
+            media_types: None,
+            ..fixture_filters("ne", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        assert_eq!(names(results), vec!["Général", "News"]);
+        let results = search(Filters {
+            media_types: None,
+            ..fixture_filters("new", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        assert_eq!(names(results), vec!["News"]);
+    }
 
-pub fn group_exists(name: &str, source_id: i64) -> Result<bool> {
-    let sql = get_conn()?;
-    Ok(sql
-        .query_row(
-            r#"
-            SELECT 1
-            FROM groups
-            WHERE name = ? AND source_id = ?
-        "#,
-            params![name, source_id],
-            |row| row.get::<_, u8>(0),
-        )
-        .optional()?
-        .is_some())
-}
+    #[test]
+    fn test_search_group_ids() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let groups = search(Filters {
+            media_types: None,
+            ..fixture_filters("", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        let selected: Vec<i64> = groups
+            .iter()
+            .filter(|g| g.name != "Général")
+            .map(|g| g.id.unwrap())
+            .collect();
+        let results = search(Filters {
+            group_ids: Some(selected),
+            ..fixture_filters("", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        assert_eq!(
+            names(results),
+            vec!["Amélie", "BBC One", "Fracture", "France 24"]
+        );
+        let results = search(Filters {
+            media_types: None,
+            group_ids: Some(vec![]),
+            ..fixture_filters("", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        assert_eq!(names(results), vec!["Films", "Général", "News"]);
+    }
 
-pub fn channel_exists(name: &str, url: &str, source_id: i64) -> Result<bool> {
-    let sql = get_conn()?;
-    Ok(sql
-        .query_row(
-            r#"
-            SELECT 1
-            FROM channels
-            WHERE name = ? AND source_id = ? AND url = ?
-        "#,
-            params![name, source_id, url],
-            |row| row.get::<_, u8>(0),
-        )
-        .optional()?
-        .is_some())
-}
+    #[test]
+    fn test_search_groups() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        do_tx(|tx| finish_import(tx, source_id)).unwrap();
+        let mut groups = search_groups(Filters {
+            media_types: None,
+            ..fixture_filters("", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        let summary: Vec<(&str, u64, u8)> = groups
+            .iter()
+            .map(|g| (g.name.as_str(), g.channel_count, g.media_types))
+            .collect();
+        let live = media_type::to_mask(&[media_type::LIVESTREAM]);
+        let movie = media_type::to_mask(&[media_type::MOVIE]);
+        assert_eq!(
+            summary,
+            vec![("Films", 2, movie), ("Général", 1, live), ("News", 2, live)]
+        );
+        assert!(groups.iter().all(|g| g.source_name == "fixture"));
+    }
 
-pub fn add_custom_group(tx: &Transaction, group: Group) -> Result<i64> {
-    tx.execute(
-        r#"
-        INSERT INTO groups (name, image, source_id)
-        VALUES (?, ?, ?)
-    "#,
-        params!(group.name, group.image, group.source_id),
-    )?;
-    Ok(tx.last_insert_rowid())
-}
+    #[test]
+    fn test_disabled_source_is_hidden() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let channel = search(fixture_filters("bbc", view_type::ALL, source_id)).unwrap();
+        favorite_channel(channel[0].id.unwrap(), true).unwrap();
+        let visible = || {
+            let channels = search(fixture_filters("", view_type::ALL, source_id)).unwrap();
+            let groups = search(Filters {
+                media_types: None,
+                ..fixture_filters("", view_type::CATEGORIES, source_id)
+            })
+            .unwrap();
+            let favorites = search(fixture_filters("", view_type::FAVORITES, source_id)).unwrap();
+            (channels.len(), groups.len(), names(favorites))
+        };
+        assert_eq!(visible(), (5, 3, vec!["BBC One".to_string()]));
+        set_source_enabled(false, source_id).unwrap();
+        assert_eq!(visible(), (0, 0, vec![]));
+        set_source_enabled(true, source_id).unwrap();
+        assert_eq!(visible(), (5, 3, vec!["BBC One".to_string()]));
+    }
 
-pub fn group_auto_complete(query: Option<String>, source_id: i64) -> Result<Vec<IdName>> {
-    let sql = get_conn()?;
-    let groups = sql
-        .prepare(
-            r#"
-        SELECT id, name
-        FROM groups
-        WHERE name LIKE ?
-        AND source_id = ?
-    "#,
-        )?
-        .query_map(params![to_sql_like(query), source_id], row_to_id_name)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(groups)
-}
+    #[test]
+    fn test_compact_resets_deleted_rows() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        delete_source(source_id).unwrap();
+        assert_eq!(get_deleted_rows_since_vacuum().unwrap(), 8);
+        compact().unwrap();
+        assert_eq!(get_deleted_rows_since_vacuum().unwrap(), 0);
+    }
 
-fn row_to_id_name(row: &Row) -> Result<IdName, rusqlite::Error> {
-    Ok(IdName {
-        id: row.get("id")?,
-        name: row.get("name")?,
-    })
-}
+    #[test]
+    fn test_get_sources() {
+        let _db = init_test_db();
+        seed_fixtures();
+        let names: Vec<String> = get_sources().unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["fixture"]);
+    }
 
-pub fn edit_custom_group(group: Group) -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute(
-        r#"
-        UPDATE groups
-        SET name = ?, image = ?
-        WHERE id = ?
-    "#,
-        params![group.name, group.image, group.id],
-    )?;
-    Ok(())
-}
+    #[test]
+    fn test_pick_group_image() {
+        let candidates = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+            items
+                .iter()
+                .map(|(name, image)| (name.to_string(), image.to_string()))
+                .collect()
+        };
+        let news = candidates(&[
+            ("radio one", "http://radio.example/1.png"),
+            ("bbc news hd", "http://logos.example/bbc.png"),
+        ]);
+        assert_eq!(
+            pick_group_image("news", &news).as_deref(),
+            Some("http://logos.example/bbc.png")
+        );
+        let films = candidates(&[
+            ("radio one", "http://radio.example/1.png"),
+            ("amelie", "http://logos.example/amelie.png"),
+            ("fracture", "http://logos.example/fracture.png"),
+        ]);
+        assert_eq!(
+            pick_group_image("films", &films).as_deref(),
+            Some("http://logos.example/amelie.png")
+        );
+    }
 
-fn get_group_by_id(id: i64) -> Result<Option<Group>> {
-    let sql = get_conn()?;
-    let group: Option<Group> = sql
-        .query_row(
-            "SELECT * FROM groups WHERE id = ?",
-            params![id],
-            row_to_custom_group,
+    #[test]
+    fn test_group_image_override_survives_refresh() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let group_id = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT id FROM groups WHERE name = 'Films'", [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        set_group_image(
+            group_id(&get_conn().unwrap()),
+            Some("/tmp/films.png".to_string()),
         )
-        .optional()?;
-    Ok(group)
-}
+        .unwrap();
+        do_tx(|tx| {
+            wipe(tx, source_id)?;
+            let mut groups = HashMap::new();
+            let mut channel = fixture_channel("Amélie", "Films", media_type::MOVIE, source_id);
+            channel.image = Some("http://logos.example/amelie.png".to_string());
+            set_channel_group_id(&mut groups, &mut channel, tx, &source_id)?;
+            insert_channels_batch(tx, &[channel])?;
+            pick_group_images(tx, source_id)
+        })
+        .unwrap();
+        let conn = get_conn().unwrap();
+        let image: String = conn
+            .query_row("SELECT image FROM groups WHERE name = 'Films'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(image, "/tmp/films.png");
+    }
 
-fn row_to_custom_group(row: &Row) -> Result<Group, rusqlite::Error> {
-    Ok(Group {
-        id: row.get("id")?,
-        name: row.get("name")?,
-        image: row.get("image")?,
-        source_id: row.get("source_id")?,
-    })
-}
+    #[test]
+    fn test_merged_groups_stay_merged() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let other_id = do_tx(|tx| create_source(tx, &test_source("other"))).unwrap();
+        let conn = get_conn().unwrap();
+        let group_id = |name: &str, source_id: i64| -> Option<i64> {
+            conn.query_row(
+                "SELECT id FROM groups WHERE name = ? AND source_id = ?",
+                params![name, source_id],
+                |row| row.get(0),
+            )
+            .ok()
+        };
+        let in_group = |group_id: i64| -> u64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM channels WHERE group_id = ?",
+                params![group_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        let refresh = || {
+            do_tx(|tx| {
+                wipe(tx, source_id)?;
+                let mut groups = HashMap::new();
+                let mut channels = vec![
+                    fixture_channel("France 24", "News", media_type::LIVESTREAM, source_id),
+                    fixture_channel("TF1 France", "Général", media_type::LIVESTREAM, source_id),
+                ];
+                for channel in channels.iter_mut() {
+                    set_channel_group_id(&mut groups, channel, tx, &source_id)?;
+                }
+                insert_channels_batch(tx, &channels)?;
+                finish_import(tx, source_id)
+            })
+            .unwrap()
+        };
+        let news = group_id("News", source_id).unwrap();
+        let general = group_id("Général", source_id).unwrap();
+        let other = do_tx(|tx| get_or_insert_group(tx, "News", &None, &other_id)).unwrap();
+        favorite_group(general, true).unwrap();
+        assert!(merge_groups(news, vec![other], false, true, None).is_err());
+        assert!(merge_groups(news, vec![news], false, true, None).is_err());
+        let preview = merge_groups(news, vec![general, news], false, true, None).unwrap();
+        assert_eq!(
+            preview,
+            GroupMerge {
+                channels: 1,
+                total_channels: 3,
+                groups: 1,
+            }
+        );
+        assert_eq!(group_id("Général", source_id), Some(general));
+        assert_eq!(
+            merge_groups(news, vec![general], false, false, None).unwrap(),
+            preview
+        );
+        assert_eq!(group_id("Général", source_id), None);
+        assert_eq!(in_group(news), 3);
 
-pub fn get_custom_channel_extra_data(
-    id: i64,
-    group_id: Option<i64>,
-) -> Result<CustomChannelExtraData> {
-    Ok(CustomChannelExtraData {
-        headers: get_channel_headers_by_id(id)?,
-        group: match group_id {
-            None => None,
-            Some(group) => get_group_by_id(group)?,
-        },
-    })
-}
+        refresh();
+        let news = group_id("News", source_id).unwrap();
+        assert_eq!(group_id("Général", source_id), None);
+        assert_eq!(in_group(news), 2);
+        let favorite: bool = conn
+            .query_row(
+                "SELECT favorite FROM groups WHERE id = ?",
+                params![news],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(favorite);
 
-pub fn delete_custom_group(id: i64, new_id: Option<i64>, do_channels_update: bool) -> Result<()> {
-    let sql = get_conn()?;
-    if do_channels_update {
-        sql.execute(
-            r#"
-        UPDATE channels
-        SET group_id = ?
-        WHERE group_id = ?
-    "#,
-            params![new_id, id],
-        )?;
+        let rules = get_group_merge_rules(source_id).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].target_name, "News");
+        assert_eq!(rules[0].pattern, "Général");
+        delete_group_merge_rule(rules[0].id).unwrap();
+        refresh();
+        let general = group_id("Général", source_id).unwrap();
+
+        let news = group_id("News", source_id).unwrap();
+        let merge = |pattern: &str| {
+            merge_groups(news, vec![general], false, false, Some(pattern.to_string()))
+        };
+        assert!(merge("Sport*").is_err());
+        assert!(merge("G_n*").is_err());
+        merge(" Gén* ").unwrap();
+        refresh();
+        assert_eq!(group_id("Général", source_id), None);
+        let rules = get_group_merge_rules(source_id).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].pattern, "Gén*");
     }
-    sql.execute(
-        r#"
-        DELETE FROM groups
-        WHERE id = ?
-    "#,
-        params![id],
-    )?;
-    Ok(())
-}
 
-pub fn group_not_empty(id: i64) -> Result<bool> {
-    let sql = get_conn()?;
-    Ok(sql
-        .query_row(
-            r#"
-                SELECT 1
-                FROM channels
-                WHERE group_id = ?
-            "#,
-            params![id],
-            |row| row.get::<_, u8>(0),
-        )
-        .optional()?
-        .is_some())
-}
+    #[test]
+    fn test_skipped_media_types_are_purged() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let conn = get_conn().unwrap();
+        conn.execute("UPDATE channels SET favorite = 1 WHERE name = 'Amélie'", [])
+            .unwrap();
+        let mut source = get_source_from_id(source_id).unwrap();
+        source.import_vod = false;
+        update_source(source).unwrap();
+        do_tx(|tx| finish_import(tx, source_id)).unwrap();
+        let remaining: Vec<String> = conn
+            .prepare("SELECT name FROM channels ORDER BY name")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(remaining, ["BBC One", "France 24", "TF1 France"]);
+        let films: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM groups WHERE name = 'Films'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(films, 0);
+    }
 
-pub fn get_custom_channels(group_id: Option<i64>, source_id: i64) -> Result<Vec<CustomChannel>> {
-    let sql = get_conn()?;
-    let mut sql_query = r#"
-        SELECT c.name, c.image, c.url, c.media_type, ch.referrer, ch.user_agent, ch.http_origin, ch.ignore_ssl
-        FROM channels c
-        LEFT JOIN channel_http_headers ch on ch.channel_id = c.id
-        WHERE source_id = ?
-    "#.to_string();
-    let mut params: Vec<i64> = Vec::with_capacity(2);
-    params.push(source_id);
-    if let Some(id) = group_id {
-        sql_query.push_str("\nAND group_id = ?");
-        params.push(id);
-    } else {
-        sql_query.push_str("\nAND group_id IS NULL");
+    #[test]
+    fn test_media_type_override_survives_refresh() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let conn = get_conn().unwrap();
+        let media_type_of = |name: &str| -> u8 {
+            conn.query_row(
+                "SELECT media_type FROM channels WHERE name = ?",
+                [name],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        let refresh = || {
+            do_tx(|tx| {
+                wipe(tx, source_id)?;
+                let channels = [
+                    fixture_channel("BBC One", "News", media_type::LIVESTREAM, source_id),
+                    fixture_channel("Amélie", "Films", media_type::MOVIE, source_id),
+                ];
+                insert_channels_batch(tx, &channels)?;
+                finish_import(tx, source_id)
+            })
+            .unwrap()
+        };
+        let id_of = |name: &str| -> i64 {
+            conn.query_row("SELECT id FROM channels WHERE name = ?", [name], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert!(set_channel_media_type(id_of("Amélie"), Some(media_type::RADIO)).is_err());
+        assert!(set_channel_media_type(id_of("BBC One"), Some(media_type::MOVIE)).is_err());
+        set_channel_media_type(id_of("BBC One"), Some(media_type::RADIO)).unwrap();
+        assert_eq!(media_type_of("BBC One"), media_type::RADIO);
+        refresh();
+        assert_eq!(media_type_of("BBC One"), media_type::RADIO);
+        assert_eq!(media_type_of("Amélie"), media_type::MOVIE);
+        set_channel_media_type(id_of("BBC One"), None).unwrap();
+        refresh();
+        assert_eq!(media_type_of("BBC One"), media_type::LIVESTREAM);
     }
-    let result = sql
-        .prepare(&sql_query)?
-        .query_map(params_from_iter(params), row_to_custom_channel)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(result)
-}
 
-fn row_to_custom_channel(row: &Row) -> Result<CustomChannel, rusqlite::Error> {
-    Ok(CustomChannel {
-        data: Channel {
-            name: row.get("name")?,
-            image: row.get("image")?,
-            url: row.get("url")?,
-            media_type: row.get("media_type")?,
-            favorite: false,
-            group_id: None,
-            group: None,
-            id: None,
-            series_id: None,
-            source_id: None,
-            stream_id: None,
-        },
-        headers: Some(ChannelHttpHeaders {
-            http_origin: row.get("http_origin")?,
-            ignore_ssl: row.get("ignore_ssl")?,
-            referrer: row.get("referrer")?,
-            user_agent: row.get("user_agent")?,
-            channel_id: None,
-            id: None,
-        }),
-    })
-}
+    #[test]
+    fn test_xtream_urls_become_templates() {
+        let _db = init_test_db();
+        let source_id = do_tx(|tx| create_source(tx, &test_source("xtream"))).unwrap();
+        let conn = get_conn().unwrap();
+        conn.execute(
+            "UPDATE sources SET source_type = ? WHERE id = ?",
+            params![source_type::XTREAM, source_id],
+        )
+        .unwrap();
+        let mut channel = fixture_channel("France 24", "News", media_type::LIVESTREAM, source_id);
+        channel.url = Some("http://provider.tv/live/fred/old/42.ts".to_string());
+        let id = do_tx(|tx| insert_channel(tx, channel)).unwrap();
+        set_watched(id, true).unwrap();
+        template_xtream_urls().unwrap();
+        let channel = get_channel_by_id(id).unwrap();
+        assert_eq!(channel.url.as_deref(), Some("xtream:live/42.ts"));
+        assert!(channel.watched);
+        let watched_url: String = conn
+            .query_row("SELECT url FROM watched_channels", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(watched_url, "xtream:live/42.ts");
+    }
 
-fn get_groups_by_source_id(id: i64) -> Result<Vec<Group>> {
-    let sql = get_conn()?;
-    let result = sql
-        .prepare(
-            r#"
-        SELECT *
-        FROM groups
-        WHERE source_id = ?
-    "#,
-        )?
-        .query_map(params![id], row_to_custom_group)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(result)
-}
+    #[test]
+    fn test_mixed_group_media_types() {
+        let _db = init_test_db();
+        let source_id = do_tx(|tx| {
+            let source_id = create_source(tx, &test_source("mixed"))?;
+            let mut groups = HashMap::new();
+            let mut channels = vec![
+                fixture_channel("Dune", "EN - Cinema", media_type::MOVIE, source_id),
+                fixture_channel("Severance", "EN - Cinema", media_type::SERIE, source_id),
+                fixture_channel("CNN", "EN - News", media_type::LIVESTREAM, source_id),
+            ];
+            for channel in channels.iter_mut() {
+                set_channel_group_id(&mut groups, channel, tx, &source_id)?;
+            }
+            insert_channels_batch(tx, &channels)?;
+            finish_import(tx, source_id)?;
+            Ok(source_id)
+        })
+        .unwrap();
+        let groups = search(Filters {
+            media_types: None,
+            ..fixture_filters("cinema", view_type::CATEGORIES, source_id)
+        })
+        .unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            media_type::from_mask(groups[0].media_types.unwrap()),
+            vec![media_type::MOVIE, media_type::SERIE]
+        );
+        let in_group = |media_types: Option<Vec<u8>>| {
+            names(
+                search(Filters {
+                    media_types,
+                    group_id: groups[0].id,
+                    ..fixture_filters("", view_type::CATEGORIES, source_id)
+                })
+                .unwrap(),
+            )
+        };
+        assert_eq!(in_group(None), vec!["Dune", "Severance"]);
+        assert_eq!(in_group(Some(vec![media_type::MOVIE])), vec!["Dune"]);
+    }
+
+    #[test]
+    fn test_channel_group_override_survives_refresh() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let conn = get_conn().unwrap();
+        let channel_group = || -> String {
+            conn.query_row(
+                r#"
+                SELECT g.name FROM channels c JOIN groups g ON g.id = c.group_id
+                WHERE c.name = 'BBC One'"#,
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        let refresh = || {
+            do_tx(|tx| {
+                wipe(tx, source_id)?;
+                let mut groups = HashMap::new();
+                let mut channel =
+                    fixture_channel("BBC One", "News", media_type::LIVESTREAM, source_id);
+                set_channel_group_id(&mut groups, &mut channel, tx, &source_id)?;
+                insert_channels_batch(tx, &[channel])?;
+                apply_channel_overrides(tx, source_id)
+            })
+            .unwrap()
+        };
+        let (channel_id, films_id): (i64, i64) = conn
+            .query_row(
+                r#"
+                SELECT c.id, g.id FROM channels c, groups g
+                WHERE c.name = 'BBC One' AND g.name = 'Films'"#,
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        set_channel_group(channel_id, Some(films_id)).unwrap();
+        assert_eq!(channel_group(), "Films");
+        refresh();
+        assert_eq!(channel_group(), "Films");
+        let channel_id: i64 = conn
+            .query_row(
+                "SELECT id FROM channels WHERE name = 'BBC One'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        set_channel_group(channel_id, None).unwrap();
+        refresh();
+        assert_eq!(channel_group(), "News");
+    }
 
-pub fn get_custom_groups(source_id: i64) -> Result<Vec<ExportedGroup>> {
-    let groups = get_groups_by_source_id(source_id)?;
-    let mut export: Vec<ExportedGroup> = Vec::new();
-    for group in groups {
-        export.push(ExportedGroup {
-            group: Group {
-                name: group.name,
-                image: group.image,
-                source_id: None,
-                id: None,
-            },
-            channels: get_custom_channels(group.id, source_id)?,
-        });
+    /// Refreshes the fixture source down to a reinserted BBC One, which gets a new id
+    fn refresh_bbc_one(source_id: i64) {
+        do_tx(|tx| {
+            wipe(tx, source_id)?;
+            let mut groups = HashMap::new();
+            let mut channel = fixture_channel("BBC One", "News", media_type::LIVESTREAM, source_id);
+            set_channel_group_id(&mut groups, &mut channel, tx, &source_id)?;
+            insert_channels_batch(tx, &[channel])?;
+            finish_import(tx, source_id)
+        })
+        .unwrap()
     }
-    Ok(export)
-}
 
-pub fn do_tx<F, T>(f: F) -> Result<T>
-where
-    F: FnOnce(&Transaction) -> Result<T>,
-{
-    let mut sql = get_conn()?;
-    let tx = sql.transaction()?;
-    let result = f(&tx)?;
-    tx.commit()?;
-    Ok(result)
-}
+    fn bbc_one_id() -> i64 {
+        get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT id FROM channels WHERE name = 'BBC One'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
 
-pub fn update_source(source: Source) -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute(
-        r#"
-        UPDATE sources
-        SET username = ?, password = ?, url = ?, use_tvg_id = ?
-        WHERE id = ?"#,
-        params![
-            source.username,
-            source.password,
-            source.url,
-            source.use_tvg_id,
-            source.id
-        ],
-    )?;
-    Ok(())
-}
+    #[test]
+    fn test_channel_player_survives_refresh() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        set_channel_player(bbc_one_id(), Some(crate::players::VLC)).unwrap();
+        refresh_bbc_one(source_id);
+        assert_eq!(
+            get_channel_player(bbc_one_id()).unwrap(),
+            Some(crate::players::VLC)
+        );
+        set_channel_player(bbc_one_id(), None).unwrap();
+        refresh_bbc_one(source_id);
+        assert_eq!(get_channel_player(bbc_one_id()).unwrap(), None);
+        set_channel_player(bbc_one_id(), Some(crate::players::VLC)).unwrap();
+        delete_source(source_id).unwrap();
+        let left: i64 = get_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM channel_player_overrides", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(left, 0);
+    }
 
-pub fn wipe(tx: &Transaction, id: i64) -> Result<()> {
-    delete_channels_by_source(tx, id)?;
-    delete_groups_by_source(tx, id)?;
-    Ok(())
-}
+    #[test]
+    fn test_channel_volume_follows_the_channel() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let old_id = bbc_one_id();
+        set_channel_volume(old_id, 40).unwrap();
+        refresh_bbc_one(source_id);
+        assert_ne!(bbc_one_id(), old_id);
+        assert_eq!(get_channel_volume(bbc_one_id()).unwrap(), Some(40));
+        delete_source(source_id).unwrap();
+        let left: i64 = get_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM channel_volume", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(left, 0);
+    }
 
-pub fn clean_epgs() -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute_batch(
-        r#"
-          DELETE FROM epg
-          WHERE start_timestamp < strftime('%s', 'now')
-        "#,
-    )?;
-    Ok(())
-}
+    #[test]
+    fn test_stream_probe_follows_the_url() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let probe = StreamProbe {
+            channel_id: bbc_one_id(),
+            latency_ms: 120,
+            throughput_kbps: 4000,
+            container: "hls".to_string(),
+            variants: vec![],
+            probed_at: chrono::Utc::now().timestamp(),
+        };
+        set_stream_probe(&probe).unwrap();
+        refresh_bbc_one(source_id);
+        let cached = get_stream_probe(bbc_one_id(), 3600).unwrap().unwrap();
+        assert_ne!(cached.channel_id, probe.channel_id);
+        assert_eq!(cached.channel_id, bbc_one_id());
+        assert_eq!(cached.throughput_kbps, 4000);
+        assert!(get_stream_probe(bbc_one_id(), -1).unwrap().is_none());
+        delete_source(source_id).unwrap();
+        let left: i64 = get_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM stream_probe", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(left, 0);
+    }
 
-pub fn add_epg(epg: EPGNotify) -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute(
-        "INSERT INTO epg (epg_id, channel_name, title, start_timestamp) VALUES (?,?,?,?)",
-        params![epg.epg_id, epg.channel_name, epg.title, epg.start_timestamp],
-    )?;
-    Ok(())
-}
+    #[test]
+    fn test_playlist_survives_refresh() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let conn = get_conn().unwrap();
+        let channel_id = |name: &str| -> i64 {
+            conn.query_row("SELECT id FROM channels WHERE name = ?", [name], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        let playlist_id = create_playlist("Kids".to_string()).unwrap();
+        add_to_playlist(playlist_id, channel_id("Fracture")).unwrap();
+        add_to_playlist(playlist_id, channel_id("BBC One")).unwrap();
+        reorder_playlist(playlist_id, vec![channel_id("BBC One")]).unwrap();
+        let filters = Filters {
+            playlist_id: Some(playlist_id),
+            after_id: None,
+            after_name: None,
+            ..fixture_filters("", view_type::PLAYLIST, source_id)
+        };
+        let members = |filters: &Filters| -> Vec<String> {
+            search(filters.clone())
+                .unwrap()
+                .into_iter()
+                .map(|c| c.name)
+                .collect()
+        };
+        assert_eq!(members(&filters), vec!["BBC One", "Fracture"]);
+        do_tx(|tx| {
+            wipe(tx, source_id)?;
+            let mut groups = HashMap::new();
+            let mut channels = vec![
+                fixture_channel("Fracture", "Films", media_type::MOVIE, source_id),
+                fixture_channel("BBC One", "News", media_type::LIVESTREAM, source_id),
+            ];
+            for channel in channels.iter_mut() {
+                set_channel_group_id(&mut groups, channel, tx, &source_id)?;
+            }
+            insert_channels_batch(tx, &channels)?;
+            finish_import(tx, source_id)
+        })
+        .unwrap();
+        assert_eq!(members(&filters), vec!["BBC One", "Fracture"]);
+        delete_source(source_id).unwrap();
+        let playlists = get_playlists().unwrap();
+        assert_eq!(playlists.len(), 1);
+        assert_eq!(playlists[0].channel_count, 0);
+    }
 
-pub fn remove_epg(epg_id: String) -> Result<()> {
-    let sql = get_conn()?;
-    sql.execute("DELETE FROM epg WHERE epg_id = ?", params![epg_id])?;
-    Ok(())
-}
+    #[test]
+    fn test_filter_presets() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let filters = Filters {
+            source_ids: vec![source_id, source_id + 1],
+            ..fixture_filters("news", view_type::ALL, source_id)
+        };
+        let id = save_filter_preset("Evening".to_string(), filters.clone()).unwrap();
+        let updated = Filters {
+            query: Some("films".to_string()),
+            ..filters
+        };
+        assert_eq!(
+            save_filter_preset("Evening".to_string(), updated).unwrap(),
+            id
+        );
+        assert_eq!(list_filter_presets().unwrap().len(), 1);
+        let applied = apply_filter_preset(id).unwrap();
+        assert!(applied.missing_sources);
+        assert_eq!(applied.filters.source_ids, vec![source_id]);
+        assert_eq!(applied.filters.query.as_deref(), Some("films"));
+        delete_filter_preset(id).unwrap();
+        assert!(list_filter_presets().unwrap().is_empty());
+    }
 
-pub fn get_epgs() -> Result<Vec<EPGNotify>> {
-    let sql = get_conn()?;
-    let epgs = sql
-        .prepare("SELECT * FROM epg")?
-        .query_map(params![], row_to_epg)?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(epgs)
-}
+    #[test]
+    fn test_duplicate_custom_group() {
+        let _db = init_test_db();
+        let (group_id, channel_id) = do_tx(|tx| {
+            let source_id = create_or_find_source_by_name(tx, &get_custom_source("mine".into()))?;
+            let group_id = add_custom_group(
+                tx,
+                Group {
+                    id: None,
+                    name: "Sports".to_string(),
+                    image: None,
+                    source_id: Some(source_id),
+                },
+            )?;
+            let mut data = fixture_channel("Match HD", "Sports", media_type::LIVESTREAM, source_id);
+            data.group_id = Some(group_id);
+            add_custom_channel(
+                tx,
+                CustomChannel {
+                    data,
+                    headers: Some(ChannelHttpHeaders {
+                        user_agent: Some("VLC".to_string()),
+                        ..Default::default()
+                    }),
+                },
+            )?;
+            let channel_id = tx.query_row(
+                "SELECT id FROM channels WHERE name = 'Match HD'",
+                [],
+                |row| row.get(0),
+            )?;
+            Ok((group_id, channel_id))
+        })
+        .unwrap();
+        let conn = get_conn().unwrap();
+        let name = |id: i64| -> String {
+            conn.query_row("SELECT name FROM channels WHERE id = ?", [id], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        let copy = duplicate_custom_channel(channel_id).unwrap();
+        assert_eq!(name(copy), "Match HD (copy)");
+        assert_eq!(
+            name(duplicate_custom_channel(channel_id).unwrap()),
+            "Match HD (copy 2)"
+        );
+        let headers = get_channel_headers_by_id(copy).unwrap().unwrap();
+        assert_eq!(headers.user_agent.as_deref(), Some("VLC"));
+        let group = duplicate_custom_group(group_id).unwrap();
+        assert_eq!(group.channel_ids.len(), 3);
+        assert_eq!(name(group.channel_ids[0]), "Match HD (copy 3)");
+        let position = |id: i64| -> Option<i64> {
+            conn.query_row("SELECT position FROM channels WHERE id = ?", [id], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(position(copy), Some(2));
+        let copied: Vec<Option<i64>> = group.channel_ids.iter().map(|id| position(*id)).collect();
+        assert_eq!(copied, vec![Some(1), Some(2), Some(3)]);
+        assert_eq!(
+            get_group_by_id(group.id).unwrap().unwrap().name,
+            "Sports (copy)"
+        );
+        let source_id = seed_fixtures();
+        let provider_channel: i64 = conn
+            .query_row(
+                "SELECT id FROM channels WHERE source_id = ? LIMIT 1",
+                [source_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(duplicate_custom_channel(provider_channel).is_err());
+    }
 
-pub fn get_epg_ids() -> Result<Vec<String>> {
-    let sql = get_conn()?;
-    let epgs = sql
-        .prepare("SELECT epg_id FROM epg")?
-        .query_map(params![], |row| row.get::<_, String>(0))?
-        .filter_map(Result::ok)
-        .collect();
-    Ok(epgs)
-}
+    #[test]
+    fn test_reorder_custom_channels() {
+        let _db = init_test_db();
+        let source_id = do_tx(|tx| {
+            let source_id = create_or_find_source_by_name(tx, &get_custom_source("mine".into()))?;
+            for name in ["One", "Two", "Three"] {
+                add_custom_channel(
+                    tx,
+                    CustomChannel {
+                        data: fixture_channel(name, "", media_type::LIVESTREAM, source_id),
+                        headers: None,
+                    },
+                )?;
+            }
+            Ok(source_id)
+        })
+        .unwrap();
+        let conn = get_conn().unwrap();
+        let id = |name: &str| -> i64 {
+            conn.query_row("SELECT id FROM channels WHERE name = ?", [name], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        reorder_custom_channels(None, vec![id("Three"), id("One"), id("Two")]).unwrap();
+        let custom: Vec<String> = get_custom_channels(None, source_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.data.name)
+            .collect();
+        assert_eq!(custom, vec!["Three", "One", "Two"]);
+        let searched: Vec<String> = search(fixture_filters("", view_type::ALL, source_id))
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(searched, vec!["Three", "One", "Two"]);
+    }
 
-fn row_to_epg(row: &Row) -> Result<EPGNotify, rusqlite::Error> {
-    Ok(EPGNotify {
-        epg_id: row.get("epg_id")?,
-        channel_name: row.get("channel_name")?,
-        start_timestamp: row.get("start_timestamp")?,
-        title: row.get("title")?,
-    })
-}
+    #[test]
+    fn test_reorder_favorites() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let conn = get_conn().unwrap();
+        let id = |name: &str| -> i64 {
+            conn.query_row("SELECT id FROM channels WHERE name = ?", [name], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        for name in ["France 24", "BBC One", "TF1 France", "Amélie"] {
+            favorite_channel(id(name), true).unwrap();
+        }
+        reorder_favorites(vec![id("TF1 France"), id("Fracture"), id("France 24")]).unwrap();
+        let filters = fixture_filters("", view_type::FAVORITES, source_id);
+        let favorites: Vec<String> = search(filters.clone())
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(
+            favorites,
+            vec!["TF1 France", "France 24", "Amélie", "BBC One"]
+        );
+        let position = |name: &str| -> Option<i64> {
+            conn.query_row(
+                "SELECT favorite_position FROM channels WHERE name = ?",
+                [name],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(position("Fracture"), None);
+        assert_eq!(position("France 24"), Some(2));
+        let first = search_channels(&conn, &filters, 2, 0, false).unwrap();
+        let second = search_channels(
+            &conn,
+            &Filters {
+                page: 2,
+                ..filters.clone()
+            },
+            2,
+            0,
+            false,
+        )
+        .unwrap();
+        let paged: Vec<String> = first.into_iter().chain(second).map(|c| c.name).collect();
+        assert_eq!(paged, favorites);
+        favorite_channel(id("TF1 France"), false).unwrap();
+        assert_eq!(position("TF1 France"), None);
+    }
 
-#[cfg(test)]
-mod test_sql {
-    use std::collections::HashMap;
+    #[test]
+    fn test_refresh_log_keeps_last_attempts() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        for i in 0..25 {
+            record_refresh(source_id, Some(format!("HTTP 403 #{i}"))).unwrap();
+        }
+        record_refresh(source_id, None).unwrap();
+        let log = get_refresh_log(source_id).unwrap();
+        assert_eq!(log.len(), 20);
+        assert_eq!(log[0].status, refresh_status::SUCCESS);
+        assert_eq!(log[1].error.as_deref(), Some("HTTP 403 #24"));
+        let source = get_sources().unwrap().pop().unwrap();
+        assert_eq!(source.last_refresh_status, Some(refresh_status::SUCCESS));
+        assert_eq!(source.last_refresh_error, None);
+        assert_eq!(source.channel_count, Some(5));
+    }
 
-    use crate::{
-        media_type,
-        settings::{RECORDING_PATH, USE_STREAM_CACHING},
-        sql::{create_structure, drop_db, structure_exists},
-        types::Filters,
-        view_type,
-    };
+    #[test]
+    fn test_backup_restores_favorites() {
+        let dir = init_test_db();
+        let source_id = seed_fixtures();
+        get_conn()
+            .unwrap()
+            .execute(
+                "UPDATE sources SET password = ? WHERE id = ?",
+                params![
+                    crate::secrets::store(source_id, "hunter2").unwrap(),
+                    source_id
+                ],
+            )
+            .unwrap();
+        let channel = search(fixture_filters("bbc", view_type::ALL, source_id)).unwrap();
+        let channel_id = channel[0].id.unwrap();
+        favorite_channel(channel_id, true).unwrap();
+        let path = dir.path().join("backup.sqlite");
+        let path = path.to_string_lossy();
+        {
+            let _import = lock_import();
+            assert!(!backup_to(&path).unwrap());
+        }
+        assert_eq!(get_last_backup_at().unwrap(), None);
+        // other tests may be importing, it's only a matter of turns
+        while !backup_to(&path).unwrap() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(get_last_backup_at().unwrap().is_some());
+        favorite_channel(channel_id, false).unwrap();
+        // starting from the backup brings the favorite back
+        let restored = open_database(path.to_string()).unwrap();
+        TEST_DB.with(|db| *db.borrow_mut() = Some(restored));
+        let favorites = search(fixture_filters("", view_type::FAVORITES, source_id)).unwrap();
+        assert_eq!(names(favorites), vec!["BBC One"]);
+        let stored: String = get_conn()
+            .unwrap()
+            .query_row("SELECT password FROM sources", [], |row| row.get(0))
+            .unwrap();
+        assert_ne!(stored, "hunter2");
+        let source = get_sources().unwrap().pop().unwrap();
+        assert_eq!(source.password.as_deref(), Some("hunter2"));
+    }
 
-    use super::{get_sources, search, update_settings};
+    #[test]
+    fn test_move_database() {
+        let dir = init_test_db();
+        do_tx(|tx| create_source(tx, &test_source("before move"))).unwrap();
+        let old = get_db_path().unwrap();
+        let new = dir.path().join("usb/db.sqlite");
+        std::fs::create_dir_all(new.parent().unwrap()).unwrap();
+        let new = new.to_string_lossy().to_string();
+        move_database(&new).unwrap();
+        assert_eq!(get_db_path().unwrap(), new);
+        assert_eq!(get_sources().unwrap()[0].name, "before move");
+        do_tx(|tx| create_source(tx, &test_source("after move"))).unwrap();
+        assert_eq!(get_sources().unwrap().len(), 2);
+        assert!(std::path::Path::new(&old).exists());
+        assert!(move_database(&new).is_err());
+    }
 
     #[test]
-    fn test_structure_exists() {
-        drop_db().unwrap();
-        assert_eq!(structure_exists().unwrap(), false);
-        create_structure().unwrap();
-        assert_eq!(structure_exists().unwrap(), true);
+    fn test_corrupt_database_is_moved_aside() {
+        let dir = init_test_db();
+        let healthy = dir.path().join("db.sqlite").to_string_lossy().to_string();
+        assert!(!is_corrupt(&healthy).unwrap());
+        let path = dir
+            .path()
+            .join("broken.sqlite")
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(&path, vec![0x42; 8192]).unwrap();
+        std::fs::write(format!("{path}-wal"), b"journal").unwrap();
+        assert!(is_corrupt(&path).unwrap());
+        let moved = move_corrupt_database(&path).unwrap();
+        assert!(moved.starts_with(&format!("{path}.corrupt-")));
+        assert!(!std::path::Path::new(&path).exists());
+        assert!(std::path::Path::new(&format!("{moved}-wal")).exists());
+        assert!(!is_corrupt(&path).unwrap());
     }
+
     #[test]
-    fn test_update_settings() {
-        drop_db().unwrap_or_default();
-        create_structure().unwrap();
-        let mut map: HashMap<String, String> = HashMap::with_capacity(3);
-        map.insert(USE_STREAM_CACHING.to_string(), true.to_string());
-        map.insert(RECORDING_PATH.to_string(), "somePath".to_string());
-        update_settings(map.clone()).unwrap();
-        update_settings(map).unwrap();
+    fn test_interrupted_refresh_keeps_favorites() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let bbc: i64 = get_conn()
+            .unwrap()
+            .query_row(
+                "SELECT id FROM channels WHERE name = 'BBC One'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        favorite_channel(bbc, true).unwrap();
+        let mut source = get_source_from_id(source_id).unwrap();
+        // cut short right after the source was emptied
+        do_tx(|tx| begin_import(tx, &mut source, true).map(|_| ())).unwrap();
+        let pending = get_pending_imports().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert!(!pending[0].new_source);
+        do_tx(|tx| {
+            begin_import(tx, &mut source, true)?;
+            let mut channel = fixture_channel("BBC One", "News", media_type::LIVESTREAM, source_id);
+            set_channel_group_id(&mut HashMap::new(), &mut channel, tx, &source_id)?;
+            insert_channels_batch(tx, &[channel])?;
+            finish_import(tx, source_id)
+        })
+        .unwrap();
+        let favorites = search(fixture_filters("", view_type::FAVORITES, source_id)).unwrap();
+        assert_eq!(names(favorites), vec!["BBC One"]);
+        assert!(get_pending_imports().unwrap().is_empty());
     }
 
     #[test]
-    fn test_search() {
-        let results = search(Filters {
+    fn test_sources_with_stats() {
+        let _db = init_test_db();
+        let fixture = seed_fixtures();
+        let empty = do_tx(|tx| create_source(tx, &test_source("empty"))).unwrap();
+        set_source_enabled(false, empty).unwrap();
+        let stats = get_sources_with_stats().unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].source.id, Some(fixture));
+        assert_eq!(
+            stats[0].channels,
+            HashMap::from([(media_type::LIVESTREAM, 3), (media_type::MOVIE, 2)])
+        );
+        assert_eq!(
+            stats[0].groups,
+            HashMap::from([(media_type::LIVESTREAM, 2), (media_type::MOVIE, 1)])
+        );
+        assert_eq!(stats[1].source.id, Some(empty));
+        assert!(!stats[1].source.enabled);
+        assert!(stats[1].channels.is_empty() && stats[1].groups.is_empty());
+    }
+
+    #[test]
+    fn test_search_single_source() {
+        let _db = init_test_db();
+        let first = seed_fixtures();
+        let second = do_tx(|tx| {
+            let source_id = create_or_find_source_by_name(tx, &test_source("other"))?;
+            let mut groups = HashMap::new();
+            let mut channels = vec![
+                fixture_channel("France 2", "News", media_type::LIVESTREAM, source_id),
+                Channel {
+                    series_id: Some(7),
+                    ..fixture_channel("Other Episode", "", media_type::MOVIE, source_id)
+                },
+            ];
+            for channel in channels.iter_mut() {
+                set_channel_group_id(&mut groups, channel, tx, &source_id)?;
+            }
+            insert_channels_batch(tx, &channels)?;
+            insert_channels_batch(
+                tx,
+                &[Channel {
+                    series_id: Some(7),
+                    ..fixture_channel("First Episode", "", media_type::MOVIE, first)
+                }],
+            )?;
+            Ok(source_id)
+        })
+        .unwrap();
+        let conn = get_conn().unwrap();
+        let id = |name: &str| -> i64 {
+            conn.query_row("SELECT id FROM channels WHERE name = ?", [name], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        favorite_channel(id("France 24"), true).unwrap();
+        favorite_channel(id("France 2"), true).unwrap();
+        let found = |filters: Filters| names(search(filters).unwrap());
+        assert_eq!(
+            found(fixture_filters("fra", view_type::ALL, second)),
+            vec!["France 2"]
+        );
+        assert_eq!(
+            found(fixture_filters("", view_type::FAVORITES, first)),
+            vec!["France 24"]
+        );
+        assert_eq!(
+            found(fixture_filters("", view_type::FAVORITES, second)),
+            vec!["France 2"]
+        );
+        let episodes = |source_id: i64| {
+            found(Filters {
+                series_id: Some(7),
+                ..fixture_filters("", view_type::ALL, source_id)
+            })
+        };
+        assert_eq!(episodes(first), vec!["First Episode"]);
+        assert_eq!(episodes(second), vec!["Other Episode"]);
+        let counts = get_media_type_counts(fixture_filters("", view_type::ALL, second)).unwrap();
+        assert_eq!(counts.get(&media_type::LIVESTREAM), Some(&1));
+    }
+
+    #[test]
+    fn test_source_names_ignore_case() {
+        let _db = init_test_db();
+        let id = do_tx(|tx| create_source(tx, &test_source("Home"))).unwrap();
+        let found = do_tx(|tx| create_or_find_source_by_name(tx, &test_source("home"))).unwrap();
+        assert_eq!(found, id);
+        let e = do_tx(|tx| create_source(tx, &test_source("HOME"))).unwrap_err();
+        assert!(e.downcast_ref::<SourceAlreadyExists>().is_some());
+        assert!(source_name_exists("hOmE").unwrap());
+        assert_eq!(get_sources().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_plaintext_passwords_are_migrated() {
+        let _db = init_test_db();
+        let conn = get_conn().unwrap();
+        conn.execute(
+            "INSERT INTO sources (name, source_type, password) VALUES ('legacy', 1, 'hunter2')",
+            [],
+        )
+        .unwrap();
+        migrate_plaintext_passwords().unwrap();
+        let stored: String = conn
+            .query_row("SELECT password FROM sources", [], |row| row.get(0))
+            .unwrap();
+        assert_ne!(stored, "hunter2");
+        let source = get_sources().unwrap().pop().unwrap();
+        assert_eq!(source.password.as_deref(), Some("hunter2"));
+    }
+
+    fn create_channels_table(conn: &Connection) {
+        conn.execute_batch(
+            r#"
+            CREATE TABLE channels (
+                id INTEGER PRIMARY KEY,
+                name varchar(100),
+                group_id integer,
+                image varchar(256),
+                url varchar(500),
+                media_type integer,
+                source_id integer,
+                favorite integer,
+                series_id integer,
+                stream_id integer,
+                tvg_id varchar(100),
+                catchup integer,
+                added_at integer,
+                last_check_status integer,
+                search_name varchar(100),
+                watched_at integer
+            );
+            CREATE INDEX index_channel_name ON channels(name);
+            CREATE INDEX index_channel_source_id ON channels(source_id);
+            CREATE INDEX index_channel_favorite ON channels(favorite);
+            CREATE INDEX index_channel_series_id ON channels(series_id);
+            CREATE INDEX index_channel_group_id ON channels(group_id);
+            CREATE INDEX index_channel_media_type ON channels(media_type);
+            CREATE INDEX index_channel_added_at ON channels(added_at);
+            CREATE INDEX index_channel_search_name ON channels(search_name);
+        "#,
+        )
+        .unwrap();
+    }
+
+    fn test_filters(query: &str) -> Filters {
+        Filters {
             media_types: Some(vec![media_type::LIVESTREAM, media_type::MOVIE]),
             page: 1,
-            query: Some("Fra".to_string()),
-            source_ids: get_sources()
-                .unwrap()
-                .iter()
-                .map(|x| x.id.unwrap())
-                .collect(),
+            query: Some(query.to_string()),
+            source_ids: vec![0, 1, 2, 3],
             view_type: view_type::ALL,
             group_id: None,
+            group_ids: None,
             series_id: None,
             use_keywords: false,
+            exclude_dead: None,
+            playlist_id: None,
+            after_id: None,
+            after_name: None,
+            include_group_names: false,
+            hide_watched: false,
+            tag: None,
+            advanced_query: false,
+        }
+    }
+
+    #[test]
+    fn test_search_accents() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_channels_table(&conn);
+        for name in [
+            "Télefe",
+            "München TV",
+            "İzmir Haber",
+            "Kırıkkale TV",
+            "Antena 3",
+        ] {
+            conn.execute(
+                r#"
+                INSERT INTO channels (name, url, media_type, source_id, favorite, search_name)
+                VALUES (?, 'http://example.com', 0, 0, 0, ?)
+            "#,
+                params![name, fold_search_name(name)],
+            )
+            .unwrap();
+        }
+        let search = |query: &str| {
+            search_channels(&conn, &test_filters(query), 36, 0, false)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.name)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(search("telefe"), vec!["Télefe"]);
+        assert_eq!(search("TÉLÉFE"), vec!["Télefe"]);
+        assert_eq!(search("munchen"), vec!["München TV"]);
+        assert_eq!(search("izmir"), vec!["İzmir Haber"]);
+        assert_eq!(search("kirikkale"), vec!["Kırıkkale TV"]);
+        assert_eq!(search("KIRIKKALE"), vec!["Kırıkkale TV"]);
+    }
+
+    #[test]
+    fn test_search_surfaces_invalid_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_channels_table(&conn);
+        conn.execute_batch(
+            r#"
+            INSERT INTO channels (name, url, media_type, source_id, favorite, search_name)
+            VALUES ('Working', 'http://example.com/1', 0, 0, 0, 'working');
+            INSERT INTO channels (name, url, media_type, source_id, favorite, search_name)
+            VALUES ('Broken', 'http://example.com/2', 0, 0, NULL, 'broken');
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            search_channels(&conn, &test_filters("working"), 36, 0, false)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(search_channels(&conn, &test_filters(""), 36, 0, false).is_err());
+    }
+
+    #[test]
+    fn test_channel_auto_complete() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let conn = get_conn().unwrap();
+        conn.execute(
+            "UPDATE channels SET favorite = 1 WHERE name = 'TF1 France'",
+            [],
+        )
+        .unwrap();
+        let media_types = [media_type::LIVESTREAM, media_type::MOVIE];
+        let suggestions = |query: &str, limit: u16| -> Vec<String> {
+            auto_complete_channels(&conn, query, &[source_id], &media_types, limit)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect()
+        };
+        assert_eq!(
+            suggestions("fra", 10),
+            vec!["Fracture", "France 24", "TF1 France"]
+        );
+        assert_eq!(suggestions("france", 10), vec!["France 24", "TF1 France"]);
+        assert_eq!(suggestions("AME", 10), vec!["Amélie"]);
+        assert_eq!(suggestions("fra", 1), vec!["Fracture"]);
+        assert!(suggestions(" ", 10).is_empty());
+    }
+
+    #[test]
+    fn test_channel_auto_complete_uses_the_search_index() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_channels_table(&conn);
+        let plan = query_plan(&conn, AUTO_COMPLETE_PREFIX_QUERY);
+        assert!(
+            plan.iter()
+                .any(|step| step
+                    .starts_with("SEARCH channels USING INDEX index_channel_search_name")),
+            "{plan:?}"
+        );
+        conn.execute(
+            "INSERT INTO channels (name, url, media_type, source_id, search_name) VALUES (?, ?, ?, ?, ?)",
+            params!["France 24", "http://example.com/1.ts", media_type::LIVESTREAM, 1, fold_search_name("France 24")],
+        )
+        .unwrap();
+        let results =
+            auto_complete_channels(&conn, "fra", &[1], &[media_type::LIVESTREAM], 10).unwrap();
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_clean_epgs_follows_the_guide() {
+        let _db = init_test_db();
+        let now = chrono::Utc::now().timestamp();
+        let base = now + 86400;
+        let feed = add_epg_source("guide", "http://example.com/guide.xml").unwrap();
+        do_tx(|tx| {
+            for (title, start, stop) in [
+                ("News", base, base + 1800),
+                ("Sport", base + 1800, base + 3600),
+                ("Film", base + 3600, base + 7200),
+            ] {
+                insert_programme(
+                    tx,
+                    feed,
+                    &Programme {
+                        tvg_id: "bbc1.uk".to_string(),
+                        title: title.to_string(),
+                        description: None,
+                        start,
+                        stop,
+                    },
+                )?;
+            }
+            Ok(())
         })
         .unwrap();
-        println!("{:?}\n\n", results);
-        println!("{}", results.len());
+        let reminder = |id: &str, title: &str, start: i64| {
+            add_epg(EPGNotify {
+                epg_id: id.to_string(),
+                title: title.to_string(),
+                start_timestamp: start,
+                channel_id: None,
+                tvg_id: Some("bbc1.uk".to_string()),
+                source_id: None,
+                repeat: None,
+                snooze_minutes: None,
+                snoozed_until: None,
+                channel_name: "BBC One".to_string(),
+            })
+            .unwrap()
+        };
+        reminder("same", "News", base);
+        reminder("moved", "Film", base + 1800);
+        reminder("cancelled", "Quiz", base + 1800);
+        reminder("not in the guide", "Drama", base + 5 * 86400);
+        reminder("over", "News", now - 60);
+        assert_eq!(clean_epgs().unwrap(), 2);
+        let mut left: Vec<(String, i64)> = get_epgs()
+            .unwrap()
+            .into_iter()
+            .map(|epg| (epg.epg_id, epg.start_timestamp))
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            vec![
+                ("moved".to_string(), base + 3600),
+                ("not in the guide".to_string(), base + 5 * 86400),
+                ("same".to_string(), base),
+            ]
+        );
     }
 
     #[test]
-    fn test_drop_db() {
-        drop_db().unwrap();
+    fn test_search_epg() {
+        let _db = init_test_db();
+        let feed = add_epg_source("guide", "http://example.com/guide.xml").unwrap();
+        let source_id = seed_fixtures();
+        let sql = get_conn().unwrap();
+        sql.execute(
+            "UPDATE channels SET tvg_id = 'sport.tv', catchup = 1 WHERE name = 'France 24'",
+            [],
+        )
+        .unwrap();
+        sql.execute(
+            "UPDATE channels SET tvg_id = 'sport.tv' WHERE name = 'BBC One'",
+            [],
+        )
+        .unwrap();
+        sql.execute(
+            "UPDATE channels SET tvg_id = 'sport.tv', media_type = ?1 WHERE name = 'TF1 France'",
+            params![media_type::RADIO],
+        )
+        .unwrap();
+        do_tx(|tx| {
+            for (title, description, start) in [
+                ("MotoGP: Qualifying", None, 1000),
+                ("Magazine", Some("Highlights of the motogp weekend"), 2000),
+                ("MotoGP: Race", None, 5000),
+                ("Tennis", None, 3000),
+            ] {
+                insert_programme(
+                    tx,
+                    feed,
+                    &Programme {
+                        tvg_id: "sport.tv".to_string(),
+                        title: title.to_string(),
+                        description: description.map(|d| d.to_string()),
+                        start,
+                        stop: start + 900,
+                    },
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let results = search_epg("motogp", &[source_id], 0, 4000, 1).unwrap();
+        let found: Vec<(&str, &str, bool)> = results
+            .iter()
+            .map(|r| {
+                (
+                    r.programme.title.as_str(),
+                    r.channel_name.as_str(),
+                    r.catchup,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("MotoGP: Qualifying", "BBC One", false),
+                ("MotoGP: Qualifying", "France 24", true),
+                ("MotoGP: Qualifying", "TF1 France", false),
+                ("Magazine", "BBC One", false),
+                ("Magazine", "France 24", true),
+                ("Magazine", "TF1 France", false),
+            ]
+        );
+        assert_eq!(results[2].media_type, media_type::RADIO);
+        assert!(search_epg("motogp", &[source_id], 0, 4000, 2)
+            .unwrap()
+            .is_empty());
+        assert!(search_epg("motogp", &[source_id + 1], 0, 4000, 1)
+            .unwrap()
+            .is_empty());
+        assert!(search_epg(" ", &[source_id], 0, 4000, 1)
+            .unwrap()
+            .is_empty());
     }
 
     #[test]
-    fn test_search_group() {
-        let results = search(Filters {
-            media_types: None,
-            page: 1,
-            query: Some("Fra".to_string()),
-            source_ids: get_sources()
-                .unwrap()
-                .iter()
-                .map(|x| x.id.unwrap())
-                .collect(),
-            view_type: view_type::CATEGORIES,
-            group_id: None,
-            series_id: None,
-            use_keywords: false,
+    fn test_epg_grid() {
+        const CHANNELS: i64 = 5;
+        const HALF_HOURS_IN_A_WEEK: i64 = 7 * 48;
+        let _db = init_test_db();
+        let plan = query_plan(&get_conn().unwrap(), PROGRAMMES_QUERY);
+        assert!(
+            plan.iter()
+                .any(|step| step
+                    .starts_with("SEARCH programmes USING INDEX index_programmes_tvg_id")),
+            "{plan:?}"
+        );
+        let feed = add_epg_source("week", "http://example.com/week.xml").unwrap();
+        let channel_ids: Vec<i64> = do_tx(|tx| {
+            let source_id = create_or_find_source_by_name(tx, &test_source("grid"))?;
+            let mut ids = Vec::new();
+            for i in 0..CHANNELS {
+                let mut channel = fixture_channel(
+                    &format!("Channel {i}"),
+                    "News",
+                    media_type::LIVESTREAM,
+                    source_id,
+                );
+                channel.tvg_id = Some(format!("channel{i}.tv"));
+                ids.push(insert_channel(tx, channel)?);
+                for slot in 0..HALF_HOURS_IN_A_WEEK {
+                    insert_programme(
+                        tx,
+                        feed,
+                        &Programme {
+                            tvg_id: format!("channel{i}.tv"),
+                            title: format!("Programme {slot}"),
+                            description: None,
+                            start: slot * 1800,
+                            stop: (slot + 1) * 1800,
+                        },
+                    )?;
+                }
+            }
+            finish_epg_refresh(tx, feed)?;
+            Ok(ids)
         })
         .unwrap();
-        println!("{:?}\n\n", results);
-        println!("{}", results.len());
+        // 18:15 to 00:15 on the third day, both edges cut a programme
+        let from = 2 * 86400 + 18 * 3600 + 900;
+        let to = from + 6 * 3600;
+        let grid = get_epg_grid(&channel_ids, from, to).unwrap();
+        assert_eq!(grid.len(), CHANNELS as usize);
+        assert_eq!(grid[3].channel_id, channel_ids[3]);
+        assert_eq!(grid[3].name, "Channel 3");
+        let programmes = &grid[3].programmes;
+        assert_eq!(programmes.len(), 13);
+        assert!(programmes[0].start < from && programmes[12].stop > to);
+        assert!(programmes.windows(2).all(|w| w[0].start < w[1].start));
+        assert!(get_epg_grid(&[-5], from, to).unwrap().is_empty());
     }
 
     #[test]
-    fn test_get_sources() {
-        let results = get_sources().unwrap();
-        println!("{:?}", results);
+    fn test_search_uses_an_index() {
+        let _db = init_test_db();
+        let conn = get_conn().unwrap();
+        let filters = test_filters("Fra");
+        let variants = [
+            filters.clone(),
+            Filters {
+                query: Some("1 France".to_string()),
+                use_keywords: true,
+                ..filters.clone()
+            },
+            Filters {
+                view_type: view_type::FAVORITES,
+                page: 20,
+                ..filters.clone()
+            },
+            Filters {
+                group_ids: Some(vec![1, 2, 3]),
+                exclude_dead: Some(true),
+                ..filters.clone()
+            },
+            Filters {
+                view_type: view_type::NEW,
+                ..filters.clone()
+            },
+        ];
+        for filters in variants {
+            let media_types = filters.media_types.clone().unwrap();
+            let conditions = ChannelConditions::new(&filters, &media_types, 0).unwrap();
+            let (query, _) = get_channel_search_query(&filters, &conditions, false, &0, &36);
+            let plan = query_plan(&conn, &query);
+            assert!(
+                !plan.iter().any(|step| step.starts_with("SCAN channels")),
+                "{filters:?} scans every channel: {plan:?}"
+            );
+        }
+    }
+
+    /// Each step of the plan sqlite picks for `query`, its parameters left unbound
+    fn query_plan(conn: &Connection, query: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {query}"))
+            .unwrap();
+        let nulls = vec![rusqlite::types::Null; stmt.parameter_count()];
+        stmt.query_map(rusqlite::params_from_iter(nulls), |row| row.get(3))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    fn synthetic_playlist(source_id: i64) -> Vec<Channel> {
+        (0..12_000)
+            .map(|i| Channel {
+                id: None,
+                name: format!("Channel {i}"),
+                url: Some(format!("http://example.com/{i}.ts")),
+                group: Some(format!("Group {}", i % 200)),
+                image: None,
+                media_type: media_type::LIVESTREAM,
+                source_id: Some(source_id),
+                series_id: None,
+                group_id: None,
+                favorite: false,
+                watched: false,
+                stream_id: None,
+                tvg_id: Some(format!("channel{i}.tv")),
+                media_types: None,
+                catchup: None,
+            })
+            .collect()
+    }
+
+    fn test_source(name: &str) -> Source {
+        Source {
+            id: None,
+            name: name.to_string(),
+            url: None,
+            url_origin: None,
+            last_refresh_at: None,
+            last_refresh_status: None,
+            last_refresh_error: None,
+            channel_count: None,
+            import_live: true,
+            import_vod: true,
+            import_series: true,
+            group_filter: None,
+            username: None,
+            password: None,
+            source_type: source_type::M3U,
+            use_tvg_id: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_insert_channels_batch() {
+        let _db = init_test_db();
+        let mut conn = get_conn().unwrap();
+        set_bulk_import(&conn, true).unwrap();
+        let tx = conn.transaction().unwrap();
+        let source_id = create_or_find_source_by_name(&tx, &test_source("batched")).unwrap();
+        let mut groups = HashMap::new();
+        let mut channels = synthetic_playlist(source_id);
+        for channel in channels.iter_mut() {
+            set_channel_group_id(&mut groups, channel, &tx, &source_id).unwrap();
+        }
+        for batch in channels.chunks(INSERT_BATCH_SIZE) {
+            let ids = insert_channels_batch(&tx, batch).unwrap();
+            assert!(ids.iter().all(Option::is_some));
+        }
+        // the same rows again update the ones already there
+        let ids = insert_channels_batch(&tx, &channels[..10]).unwrap();
+        assert!(ids.iter().all(Option::is_some));
+        tx.commit().unwrap();
+        set_bulk_import(&conn, false).unwrap();
+
+        assert_eq!(groups.len(), 200);
+        assert!(channels.iter().all(|c| c.group_id.is_some()
+            && c.group_id == groups.get(c.group.as_ref().unwrap()).copied()));
+        let count: usize = conn
+            .query_row(
+                "SELECT COUNT(*) FROM channels WHERE source_id = ?",
+                [source_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, channels.len());
+    }
+
+    #[test]
+    fn test_interrupted_refresh_keeps_channels() {
+        let _db = init_test_db();
+        let source_id = seed_fixtures();
+        let mut source = get_source_from_id(source_id).unwrap();
+        let before = search(fixture_filters("", view_type::ALL, source_id)).unwrap();
+        let mut conn = get_conn().unwrap();
+        let tx = import_transaction(&mut conn).unwrap();
+        begin_staged_import(&tx, &mut source, true).unwrap();
+        let channel = fixture_channel("Arte", "News", media_type::LIVESTREAM, source_id);
+        stage_channels(&tx, source_id, &[(channel, None)]).unwrap();
+        // a batch is committed, then the import is cut short
+        commit_batch(&tx).unwrap();
+        drop(tx);
+        drop(conn);
+        let after = search(fixture_filters("", view_type::ALL, source_id)).unwrap();
+        assert_eq!(names(after), names(before));
+
+        let mut conn = get_conn().unwrap();
+        let tx = import_transaction(&mut conn).unwrap();
+        swap_in_staged(&tx, source_id, true).unwrap();
+        finish_import(&tx, source_id).unwrap();
+        tx.commit().unwrap();
+        let after = search(fixture_filters("", view_type::ALL, source_id)).unwrap();
+        assert_eq!(names(after), vec!["Arte"]);
+        assert!(get_pending_imports().unwrap().is_empty());
     }
 }
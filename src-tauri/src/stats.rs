@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Result};
+use chrono::{Local, NaiveDate, TimeZone};
+
+use crate::{
+    settings::get_settings,
+    sql,
+    types::{ChannelWatchTime, DayWatchTime, WatchSession, WatchStats},
+};
+
+/// Plays shorter than this are zapping and aren't counted
+pub const MIN_WATCH_SECONDS: i64 = 30;
+const TOP_CHANNELS: usize = 10;
+
+/// Copies an ended playback session to the watch statistics, unless tracking is disabled
+pub fn record(session_id: i64) -> Result<()> {
+    if get_settings()?.track_watch_stats == Some(false) {
+        return Ok(());
+    }
+    sql::record_watch_session(session_id, MIN_WATCH_SECONDS)
+}
+
+/// Watch time between the `from` and `to` timestamps, the histogram has one entry per
+/// local day of the window
+pub fn get_watch_stats(from: i64, to: i64) -> Result<WatchStats> {
+    if to <= from {
+        bail!("The end of the range must come after its start");
+    }
+    let sessions = sql::get_watch_sessions(from, to)?;
+    Ok(compute(&sessions, from, to))
+}
+
+fn compute(sessions: &[WatchSession], from: i64, to: i64) -> WatchStats {
+    let mut total = 0;
+    let mut channels: HashMap<&str, (i64, i64)> = HashMap::new();
+    let mut media_types: HashMap<u8, i64> = HashMap::new();
+    let mut days: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    let last_day = local_date(to - 1);
+    let mut day = local_date(from);
+    while day <= last_day {
+        days.insert(day, 0);
+        let Some(next) = day.succ_opt() else {
+            break;
+        };
+        day = next;
+    }
+    for session in sessions {
+        let start = session.started_at.max(from);
+        let end = session.ended_at.min(to);
+        if end <= start {
+            continue;
+        }
+        total += end - start;
+        // sessions come oldest first, the channel keeps its latest id
+        let channel = channels.entry(session.name.as_str()).or_default();
+        *channel = (session.channel_id, channel.1 + end - start);
+        if let Some(media_type) = session.media_type {
+            *media_types.entry(media_type).or_default() += end - start;
+        }
+        let mut cursor = start;
+        while cursor < end {
+            let day = local_date(cursor);
+            let next = next_midnight(day).min(end);
+            *days.entry(day).or_default() += next - cursor;
+            cursor = next;
+        }
+    }
+    let mut top_channels: Vec<ChannelWatchTime> = channels
+        .into_iter()
+        .map(|(name, (channel_id, seconds))| ChannelWatchTime {
+            channel_id,
+            name: name.to_string(),
+            hours: hours(seconds),
+        })
+        .collect();
+    top_channels.sort_by(|a, b| b.hours.total_cmp(&a.hours).then(a.name.cmp(&b.name)));
+    top_channels.truncate(TOP_CHANNELS);
+    WatchStats {
+        total_hours: hours(total),
+        top_channels,
+        media_type_hours: media_types
+            .into_iter()
+            .map(|(media_type, seconds)| (media_type, hours(seconds)))
+            .collect(),
+        days: days
+            .into_iter()
+            .map(|(day, seconds)| DayWatchTime {
+                day: day.format("%Y-%m-%d").to_string(),
+                hours: hours(seconds),
+            })
+            .collect(),
+    }
+}
+
+fn hours(seconds: i64) -> f64 {
+    seconds as f64 / 3600.0
+}
+
+fn local_date(timestamp: i64) -> NaiveDate {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .earliest()
+        .map(|time| time.date_naive())
+        .unwrap_or_default()
+}
+
+fn next_midnight(day: NaiveDate) -> i64 {
+    day.succ_opt()
+        .and_then(|next| next.and_hms_opt(0, 0, 0))
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|midnight| midnight.timestamp())
+        .unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod test_stats {
+    use chrono::{Local, TimeZone};
+
+    use rusqlite::params;
+
+    use super::{compute, MIN_WATCH_SECONDS};
+    use crate::{media_type, sql, types::WatchSession};
+
+    fn at(day: u32, hour: u32, minute: u32) -> i64 {
+        Local
+            .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+            .earliest()
+            .unwrap()
+            .timestamp()
+    }
+
+    fn session(name: &str, media_type: Option<u8>, started_at: i64, ended_at: i64) -> WatchSession {
+        WatchSession {
+            channel_id: 1,
+            name: name.to_string(),
+            media_type,
+            started_at,
+            ended_at,
+        }
+    }
+
+    #[test]
+    fn test_compute_splits_midnight() {
+        let sessions = [
+            session(
+                "France 24",
+                Some(media_type::LIVESTREAM),
+                at(10, 23, 0),
+                at(11, 1, 0),
+            ),
+            session(
+                "Dune",
+                Some(media_type::MOVIE),
+                at(11, 20, 0),
+                at(11, 23, 0),
+            ),
+            session("Transient", None, at(12, 8, 0), at(12, 9, 30)),
+        ];
+        let stats = compute(&sessions, at(10, 0, 0), at(13, 0, 0));
+        assert_eq!(stats.total_hours, 6.5);
+        let days: Vec<(&str, f64)> = stats
+            .days
+            .iter()
+            .map(|day| (day.day.as_str(), day.hours))
+            .collect();
+        assert_eq!(
+            days,
+            [
+                ("2024-06-10", 1.0),
+                ("2024-06-11", 4.0),
+                ("2024-06-12", 1.5)
+            ]
+        );
+        assert_eq!(stats.top_channels[0].name, "Dune");
+        assert_eq!(stats.top_channels[1].name, "France 24");
+        assert_eq!(stats.media_type_hours[&media_type::LIVESTREAM], 2.0);
+        assert!(!stats.media_type_hours.contains_key(&media_type::RADIO));
+        let clipped = compute(&sessions, at(11, 0, 0), at(11, 21, 0));
+        assert_eq!(clipped.total_hours, 2.0);
+        assert_eq!(clipped.days.len(), 1);
+    }
+
+    #[test]
+    fn test_zapping_is_ignored() {
+        let _db = sql::init_test_db();
+        let conn = sql::get_conn().unwrap();
+        for (started_at, ended_at) in [(1000, 1010), (2000, 2600)] {
+            conn.execute(
+                "INSERT INTO playback_sessions (channel_id, started_at, ended_at, transient, name) VALUES (-1, ?, ?, 1, 'Adhoc')",
+                params![started_at, ended_at],
+            )
+            .unwrap();
+            sql::record_watch_session(conn.last_insert_rowid(), MIN_WATCH_SECONDS).unwrap();
+        }
+        let sessions = sql::get_watch_sessions(0, 3000).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].name, "Adhoc");
+        assert_eq!(sessions[0].started_at, 2000);
+        sql::clear_watch_sessions().unwrap();
+        assert!(sql::get_watch_sessions(0, 3000).unwrap().is_empty());
+    }
+}
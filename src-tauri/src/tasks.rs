@@ -0,0 +1,94 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+
+use crate::{sleep_timer, types::AppState};
+
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+const CANCEL_POLL: Duration = Duration::from_millis(200);
+
+/// Cooperative cancellation of a background task. Every run gets a fresh token so
+/// cancelling a finished run can't leak into the next one
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Relaxed)
+    }
+
+    /// Resolves once the token is cancelled, meant for `tokio::select!`
+    pub async fn cancelled(&self) {
+        while !self.is_cancelled() {
+            tokio::time::sleep(CANCEL_POLL).await;
+        }
+    }
+}
+
+/// Cancels everything running in the background on exit and waits briefly for it to stop
+pub fn shutdown(app: &AppHandle) {
+    let state = app.state::<Mutex<AppState>>();
+    let epg = {
+        let mut state = state.blocking_lock();
+        state.epg.stop.cancel();
+        state.playback.restream_stop.cancel();
+        sleep_timer::cancel(&state.playback.player);
+        for relay in [
+            state.playback.relay.take(),
+            state.playback.cast_relay.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let _ = relay.stop.send(true);
+        }
+        state.refreshes.stop.cancel();
+        state.refreshes.health_check_stop.cancel();
+        state.refreshes.probe_stop.cancel();
+        for stop in state.refreshes.series_loads.values() {
+            stop.cancel();
+        }
+        // removes the playlists previews downloaded for nothing
+        state.prepared_imports.clear();
+        state.epg.handle.take()
+    };
+    if let Some(epg) = epg {
+        tauri::async_runtime::block_on(async {
+            if tokio::time::timeout(SHUTDOWN_GRACE, epg).await.is_err() {
+                tracing::warn!("The EPG watcher did not stop in time");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test_tasks {
+    use std::time::Duration;
+
+    use super::CancelToken;
+
+    #[tokio::test]
+    async fn test_cancel_token() {
+        let token = CancelToken::default();
+        let task = token.clone();
+        assert!(!token.is_cancelled());
+        let waiting = tokio::spawn(async move { task.cancelled().await });
+        token.cancel();
+        tokio::time::timeout(Duration::from_secs(2), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,50 @@
+//! Channels and sources the tests start from, each test overrides the fields it checks
+
+use crate::{
+    media_type,
+    types::{Channel, Source},
+};
+
+/// A live channel that isn't stored nor tied to a source
+pub fn channel(name: &str) -> Channel {
+    Channel {
+        id: None,
+        name: name.to_string(),
+        url: Some(format!("http://example.com/{name}")),
+        group: None,
+        group_id: None,
+        image: None,
+        media_type: media_type::LIVESTREAM,
+        source_id: None,
+        series_id: None,
+        favorite: false,
+        watched: false,
+        stream_id: None,
+        tvg_id: None,
+        media_types: None,
+        catchup: None,
+    }
+}
+
+/// An enabled source importing everything, matching channels on their tvg id
+pub fn source(name: &str, source_type: u8) -> Source {
+    Source {
+        id: None,
+        name: name.to_string(),
+        url: None,
+        url_origin: None,
+        username: None,
+        password: None,
+        source_type,
+        use_tvg_id: Some(true),
+        enabled: true,
+        last_refresh_at: None,
+        last_refresh_status: None,
+        last_refresh_error: None,
+        channel_count: None,
+        import_live: true,
+        import_vod: true,
+        import_series: true,
+        group_filter: None,
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use tokio::{process::Command, sync::Semaphore};
+
+use crate::{
+    sql,
+    types::{Channel, ChannelHttpHeaders},
+    utils::{get_bin, get_playable_url},
+};
+
+const FFMPEG_BIN_NAME: &str = "ffmpeg";
+const THUMBNAIL_TIMEOUT: Duration = Duration::from_secs(8);
+const CACHE_DURATION: Duration = Duration::from_secs(5 * 60);
+const MAX_CONCURRENT: usize = 2;
+const THUMBNAIL_WIDTH: u16 = 320;
+#[cfg(target_os = "windows")]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+static SLOTS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(MAX_CONCURRENT));
+/// Channels whose last attempt failed, so a dead stream isn't retried on every scroll
+static FAILED: LazyLock<Mutex<HashMap<i64, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Grabs a single frame of the channel into the thumbnail cache and returns its path.
+/// None is the placeholder for streams that are dead or too slow to answer
+pub async fn generate_thumbnail(channel_id: i64) -> Result<Option<String>> {
+    let path = get_thumbnail_path(channel_id)?;
+    if is_fresh(&path) {
+        return Ok(Some(path.to_string_lossy().to_string()));
+    }
+    if failed_recently(channel_id) {
+        return Ok(None);
+    }
+    let _slot = SLOTS.acquire().await?;
+    // Another request for the same channel may have finished while this one waited
+    if is_fresh(&path) {
+        return Ok(Some(path.to_string_lossy().to_string()));
+    }
+    let channel = sql::get_channel_by_id(channel_id)?;
+    let headers = sql::get_channel_headers_by_id(channel_id)?;
+    match tokio::time::timeout(THUMBNAIL_TIMEOUT, grab_frame(&channel, headers, &path)).await {
+        Ok(Ok(())) => {
+            FAILED.lock().unwrap().remove(&channel_id);
+            Ok(Some(path.to_string_lossy().to_string()))
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(
+                "Failed to generate a thumbnail for {}: {:?}",
+                channel.name,
+                e
+            );
+            FAILED.lock().unwrap().insert(channel_id, Instant::now());
+            Ok(None)
+        }
+        Err(_) => {
+            tracing::warn!("Timed out generating a thumbnail for {}", channel.name);
+            FAILED.lock().unwrap().insert(channel_id, Instant::now());
+            Ok(None)
+        }
+    }
+}
+
+async fn grab_frame(
+    channel: &Channel,
+    headers: Option<ChannelHttpHeaders>,
+    path: &Path,
+) -> Result<()> {
+    let url = get_playable_url(channel)?;
+    // Written next to the cache entry first so a killed ffmpeg never leaves a half written jpeg
+    let partial = path.with_extension("part.jpg");
+    let mut command = Command::new(get_bin(FFMPEG_BIN_NAME));
+    command.args(get_ffmpeg_args(&url, headers, &partial));
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    #[cfg(target_os = "windows")]
+    command.creation_flags(CREATE_NO_WINDOW);
+    let status = command.status().await?;
+    if !status.success() || !partial.exists() {
+        anyhow::bail!("ffmpeg exited with {status}");
+    }
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+fn get_ffmpeg_args(url: &str, headers: Option<ChannelHttpHeaders>, path: &Path) -> Vec<String> {
+    let mut args = vec![
+        "-hide_banner".to_string(),
+        "-loglevel".into(),
+        "error".into(),
+    ];
+    if let Some(headers) = headers {
+        if let Some(referrer) = headers.referrer {
+            args.push("-headers".into());
+            args.push(format!("Referer: {referrer}"));
+        }
+        if let Some(user_agent) = headers.user_agent {
+            args.push("-user_agent".into());
+            args.push(user_agent);
+        }
+        if let Some(origin) = headers.http_origin {
+            args.push("-headers".into());
+            args.push(format!("Origin: {origin}"));
+        }
+        if headers.ignore_ssl == Some(true) {
+            args.push("-tls_verify".into());
+            args.push("0".into());
+        }
+    }
+    args.extend([
+        "-i".into(),
+        url.to_string(),
+        "-frames:v".into(),
+        "1".into(),
+        "-vf".into(),
+        format!("scale={THUMBNAIL_WIDTH}:-2"),
+        "-q:v".into(),
+        "5".into(),
+        "-y".into(),
+        path.to_string_lossy().to_string(),
+    ]);
+    args
+}
+
+fn is_fresh(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < CACHE_DURATION)
+}
+
+fn failed_recently(channel_id: i64) -> bool {
+    let mut failed = FAILED.lock().unwrap();
+    failed.retain(|_, at| at.elapsed() < CACHE_DURATION);
+    failed.contains_key(&channel_id)
+}
+
+fn get_thumbnail_path(channel_id: i64) -> Result<PathBuf> {
+    let mut path = directories::ProjectDirs::from("dev", "fredol", "open-tv")
+        .context("can't find project folder")?
+        .cache_dir()
+        .to_owned();
+    path.push("thumbnails");
+    if !path.exists() {
+        std::fs::create_dir_all(&path)?;
+    }
+    path.push(format!("{channel_id}.jpg"));
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test_thumbnail {
+    use std::path::Path;
+
+    use super::get_ffmpeg_args;
+    use crate::types::ChannelHttpHeaders;
+
+    #[test]
+    fn test_ffmpeg_args_put_headers_before_input() {
+        let headers = ChannelHttpHeaders {
+            referrer: Some("https://example.com".to_string()),
+            user_agent: Some("Open TV".to_string()),
+            ..Default::default()
+        };
+        let args = get_ffmpeg_args("http://host/1.ts", Some(headers), Path::new("1.jpg"));
+        let input = args.iter().position(|a| a == "-i").unwrap();
+        let referrer = args
+            .iter()
+            .position(|a| a == "Referer: https://example.com")
+            .unwrap();
+        let user_agent = args.iter().position(|a| a == "Open TV").unwrap();
+        assert!(referrer < input && user_agent < input);
+        assert_eq!(args[input + 1], "http://host/1.ts");
+        assert_eq!(args.last().unwrap(), "1.jpg");
+    }
+}
@@ -1,10 +1,18 @@
 use std::{
-    sync::{atomic::AtomicBool, Arc},
-    thread::JoinHandle,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize},
+        Arc,
+    },
 };
 
 use serde::{Deserialize, Serialize};
 
+use crate::media_type;
+use crate::preview::PreparedImport;
+use crate::tasks::CancelToken;
+
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub struct Channel {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -22,6 +30,15 @@ pub struct Channel {
     pub group_id: Option<i64>,
     pub favorite: bool,
     pub stream_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tvg_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub catchup: Option<bool>,
+    #[serde(default)]
+    pub watched: bool,
+    /// Bitmask of the media types a group holds, only set on groups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_types: Option<u8>,
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
@@ -40,17 +57,114 @@ pub struct Source {
     pub source_type: u8,
     pub use_tvg_id: Option<bool>,
     pub enabled: bool,
+    pub last_refresh_at: Option<i64>,
+    pub last_refresh_status: Option<u8>,
+    pub last_refresh_error: Option<String>,
+    pub channel_count: Option<u64>,
+    #[serde(default = "default_true")]
+    pub import_live: bool,
+    #[serde(default = "default_true")]
+    pub import_vod: bool,
+    #[serde(default = "default_true")]
+    pub import_series: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_filter: Option<Vec<String>>,
+}
+
+/// A source with what it holds, counted by media type
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct SourceStats {
+    #[serde(flatten)]
+    pub source: Source,
+    pub channels: HashMap<u8, u64>,
+    pub groups: HashMap<u8, u64>,
+}
+
+impl Source {
+    pub fn imports_media_type(&self, media_type: u8) -> bool {
+        match media_type {
+            media_type::LIVESTREAM | media_type::RADIO => self.import_live,
+            media_type::MOVIE => self.import_vod,
+            media_type::SERIE => self.import_series,
+            _ => true,
+        }
+    }
+
+    /// The groups picked for a source not saved yet, `None` when every group is imported
+    pub fn get_group_filter(&self) -> Option<HashSet<String>> {
+        self.group_filter
+            .as_ref()
+            .filter(|groups| !groups.is_empty())
+            .map(|groups| groups.iter().cloned().collect())
+    }
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub struct Settings {
-    pub recording_path: Option<String>,
+    pub recording_path: Option<PathBuf>,
     pub mpv_params: Option<String>,
-    pub use_stream_caching: Option<bool>,
+    #[serde(default = "default_true")]
+    pub use_stream_caching: bool,
     pub default_view: Option<u8>,
     pub volume: Option<u8>,
     pub refresh_on_start: Option<bool>,
     pub restream_port: Option<u16>,
+    pub new_channels_days: Option<u16>,
+    pub preferred_player: Option<u8>,
+    pub health_check_concurrency: Option<u8>,
+    pub refresh_interval_hours: Option<u16>,
+    pub page_size: Option<u8>,
+    pub remember_channel_volume: Option<bool>,
+    pub preferred_audio_lang: Option<String>,
+    pub preferred_subtitle_lang: Option<String>,
+    pub subtitles_enabled: Option<bool>,
+    pub hwdec: Option<String>,
+    pub vo: Option<String>,
+    pub ytdlp_auto_update: Option<bool>,
+    pub hotkey_show_window: Option<String>,
+    pub hotkey_play_pause: Option<String>,
+    pub gamepad_enabled: Option<bool>,
+    pub gamepad_mapping: Option<String>,
+    pub folder_extensions: Option<String>,
+    pub player_startup_timeout: Option<u16>,
+    pub single_instance_playback: Option<bool>,
+    pub recording_filename_template: Option<String>,
+    pub min_free_space_mb: Option<u32>,
+    pub max_recording_minutes: Option<u16>,
+    pub pip_mode: Option<bool>,
+    pub radio_mpv_params: Option<String>,
+    pub auto_mark_watched: Option<bool>,
+    pub epg_media_title: Option<bool>,
+    pub epg_retention_days: Option<u16>,
+    pub epg_future_days: Option<u16>,
+    pub xtream_requests_per_second: Option<u8>,
+    pub track_watch_stats: Option<bool>,
+    pub hdhomerun_enabled: Option<bool>,
+    pub hdhomerun_port: Option<u16>,
+    pub hdhomerun_lan: Option<bool>,
+    /// Served instead of the favorites when set
+    pub hdhomerun_playlist_id: Option<i64>,
+    pub hdhomerun_max_streams: Option<u8>,
+    /// One of `AUTO_BACKUP_VALUES`, off when unset
+    pub auto_backup: Option<String>,
+    pub auto_backup_path: Option<PathBuf>,
+    pub auto_backup_count: Option<u8>,
+    /// Used for every request and passed to the players, takes precedence over the system proxy
+    pub proxy_url: Option<String>,
+    pub use_system_proxy: Option<bool>,
+    pub control_api_enabled: Option<bool>,
+    pub control_api_port: Option<u16>,
+    pub control_api_lan: Option<bool>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct SettingsFieldError {
+    pub field: String,
+    pub message: String,
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
@@ -62,7 +176,43 @@ pub struct Filters {
     pub page: u8,
     pub series_id: Option<i64>,
     pub group_id: Option<i64>,
+    pub group_ids: Option<Vec<i64>>,
     pub use_keywords: bool,
+    pub exclude_dead: Option<bool>,
+    pub playlist_id: Option<i64>,
+    #[serde(default)]
+    pub after_id: Option<i64>,
+    #[serde(default)]
+    pub after_name: Option<String>,
+    #[serde(default)]
+    pub include_group_names: bool,
+    #[serde(default)]
+    pub hide_watched: bool,
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Reads the query with the syntax of `search_syntax`, also on when it starts with a `/`
+    #[serde(default)]
+    pub advanced_query: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct GroupItem {
+    pub id: i64,
+    pub name: String,
+    pub image: Option<String>,
+    pub source_id: i64,
+    pub source_name: String,
+    pub channel_count: u64,
+    /// Bitmask of the media types of the group's channels
+    pub media_types: u8,
+    pub favorite: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct SearchPage {
+    pub channels: Vec<Channel>,
+    pub after_id: Option<i64>,
+    pub after_name: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize, Default)]
@@ -94,12 +244,156 @@ pub struct Group {
     pub source_id: Option<i64>,
 }
 
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct DbInfo {
+    pub path: String,
+    pub schema_version: usize,
+    pub expected_schema_version: usize,
+    pub file_size: u64,
+    pub row_counts: HashMap<String, u64>,
+    pub secret_storage: String,
+    pub last_backup_at: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct UrlValidation {
+    pub valid: bool,
+    pub code: u8,
+    pub message: Option<String>,
+    pub http_status: Option<u16>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct DuplicatedGroup {
+    pub id: i64,
+    pub channel_ids: Vec<i64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct FilterPreset {
+    pub id: i64,
+    pub name: String,
+    pub filters: Filters,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct AppliedFilterPreset {
+    pub filters: Filters,
+    pub missing_sources: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct Playlist {
+    pub id: i64,
+    pub name: String,
+    pub channel_count: u64,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct SourceRefreshResult {
+    pub source_id: i64,
+    pub name: String,
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub duplicates: usize,
+    pub error: Option<String>,
+}
+
+/// What an import inserted, after collapsing rows repeating the same name and url
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct ImportSummary {
+    pub channels: usize,
+    pub duplicates: usize,
+    /// Entries that can't be played from here, like the tuner services of an Enigma2 bouquet
+    pub skipped: usize,
+}
+
+/// A request the provider refused and that is retried after `wait_seconds`
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct XtreamThrottle {
+    pub attempt: u32,
+    pub wait_seconds: u64,
+    pub status: Option<u16>,
+    pub banned: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct SeriesPrefetchProgress {
+    pub source_id: i64,
+    pub fetched: usize,
+    pub total: usize,
+    pub throttle: Option<XtreamThrottle>,
+    pub finished: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct RefreshLogEntry {
+    pub timestamp: i64,
+    pub status: u8,
+    pub error: Option<String>,
+    pub channel_count: u64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct TableCheck {
+    pub table: String,
+    pub total: u64,
+    pub invalid: u64,
+}
+
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub struct IdName {
     pub id: i64,
     pub name: String,
 }
 
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct WatchSession {
+    pub channel_id: i64,
+    pub name: String,
+    pub media_type: Option<u8>,
+    pub started_at: i64,
+    pub ended_at: i64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct WatchStats {
+    pub total_hours: f64,
+    pub top_channels: Vec<ChannelWatchTime>,
+    pub media_type_hours: HashMap<u8, f64>,
+    /// One entry per local day of the window, days without watching included
+    pub days: Vec<DayWatchTime>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ChannelWatchTime {
+    pub channel_id: i64,
+    pub name: String,
+    pub hours: f64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct DayWatchTime {
+    /// YYYY-MM-DD
+    pub day: String,
+    pub hours: f64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub channel_count: u64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ChannelNote {
+    pub note: String,
+    pub updated_at: i64,
+}
+
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub struct CustomChannelExtraData {
     pub headers: Option<ChannelHttpHeaders>,
@@ -119,6 +413,61 @@ pub struct ExportedSource {
     pub channels: Vec<CustomChannel>,
 }
 
+/// A source without its channels, as written in a source list file
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct SourceListEntry {
+    pub name: String,
+    pub source_type: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub use_tvg_id: Option<bool>,
+    #[serde(default = "default_true")]
+    pub import_live: bool,
+    #[serde(default = "default_true")]
+    pub import_vod: bool,
+    #[serde(default = "default_true")]
+    pub import_series: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_filter: Option<Vec<String>>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct SourceList {
+    pub version: u8,
+    pub sources: Vec<SourceListEntry>,
+}
+
+/// What importing a source would bring in, computed without writing anything
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct SourcePreview {
+    /// Hands what the preview downloaded over to the import, when there is something to reuse
+    pub token: Option<u64>,
+    pub channel_count: u64,
+    pub media_type_counts: HashMap<u8, u64>,
+    pub groups: Vec<GroupCount>,
+    pub tvg_id_count: u64,
+    pub sample: Vec<Channel>,
+    /// Rows repeating the name and url of an earlier one, the import skips them
+    pub duplicates: usize,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct GroupCount {
+    pub name: String,
+    pub count: u64,
+}
+
+#[derive(Clone, PartialEq, Debug, Default, Deserialize, Serialize)]
+pub struct SourceListImport {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 pub struct EPG {
     pub epg_id: String,
@@ -127,6 +476,62 @@ pub struct EPG {
     pub start_time: String,
     pub start_timestamp: i64,
     pub end_time: String,
+    #[serde(default)]
+    pub end_timestamp: i64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct EpgSource {
+    pub id: i64,
+    pub name: String,
+    pub url: String,
+    /// Set on the feed of a source, its programmes win for that source's channels
+    pub source_id: Option<i64>,
+    pub last_refresh_at: Option<i64>,
+    /// IANA name applied to times without an offset, UTC when None
+    pub timezone: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct Programme {
+    pub tvg_id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub start: i64,
+    pub stop: i64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct EpgGridRow {
+    pub channel_id: i64,
+    pub name: String,
+    pub programmes: Vec<Programme>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ProgrammeSearchResult {
+    pub programme: Programme,
+    pub channel_id: i64,
+    pub channel_name: String,
+    pub channel_image: Option<String>,
+    pub source_id: Option<i64>,
+    pub media_type: u8,
+    pub catchup: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize, Default)]
+pub struct NowNext {
+    pub current: Option<Programme>,
+    pub next: Option<Programme>,
+    pub progress: Option<u8>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct EpgRefreshResult {
+    pub epg_source_id: i64,
+    pub programmes: u64,
+    pub channels: u64,
+    pub pruned: u64,
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
@@ -134,14 +539,117 @@ pub struct EPGNotify {
     pub epg_id: String,
     pub title: String,
     pub start_timestamp: i64,
+    pub channel_id: Option<i64>,
+    pub tvg_id: Option<String>,
+    pub source_id: Option<i64>,
+    pub repeat: Option<u8>,
+    pub snooze_minutes: Option<u16>,
+    pub snoozed_until: Option<i64>,
     pub channel_name: String,
 }
 
+/// What went wrong at startup, and what was cleaned up after a crash
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize, Default)]
+pub struct StartupState {
+    pub database_error: Option<String>,
+    /// Where a corrupt database was moved before starting over with an empty one
+    pub corrupt_database_backup: Option<String>,
+    /// New sources whose interrupted first import was removed
+    pub rolled_back_imports: Vec<String>,
+    /// Sources left half imported by a crash, see `resume_import`
+    pub interrupted_imports: Vec<IdName>,
+}
+
+/// How far an import got, saved with each batch it commits
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportCheckpoint {
+    /// The byte offset of the first line left to read in a playlist of `file_size` bytes
+    M3u { offset: u64, file_size: u64 },
+    /// The last category fully imported, categories go in the order the provider lists them
+    Xtream {
+        media_type: u8,
+        category_id: Option<String>,
+    },
+}
+
+/// An import that started and never finished
+#[derive(Clone, PartialEq, Debug)]
+pub struct PendingImport {
+    pub source_id: i64,
+    pub name: String,
+    pub started_at: i64,
+    pub new_source: bool,
+    pub checkpoint: Option<ImportCheckpoint>,
+}
+
 #[derive(Debug, Default)]
 pub struct AppState {
-    pub notify_stop: Arc<AtomicBool>,
-    pub thread_handle: Option<JoinHandle<Result<(), anyhow::Error>>>,
-    pub restream_stop_signal: Arc<AtomicBool>,
+    pub epg: EpgWatcher,
+    pub playback: ActivePlayback,
+    pub refreshes: ActiveRefreshes,
+    pub cast_devices: Option<Vec<CastDevice>>,
+    pub resume_candidate: Option<i64>,
+    pub frontend_ready: bool,
+    pub pending_actions: Vec<LaunchAction>,
+    pub startup: StartupState,
+    pub last_adhoc: Option<CustomChannel>,
+    /// The last two distinct channels played, most recent first
+    pub last_channels: Vec<i64>,
+    /// What each preview downloaded, with the source it was made for
+    pub prepared_imports: HashMap<u64, (Source, PreparedImport)>,
+}
+
+/// The task firing EPG reminders, restarted whenever the reminders change
+#[derive(Debug, Default)]
+pub struct EpgWatcher {
+    pub stop: CancelToken,
+    pub handle: Option<tauri::async_runtime::JoinHandle<()>>,
+}
+
+/// The player and what runs alongside it
+#[derive(Debug, Default)]
+pub struct ActivePlayback {
+    pub player: Arc<PlayerProcess>,
+    pub restream_stop: CancelToken,
+    pub relay: Option<RelayHandle>,
+    /// The relay cast devices stream channels needing headers from
+    pub cast_relay: Option<RelayHandle>,
+}
+
+/// Imports, refreshes and the checks run against sources
+#[derive(Debug, Default)]
+pub struct ActiveRefreshes {
+    pub running: Arc<AtomicUsize>,
+    pub stop: CancelToken,
+    pub health_check_stop: CancelToken,
+    pub probe_stop: CancelToken,
+    /// Series being loaded, by the id their request picked so each can be cancelled alone
+    pub series_loads: HashMap<u64, CancelToken>,
+}
+
+/// The last spawned player, 0 when nothing is playing. Launches are serialized
+/// so replacing the running player can't race with another play
+#[derive(Debug, Default)]
+pub struct PlayerProcess {
+    pub pid: AtomicU32,
+    pub stop: tokio::sync::Notify,
+    pub launch: tokio::sync::Mutex<()>,
+    /// Kept with the player so any way playback stops can cancel it
+    pub sleep_timer: std::sync::Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct CompactResult {
+    pub before: u64,
+    pub after: u64,
+}
+
+#[derive(Debug)]
+pub struct RelayHandle {
+    pub ip: std::net::IpAddr,
+    pub port: u16,
+    pub stop: tokio::sync::oneshot::Sender<bool>,
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
@@ -150,3 +658,139 @@ pub struct NetworkInfo {
     pub local_ips: Vec<String>,
     pub wan_ip: String,
 }
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct CastDevice {
+    pub id: String,
+    pub name: String,
+    pub protocol: u8,
+    /// The description url of DLNA renderers, the address of Chromecasts
+    pub location: String,
+    pub av_transport_url: Option<String>,
+    pub rendering_control_url: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct StreamUrl {
+    pub url: String,
+    pub requires_headers: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct FolderScanProgress {
+    pub found: usize,
+    pub finished: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct HlsVariant {
+    pub bandwidth: Option<u64>,
+    pub resolution: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize, Default)]
+pub struct DeleteImpact {
+    pub channels: u64,
+    pub groups: u64,
+    pub favorites: u64,
+    pub headers: u64,
+    pub playlist_channels: u64,
+    pub reminders: u64,
+}
+
+/// What merging groups moves, the same whether it ran or was a dry run
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize, Default)]
+pub struct GroupMerge {
+    pub channels: u64,
+    pub total_channels: u64,
+    pub groups: u64,
+}
+
+/// Groups of `source_id` whose name matches `pattern` (a LIKE pattern) are merged into
+/// the group named `target_name` after each refresh
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct GroupMergeRule {
+    pub id: i64,
+    pub source_id: i64,
+    pub pattern: String,
+    pub target_source_id: i64,
+    pub target_name: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct SeriesSummary {
+    pub series_id: i64,
+    pub episode_count: u32,
+    pub last_added_at: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct StreamProbe {
+    pub channel_id: i64,
+    pub latency_ms: u64,
+    pub throughput_kbps: u64,
+    pub container: String,
+    pub variants: Vec<HlsVariant>,
+    pub probed_at: i64,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct DependencyStatus {
+    pub name: String,
+    pub path: String,
+    pub version: Option<String>,
+    pub minimum_version: String,
+    pub status: u8,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct DependencyReport {
+    pub dependencies: Vec<DependencyStatus>,
+    pub missing_deps_files: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct YtdlpUpdate {
+    pub status: u8,
+    pub version: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct PlaybackSession {
+    pub id: i64,
+    pub channel_id: i64,
+    pub started_at: i64,
+    pub record_path: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct LaunchAction {
+    pub action: u8,
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct HotkeyConflict {
+    pub accelerator: String,
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ActiveReminder {
+    pub epg: EPGNotify,
+    pub resolved_channel_id: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+pub struct ChannelDetails {
+    pub channel: Channel,
+    pub group: Option<Group>,
+    pub headers: Option<ChannelHttpHeaders>,
+    pub favorite: bool,
+    pub catchup: bool,
+    pub last_watched: Option<i64>,
+    pub now: Option<EPG>,
+    pub next: Option<EPG>,
+    pub has_note: bool,
+    pub tags: Vec<String>,
+}
@@ -1,22 +1,31 @@
 use crate::{
-    log::log,
-    m3u,
+    events::{self, ImportProgress, RefreshProgress},
+    folder, m3u, proxy,
     settings::{get_default_record_path, get_settings},
     source_type, sql,
-    types::{Channel, Source},
-    xtream,
+    tasks::CancelToken,
+    tvheadend,
+    types::{
+        Channel, ChannelHttpHeaders, ImportSummary, Source, SourceRefreshResult, StreamUrl,
+        XtreamThrottle,
+    },
+    xtream, xtream_http,
 };
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::{DateTime, Local, Utc};
 use regex::Regex;
-use reqwest::Client;
 use std::{
+    collections::HashSet,
     env::{consts::OS, current_exe},
+    future::Future,
     io::Write,
-    path::Path,
-    sync::LazyLock,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
 };
 use tauri::{AppHandle, Emitter};
+use tokio::{sync::Semaphore, task::JoinSet};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use url::Url;
 use which::which;
 
 const MACOS_POTENTIAL_PATHS: [&str; 3] = [
@@ -25,26 +34,274 @@ const MACOS_POTENTIAL_PATHS: [&str; 3] = [
     "/usr/local/bin",    // Homebrew on AMD64 Mac
 ];
 
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+const MAX_CONCURRENT_REFRESHES: usize = 2;
+
+const QUALITY_TOKENS: [&str; 14] = [
+    "sd", "hd", "fhd", "uhd", "4k", "8k", "hevc", "h264", "h265", "hdr", "480p", "720p", "1080p",
+    "2160p",
+];
+
 static ILLEGAL_CHARS_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r#"[<>:"/\\|?*\x00-\x1F]"#).unwrap());
 
-pub async fn refresh_source(source: Source) -> Result<()> {
-    match source.source_type {
-        source_type::M3U => m3u::read_m3u8(source, true)?,
+pub async fn refresh_source(source: Source) -> Result<ImportSummary> {
+    let source_id = source
+        .id
+        .filter(|_| source.source_type != source_type::CUSTOM);
+    let result = import_source(source).await;
+    if let Some(id) = source_id {
+        let error = result.as_ref().err().map(|e| format!("{:?}", e));
+        sql::record_refresh(id, error).unwrap_or_else(|e| {
+            tracing::error!("Failed to record refresh of source {id}: {:?}", e)
+        });
+    }
+    result
+}
+
+async fn import_source(source: Source) -> Result<ImportSummary> {
+    let summary = match source.source_type {
+        source_type::M3U => m3u::import_m3u8(source, true).await?,
         source_type::M3U_LINK => m3u::get_m3u8_from_link(source, true).await?,
-        source_type::XTREAM => xtream::get_xtream(source, true).await?,
-        source_type::CUSTOM => {}
+        source_type::XTREAM => xtream::get_xtream(source, true, &xtream_http::log_retry).await?,
+        source_type::TVHEADEND => tvheadend::get_tvheadend(source, true).await?,
+        source_type::FOLDER => {
+            folder::scan_folder(source, true, None).await?;
+            ImportSummary::default()
+        }
+        source_type::CUSTOM => ImportSummary::default(),
         _ => return Err(anyhow!("invalid source_type")),
+    };
+    Ok(summary)
+}
+
+/// Runs a first import and reports when it starts and ends
+pub async fn report_import<T>(
+    app: &AppHandle,
+    name: String,
+    import: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let report = ImportReport::start(app, name);
+    let result = import.await;
+    report.finish(&result);
+    result
+}
+
+/// The progress events of one first import, for imports with more to report than
+/// their start and end
+pub struct ImportReport {
+    app: AppHandle,
+    operation_id: u64,
+    name: String,
+}
+
+impl ImportReport {
+    pub fn start(app: &AppHandle, name: String) -> Self {
+        let report = ImportReport {
+            app: app.clone(),
+            operation_id: events::new_operation_id(),
+            name,
+        };
+        report.emit(false, None, None);
+        report
+    }
+
+    pub fn throttled(&self, throttle: &XtreamThrottle) {
+        xtream_http::log_retry(throttle);
+        self.emit(false, None, Some(throttle.clone()));
+    }
+
+    pub fn finish<T>(&self, result: &Result<T>) {
+        let error = result.as_ref().err().map(|e| format!("{e:#}"));
+        self.emit(true, error, None);
+    }
+
+    fn emit(&self, finished: bool, error: Option<String>, throttle: Option<XtreamThrottle>) {
+        let progress = ImportProgress {
+            name: self.name.clone(),
+            finished,
+            error,
+            throttle,
+        };
+        events::emit(
+            &self.app,
+            events::IMPORT_PROGRESS,
+            self.operation_id,
+            progress,
+        );
     }
-    Ok(())
 }
 
-pub async fn refresh_all() -> Result<()> {
-    let sources = sql::get_sources()?;
-    for source in sources {
-        refresh_source(source).await?;
+pub async fn preview_source_groups(source: Source) -> Result<Vec<String>> {
+    match source.source_type {
+        source_type::M3U | source_type::M3U_LINK => m3u::preview_groups(&source).await,
+        source_type::XTREAM => xtream::preview_groups(source).await,
+        _ => Err(anyhow!("this source type has no groups to preview")),
     }
-    Ok(())
+}
+
+/// Refreshes every enabled source, a failing source is reported in its result
+/// instead of stopping the others. Sources not started yet are skipped once `stop` is cancelled
+pub async fn refresh_all_sources(
+    app: Option<AppHandle>,
+    stop: CancelToken,
+) -> Result<Vec<SourceRefreshResult>> {
+    let sources = sql::get_enabled_sources()?;
+    let slots = Arc::new(Semaphore::new(MAX_CONCURRENT_REFRESHES));
+    let mut tasks = JoinSet::new();
+    let operation_id = events::new_operation_id();
+    for (index, source) in sources.into_iter().enumerate() {
+        let slots = slots.clone();
+        let app = app.clone();
+        let stop = stop.clone();
+        tasks.spawn(async move {
+            let _slot = slots.acquire_owned().await;
+            if stop.is_cancelled() {
+                return (index, cancelled_refresh(source));
+            }
+            (
+                index,
+                refresh_source_isolated(source, app, operation_id).await,
+            )
+        });
+    }
+    let mut results = Vec::with_capacity(tasks.len());
+    while let Some(joined) = tasks.join_next().await {
+        results.push(joined?);
+    }
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+fn cancelled_refresh(source: Source) -> SourceRefreshResult {
+    SourceRefreshResult {
+        source_id: source.id.unwrap_or_default(),
+        name: source.name,
+        error: Some("Refresh cancelled".to_string()),
+        ..Default::default()
+    }
+}
+
+async fn refresh_source_isolated(
+    source: Source,
+    app: Option<AppHandle>,
+    operation_id: u64,
+) -> SourceRefreshResult {
+    let source_id = source.id.unwrap_or_default();
+    let mut result = SourceRefreshResult {
+        source_id,
+        name: source.name.clone(),
+        ..Default::default()
+    };
+    let emit = |finished: bool, error: Option<String>| {
+        if let Some(app) = &app {
+            let progress = RefreshProgress {
+                source_id,
+                finished,
+                error,
+            };
+            events::emit(app, events::SOURCE_REFRESH_PROGRESS, operation_id, progress);
+        }
+    };
+    emit(false, None);
+    let refreshed = async {
+        let before = sql::get_source_channel_keys(source_id)?;
+        let summary = refresh_source(source).await?;
+        let after = sql::get_source_channel_keys(source_id)?;
+        Ok::<_, anyhow::Error>((before, after, summary))
+    }
+    .await;
+    match refreshed {
+        Ok((before, after, summary)) => {
+            (result.added, result.updated, result.removed) = diff_channel_keys(&before, &after);
+            result.duplicates = summary.duplicates;
+        }
+        Err(e) => {
+            tracing::error!("Failed to refresh source {}: {:?}", result.name, e);
+            result.error = Some(format!("{e:#}"));
+        }
+    }
+    emit(true, result.error.clone());
+    result
+}
+
+/// Counts the channels added, kept and removed between two snapshots of a source
+fn diff_channel_keys(before: &HashSet<String>, after: &HashSet<String>) -> (usize, usize, usize) {
+    let updated = after.intersection(before).count();
+    (after.len() - updated, updated, before.len() - updated)
+}
+
+pub fn get_stream_url(channel_id: i64) -> Result<StreamUrl> {
+    let channel = sql::get_channel_by_id(channel_id)?;
+    let source = sql::get_source_from_id(channel.source_id.context("no source id")?)?;
+    Ok(StreamUrl {
+        url: resolve_url(&channel, &source)?,
+        requires_headers: sql::get_channel_headers_by_id(channel_id)?.is_some(),
+    })
+}
+
+/// The archive of a live Xtream channel between `start` and `stop`
+pub fn get_catchup_url(channel_id: i64, start: i64, stop: i64) -> Result<StreamUrl> {
+    let channel = sql::get_channel_by_id(channel_id)?;
+    let source = sql::get_source_from_id(channel.source_id.context("no source id")?)?;
+    if source.source_type != source_type::XTREAM {
+        bail!("Catch-up is only available for Xtream sources");
+    }
+    Ok(StreamUrl {
+        url: xtream::resolve_timeshift_url(&channel, &source, start, stop)?,
+        requires_headers: sql::get_channel_headers_by_id(channel_id)?.is_some(),
+    })
+}
+
+/// The url players and requests should use, Xtream and TVHeadend templates are completed
+/// from their source
+pub fn get_playable_url(channel: &Channel) -> Result<String> {
+    let url = channel.url.as_deref().context("no url")?;
+    if !xtream::is_url_template(url) && !tvheadend::is_url_template(url) {
+        return Ok(url.to_string());
+    }
+    let source = sql::get_source_from_id(channel.source_id.context("no source id")?)?;
+    resolve_url(channel, &source)
+}
+
+/// The url of a channel of `source`, templates completed for the source types storing them
+pub fn resolve_url(channel: &Channel, source: &Source) -> Result<String> {
+    match source.source_type {
+        source_type::XTREAM => xtream::resolve_url(channel, source),
+        source_type::TVHEADEND => tvheadend::resolve_url(channel, source),
+        _ => Ok(channel.url.clone().context("no url")?),
+    }
+}
+
+pub fn build_channel_request(
+    url: &str,
+    headers: Option<ChannelHttpHeaders>,
+) -> Result<reqwest::RequestBuilder> {
+    let headers = headers.unwrap_or_default();
+    let client = proxy::client_builder()?
+        .danger_accept_invalid_certs(headers.ignore_ssl.unwrap_or(false))
+        .build()?;
+    Ok(with_http_headers(client.get(url), &headers))
+}
+
+pub fn with_http_headers(
+    mut request: reqwest::RequestBuilder,
+    headers: &ChannelHttpHeaders,
+) -> reqwest::RequestBuilder {
+    if let Some(referrer) = &headers.referrer {
+        request = request.header(reqwest::header::REFERER, referrer);
+    }
+    if let Some(user_agent) = &headers.user_agent {
+        request = request.header(reqwest::header::USER_AGENT, user_agent);
+    }
+    if let Some(origin) = &headers.http_origin {
+        request = request.header(reqwest::header::ORIGIN, origin);
+    }
+    request
 }
 
 pub fn get_local_time(timestamp: i64) -> Result<DateTime<Local>> {
@@ -53,17 +310,12 @@ pub fn get_local_time(timestamp: i64) -> Result<DateTime<Local>> {
 }
 
 pub async fn download(app: AppHandle, channel: Channel) -> Result<()> {
-    let client = Client::new();
-    let mut response = client
-        .get(channel.url.as_ref().context("no url")?)
-        .send()
-        .await?;
+    let client = proxy::get_client()?;
+    let url = get_playable_url(&channel)?;
+    let mut response = client.get(&url).send().await?;
     let total_size = response.content_length().unwrap_or(0);
     let mut downloaded = 0;
-    let mut file = std::fs::File::create(get_download_path(get_filename(
-        channel.name,
-        channel.url.context("no url")?,
-    )?)?)?;
+    let mut file = std::fs::File::create(get_download_path(get_filename(channel.name, url)?)?)?;
     let mut send_threshold: u8 = 5;
     if !response.status().is_success() {
         let error = response.status();
@@ -75,7 +327,7 @@ pub async fn download(app: AppHandle, channel: Channel) -> Result<()> {
         if total_size > 0 {
             let progress: u8 = ((downloaded as f64 / total_size as f64) * 100.0) as u8;
             if progress > send_threshold {
-                app.emit("progress", progress)?;
+                app.emit(events::DOWNLOAD_PROGRESS, progress)?;
                 send_threshold = progress + 5;
             }
         }
@@ -84,49 +336,187 @@ pub async fn download(app: AppHandle, channel: Channel) -> Result<()> {
 }
 
 fn get_filename(channel_name: String, url: String) -> Result<String> {
+    let url = Url::parse(&url)?;
     let extension = url
-        .split(".")
-        .last()
-        .context("url has no extension")?
-        .to_string();
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|file| file.rsplit_once('.'))
+        .map(|(_, extension)| extension)
+        .filter(|extension| !extension.is_empty())
+        .context("url has no extension")?;
     let channel_name = sanitize(channel_name);
     let filename = format!("{channel_name}.{extension}").to_string();
     Ok(filename)
 }
 
+/// Checks the url of a remote source and stores its canonical form, so everything built
+/// from it later starts from the same url. Xtream credentials left in the query are moved
+/// to their fields
+pub fn normalize_source_url(source: &mut Source) -> Result<()> {
+    if ![
+        source_type::M3U_LINK,
+        source_type::XTREAM,
+        source_type::TVHEADEND,
+    ]
+    .contains(&source.source_type)
+    {
+        return Ok(());
+    }
+    let url = source.url.as_deref().context("Missing URL")?.trim();
+    let mut url = Url::parse(url).map_err(|e| anyhow!("Invalid source url {url}: {e}"))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        bail!("Source urls must start with http:// or https://");
+    }
+    if url.host().is_none() {
+        bail!("The source url has no host");
+    }
+    url.set_fragment(None);
+    if source.source_type == source_type::XTREAM {
+        let mut kept = Vec::new();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "username" if source.username.as_deref().map_or(true, str::is_empty) => {
+                    source.username = Some(value.into_owned())
+                }
+                "password" if source.password.as_deref().map_or(true, str::is_empty) => {
+                    source.password = Some(value.into_owned())
+                }
+                "username" | "password" => {}
+                _ => kept.push((key.into_owned(), value.into_owned())),
+            }
+        }
+        url.set_query(None);
+        if !kept.is_empty() {
+            url.query_pairs_mut().extend_pairs(kept);
+        }
+    }
+    source.url = Some(url.to_string());
+    Ok(())
+}
+
+/// Names an unnamed playlist entry after the last segment of its url, without the extension
+pub fn name_from_url(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?.trim_end_matches('/');
+    let segment = path.rsplit('/').next()?;
+    let name = match segment.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => segment,
+    };
+    Some(name.trim().to_string()).filter(|name| !name.is_empty() && !name.ends_with(':'))
+}
+
 pub fn sanitize(str: String) -> String {
     ILLEGAL_CHARS_REGEX.replace_all(&str, "").to_string()
 }
 
+/// Makes a name safe to use as a file name on the current OS, truncated to
+/// `max_len` bytes without splitting a character
+pub fn sanitize_file_name(name: &str, max_len: usize) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match is_forbidden_in_file_name(c) {
+            true => '_',
+            false => c,
+        })
+        .collect();
+    let mut name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.len() > max_len {
+        let end = (0..=max_len)
+            .rev()
+            .find(|i| name.is_char_boundary(*i))
+            .unwrap_or(0);
+        name.truncate(end);
+    }
+    // windows drops trailing dots and spaces, which would break collision checks
+    let name = name.trim_end_matches(['.', ' ']).to_string();
+    if cfg!(target_os = "windows") && WINDOWS_RESERVED_NAMES.contains(&name.to_uppercase().as_str())
+    {
+        return format!("{name}_");
+    }
+    name
+}
+
+fn is_forbidden_in_file_name(c: char) -> bool {
+    match cfg!(target_os = "windows") {
+        true => matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*'),
+        false => c == '/',
+    }
+}
+
+/// Lowercases and strips accents so searches match regardless of diacritics,
+/// e.g. "München" and "İstanbul" become "munchen" and "istanbul"
+pub fn fold_search_name(name: &str) -> String {
+    let mut folded = String::with_capacity(name.len());
+    for c in name
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+    {
+        match c {
+            'ı' => folded.push('i'),
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            'ø' => folded.push('o'),
+            'ł' => folded.push('l'),
+            'đ' => folded.push('d'),
+            _ => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// Escapes `text` for a LIKE pattern with `ESCAPE '\'`, so it matches only itself
+pub fn like_literal(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Reduces a channel name to the words that identify it, dropping punctuation and
+/// quality markers, e.g. "Sky Sports: Main Event FHD" becomes "sky sports main event"
+pub fn channel_name_key(name: &str) -> String {
+    fold_search_name(name)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !QUALITY_TOKENS.contains(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn get_download_path(file_name: String) -> Result<String> {
     let settings = get_settings()?;
-    let path = match settings.recording_path {
+    let mut path = match settings.recording_path {
         Some(path) => path,
         None => get_default_record_path()?,
     };
-    let mut path = Path::new(&path).to_path_buf();
     path.push(file_name);
     Ok(path.to_string_lossy().to_string())
 }
 
-pub fn get_bin(bin: &str) -> String {
+pub fn get_bin(bin: &str) -> PathBuf {
     if OS == "linux" || which(bin).is_ok() {
-        return bin.to_string();
+        return PathBuf::from(bin);
     } else if OS == "macos" {
         return find_macos_bin(bin);
     }
     return get_bin_from_deps(bin);
 }
 
-fn get_bin_from_deps(bin: &str) -> String {
+fn get_bin_from_deps(bin: &str) -> PathBuf {
+    let mut path = get_deps_dir();
+    path.push(bin);
+    path
+}
+
+pub fn get_deps_dir() -> PathBuf {
     let mut path = current_exe().unwrap();
     path.pop();
     path.push("deps");
-    path.push(bin);
-    return path.to_string_lossy().to_string();
+    path
 }
 
-pub fn find_macos_bin(bin: &str) -> String {
+pub fn find_macos_bin(bin: &str) -> PathBuf {
     return MACOS_POTENTIAL_PATHS
         .iter()
         .map(|path| {
@@ -135,16 +525,149 @@ pub fn find_macos_bin(bin: &str) -> String {
             return path;
         })
         .find(|path| path.exists())
-        .map(|s| s.to_string_lossy().to_string())
         .unwrap_or_else(|| {
-            log(format!("Could not find {} on MacOS host", bin));
-            return bin.to_string();
+            tracing::warn!("Could not find {} on MacOS host", bin);
+            return PathBuf::from(bin);
         });
 }
 
 #[cfg(test)]
 mod test_utils {
-    use super::sanitize;
+    use super::{
+        channel_name_key, fold_search_name, get_filename, name_from_url, normalize_source_url,
+        refresh_all_sources, sanitize,
+    };
+    use crate::{m3u, source_type, sql, tasks::CancelToken, types::Source};
+
+    fn m3u_source(name: &str, path: &std::path::Path) -> Source {
+        Source {
+            url: Some(path.to_string_lossy().to_string()),
+            name: name.to_string(),
+            id: None,
+            password: None,
+            username: None,
+            url_origin: None,
+            last_refresh_at: None,
+            last_refresh_status: None,
+            last_refresh_error: None,
+            channel_count: None,
+            import_live: true,
+            import_vod: true,
+            import_series: true,
+            group_filter: None,
+            source_type: source_type::M3U,
+            enabled: true,
+            use_tvg_id: Some(true),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_all_sources_isolates_failures() {
+        let _db = sql::init_test_db();
+        let dir = tempfile::tempdir().unwrap();
+        let healthy = dir.path().join("healthy.m3u");
+        let broken = dir.path().join("broken.m3u");
+        let playlist = |names: &[&str]| {
+            let mut content = "#EXTM3U\n".to_string();
+            for name in names {
+                content += &format!(
+                    "#EXTINF:-1 tvg-name=\"{name}\" group-title=\"News\",{name}\nhttp://example.com/{name}.ts\n"
+                );
+            }
+            content
+        };
+        std::fs::write(&broken, playlist(&["Euronews"])).unwrap();
+        std::fs::write(&healthy, playlist(&["France24", "BBC"])).unwrap();
+        m3u::read_m3u8(m3u_source("broken", &broken), false).unwrap();
+        m3u::read_m3u8(m3u_source("healthy", &healthy), false).unwrap();
+        std::fs::remove_file(&broken).unwrap();
+        std::fs::write(&healthy, playlist(&["France24", "CNN", "Arte"])).unwrap();
+
+        let results = refresh_all_sources(None, CancelToken::default())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        let broken = results.iter().find(|r| r.name == "broken").unwrap();
+        assert!(broken.error.is_some());
+        let healthy = results.iter().find(|r| r.name == "healthy").unwrap();
+        assert_eq!(healthy.error, None);
+        assert_eq!((healthy.added, healthy.updated, healthy.removed), (2, 1, 1));
+
+        let stop = CancelToken::default();
+        stop.cancel();
+        let results = refresh_all_sources(None, stop).await.unwrap();
+        assert!(results
+            .iter()
+            .all(|r| r.error.as_deref() == Some("Refresh cancelled")));
+    }
+
+    #[test]
+    fn test_normalize_source_url() {
+        let normalize = |url: &str, source_type: u8| {
+            let mut source = Source {
+                url: Some(url.to_string()),
+                source_type,
+                ..m3u_source("remote", std::path::Path::new(""))
+            };
+            normalize_source_url(&mut source).map(|_| source)
+        };
+        let source = normalize(
+            " http://[2A02:0:0::1]:8080/get.php#top ",
+            source_type::M3U_LINK,
+        )
+        .unwrap();
+        assert_eq!(source.url.as_deref(), Some("http://[2a02::1]:8080/get.php"));
+        let source = normalize("https://user:pw@tv.local:443", source_type::TVHEADEND).unwrap();
+        assert_eq!(source.url.as_deref(), Some("https://user:pw@tv.local/"));
+        let source = normalize(
+            "http://provider.tv:8080/player_api.php?username=fred&password=a%2Bb&x=1",
+            source_type::XTREAM,
+        )
+        .unwrap();
+        assert_eq!(
+            source.url.as_deref(),
+            Some("http://provider.tv:8080/player_api.php?x=1")
+        );
+        assert_eq!(source.username.as_deref(), Some("fred"));
+        assert_eq!(source.password.as_deref(), Some("a+b"));
+        assert!(normalize("provider.tv:8080", source_type::XTREAM).is_err());
+        assert!(normalize("ftp://provider.tv/list.m3u", source_type::M3U_LINK).is_err());
+        let local = normalize("/home/fred/list.m3u", source_type::M3U).unwrap();
+        assert_eq!(local.url.as_deref(), Some("/home/fred/list.m3u"));
+    }
+
+    #[test]
+    fn test_get_filename() {
+        let filename = |url: &str| get_filename("Movie: 2".to_string(), url.to_string());
+        assert_eq!(
+            filename("http://[2a02::1]:8080/movie/u/p/7.mkv?token=a.b").unwrap(),
+            "Movie 2.mkv"
+        );
+        assert!(filename("http://[2a02::1]:8080/movie/u/p/7").is_err());
+    }
+
+    #[test]
+    fn test_name_from_url() {
+        assert_eq!(
+            name_from_url("http://example.com/live/user/pass/1234.ts").as_deref(),
+            Some("1234")
+        );
+        assert_eq!(
+            name_from_url("http://example.com/stream/?token=abc").as_deref(),
+            Some("stream")
+        );
+        assert_eq!(name_from_url("xtream:movie/77.mkv").as_deref(), Some("77"));
+        assert_eq!(name_from_url("http://").as_deref(), None);
+    }
+
+    #[test]
+    fn test_channel_name_key() {
+        assert_eq!(
+            channel_name_key("Sky Sports: Main Event FHD"),
+            "sky sports main event"
+        );
+        assert_eq!(channel_name_key("|UK| BBC One HD"), "uk bbc one");
+    }
 
     #[test]
     fn test_sanitize() {
@@ -153,4 +676,21 @@ mod test_utils {
             sanitize("SuperShow: Who will win the million?".to_string())
         );
     }
+
+    #[test]
+    fn test_fold_search_name() {
+        // French
+        assert_eq!(fold_search_name("Télé Française"), "tele francaise");
+        assert_eq!(fold_search_name("ŒUVRE"), "oeuvre");
+        // German
+        assert_eq!(fold_search_name("München"), "munchen");
+        assert_eq!(fold_search_name("STRAßE"), "strasse");
+        // Spanish
+        assert_eq!(fold_search_name("Télefe"), "telefe");
+        assert_eq!(fold_search_name("España Ñandú"), "espana nandu");
+        // Turkish dotted and dotless i
+        assert_eq!(fold_search_name("İstanbul"), "istanbul");
+        assert_eq!(fold_search_name("Kırıkkale"), "kirikkale");
+        assert_eq!(fold_search_name("TRT ÇOCUK"), "trt cocuk");
+    }
 }
@@ -0,0 +1,198 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::Result;
+use reqwest::{header::RANGE, StatusCode};
+use url::Url;
+
+use crate::{
+    proxy,
+    types::{ChannelHttpHeaders, CustomChannel, UrlValidation},
+    utils::with_http_headers,
+};
+
+pub const OK: u8 = 0;
+pub const INVALID_URL: u8 = 1;
+pub const INVALID_SCHEME: u8 = 2;
+pub const FILE_NOT_FOUND: u8 = 3;
+pub const HOST_UNRESOLVABLE: u8 = 4;
+pub const HTTP_ERROR: u8 = 5;
+pub const UNREACHABLE: u8 = 6;
+const ALLOWED_SCHEMES: [&str; 5] = ["http", "https", "rtmp", "rtsp", "udp"];
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Saves the channel unless validation was requested and found a hard failure
+pub async fn save_custom_channel(
+    channel: CustomChannel,
+    validate: bool,
+    probe: bool,
+    save: impl FnOnce(CustomChannel) -> Result<()>,
+) -> Result<Option<UrlValidation>> {
+    let validation = match validate {
+        true => Some(
+            validate_url(
+                channel.data.url.as_deref().unwrap_or_default(),
+                channel.headers.as_ref(),
+                probe,
+            )
+            .await,
+        ),
+        false => None,
+    };
+    if validation.as_ref().is_some_and(|v| !v.valid) {
+        return Ok(validation);
+    }
+    save(channel)?;
+    Ok(validation)
+}
+
+/// Checks that a custom channel url can be played. Only the syntax, missing files and
+/// unresolvable hosts are hard failures, a provider answering with an error is reported
+/// but doesn't prevent saving since it may only reject the probe
+pub async fn validate_url(
+    url: &str,
+    headers: Option<&ChannelHttpHeaders>,
+    probe: bool,
+) -> UrlValidation {
+    let url = url.trim();
+    if url.is_empty() {
+        return failure(INVALID_URL, "The url is empty".to_string());
+    }
+    if Path::new(url).exists() {
+        return success();
+    }
+    let parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(e) => return failure(INVALID_URL, format!("Invalid url: {e}")),
+    };
+    if parsed.scheme() == "file" {
+        return match parsed.to_file_path().map(|path| path.exists()) {
+            Ok(true) => success(),
+            _ => failure(FILE_NOT_FOUND, format!("{url} does not exist")),
+        };
+    }
+    if !ALLOWED_SCHEMES.contains(&parsed.scheme()) {
+        return failure(
+            INVALID_SCHEME,
+            format!(
+                "Unsupported scheme {}, expected one of {}",
+                parsed.scheme(),
+                ALLOWED_SCHEMES.join(", ")
+            ),
+        );
+    }
+    let Some(host) = parsed.host_str().filter(|host| !host.is_empty()) else {
+        return failure(INVALID_URL, format!("{url} has no host"));
+    };
+    // multicast and LAN udp streams can't be probed, the group address is enough
+    if !probe || parsed.scheme() == "udp" {
+        return success();
+    }
+    let port = parsed
+        .port_or_known_default()
+        .unwrap_or(match parsed.scheme() {
+            "rtmp" => 1935,
+            "rtsp" => 554,
+            _ => 80,
+        });
+    if let Err(e) = tokio::net::lookup_host((host.trim_matches(['[', ']']), port)).await {
+        return failure(HOST_UNRESOLVABLE, format!("Could not resolve {host}: {e}"));
+    }
+    match parsed.scheme() {
+        "http" | "https" => probe_http(url, headers).await,
+        _ => success(),
+    }
+}
+
+async fn probe_http(url: &str, headers: Option<&ChannelHttpHeaders>) -> UrlValidation {
+    let headers = headers.cloned().unwrap_or_default();
+    let client = match proxy::client_builder().and_then(|builder| {
+        Ok(builder
+            .timeout(PROBE_TIMEOUT)
+            .danger_accept_invalid_certs(headers.ignore_ssl.unwrap_or(false))
+            .build()?)
+    }) {
+        Ok(client) => client,
+        Err(e) => return warning(UNREACHABLE, format!("{e}"), None),
+    };
+    if let Ok(response) = with_http_headers(client.head(url), &headers).send().await {
+        if response.status().is_success() {
+            return success();
+        }
+    }
+    // plenty of stream servers don't implement HEAD, so the ranged GET decides
+    let get = with_http_headers(client.get(url), &headers)
+        .header(RANGE, "bytes=0-1023")
+        .send()
+        .await;
+    match get {
+        Ok(response)
+            if response.status().is_success()
+                || response.status() == StatusCode::PARTIAL_CONTENT =>
+        {
+            success()
+        }
+        Ok(response) => warning(
+            HTTP_ERROR,
+            format!("The server answered with {}", response.status()),
+            Some(response.status().as_u16()),
+        ),
+        Err(e) => warning(UNREACHABLE, format!("{e}"), None),
+    }
+}
+
+fn success() -> UrlValidation {
+    UrlValidation {
+        valid: true,
+        code: OK,
+        message: None,
+        http_status: None,
+    }
+}
+
+fn failure(code: u8, message: String) -> UrlValidation {
+    UrlValidation {
+        valid: false,
+        code,
+        message: Some(message),
+        http_status: None,
+    }
+}
+
+fn warning(code: u8, message: String, http_status: Option<u16>) -> UrlValidation {
+    UrlValidation {
+        valid: true,
+        code,
+        message: Some(message),
+        http_status,
+    }
+}
+
+#[cfg(test)]
+mod test_validation {
+    use super::{validate_url, FILE_NOT_FOUND, INVALID_SCHEME, INVALID_URL, OK};
+
+    #[tokio::test]
+    async fn test_validate_url_without_probe() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_string_lossy().to_string();
+        let cases = [
+            ("http://example.com/live.m3u8", OK),
+            ("rtsp://192.168.1.20/stream", OK),
+            ("udp://@239.0.0.1:1234", OK),
+            (path.as_str(), OK),
+            ("htp//example.com", INVALID_URL),
+            ("ftp://example.com/live.ts", INVALID_SCHEME),
+            ("file:///does/not/exist.ts", FILE_NOT_FOUND),
+        ];
+        for (url, code) in cases {
+            assert_eq!(validate_url(url, None, false).await.code, code, "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_udp_is_not_probed() {
+        let result = validate_url("udp://239.0.0.1:1234", None, true).await;
+        assert!(result.valid);
+        assert_eq!(result.code, OK);
+    }
+}
@@ -1,3 +1,5 @@
 pub const ALL: u8 = 0;
 pub const FAVORITES: u8 = 1;
 pub const CATEGORIES: u8 = 2;
+pub const NEW: u8 = 3;
+pub const PLAYLIST: u8 = 4;
@@ -0,0 +1,95 @@
+use std::{
+    env::consts::OS,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use anyhow::{Context, Result};
+use which::which;
+
+use crate::{
+    media_type,
+    players::Player,
+    types::{Channel, ChannelHttpHeaders, Settings},
+    utils::get_bin,
+};
+
+const ARG_TITLE: &str = "--meta-title=";
+const ARG_PLAY_AND_EXIT: &str = "--play-and-exit";
+const ARG_NO_CACHING: &str = "--network-caching=0";
+const ARG_USER_AGENT: &str = "--http-user-agent=";
+const ARG_REFERRER: &str = "--http-referrer=";
+const ARG_SOUT: &str = "--sout=";
+const ARG_NO_VIDEO: &str = "--no-video";
+const VLC_BIN_NAME: &str = "vlc";
+const MACOS_VLC_PATH: &str = "/Applications/VLC.app/Contents/MacOS/VLC";
+const WINDOWS_VLC_PATH: &str = r"C:\Program Files\VideoLAN\VLC\vlc.exe";
+static VLC_PATH: LazyLock<PathBuf> = LazyLock::new(|| get_vlc_bin());
+
+pub struct Vlc;
+
+impl Player for Vlc {
+    fn name(&self) -> &'static str {
+        VLC_BIN_NAME
+    }
+
+    fn bin(&self) -> PathBuf {
+        VLC_PATH.clone()
+    }
+
+    fn record_extension(&self) -> &'static str {
+        "ts"
+    }
+
+    fn get_args(
+        &self,
+        channel: Channel,
+        headers: Option<ChannelHttpHeaders>,
+        settings: Settings,
+        record_path: Option<PathBuf>,
+    ) -> Result<Vec<OsString>> {
+        let mut args: Vec<OsString> = Vec::new();
+        args.push(channel.url.context("no url")?.into());
+        args.push(format!("{ARG_TITLE}{}", channel.name).into());
+        args.push(ARG_PLAY_AND_EXIT.into());
+        if !settings.use_stream_caching {
+            args.push(ARG_NO_CACHING.into());
+        }
+        if channel.media_type == media_type::RADIO {
+            args.push(ARG_NO_VIDEO.into());
+        }
+        if let Some(record_path) = record_path {
+            let mut sout = OsString::from(format!(
+                "{ARG_SOUT}#duplicate{{dst=display,dst=std{{access=file,mux=ts,dst='"
+            ));
+            sout.push(record_path);
+            sout.push("'}}");
+            args.push(sout);
+        }
+        if let Some(headers) = headers {
+            if let Some(user_agent) = headers.user_agent {
+                args.push(format!("{ARG_USER_AGENT}{user_agent}").into());
+            }
+            if let Some(referrer) = headers.referrer {
+                args.push(format!("{ARG_REFERRER}{referrer}").into());
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn get_vlc_bin() -> PathBuf {
+    if which(VLC_BIN_NAME).is_ok() {
+        return PathBuf::from(VLC_BIN_NAME);
+    }
+    let path = match OS {
+        "macos" => MACOS_VLC_PATH,
+        "windows" => WINDOWS_VLC_PATH,
+        _ => return get_bin(VLC_BIN_NAME),
+    };
+    if Path::new(path).exists() {
+        return PathBuf::from(path);
+    }
+    get_bin(VLC_BIN_NAME)
+}
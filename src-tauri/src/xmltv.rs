@@ -0,0 +1,416 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use flate2::read::MultiGzDecoder;
+use quick_xml::events::{BytesStart, Event};
+use rusqlite::Transaction;
+
+use crate::{
+    proxy,
+    settings::{get_settings, DEFAULT_EPG_FUTURE_DAYS, DEFAULT_EPG_RETENTION_DAYS},
+    source_type, sql, tvheadend,
+    types::{EpgRefreshResult, EpgSource, Programme, Settings},
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const DAY_SECONDS: i64 = 86400;
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(DAY_SECONDS as u64);
+const XMLTV_TIME_FORMAT: &str = "%Y%m%d%H%M%S";
+
+/// Downloads a feed and replaces its programmes, independently from the channel lists
+pub async fn refresh_epg_source(id: i64) -> Result<EpgRefreshResult> {
+    let feed = sql::get_epg_source(id)?;
+    if let Some(source) = feed
+        .source_id
+        .map(sql::get_source_from_id)
+        .transpose()?
+        .filter(|source| source.source_type == source_type::TVHEADEND)
+    {
+        return tvheadend::refresh_epg(&source).await;
+    }
+    let timezone = match feed.timezone.as_deref() {
+        Some(timezone) => parse_timezone(timezone)?,
+        None => Tz::UTC,
+    };
+    let window = get_retention_window(&get_settings()?, Utc::now().timestamp());
+    let path = download(&feed).await?;
+    let result = tokio::task::spawn_blocking(move || import(id, &path, timezone, window)).await?;
+    if let Err(e) = &result {
+        tracing::error!("Failed to refresh EPG {}: {:?}", feed.name, e);
+    }
+    result
+}
+
+async fn download(feed: &EpgSource) -> Result<PathBuf> {
+    let path = get_tmp_path(feed.id)?;
+    let mut response = proxy::get_client()?
+        .get(&feed.url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut file = File::create(&path)?;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+    }
+    Ok(path)
+}
+
+fn import(id: i64, path: &PathBuf, timezone: Tz, window: (i64, i64)) -> Result<EpgRefreshResult> {
+    let reader = open_feed(path)?;
+    let result = store_programmes(id, window, |tx| {
+        parse(reader, timezone, |programme| {
+            sql::insert_programme(tx, id, &programme)
+        })?;
+        Ok(())
+    })?;
+    let _ = std::fs::remove_file(path);
+    Ok(result)
+}
+
+/// Replaces the programmes of feed `id` with the ones `insert` adds, then prunes the
+/// guide to the retention window
+pub fn store_programmes(
+    id: i64,
+    window: (i64, i64),
+    insert: impl FnOnce(&Transaction) -> Result<()>,
+) -> Result<EpgRefreshResult> {
+    let mut conn = sql::get_conn()?;
+    let _import = sql::lock_import();
+    let tx = sql::import_transaction(&mut conn)?;
+    sql::delete_programmes(&tx, id)?;
+    insert(&tx)?;
+    let pruned = sql::delete_programmes_outside(&tx, window.0, window.1)?;
+    let result = EpgRefreshResult {
+        pruned,
+        ..sql::finish_epg_refresh(&tx, id)?
+    };
+    tx.commit()?;
+    Ok(result)
+}
+
+/// Drops programmes outside of the retention window, returns how many were removed
+pub fn clean_epg_programmes() -> Result<u64> {
+    let (before, after) = get_retention_window(&get_settings()?, Utc::now().timestamp());
+    let mut conn = sql::get_conn()?;
+    let _import = sql::lock_import();
+    let tx = sql::import_transaction(&mut conn)?;
+    let pruned = sql::delete_programmes_outside(&tx, before, after)?;
+    tx.commit()?;
+    Ok(pruned)
+}
+
+/// Cleans the guide at startup and then once a day, feeds that are never refreshed
+/// would otherwise keep their programmes forever
+pub async fn schedule_cleanup() {
+    loop {
+        let result = tokio::task::spawn_blocking(clean_epg_programmes).await;
+        match result
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result)
+        {
+            Ok(pruned) => {
+                tracing::info!("Removed {pruned} programmes outside of the EPG retention")
+            }
+            Err(e) => tracing::error!("Failed to clean EPG programmes: {:?}", e),
+        }
+        tokio::time::sleep(CLEANUP_INTERVAL).await;
+    }
+}
+
+pub fn get_retention_window(settings: &Settings, now: i64) -> (i64, i64) {
+    let past = settings
+        .epg_retention_days
+        .unwrap_or(DEFAULT_EPG_RETENTION_DAYS);
+    let future = settings.epg_future_days.unwrap_or(DEFAULT_EPG_FUTURE_DAYS);
+    (
+        now - past as i64 * DAY_SECONDS,
+        now + future as i64 * DAY_SECONDS,
+    )
+}
+
+/// Feeds are often served gzipped, sometimes without saying so in the url
+fn open_feed(path: &PathBuf) -> Result<Box<dyn BufRead>> {
+    let mut magic = [0; 2];
+    let gzipped = File::open(path)?.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+    let file = File::open(path)?;
+    Ok(match gzipped {
+        true => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        false => Box::new(BufReader::new(file)),
+    })
+}
+
+pub fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Unknown timezone {timezone}, expected a name like Europe/Paris"))
+}
+
+/// Streams the programmes of an XMLTV document, the first title and description
+/// of each programme are kept. Times without an offset are read in `timezone`.
+/// Returns how many programmes were read
+pub fn parse(
+    reader: impl BufRead,
+    timezone: Tz,
+    mut on_programme: impl FnMut(Programme) -> Result<()>,
+) -> Result<usize> {
+    let mut reader = quick_xml::Reader::from_reader(reader);
+    reader.config_mut().trim_text(true);
+    let mut buf = Vec::new();
+    let mut current: Option<Programme> = None;
+    let mut field: Option<&'static str> = None;
+    let mut count = 0;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"programme" => current = read_programme(&e, timezone)?,
+                b"title" if current.is_some() => field = Some("title"),
+                b"desc" if current.is_some() => field = Some("desc"),
+                _ => {}
+            },
+            Event::Text(text) => {
+                if let (Some(programme), Some(field)) = (current.as_mut(), field) {
+                    set_field(programme, field, &text.unescape()?);
+                }
+            }
+            Event::CData(data) => {
+                if let (Some(programme), Some(field)) = (current.as_mut(), field) {
+                    set_field(programme, field, &String::from_utf8_lossy(&data));
+                }
+            }
+            Event::End(e) => match e.name().as_ref() {
+                b"programme" => {
+                    if let Some(programme) = current.take() {
+                        if !programme.title.is_empty() {
+                            on_programme(programme)?;
+                            count += 1;
+                        }
+                    }
+                }
+                b"title" | b"desc" => field = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(count)
+}
+
+/// Programmes missing their channel or a valid start and stop are skipped
+fn read_programme(e: &BytesStart, timezone: Tz) -> Result<Option<Programme>> {
+    let attribute = |name: &str| -> Result<Option<String>> {
+        Ok(e.try_get_attribute(name)?
+            .map(|attribute| attribute.unescape_value().map(|value| value.to_string()))
+            .transpose()?)
+    };
+    let channel = attribute("channel")?.filter(|channel| !channel.trim().is_empty());
+    let start = attribute("start")?.and_then(|start| parse_time(&start, timezone));
+    let stop = attribute("stop")?.and_then(|stop| parse_time(&stop, timezone));
+    Ok(match (channel, start, stop) {
+        (Some(tvg_id), Some(start), Some(stop)) => Some(Programme {
+            tvg_id: tvg_id.trim().to_string(),
+            title: String::new(),
+            description: None,
+            start,
+            stop,
+        }),
+        _ => None,
+    })
+}
+
+fn set_field(programme: &mut Programme, field: &str, value: &str) {
+    match field {
+        "title" if programme.title.is_empty() => programme.title = value.to_string(),
+        "desc" if programme.description.is_none() => {
+            programme.description = Some(value.to_string())
+        }
+        _ => {}
+    }
+}
+
+/// XMLTV times look like `20240301120000 +0100` and are stored as UTC epoch seconds.
+/// Local times skipped by a DST change are pushed past the gap, repeated ones take
+/// their first occurrence
+pub fn parse_time(value: &str, timezone: Tz) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_str(value, &format!("{XMLTV_TIME_FORMAT} %z")) {
+        return Some(time.timestamp());
+    }
+    let time = NaiveDateTime::parse_from_str(value.get(..14)?, XMLTV_TIME_FORMAT).ok()?;
+    timezone
+        .from_local_datetime(&time)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(time + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .map(|time| time.timestamp())
+}
+
+fn get_tmp_path(id: i64) -> Result<PathBuf> {
+    let mut path = directories::ProjectDirs::from("dev", "fredol", "open-tv")
+        .context("can't find project folder")?
+        .cache_dir()
+        .to_owned();
+    std::fs::create_dir_all(&path)?;
+    path.push(format!("epg-{id}.xml"));
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test_xmltv {
+    use chrono_tz::Tz;
+
+    use super::{parse, parse_time};
+    use crate::{
+        sql,
+        types::{Channel, Programme},
+    };
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tv>
+  <channel id="france24.fr"><display-name>France 24</display-name></channel>
+  <programme start="20240301120000 +0100" stop="20240301130000 +0100" channel="france24.fr">
+    <title lang="fr">Le Journal</title>
+    <title lang="en">The News</title>
+    <desc lang="fr"><![CDATA[Actualités & météo]]></desc>
+  </programme>
+  <programme start="20240301130000 +0100" stop="20240301140000 +0100" channel="france24.fr">
+    <title>Débat &amp; analyse</title>
+  </programme>
+  <programme start="invalid" stop="20240301140000 +0100" channel="france24.fr">
+    <title>Broken</title>
+  </programme>
+</tv>"#;
+
+    #[test]
+    fn test_parse() {
+        let mut programmes = Vec::new();
+        let count = parse(FEED.as_bytes(), Tz::UTC, |programme| {
+            programmes.push(programme);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(programmes[0].title, "Le Journal");
+        assert_eq!(
+            programmes[0].description.as_deref(),
+            Some("Actualités & météo")
+        );
+        assert_eq!(programmes[0].start, 1709290800);
+        assert_eq!(programmes[1].title, "Débat & analyse");
+        assert_eq!(parse_time("20240301110000", Tz::UTC), Some(1709290800));
+    }
+
+    #[test]
+    fn test_parse_time_on_dst_transitions() {
+        let paris = Tz::Europe__Paris;
+        // The offset in the data always wins over the feed timezone
+        assert_eq!(
+            parse_time("20240331033000 +0200", paris),
+            parse_time("20240331013000 +0000", Tz::UTC)
+        );
+        // 02:30 doesn't exist in Paris on the spring change, it becomes 03:30 CEST
+        assert_eq!(
+            parse_time("20240331023000", paris),
+            parse_time("20240331013000", Tz::UTC)
+        );
+        // 02:30 happens twice on the autumn change, the first one is CEST
+        assert_eq!(
+            parse_time("20241027023000", paris),
+            parse_time("20241027003000", Tz::UTC)
+        );
+        assert_eq!(
+            parse_time("20241027033000", paris),
+            parse_time("20241027023000", Tz::UTC)
+        );
+        let new_york = Tz::America__New_York;
+        assert_eq!(
+            parse_time("20240310120000", new_york),
+            parse_time("20240310160000", Tz::UTC)
+        );
+        assert_eq!(
+            parse_time("20240309120000", new_york),
+            parse_time("20240309170000", Tz::UTC)
+        );
+    }
+
+    #[test]
+    fn test_programmes_prefer_the_feed_of_the_source() {
+        let _db = sql::init_test_db();
+        let source_id = sql::do_tx(|tx| {
+            sql::create_or_find_source_by_name(tx, &sql::get_custom_source("m3u".to_string()))
+        })
+        .unwrap();
+        let channel_id = sql::do_tx(|tx| {
+            sql::insert_channel(
+                tx,
+                Channel {
+                    id: None,
+                    name: "France 24".to_string(),
+                    url: Some("http://example.com/france24".to_string()),
+                    group: None,
+                    group_id: None,
+                    image: None,
+                    media_type: crate::media_type::LIVESTREAM,
+                    source_id: Some(source_id),
+                    series_id: None,
+                    favorite: false,
+                    watched: false,
+                    stream_id: None,
+                    tvg_id: Some("france24.fr".to_string()),
+                    media_types: None,
+                    catchup: None,
+                },
+            )
+        })
+        .unwrap();
+        let standalone = sql::add_epg_source("extra", "http://example.com/extra.xml").unwrap();
+        let fill = |id: i64, title: &str| {
+            sql::do_tx(|tx| {
+                sql::insert_programme(
+                    tx,
+                    id,
+                    &Programme {
+                        tvg_id: "france24.fr".to_string(),
+                        title: title.to_string(),
+                        description: None,
+                        start: 100,
+                        stop: 200,
+                    },
+                )?;
+                sql::finish_epg_refresh(tx, id)
+            })
+            .unwrap()
+        };
+        fill(standalone, "From the extra feed");
+        let titles = || -> Vec<String> {
+            sql::get_programmes(channel_id, 0, 1000)
+                .unwrap()
+                .into_iter()
+                .map(|programme| programme.title)
+                .collect()
+        };
+        assert_eq!(titles(), ["From the extra feed"]);
+
+        let own = sql::set_source_epg_url(source_id, Some("http://example.com/own.xml".into()))
+            .unwrap()
+            .unwrap();
+        let result = fill(own, "From the source feed");
+        assert_eq!((result.programmes, result.channels), (1, 1));
+        assert_eq!(titles(), ["From the source feed"]);
+
+        sql::set_source_epg_url(source_id, None).unwrap();
+        assert_eq!(titles(), ["From the extra feed"]);
+    }
+}
@@ -1,20 +1,33 @@
-use crate::log;
+use crate::events;
 use crate::media_type;
+use crate::preview::PreviewBuilder;
 use crate::sql;
+use crate::tasks::CancelToken;
+use crate::types::AppState;
 use crate::types::Channel;
+use crate::types::ChannelHttpHeaders;
+use crate::types::ImportCheckpoint;
+use crate::types::ImportSummary;
+use crate::types::SeriesPrefetchProgress;
 use crate::types::Source;
+use crate::types::XtreamThrottle;
 use crate::types::EPG;
+use crate::utils;
 use crate::utils::get_local_time;
+use crate::xtream_http::{self, ProviderThrottled};
 use anyhow::anyhow;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use rusqlite::Transaction;
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+use tauri::AppHandle;
 use tokio::join;
+use tokio::sync::Mutex;
 use url::Url;
 
 const GET_LIVE_STREAMS: &str = "get_live_streams";
@@ -26,6 +39,11 @@ const GET_LIVE_STREAM_CATEGORIES: &str = "get_live_categories";
 const GET_VOD_CATEGORIES: &str = "get_vod_categories";
 const GET_SHORT_EPG: &str = "get_short_epg";
 const LIVE_STREAM_EXTENSION: &str = "ts";
+/// Xtream channels store `xtream:{type}/{id}.{extension}`, the server and the credentials are
+/// only added when the channel is played so they can change without a refresh
+const URL_SCHEME: &str = "xtream:";
+const TIMESHIFT_FORMAT: &str = "%Y-%m-%d:%H-%M";
+const SERIES_LOAD_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct XtreamStream {
@@ -36,6 +54,8 @@ struct XtreamStream {
     series_id: Option<u64>,
     cover: Option<String>,
     container_extension: Option<String>,
+    epg_channel_id: Option<String>,
+    tv_archive: Option<serde_json::Value>,
 }
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct XtreamSeries {
@@ -75,13 +95,17 @@ struct XtreamEPGItem {
 }
 
 fn build_xtream_url(source: &mut Source) -> Result<Url> {
-    let mut url = Url::parse(&source.url.clone().context("Missing URL")?)?;
-    source.url_origin = Some(
-        Url::from_str(&source.url.clone().unwrap())?
-            .origin()
-            .ascii_serialization(),
-    );
+    let mut url = Url::parse(source.url.as_deref().context("Missing URL")?)?;
+    source.url_origin = Some(url.origin().ascii_serialization());
+    // urls saved before they were normalized may still carry credentials
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "username" && key != "password")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.set_query(None);
     url.query_pairs_mut()
+        .extend_pairs(kept)
         .append_pair(
             "username",
             &source.username.clone().context("Missing username")?,
@@ -93,103 +117,362 @@ fn build_xtream_url(source: &mut Source) -> Result<Url> {
     Ok(url)
 }
 
-pub async fn get_xtream(mut source: Source, wipe: bool) -> Result<()> {
-    let url = build_xtream_url(&mut source)?;
+/// The stream lists and categories of a provider, as fetched before the import
+#[derive(Debug)]
+pub struct XtreamData {
+    live: Result<Vec<XtreamStream>>,
+    live_cats: Result<Vec<XtreamCategory>>,
+    vods: Result<Vec<XtreamStream>>,
+    vods_cats: Result<Vec<XtreamCategory>>,
+    series: Result<Vec<XtreamStream>>,
+    series_cats: Result<Vec<XtreamCategory>>,
+}
+
+/// `on_retry` hears about every request the provider refused and that is retried
+pub async fn get_xtream(
+    mut source: Source,
+    wipe: bool,
+    on_retry: &(dyn Fn(&XtreamThrottle) + Sync),
+) -> Result<ImportSummary> {
+    let data = fetch_xtream(&mut source, on_retry).await?;
+    import_xtream(source, wipe, data, None).await
+}
+
+/// Imports a new source from what a preview fetched
+pub async fn import_prepared(mut source: Source, data: XtreamData) -> Result<ImportSummary> {
+    build_xtream_url(&mut source)?;
+    import_xtream(source, false, data, None).await
+}
+
+/// Converts the streams like the import does, without touching the database
+pub async fn preview(mut source: Source, preview: &mut PreviewBuilder) -> Result<XtreamData> {
+    let data = fetch_xtream(&mut source, &xtream_http::log_retry).await?;
+    let group_filter = source.get_group_filter();
+    let lists = [
+        (&data.live, &data.live_cats, media_type::LIVESTREAM),
+        (&data.vods, &data.vods_cats, media_type::MOVIE),
+        (&data.series, &data.series_cats, media_type::SERIE),
+    ];
+    for (streams, cats, stream_type) in lists {
+        let (Ok(streams), Ok(cats)) = (streams, cats) else {
+            continue;
+        };
+        let cats = get_cat_map(cats);
+        for stream in streams {
+            let category_name = get_cat_name(&cats, stream.category_id.clone());
+            if !is_category_allowed(group_filter.as_ref(), category_name.as_ref()) {
+                continue;
+            }
+            match convert_xtream_live_to_channel(
+                stream.clone(),
+                &source,
+                stream_type,
+                category_name,
+            ) {
+                Ok(channel) => preview.add(channel),
+                Err(e) => tracing::debug!("Skipped a stream while previewing: {:?}", e),
+            }
+        }
+    }
+    Ok(data)
+}
+
+async fn fetch_xtream(
+    source: &mut Source,
+    on_retry: &(dyn Fn(&XtreamThrottle) + Sync),
+) -> Result<XtreamData> {
+    let url = build_xtream_url(source)?;
+    let live_enabled = source.imports_media_type(media_type::LIVESTREAM);
+    let vods_enabled = source.imports_media_type(media_type::MOVIE);
+    let series_enabled = source.imports_media_type(media_type::SERIE);
     let (live, live_cats, vods, vods_cats, series, series_cats) = join!(
-        get_xtream_http_data::<Vec<XtreamStream>>(url.clone(), GET_LIVE_STREAMS),
-        get_xtream_http_data::<Vec<XtreamCategory>>(url.clone(), GET_LIVE_STREAM_CATEGORIES),
-        get_xtream_http_data::<Vec<XtreamStream>>(url.clone(), GET_VODS),
-        get_xtream_http_data::<Vec<XtreamCategory>>(url.clone(), GET_VOD_CATEGORIES),
-        get_xtream_http_data::<Vec<XtreamStream>>(url.clone(), GET_SERIES),
-        get_xtream_http_data::<Vec<XtreamCategory>>(url.clone(), GET_SERIES_CATEGORIES),
+        get_xtream_data_if::<Vec<XtreamStream>>(
+            live_enabled,
+            url.clone(),
+            GET_LIVE_STREAMS,
+            on_retry
+        ),
+        get_xtream_data_if::<Vec<XtreamCategory>>(
+            live_enabled,
+            url.clone(),
+            GET_LIVE_STREAM_CATEGORIES,
+            on_retry
+        ),
+        get_xtream_data_if::<Vec<XtreamStream>>(vods_enabled, url.clone(), GET_VODS, on_retry),
+        get_xtream_data_if::<Vec<XtreamCategory>>(
+            vods_enabled,
+            url.clone(),
+            GET_VOD_CATEGORIES,
+            on_retry
+        ),
+        get_xtream_data_if::<Vec<XtreamStream>>(series_enabled, url.clone(), GET_SERIES, on_retry),
+        get_xtream_data_if::<Vec<XtreamCategory>>(
+            series_enabled,
+            url.clone(),
+            GET_SERIES_CATEGORIES,
+            on_retry
+        ),
     );
-    let mut sql = sql::get_conn()?;
-    let tx = sql.transaction()?;
-    if wipe {
-        sql::wipe(&tx, source.id.context("Source should have id")?)?;
-    } else {
-        source.id = Some(sql::create_or_find_source_by_name(&tx, &source)?);
+    // rows are committed in batches, so give up before touching the source
+    let fail_count = [
+        live.is_err() || live_cats.is_err(),
+        vods.is_err() || vods_cats.is_err(),
+        series.is_err() || series_cats.is_err(),
+    ]
+    .into_iter()
+    .filter(|failed| *failed)
+    .count();
+    if fail_count > 2 {
+        if let Err(e) = live.and(live_cats) {
+            tracing::error!("{:?}", e);
+        }
+        bail!("Too many Xtream requests failed");
     }
-    let mut fail_count = 0;
-    live.and_then(|live| process_xtream(&tx, live, live_cats?, &source, media_type::LIVESTREAM))
-        .unwrap_or_else(|e| {
-            log::log(format!("{:?}", e));
-            fail_count += 1;
-        });
-    vods.and_then(|vods: Vec<XtreamStream>| {
-        process_xtream(&tx, vods, vods_cats?, &source, media_type::MOVIE)
+    Ok(XtreamData {
+        live,
+        live_cats,
+        vods,
+        vods_cats,
+        series,
+        series_cats,
     })
-    .unwrap_or_else(|e| {
-        log::log(format!("{:?}", e));
-        fail_count += 1;
-    });
-    series
-        .and_then(|series: Vec<XtreamStream>| {
-            process_xtream(&tx, series, series_cats?, &source, media_type::SERIE)
-        })
-        .unwrap_or_else(|e| {
-            log::log(format!("{:?}", e));
-            fail_count += 1;
-        });
-    if fail_count > 2 {
-        match tx.rollback() {
-            Ok(_) => {}
-            Err(e) => log::log(format!("Failed to rollback tx: {:?}", e)),
+}
+
+/// What the stream lists of one import share
+struct XtreamImport<'a> {
+    source: &'a Source,
+    group_filter: Option<&'a HashSet<String>>,
+    dedup: sql::ImportDedup,
+    /// The media type and category an interrupted import had finished
+    resume_after: Option<(u8, Option<String>)>,
+}
+
+/// Continues an interrupted import after the last category it finished, with what the
+/// provider lists now
+pub async fn resume(
+    mut source: Source,
+    media_type: u8,
+    category_id: Option<String>,
+    wipe: bool,
+) -> Result<ImportSummary> {
+    let data = fetch_xtream(&mut source, &xtream_http::log_retry).await?;
+    import_xtream(source, wipe, data, Some((media_type, category_id))).await
+}
+
+/// Writes on a blocking thread, the import lock blocks while another import writes
+async fn import_xtream(
+    source: Source,
+    wipe: bool,
+    data: XtreamData,
+    resume_after: Option<(u8, Option<String>)>,
+) -> Result<ImportSummary> {
+    sql::spawn_blocking(move || store_xtream(source, wipe, data, resume_after)).await
+}
+
+fn store_xtream(
+    mut source: Source,
+    wipe: bool,
+    data: XtreamData,
+    resume_after: Option<(u8, Option<String>)>,
+) -> Result<ImportSummary> {
+    let XtreamData {
+        live,
+        live_cats,
+        vods,
+        vods_cats,
+        series,
+        series_cats,
+    } = data;
+    let mut sql = sql::get_conn()?;
+    sql::set_bulk_import(&sql, true)?;
+    let result = (|| -> Result<ImportSummary> {
+        let _import = sql::lock_import();
+        let tx = sql::import_transaction(&mut sql)?;
+        let dedup = match resume_after {
+            Some(_) => sql::ImportDedup::resume(&tx, source.id.context("Source should have id")?)?,
+            None => {
+                sql::begin_staged_import(&tx, &mut source, wipe)?;
+                sql::ImportDedup::default()
+            }
+        };
+        let source_id = source.id.context("Source should have id")?;
+        let filter = sql::get_group_filter(&tx, source_id)?;
+        let mut import = XtreamImport {
+            source: &source,
+            group_filter: filter.as_ref(),
+            dedup,
+            resume_after,
+        };
+        // in the order of their media types, which resuming relies on
+        let lists = [
+            (live, live_cats, media_type::LIVESTREAM),
+            (vods, vods_cats, media_type::MOVIE),
+            (series, series_cats, media_type::SERIE),
+        ];
+        for (streams, cats, stream_type) in lists {
+            streams
+                .and_then(|streams| process_xtream(&mut import, &tx, streams, cats?, stream_type))
+                .unwrap_or_else(|e| tracing::error!("{:?}", e));
         }
-        return Err(anyhow::anyhow!("Too many Xtream requests failed"));
+        sql::swap_in_staged(&tx, source_id, wipe)?;
+        sql::finish_import(&tx, source_id)?;
+        tx.commit()?;
+        Ok(import.dedup.summary)
+    })();
+    sql::set_bulk_import(&sql, false)?;
+    result
+}
+
+/// Lists the category names of every media type the source imports, without importing anything
+pub async fn preview_groups(mut source: Source) -> Result<Vec<String>> {
+    let url = build_xtream_url(&mut source)?;
+    let (live, vods, series) = join!(
+        get_xtream_data_if::<Vec<XtreamCategory>>(
+            source.imports_media_type(media_type::LIVESTREAM),
+            url.clone(),
+            GET_LIVE_STREAM_CATEGORIES,
+            &xtream_http::log_retry
+        ),
+        get_xtream_data_if::<Vec<XtreamCategory>>(
+            source.imports_media_type(media_type::MOVIE),
+            url.clone(),
+            GET_VOD_CATEGORIES,
+            &xtream_http::log_retry
+        ),
+        get_xtream_data_if::<Vec<XtreamCategory>>(
+            source.imports_media_type(media_type::SERIE),
+            url.clone(),
+            GET_SERIES_CATEGORIES,
+            &xtream_http::log_retry
+        ),
+    );
+    let groups: BTreeSet<String> = [live?, vods?, series?]
+        .into_iter()
+        .flatten()
+        .map(|cat| cat.category_name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    Ok(groups.into_iter().collect())
+}
+
+/// Media types the source doesn't import are never downloaded
+async fn get_xtream_data_if<T>(
+    enabled: bool,
+    url: Url,
+    action: &str,
+    on_retry: &(dyn Fn(&XtreamThrottle) + Sync),
+) -> Result<T>
+where
+    T: serde::de::DeserializeOwned + Default,
+{
+    match enabled {
+        true => get_xtream_http_data(url, action, on_retry).await,
+        false => Ok(T::default()),
     }
-    tx.commit()?;
-    Ok(())
 }
 
-async fn get_xtream_http_data<T>(mut url: Url, action: &str) -> Result<T>
+async fn get_xtream_http_data<T>(
+    mut url: Url,
+    action: &str,
+    on_retry: &(dyn Fn(&XtreamThrottle) + Sync),
+) -> Result<T>
 where
     T: serde::de::DeserializeOwned,
 {
-    let client = reqwest::Client::new();
     url.query_pairs_mut().append_pair("action", action);
-    let data = client.get(url).send().await?.json::<T>().await?;
-    Ok(data)
+    xtream_http::get_json(url, on_retry).await
 }
 
+fn is_category_allowed(
+    group_filter: Option<&HashSet<String>>,
+    category_name: Option<&String>,
+) -> bool {
+    group_filter.map_or(true, |filter| {
+        category_name.is_some_and(|name| filter.contains(name.trim()))
+    })
+}
+
+/// Goes through the categories in the order the provider lists them, the streams of
+/// unknown categories last, committing between categories so the checkpoint saved with
+/// each batch can name the last category done
 fn process_xtream(
+    import: &mut XtreamImport,
     tx: &Transaction,
-    streams: Vec<XtreamStream>,
+    mut streams: Vec<XtreamStream>,
     cats: Vec<XtreamCategory>,
-    source: &Source,
     stream_type: u8,
 ) -> Result<()> {
-    let cats: HashMap<String, String> = cats
-        .into_iter()
-        .filter_map(|f| {
-            let category_id = f
-                .category_id
-                .as_str()
-                .map(|cid| cid.to_string())
-                .or_else(|| f.category_id.as_u64().map(|cid| cid.to_string()));
-            category_id.map(|cid| (cid, f.category_name))
-        })
+    let positions: HashMap<String, usize> = cats
+        .iter()
+        .enumerate()
+        .filter_map(|(position, cat)| get_cat_id(cat).map(|id| (id, position)))
         .collect();
-    let mut groups: HashMap<String, i64> = HashMap::new();
-    for live in streams {
-        let category_name = get_cat_name(&cats, live.category_id.clone());
-        convert_xtream_live_to_channel(live, &source, stream_type.clone(), category_name)
-            .and_then(|mut channel| {
-                sql::set_channel_group_id(
-                    &mut groups,
-                    &mut channel,
-                    &tx,
-                    source.id.as_ref().unwrap(),
-                )
-                .unwrap_or_else(|e| log::log(format!("{:?}", e)));
-                sql::insert_channel(&tx, channel)?;
-                Ok(())
-            })
-            .unwrap_or_else(|e| log::log(format!("{:?}", e)));
+    let position = |category_id: &Option<String>| {
+        category_id
+            .as_ref()
+            .and_then(|id| positions.get(id).copied())
+            .unwrap_or(cats.len())
+    };
+    let done = match &import.resume_after {
+        Some((media_type, _)) if *media_type > stream_type => return Ok(()),
+        Some((media_type, category_id)) if *media_type == stream_type => match category_id {
+            Some(id) => positions.get(id).copied(),
+            None => Some(cats.len()),
+        },
+        _ => None,
+    };
+    streams.sort_by_key(|stream| position(&stream.category_id));
+    let cat_names = get_cat_map(&cats);
+    let _span =
+        tracing::debug_span!("xtream_staging", stream_type, count = streams.len()).entered();
+    let mut channels: Vec<(Channel, Option<ChannelHttpHeaders>)> =
+        Vec::with_capacity(sql::INSERT_BATCH_SIZE);
+    let mut streams = streams
+        .into_iter()
+        .filter(|stream| done.map_or(true, |done| position(&stream.category_id) > done))
+        .peekable();
+    while let Some(stream) = streams.next() {
+        let category = position(&stream.category_id);
+        let category_name = get_cat_name(&cat_names, stream.category_id.clone());
+        if is_category_allowed(import.group_filter, category_name.as_ref()) {
+            convert_xtream_live_to_channel(stream, import.source, stream_type, category_name)
+                .map(|channel| {
+                    if import.dedup.is_new(&channel) {
+                        channels.push((channel, None));
+                    }
+                })
+                .unwrap_or_else(|e| tracing::error!("{:?}", e));
+        }
+        let category_done = streams
+            .peek()
+            .map_or(true, |next| position(&next.category_id) != category);
+        if category_done && (channels.len() >= sql::INSERT_BATCH_SIZE || streams.peek().is_none()) {
+            sql::stage_channels(tx, import.source.id.context("no source id")?, &channels)?;
+            channels.clear();
+            let checkpoint = ImportCheckpoint::Xtream {
+                media_type: stream_type,
+                category_id: cats.get(category).and_then(get_cat_id),
+            };
+            sql::set_import_checkpoint(tx, import.source.id.context("no source id")?, &checkpoint)?;
+            sql::commit_batch(tx)?;
+        }
     }
     Ok(())
 }
 
+fn get_cat_id(cat: &XtreamCategory) -> Option<String> {
+    cat.category_id
+        .as_str()
+        .map(|cid| cid.to_string())
+        .or_else(|| cat.category_id.as_u64().map(|cid| cid.to_string()))
+}
+
+fn get_cat_map(cats: &[XtreamCategory]) -> HashMap<String, String> {
+    cats.iter()
+        .filter_map(|f| get_cat_id(f).map(|cid| (cid, f.category_name.clone())))
+        .collect()
+}
+
 fn get_cat_name(cats: &HashMap<String, String>, category_id: Option<String>) -> Option<String> {
     if category_id.is_none() {
         return None;
@@ -203,50 +486,135 @@ fn convert_xtream_live_to_channel(
     stream_type: u8,
     category_name: Option<String>,
 ) -> Result<Channel> {
+    let group = category_name.map(|x| x.trim().to_string());
+    let url = if stream_type == media_type::SERIE {
+        stream.series_id.context("no series id")?.to_string()
+    } else {
+        get_url_template(
+            stream.stream_id.context("no stream id")?.to_string(),
+            stream_type,
+            stream.container_extension,
+        )?
+    };
+    let name = match stream.name.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => utils::name_from_url(&url).context("No name")?,
+    };
     Ok(Channel {
         id: None,
-        group: category_name.map(|x| x.trim().to_string()),
+        media_type: media_type::detect_radio(stream_type, group.as_deref()),
+        group,
         image: stream
             .stream_icon
             .or(stream.cover)
             .map(|x| x.trim().to_string()),
-        media_type: stream_type.clone(),
-        name: stream.name.context("No name")?.trim().to_string(),
+        name,
         source_id: source.id,
-        url: if stream_type == media_type::SERIE {
-            Some(stream.series_id.context("no series id")?.to_string())
-        } else {
-            Some(get_url(
-                stream.stream_id.context("no stream id")?.to_string(),
-                source,
-                stream_type,
-                stream.container_extension,
-            )?)
-        },
+        url: Some(url),
         stream_id: stream.stream_id,
+        tvg_id: stream.epg_channel_id.filter(|id| !id.trim().is_empty()),
+        media_types: None,
+        catchup: stream
+            .tv_archive
+            .map(|archive| archive == 1 || archive == "1"),
         favorite: false,
+        watched: false,
         group_id: None,
         series_id: None,
     })
 }
 
-fn get_url(
+fn get_url_template(
     stream_id: String,
-    source: &Source,
     stream_type: u8,
     extension: Option<String>,
 ) -> Result<String> {
     Ok(format!(
-        "{}/{}/{}/{}/{}.{}",
-        source.url_origin.clone().unwrap(),
+        "{URL_SCHEME}{}/{}.{}",
         get_media_type_string(stream_type)?,
-        source.username.clone().unwrap(),
-        source.password.clone().unwrap(),
         stream_id,
         extension.unwrap_or(LIVE_STREAM_EXTENSION.to_string())
     ))
 }
 
+pub fn is_url_template(url: &str) -> bool {
+    url.starts_with(URL_SCHEME)
+}
+
+/// Turns a full url stored before templates into its template, None when it isn't an Xtream url
+pub fn to_url_template(url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    let segments: Vec<&str> = parsed.path_segments()?.collect();
+    if segments.len() < 4 {
+        return None;
+    }
+    let stream_type = segments[segments.len() - 4];
+    let file = segments[segments.len() - 1];
+    match stream_type {
+        "live" | "movie" | "series" if !file.is_empty() => {
+            Some(format!("{URL_SCHEME}{stream_type}/{file}"))
+        }
+        _ => None,
+    }
+}
+
+/// The playable url of a channel, built from its source for Xtream templates. Any other url
+/// is returned as is
+pub fn resolve_url(channel: &Channel, source: &Source) -> Result<String> {
+    if channel.media_type == media_type::SERIE {
+        bail!("Series don't have a stream url, please pick an episode");
+    }
+    let url = channel.url.as_deref().context("no url")?;
+    let Some(template) = url.strip_prefix(URL_SCHEME) else {
+        return Ok(url.to_string());
+    };
+    let (stream_type, file) = template
+        .split_once('/')
+        .with_context(|| format!("Invalid Xtream url {url}"))?;
+    stream_url(source, stream_type, &[file])
+}
+
+/// The archive url of a live channel for a programme between `start` and `stop`
+pub fn resolve_timeshift_url(
+    channel: &Channel,
+    source: &Source,
+    start: i64,
+    stop: i64,
+) -> Result<String> {
+    if !media_type::is_live(channel.media_type) || channel.catchup != Some(true) {
+        bail!("{} has no catch-up", channel.name);
+    }
+    let stream_id = channel.stream_id.context("no stream id")?;
+    let minutes = (stop - start).max(60) / 60;
+    let minutes = minutes.to_string();
+    let start = get_local_time(start)?.format(TIMESHIFT_FORMAT).to_string();
+    let file = format!("{stream_id}.{LIVE_STREAM_EXTENSION}");
+    stream_url(
+        source,
+        "timeshift",
+        &[minutes.as_str(), start.as_str(), file.as_str()],
+    )
+}
+
+/// Streams live at the root of the provider whatever the path of the api. The credentials
+/// are path segments, percent-encoded so any character in them survives
+fn stream_url(source: &Source, stream_type: &str, rest: &[&str]) -> Result<String> {
+    let mut url = Url::parse(source.url.as_deref().context("Missing URL")?)?;
+    let username = source.username.as_deref().context("Missing username")?;
+    let password = source.password.as_deref().context("Missing password")?;
+    url.set_query(None);
+    url.set_fragment(None);
+    url.set_username("")
+        .and_then(|_| url.set_password(None))
+        .map_err(|_| anyhow!("Invalid Xtream url"))?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("Invalid Xtream url"))?
+        .clear()
+        .extend([stream_type, username, password])
+        .extend(rest);
+    Ok(url.to_string())
+}
+
 fn get_media_type_string(stream_type: u8) -> Result<String> {
     match stream_type {
         media_type::LIVESTREAM => Ok("live".to_string()),
@@ -256,21 +624,157 @@ fn get_media_type_string(stream_type: u8) -> Result<String> {
     }
 }
 
-pub async fn get_episodes(channel: Channel) -> Result<()> {
-    let series_id = channel.url.context("no url")?.parse()?;
-    if sql::series_has_episodes(series_id, channel.source_id.context("no source id")?)
-        .unwrap_or_else(|e| {
-            log::log(format!("{:?}", e));
-            return false;
-        })
-    {
-        return Ok(());
+/// Series only come with their cover at import, the episodes are fetched the first time
+/// the series is opened and served from the database afterwards
+pub async fn ensure_series_loaded(
+    stop: CancelToken,
+    series_id: u64,
+    source_id: i64,
+) -> Result<Vec<Channel>> {
+    let loaded = sql::series_has_episodes(series_id, source_id).unwrap_or_else(|e| {
+        tracing::error!("{:?}", e);
+        false
+    });
+    if !loaded {
+        load_series(stop, series_id, source_id).await?;
     }
-    let mut source = sql::get_source_from_id(channel.source_id.context("no source id")?)?;
-    let mut url = build_xtream_url(&mut source)?;
+    sql::get_series_episodes(series_id, source_id)
+}
+
+/// Fetches the episodes of a series again. When the provider fails the episodes already
+/// loaded stay as they were
+pub async fn refresh_series(
+    stop: CancelToken,
+    series_id: u64,
+    source_id: i64,
+) -> Result<Vec<Channel>> {
+    load_series(stop, series_id, source_id).await?;
+    sql::get_series_episodes(series_id, source_id)
+}
+
+/// Fetches the episodes of a series again and returns how many appeared since the last load.
+/// Known episodes keep their added_at, so the new ones stand out in the series summary
+pub async fn refresh_episodes(stop: CancelToken, channel: Channel) -> Result<u32> {
+    let series_id: u64 = channel.url.as_deref().context("no url")?.parse()?;
+    let source_id = channel.source_id.context("no source id")?;
+    let count = || -> Result<u32> {
+        Ok(sql::get_series_summary(&[series_id as i64], source_id)?
+            .first()
+            .map_or(0, |summary| summary.episode_count))
+    };
+    let before = count()?;
+    load_series(stop, series_id, source_id).await?;
+    Ok(count()?.saturating_sub(before))
+}
+
+async fn load_series(stop: CancelToken, series_id: u64, source_id: i64) -> Result<()> {
+    let mut source = sql::get_source_from_id(source_id)?;
+    let url = build_xtream_url(&mut source)?;
+    tokio::select! {
+        result = tokio::time::timeout(
+            SERIES_LOAD_TIMEOUT,
+            fetch_episodes(&source, url, series_id, xtream_http::log_retry),
+        ) => result.context("The provider took too long to answer")?,
+        _ = stop.cancelled() => bail!("Loading the series was cancelled"),
+    }
+}
+
+/// Runs a series load that `cancel_series_load` can stop with the id its request picked
+pub async fn run_series_load<T, F>(
+    state: &Mutex<AppState>,
+    load_id: u64,
+    load: impl FnOnce(CancelToken) -> F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let stop = CancelToken::default();
+    state
+        .lock()
+        .await
+        .refreshes
+        .series_loads
+        .insert(load_id, stop.clone());
+    let result = load(stop).await;
+    state.lock().await.refreshes.series_loads.remove(&load_id);
+    result
+}
+
+pub async fn cancel_series_load(state: &Mutex<AppState>, load_id: u64) {
+    if let Some(stop) = state.lock().await.refreshes.series_loads.get(&load_id) {
+        stop.cancel();
+    }
+}
+
+/// Loads the episodes of every series of a source ahead of time. Series already fetched are
+/// skipped, so when the provider starts refusing requests the next run continues from there
+pub async fn prefetch_episodes(app: AppHandle, source_id: i64) -> Result<SeriesPrefetchProgress> {
+    let mut source = sql::get_source_from_id(source_id)?;
+    let url = build_xtream_url(&mut source)?;
+    let pending = sql::get_unfetched_series(source_id)?;
+    let operation_id = events::new_operation_id();
+    let mut progress = SeriesPrefetchProgress {
+        source_id,
+        fetched: 0,
+        total: pending.len(),
+        throttle: None,
+        finished: false,
+        error: None,
+    };
+    for series_id in pending {
+        let on_retry = |throttle: &XtreamThrottle| {
+            xtream_http::log_retry(throttle);
+            let throttled = SeriesPrefetchProgress {
+                throttle: Some(throttle.clone()),
+                ..progress.clone()
+            };
+            events::emit(
+                &app,
+                events::SERIES_PREFETCH_PROGRESS,
+                operation_id,
+                throttled,
+            );
+        };
+        match fetch_episodes(&source, url.clone(), series_id, on_retry).await {
+            Ok(()) => progress.fetched += 1,
+            Err(e) if e.is::<ProviderThrottled>() => {
+                progress.error = Some(e.to_string());
+                break;
+            }
+            Err(e) => tracing::error!(
+                "Failed to fetch the episodes of series {series_id}: {:?}",
+                e
+            ),
+        }
+        events::emit(
+            &app,
+            events::SERIES_PREFETCH_PROGRESS,
+            operation_id,
+            progress.clone(),
+        );
+    }
+    progress.finished = true;
+    events::emit(
+        &app,
+        events::SERIES_PREFETCH_PROGRESS,
+        operation_id,
+        progress.clone(),
+    );
+    Ok(progress)
+}
+
+async fn fetch_episodes(
+    source: &Source,
+    mut url: Url,
+    series_id: u64,
+    on_retry: impl Fn(&XtreamThrottle),
+) -> Result<()> {
     url.query_pairs_mut()
+        .append_pair("action", GET_SERIES_INFO)
         .append_pair("series_id", &series_id.to_string());
-    let episodes = (get_xtream_http_data::<XtreamSeries>(url, GET_SERIES_INFO).await?).episodes;
+    let episodes = xtream_http::get_json::<XtreamSeries>(url, on_retry)
+        .await?
+        .episodes;
     let mut episodes: Vec<XtreamEpisode> =
         episodes.into_values().flat_map(|episode| episode).collect();
     episodes.sort_by(|a, b| {
@@ -278,12 +782,15 @@ pub async fn get_episodes(channel: Channel) -> Result<()> {
             .cmp(&b.season)
             .then_with(|| a.episode_num.cmp(&b.episode_num))
     });
+    let episodes = episodes
+        .into_iter()
+        .map(|episode| episode_to_channel(episode, source, series_id))
+        .collect::<Result<Vec<Channel>>>()?;
+    let source_id = source.id.context("no source id")?;
     sql::do_tx(|tx| {
-        for episode in episodes {
-            let episode = episode_to_channel(episode, &source, series_id)?;
-            sql::insert_channel(&tx, episode)?;
-        }
-        Ok(())
+        sql::insert_channels_batch(tx, &episodes)?;
+        sql::mark_series_fetched(tx, source_id, series_id)?;
+        sql::apply_watched(tx, source_id, Some(series_id))
     })?;
     Ok(())
 }
@@ -298,16 +805,19 @@ fn episode_to_channel(episode: XtreamEpisode, source: &Source, series_id: u64) -
         media_type: media_type::MOVIE,
         name: episode.title.trim().to_string(),
         source_id: source.id,
-        url: Some(get_url(
+        url: Some(get_url_template(
             episode.id,
-            &source,
             media_type::SERIE,
             Some(episode.container_extension),
         )?),
         series_id: Some(series_id),
         stream_id: None,
+        tvg_id: None,
+        media_types: None,
+        catchup: None,
         group_id: None,
         favorite: false,
+        watched: false,
     })
 }
 
@@ -321,7 +831,7 @@ pub async fn get_short_epg(channel: Channel) -> Result<Vec<EPG>> {
             .context("No stream id, please refresh your sources (Settings -> Refresh All) if you recently updated Open TV")?
             .to_string(),
     );
-    let epg: XtreamEPG = get_xtream_http_data(url, GET_SHORT_EPG).await?;
+    let epg: XtreamEPG = get_xtream_http_data(url, GET_SHORT_EPG, &xtream_http::log_retry).await?;
     epg.epg_listings
         .iter()
         .map(xtream_epg_to_epg)
@@ -340,6 +850,7 @@ fn xtream_epg_to_epg(epg: &XtreamEPGItem) -> Result<EPG> {
             .format("%Hh%M")
             .to_string(),
         start_timestamp: epg.start_timestamp.parse()?,
+        end_timestamp: epg.stop_timestamp.parse()?,
     })
 }
 
@@ -348,17 +859,64 @@ mod test_xtream {
 
     use std::env;
 
+    use crate::media_type;
     use crate::source_type;
-    use crate::sql::{self, drop_db};
+    use crate::sql;
+    use crate::types::Channel;
     use crate::types::Source;
     use crate::xtream::{episode_to_channel, get_xtream};
+    use crate::xtream_http;
+
+    use super::{
+        build_xtream_url, get_local_time, get_url_template, resolve_timeshift_url, resolve_url,
+        to_url_template, XtreamEpisode, XtreamSeries,
+    };
+
+    fn xtream_source() -> Source {
+        Source {
+            name: "my-xtream".to_string(),
+            id: Some(1),
+            username: Some("fred".to_string()),
+            password: Some("secret".to_string()),
+            url: Some("http://provider.tv:8080/player_api.php".to_string()),
+            url_origin: None,
+            last_refresh_at: None,
+            last_refresh_status: None,
+            last_refresh_error: None,
+            channel_count: None,
+            import_live: true,
+            import_vod: true,
+            import_series: true,
+            group_filter: None,
+            source_type: source_type::XTREAM,
+            enabled: true,
+            use_tvg_id: None,
+        }
+    }
 
-    use super::{get_local_time, XtreamEpisode, XtreamSeries};
+    fn channel(url: &str, media_type: u8) -> Channel {
+        Channel {
+            id: Some(1),
+            name: "France 24".to_string(),
+            url: Some(url.to_string()),
+            group: None,
+            group_id: None,
+            image: None,
+            media_type,
+            source_id: Some(1),
+            series_id: None,
+            favorite: false,
+            watched: false,
+            stream_id: Some(42),
+            tvg_id: None,
+            media_types: None,
+            catchup: Some(true),
+        }
+    }
 
     #[tokio::test]
     async fn test_get_xtream() {
-        drop_db().unwrap_or_default();
-        sql::create_or_initialize_db().unwrap();
+        let _db = sql::init_test_db();
         get_xtream(
             Source {
                 name: "my-xtream".to_string(),
@@ -367,11 +925,20 @@ mod test_xtream {
                 password: Some(env::var("OPEN_TV_TEST_XTREAM_PASSWORD").unwrap()),
                 url: Some(env::var("OPEN_TV_TEST_XTREAM_LINK").unwrap()),
                 url_origin: None,
+                last_refresh_at: None,
+                last_refresh_status: None,
+                last_refresh_error: None,
+                channel_count: None,
+                import_live: true,
+                import_vod: true,
+                import_series: true,
+                group_filter: None,
                 source_type: source_type::XTREAM,
                 enabled: true,
                 use_tvg_id: None,
             },
             false,
+            &xtream_http::log_retry,
         )
         .await
         .unwrap();
@@ -386,6 +953,14 @@ mod test_xtream {
             password: Some("test".to_string()),
             url: Some("http://test.com".to_string()),
             url_origin: Some("test.com".to_string()),
+            last_refresh_at: None,
+            last_refresh_status: None,
+            last_refresh_error: None,
+            channel_count: None,
+            import_live: true,
+            import_vod: true,
+            import_series: true,
+            group_filter: None,
             source_type: source_type::XTREAM,
             enabled: true,
             use_tvg_id: None,
@@ -405,6 +980,116 @@ mod test_xtream {
         println!("{:?}", e2);
     }
 
+    #[test]
+    fn test_resolve_url() {
+        let source = xtream_source();
+        let resolve =
+            |url: String, media_type: u8| resolve_url(&channel(&url, media_type), &source).unwrap();
+        let live = get_url_template("42".to_string(), media_type::LIVESTREAM, None).unwrap();
+        assert_eq!(live, "xtream:live/42.ts");
+        assert_eq!(
+            resolve(live, media_type::LIVESTREAM),
+            "http://provider.tv:8080/live/fred/secret/42.ts"
+        );
+        let movie =
+            get_url_template("7".to_string(), media_type::MOVIE, Some("mkv".to_string())).unwrap();
+        assert_eq!(
+            resolve(movie, media_type::MOVIE),
+            "http://provider.tv:8080/movie/fred/secret/7.mkv"
+        );
+        let episode =
+            get_url_template("9".to_string(), media_type::SERIE, Some("mp4".to_string())).unwrap();
+        assert_eq!(
+            resolve(episode, media_type::MOVIE),
+            "http://provider.tv:8080/series/fred/secret/9.mp4"
+        );
+        assert!(resolve_url(&channel("12", media_type::SERIE), &source).is_err());
+        assert_eq!(
+            resolve("http://other.tv/a.m3u8".to_string(), media_type::LIVESTREAM),
+            "http://other.tv/a.m3u8"
+        );
+    }
+
+    #[test]
+    fn test_resolve_timeshift_url() {
+        let source = xtream_source();
+        let live = channel("xtream:live/42.ts", media_type::LIVESTREAM);
+        let start = 1734217200;
+        let expected_start = get_local_time(start).unwrap().format("%Y-%m-%d:%H-%M");
+        assert_eq!(
+            resolve_timeshift_url(&live, &source, start, start + 5400).unwrap(),
+            format!("http://provider.tv:8080/timeshift/fred/secret/90/{expected_start}/42.ts")
+        );
+        let no_archive = Channel {
+            catchup: None,
+            ..live
+        };
+        assert!(resolve_timeshift_url(&no_archive, &source, start, start + 5400).is_err());
+    }
+
+    #[test]
+    fn test_resolve_url_edge_cases() {
+        let live = channel("xtream:live/42.ts", media_type::LIVESTREAM);
+        let resolve = |url: &str, password: &str| {
+            let source = Source {
+                url: Some(url.to_string()),
+                password: Some(password.to_string()),
+                ..xtream_source()
+            };
+            resolve_url(&live, &source).unwrap()
+        };
+        assert_eq!(
+            resolve("http://[2a02:1:2::3]:8080/player_api.php", "secret"),
+            "http://[2a02:1:2::3]:8080/live/fred/secret/42.ts"
+        );
+        assert_eq!(
+            resolve("http://provider.tv:80/player_api.php", "secret"),
+            "http://provider.tv/live/fred/secret/42.ts"
+        );
+        assert_eq!(
+            resolve("https://admin:pw@provider.tv:8443/", "secret"),
+            "https://provider.tv:8443/live/fred/secret/42.ts"
+        );
+        assert_eq!(
+            resolve("http://provider.tv:8080/player_api.php", "p@ss/w#rd"),
+            "http://provider.tv:8080/live/fred/p@ss%2Fw%23rd/42.ts"
+        );
+    }
+
+    #[test]
+    fn test_build_xtream_url() {
+        let mut source = Source {
+            url: Some(
+                "http://[2a02:1:2::3]:8080/player_api.php?username=old&password=old&type=m3u"
+                    .to_string(),
+            ),
+            ..xtream_source()
+        };
+        let url = build_xtream_url(&mut source).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://[2a02:1:2::3]:8080/player_api.php?type=m3u&username=fred&password=secret"
+        );
+        assert_eq!(
+            source.url_origin.as_deref(),
+            Some("http://[2a02:1:2::3]:8080")
+        );
+    }
+
+    #[test]
+    fn test_to_url_template() {
+        assert_eq!(
+            to_url_template("http://provider.tv:8080/live/fred/old/42.ts").as_deref(),
+            Some("xtream:live/42.ts")
+        );
+        assert_eq!(
+            to_url_template("http://provider.tv/series/fred/old/9.mp4").as_deref(),
+            Some("xtream:series/9.mp4")
+        );
+        assert_eq!(to_url_template("http://cdn.tv/hls/index.m3u8"), None);
+        assert_eq!(to_url_template("12345"), None);
+    }
+
     #[test]
     fn test_get_local_time() {
         println!("{}", get_local_time(1734217200).unwrap());
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use tokio::sync::Semaphore;
+use url::Url;
+
+use crate::{
+    proxy,
+    settings::{get_settings, DEFAULT_XTREAM_REQUESTS_PER_SECOND},
+    types::XtreamThrottle,
+};
+
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+const MAX_ATTEMPTS: u32 = 6;
+const BASE_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+
+static PERMITS: Semaphore = Semaphore::const_new(MAX_CONCURRENT_REQUESTS);
+/// When the next request to each host may start, providers ban by IP so every source
+/// of a provider shares its pace
+static NEXT_SLOTS: LazyLock<Mutex<HashMap<String, Instant>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug)]
+pub struct ProviderThrottled {
+    pub status: u16,
+}
+
+impl fmt::Display for ProviderThrottled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The provider keeps refusing requests (HTTP {}), it may have banned this IP for a while. Try again later",
+            self.status
+        )
+    }
+}
+
+impl std::error::Error for ProviderThrottled {}
+
+/// Fetches Xtream json at the configured pace. 429 and 5xx answers and dropped connections
+/// are retried with an exponential backoff, `on_retry` hears about each wait
+pub async fn get_json<T>(url: Url, on_retry: impl Fn(&XtreamThrottle)) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let _permit = PERMITS.acquire().await?;
+    let host = url.host_str().unwrap_or_default().to_string();
+    let client = proxy::get_client()?;
+    let mut attempt = 0;
+    loop {
+        wait_for_slot(&host).await?;
+        attempt += 1;
+        let (status, retry_after, error) = match client.get(url.clone()).send().await {
+            Ok(response) if is_retryable(response.status()) => (
+                Some(response.status().as_u16()),
+                get_retry_after(&response),
+                anyhow!("HTTP {}", response.status()),
+            ),
+            Ok(response) => return Ok(response.error_for_status()?.json::<T>().await?),
+            Err(e) if e.is_connect() || e.is_timeout() => (None, None, e.into()),
+            Err(e) => return Err(e.into()),
+        };
+        if attempt >= MAX_ATTEMPTS {
+            return Err(match status {
+                Some(status) => ProviderThrottled { status }.into(),
+                None => error,
+            });
+        }
+        let wait = retry_after
+            .unwrap_or_else(|| get_backoff(attempt))
+            .min(MAX_BACKOFF);
+        on_retry(&XtreamThrottle {
+            attempt,
+            wait_seconds: wait.as_secs(),
+            status,
+            banned: status == Some(StatusCode::TOO_MANY_REQUESTS.as_u16()),
+        });
+        tokio::time::sleep(wait).await;
+    }
+}
+
+pub fn log_retry(throttle: &XtreamThrottle) {
+    tracing::warn!(
+        "Xtream request refused (HTTP {:?}), attempt {} retried in {}s",
+        throttle.status,
+        throttle.attempt,
+        throttle.wait_seconds
+    );
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn get_retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Doubles from BASE_BACKOFF with up to 50% jitter so parallel imports don't retry in step
+fn get_backoff(attempt: u32) -> Duration {
+    let backoff = BASE_BACKOFF.saturating_mul(1u32 << attempt.saturating_sub(1).min(16));
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.subsec_nanos())
+        .unwrap_or_default();
+    let jitter = backoff.mul_f64(nanos as f64 / 1_000_000_000.0 / 2.0);
+    backoff + jitter
+}
+
+async fn wait_for_slot(host: &str) -> Result<()> {
+    let per_second = get_settings()?
+        .xtream_requests_per_second
+        .unwrap_or(DEFAULT_XTREAM_REQUESTS_PER_SECOND)
+        .max(1);
+    let wait = reserve_slot(
+        host,
+        Duration::from_secs(1) / per_second as u32,
+        Instant::now(),
+    );
+    tokio::time::sleep(wait).await;
+    Ok(())
+}
+
+/// Books the next free slot of a host and returns how long to wait for it
+fn reserve_slot(host: &str, interval: Duration, now: Instant) -> Duration {
+    let mut slots = NEXT_SLOTS.lock().unwrap();
+    let slot = slots
+        .get(host)
+        .copied()
+        .filter(|slot| *slot > now)
+        .unwrap_or(now);
+    slots.insert(host.to_string(), slot + interval);
+    slot - now
+}
+
+#[cfg(test)]
+mod test_xtream_http {
+    use std::time::{Duration, Instant};
+
+    use super::{get_backoff, reserve_slot, BASE_BACKOFF};
+
+    #[test]
+    fn test_slots_are_spaced() {
+        let now = Instant::now();
+        let interval = Duration::from_millis(250);
+        assert_eq!(reserve_slot("a.test", interval, now), Duration::ZERO);
+        assert_eq!(reserve_slot("a.test", interval, now), interval);
+        assert_eq!(reserve_slot("a.test", interval, now), interval * 2);
+        assert_eq!(reserve_slot("b.test", interval, now), Duration::ZERO);
+        let later = now + Duration::from_secs(5);
+        assert_eq!(reserve_slot("a.test", interval, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_backoff_grows_with_jitter() {
+        for attempt in 1..5 {
+            let backoff = get_backoff(attempt);
+            let base = BASE_BACKOFF * (1 << (attempt - 1));
+            assert!(backoff >= base && backoff <= base + base / 2);
+        }
+    }
+}
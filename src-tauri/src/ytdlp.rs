@@ -0,0 +1,203 @@
+use std::{collections::HashMap, env::consts::OS, io::Write, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use directories::ProjectDirs;
+use reqwest::Client;
+use serde::Deserialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+    doctor, events, mpv::get_ytdlp_path, proxy, settings::get_settings, sql, types::YtdlpUpdate,
+    utils::get_deps_dir,
+};
+
+pub const UPDATED: u8 = 0;
+pub const UP_TO_DATE: u8 = 1;
+pub const MANAGED_EXTERNALLY: u8 = 2;
+pub const YTDLP_LAST_UPDATE_CHECK: &str = "ytdlpLastUpdateCheck";
+const RELEASE_URL: &str = "https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest";
+const BINARY_NAME: &str = "yt-dlp.exe";
+const CHECKSUMS_NAME: &str = "SHA2-256SUMS";
+const CHECK_INTERVAL_SECS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+    assets: Vec<GithubAsset>,
+}
+
+#[derive(Deserialize)]
+struct GithubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+pub fn get_updated_path() -> Option<PathBuf> {
+    if OS != "windows" {
+        return None;
+    }
+    Some(get_update_dir().join(BINARY_NAME)).filter(|path| path.exists())
+}
+
+fn get_update_dir() -> PathBuf {
+    let mut path = ProjectDirs::from("dev", "fredol", "open-tv")
+        .unwrap()
+        .data_dir()
+        .to_owned();
+    path.push("deps");
+    path
+}
+
+fn is_managed_externally() -> bool {
+    if OS != "windows" {
+        return true;
+    }
+    let path = get_ytdlp_path();
+    !path.starts_with(get_deps_dir()) && !path.starts_with(get_update_dir())
+}
+
+pub async fn update_ytdlp(app: AppHandle) -> Result<YtdlpUpdate> {
+    if is_managed_externally() {
+        return Ok(YtdlpUpdate {
+            status: MANAGED_EXTERNALLY,
+            version: doctor::get_ytdlp_version().await,
+        });
+    }
+    let client = proxy::client_builder()?.user_agent("open-tv").build()?;
+    let release: GithubRelease = serde_json::from_str(
+        &client
+            .get(RELEASE_URL)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?,
+    )?;
+    set_last_update_check()?;
+    if doctor::get_ytdlp_version().await.as_deref() == Some(release.tag_name.as_str()) {
+        return Ok(YtdlpUpdate {
+            status: UP_TO_DATE,
+            version: Some(release.tag_name),
+        });
+    }
+    let checksums = client
+        .get(&get_asset_url(&release, CHECKSUMS_NAME)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let expected = parse_checksum(&checksums, BINARY_NAME)
+        .context("yt-dlp release has no checksum for the Windows binary")?;
+    let dir = get_update_dir();
+    std::fs::create_dir_all(&dir)?;
+    let part = dir.join(format!("{BINARY_NAME}.part"));
+    download(&app, &client, &get_asset_url(&release, BINARY_NAME)?, &part).await?;
+    let actual = get_sha256(&part).await?;
+    if actual != expected {
+        let _ = std::fs::remove_file(&part);
+        bail!("Checksum mismatch for downloaded yt-dlp, expected {expected} got {actual}");
+    }
+    std::fs::rename(&part, dir.join(BINARY_NAME))?;
+    Ok(YtdlpUpdate {
+        status: UPDATED,
+        version: Some(release.tag_name),
+    })
+}
+
+pub async fn auto_update(app: AppHandle) {
+    if let Err(e) = try_auto_update(app).await {
+        tracing::warn!("Failed to auto update yt-dlp: {:?}", e);
+    }
+}
+
+async fn try_auto_update(app: AppHandle) -> Result<()> {
+    if is_managed_externally() || get_settings()?.ytdlp_auto_update != Some(true) {
+        return Ok(());
+    }
+    let last_check: i64 = sql::get_settings()?
+        .get(YTDLP_LAST_UPDATE_CHECK)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+    if Utc::now().timestamp() - last_check < CHECK_INTERVAL_SECS {
+        return Ok(());
+    }
+    update_ytdlp(app).await?;
+    Ok(())
+}
+
+fn set_last_update_check() -> Result<()> {
+    sql::update_settings(HashMap::from([(
+        YTDLP_LAST_UPDATE_CHECK.to_string(),
+        Utc::now().timestamp().to_string(),
+    )]))
+}
+
+fn get_asset_url(release: &GithubRelease, name: &str) -> Result<String> {
+    release
+        .assets
+        .iter()
+        .find(|asset| asset.name == name)
+        .map(|asset| asset.browser_download_url.clone())
+        .with_context(|| format!("{name} missing from yt-dlp release {}", release.tag_name))
+}
+
+fn parse_checksum(checksums: &str, file: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+        (name.trim().trim_start_matches('*') == file).then(|| hash.to_lowercase())
+    })
+}
+
+async fn download(app: &AppHandle, client: &Client, url: &str, path: &PathBuf) -> Result<()> {
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    let total_size = response.content_length().unwrap_or(0);
+    let mut downloaded = 0;
+    let mut file = std::fs::File::create(path)?;
+    let mut send_threshold: u8 = 5;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        downloaded += chunk.len() as u64;
+        if total_size > 0 {
+            let progress: u8 = ((downloaded as f64 / total_size as f64) * 100.0) as u8;
+            if progress > send_threshold {
+                app.emit(events::YTDLP_UPDATE_PROGRESS, progress)?;
+                send_threshold = progress + 5;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn get_sha256(path: &PathBuf) -> Result<String> {
+    let output = tokio::process::Command::new("certutil")
+        .arg("-hashfile")
+        .arg(path)
+        .arg("SHA256")
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!("Failed to compute checksum of {}", path.to_string_lossy());
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .nth(1)
+        .map(|line| line.replace(" ", "").to_lowercase())
+        .context("Unexpected certutil output")
+}
+
+#[cfg(test)]
+mod test_ytdlp {
+    use super::parse_checksum;
+
+    #[test]
+    fn test_parse_checksum() {
+        let checksums = "aaa111  yt-dlp\nBBB222  yt-dlp.exe\nccc333  yt-dlp_x86.exe\n";
+        assert_eq!(
+            parse_checksum(checksums, "yt-dlp.exe"),
+            Some("bbb222".to_string())
+        );
+        assert_eq!(parse_checksum(checksums, "yt-dlp_macos"), None);
+    }
+}
@@ -1,4 +1,24 @@
 import { Component } from '@angular/core';
+import { Router } from '@angular/router';
+import { listen } from '@tauri-apps/api/event';
+import { invoke } from '@tauri-apps/api/core';
+import { onAction } from '@tauri-apps/plugin-notification';
+import { MemoryService } from './memory.service';
+import { ErrorService } from './error.service';
+import { ToastrService } from 'ngx-toastr';
+import { StartupState } from './models/startupState';
+import { BACKUP_FAILED, SLEEP_TIMER_WARNING } from './models/events';
+
+const SLEEP_TIMER_EXTEND_MINUTES = 15;
+
+const GAMEPAD_KEYS: Record<string, string> = {
+  nav_up: "ArrowUp",
+  nav_down: "ArrowDown",
+  nav_left: "ArrowLeft",
+  nav_right: "ArrowRight",
+  nav_select: "Enter",
+  nav_back: "Escape",
+};
 
 @Component({
   selector: 'app-root',
@@ -8,6 +28,37 @@ import { Component } from '@angular/core';
 export class AppComponent {
   title = 'open-tv';
 
-  constructor() {
+  constructor(private router: Router, private memory: MemoryService, private error: ErrorService, private toastr: ToastrService) {
+    listen<string>("add_source", (event) => {
+      this.memory.AddingAdditionalSource = true;
+      this.router.navigateByUrl("setup", { state: { url: event.payload } });
+    }).then(() => invoke("frontend_ready"));
+    invoke<StartupState>("get_startup_state").then((state) => {
+      if (state.database_error) this.error.handleError(state.database_error, "Open TV could not open its database");
+      if (state.corrupt_database_backup)
+        this.toastr.warning(`Your database was damaged and has been reset, the old file was kept at ${state.corrupt_database_backup}`,
+          "Database reset", { disableTimeOut: true, closeButton: true });
+    });
+    onAction((notification) => {
+      let extra = notification.extra ?? {};
+      if (extra["channel_name"] === undefined) return;
+      invoke("open_epg_channel", { channelId: extra["channel_id"], channelName: extra["channel_name"] })
+        .catch((e) => this.error.handleError(e));
+    });
+    listen(SLEEP_TIMER_WARNING, () => {
+      this.toastr.info(`Playback stops in a minute, click to extend by ${SLEEP_TIMER_EXTEND_MINUTES} minutes`, "Sleep timer")
+        .onTap.subscribe(() => {
+          invoke("set_sleep_timer", { minutes: SLEEP_TIMER_EXTEND_MINUTES, stopRecording: false })
+            .catch((e) => this.error.handleError(e));
+        });
+    });
+    listen<string>(BACKUP_FAILED, (event) => {
+      this.toastr.warning(event.payload, "Automatic backup failed");
+    });
+    for (const [event, key] of Object.entries(GAMEPAD_KEYS)) {
+      listen(event, () => {
+        (document.activeElement ?? document).dispatchEvent(new KeyboardEvent("keydown", { key: key, bubbles: true }));
+      });
+    }
   }
 }
@@ -83,7 +83,7 @@ export class ChannelTileComponent implements OnDestroy, AfterViewInit {
     this.renderer.setStyle(element, "background", background);
   }
 
-  async click(record = false) {
+  async click(record = false, pip?: boolean) {
     if (this.starting === true) return;
     if (this.channel?.media_type == MediaType.group) {
       this.memory.SetGroupNode.next({
@@ -96,7 +96,15 @@ export class ChannelTileComponent implements OnDestroy, AfterViewInit {
       if (!this.memory.SeriesRefreshed.has(this.channel.id!)) {
         this.memory.HideChannels.next(false);
         try {
-          await invoke("get_episodes", { channel: this.channel });
+          const loaded = await this.memory.loadSeries(
+            "ensure_series_loaded",
+            Number(this.channel.url),
+            this.channel.source_id!,
+          );
+          if (!loaded) {
+            this.memory.HideChannels.next(true);
+            return;
+          }
           this.memory.SeriesRefreshed.set(this.channel.id!, true);
         } catch (e) {
           this.error.handleError(e, "Failed to fetch series");
@@ -107,7 +115,7 @@ export class ChannelTileComponent implements OnDestroy, AfterViewInit {
     }
     this.starting = true;
     try {
-      await invoke("play", { channel: this.channel, record: record });
+      await invoke("play", { channel: this.channel, record: record, pip: pip });
     } catch (e) {
       this.error.handleError(e);
     }
@@ -150,6 +158,10 @@ export class ChannelTileComponent implements OnDestroy, AfterViewInit {
     await this.click(true);
   }
 
+  async playPip() {
+    await this.click(false, true);
+  }
+
   isMovie() {
     return this.channel?.media_type == MediaType.movie;
   }
@@ -158,6 +170,29 @@ export class ChannelTileComponent implements OnDestroy, AfterViewInit {
     return this.channel?.media_type == MediaType.livestream;
   }
 
+  isRadio() {
+    return this.channel?.media_type == MediaType.radio;
+  }
+
+  async setRadio(radio: boolean) {
+    try {
+      const mediaType = radio ? MediaType.radio : MediaType.livestream;
+      await invoke("set_channel_media_type", { channelId: this.channel!.id, mediaType: mediaType });
+      this.channel!.media_type = mediaType;
+    } catch (e) {
+      this.error.handleError(e);
+    }
+  }
+
+  async setWatched(watched: boolean) {
+    try {
+      await invoke("set_watched", { channelId: this.channel!.id, watched: watched });
+      this.channel!.watched = watched;
+    } catch (e) {
+      this.error.handleError(e);
+    }
+  }
+
   isCustom(): boolean {
     return this.memory.CustomSourceIds!.has(this.channel?.source_id!);
   }
@@ -185,6 +220,7 @@ export class ChannelTileComponent implements OnDestroy, AfterViewInit {
       this.memory.ModalRef.result.then((_) => (this.memory.ModalRef = undefined));
       this.memory.ModalRef.componentInstance.epg = data;
       this.memory.ModalRef.componentInstance.name = this.channel?.name;
+      this.memory.ModalRef.componentInstance.channelId = this.channel?.id;
     } catch (e) {
       this.error.handleError(
         e,
@@ -266,6 +302,7 @@ export class ChannelTileComponent implements OnDestroy, AfterViewInit {
       await invoke("delete_custom_group", {
         id: this.channel?.id,
         doChannelsUpdate: false,
+        cascade: false,
       });
       this.memory.Refresh.next(false);
       this.error.success("Successfully deleted category");
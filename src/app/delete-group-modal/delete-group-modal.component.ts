@@ -46,7 +46,7 @@ export class DeleteGroupModalComponent {
   async delete() {
     this.loading = true;
     try {
-      await invoke("delete_custom_group", { id: this.group?.id, newId: this.new_group_id, doChannelsUpdate: true });
+      await invoke("delete_custom_group", { id: this.group?.id, newId: this.new_group_id, doChannelsUpdate: true, cascade: false });
       this.error.success("Successfully deleted category");
       this.memory.Refresh.next(false);
       this.activeModal.close("close");
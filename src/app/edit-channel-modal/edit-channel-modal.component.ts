@@ -9,6 +9,7 @@ import { combineLatest, debounceTime, distinctUntilChanged, filter, from, map, O
 import { IdName } from '../models/idName';
 import { CustomChanelExtraData } from '../models/customChannelExtraData';
 import { ErrorService } from '../error.service';
+import { UrlValidation } from '../models/urlValidation';
 
 @Component({
   selector: 'app-edit-channel-modal',
@@ -127,7 +128,8 @@ export class EditChannelModalComponent implements OnInit {
 
   async update_channel(channel: CustomChannel) {
     try {
-      await invoke("edit_custom_channel", { channel: channel });
+      let validation = await invoke<UrlValidation | null>("edit_custom_channel", { channel: channel, validate: true, probe: true });
+      if (!this.checkValidation(validation)) return;
       this.memory.Refresh.next(false);
       this.error.success("Successfully updated channel");
       this.activeModal.close('close');
@@ -147,7 +149,8 @@ export class EditChannelModalComponent implements OnInit {
 
   async add_channel(channel: CustomChannel) {
     try {
-      await invoke("add_custom_channel", { channel: channel });
+      let validation = await invoke<UrlValidation | null>("add_custom_channel", { channel: channel, validate: true, probe: true });
+      if (!this.checkValidation(validation)) return;
       this.memory.RefreshSources.next(true);
       this.error.success("Successfully added channel");
       this.activeModal.close('close');
@@ -157,6 +160,16 @@ export class EditChannelModalComponent implements OnInit {
     }
   }
 
+  checkValidation(validation: UrlValidation | null): boolean {
+    if (validation && !validation.valid) {
+      this.error.handleError(validation.message, "The channel was not saved, the url is invalid");
+      return false;
+    }
+    if (validation?.message)
+      console.warn(validation.message);
+    return true;
+  }
+
   ngOnDestroy() {
     this.subscriptions.forEach(x => x.unsubscribe());
   }
@@ -19,6 +19,8 @@ export class EpgModalItemComponent {
   epg?: EPG;
   @Input()
   name?: string;
+  @Input()
+  channelId?: number;
 
   notificationOn(): boolean {
     return this.memory.Watched_epgs.has(this.epg!.epg_id);
@@ -48,6 +50,7 @@ export class EpgModalItemComponent {
 
   epg_to_epgNotify(epg: EPG): EPGNotify {
     return {
+      channel_id: this.channelId,
       channel_name: this.name!,
       epg_id: epg.epg_id,
       start_timestamp: epg.start_timestamp,
@@ -11,6 +11,7 @@ import { MemoryService } from "../memory.service";
 })
 export class EpgModalComponent implements OnInit {
   name?: string;
+  channelId?: number;
   epg: EPG[] = [];
   constructor(
     public activeModal: NgbActiveModal,
@@ -18,6 +18,7 @@ import { FocusArea, FocusAreaPrefix } from "../models/focusArea";
 import { invoke } from "@tauri-apps/api/core";
 import { Source } from "../models/source";
 import { Filters } from "../models/filters";
+import { SearchPage } from "../models/searchPage";
 import { SourceType } from "../models/sourceType";
 import { animate, state, style, transition, trigger } from "@angular/animations";
 import { ErrorService } from "../error.service";
@@ -70,10 +71,13 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
   chkLiveStream = true;
   chkMovie = true;
   chkSerie = true;
+  chkRadio = true;
   current_series_name?: string;
   current_group_name?: string;
+  groupMediaTypesPicked = false;
   reachedMax = false;
-  readonly PAGE_SIZE = 36;
+  mediaTypeCounts: Record<number, number> = {};
+  pageSize = 36;
   channelsVisible = true;
   prevSearchValue?: String;
   loading = false;
@@ -107,12 +111,13 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
           invoke("on_start_check_epg");
         }
         this.memory.Sources = sources.filter((x) => x.enabled);
+        this.pageSize = settings.page_size ?? 36;
         if (sources.length == 0) this.reset();
         else {
           this.filters = {
             source_ids: this.memory.Sources.map((x) => x.id!),
             view_type: settings.default_view ?? ViewMode.All,
-            media_types: [MediaType.livestream, MediaType.movie, MediaType.serie],
+            media_types: [MediaType.livestream, MediaType.movie, MediaType.serie, MediaType.radio],
             page: 1,
             use_keywords: false,
           };
@@ -136,7 +141,7 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
       "Successfully refreshed all sources (refresh on start enabled)",
       "Failed to refresh all sources (refresh on start enabled)",
       async () => {
-        await invoke("refresh_all");
+        await this.memory.refreshAllSources();
       },
     );
   }
@@ -166,6 +171,7 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
       this.memory.SetGroupNode.subscribe(async (idName) => {
         this.clearSearch();
         this.filters!.group_id = idName.id;
+        this.groupMediaTypesPicked = false;
         this.filters!.page = 1;
         this.reachedMax = false;
         this.current_group_name = idName.name;
@@ -191,15 +197,31 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
 
   async load(more = false) {
     this.loading = true;
+    if (!more) {
+      this.filters!.after_id = undefined;
+      this.filters!.after_name = undefined;
+      this.filters!.include_group_names = !!this.filters!.query?.trim();
+      invoke<Record<number, number>>("get_media_type_counts", { filters: this.filters })
+        .then((counts) => (this.mediaTypeCounts = counts))
+        .catch((e) => this.error.handleError(e));
+    }
     try {
-      let channels: Channel[] = await invoke("search", { filters: this.filters });
+      // A group opens with its own mix of media types until the user picks some
+      let filters =
+        this.filters!.group_id && !this.groupMediaTypesPicked
+          ? { ...this.filters, media_types: undefined }
+          : this.filters;
+      let page: SearchPage = await invoke("search", { filters });
+      let channels = page.channels;
+      this.filters!.after_id = page.after_id;
+      this.filters!.after_name = page.after_name;
       if (!more) {
         this.channels = channels;
         this.channelsVisible = true;
       } else {
         this.channels = this.channels.concat(channels);
       }
-      this.reachedMax = channels.length < this.PAGE_SIZE;
+      this.reachedMax = channels.length < this.pageSize;
     } catch (e) {
       this.error.handleError(e);
     }
@@ -340,6 +362,7 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
     let index = this.filters!.media_types.indexOf(mediaType);
     if (index == -1) this.filters!.media_types.push(mediaType);
     else this.filters!.media_types.splice(index, 1);
+    this.groupMediaTypesPicked = true;
     this.filters!.page = 1;
     this.reachedMax = false;
     this.load();
@@ -389,6 +412,10 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
         this.memory.ModalRef.close("close");
       return;
     }
+    if (this.memory.SeriesLoadId !== undefined) {
+      this.memory.cancelSeriesLoad();
+      return;
+    }
     if (this.filters?.group_id || this.filters?.series_id) {
       if (this.filters.group_id && this.focusArea == FocusArea.Filters) {
         this.focusArea = FocusArea.Tiles;
@@ -412,6 +439,21 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
     await this.load();
   }
 
+  async refreshSeries() {
+    try {
+      const loaded = await this.memory.loadSeries(
+        "refresh_series",
+        this.filters!.series_id!,
+        this.filters!.source_ids[0],
+      );
+      if (!loaded) return;
+      this.toast.success("Successfully refreshed the episodes");
+    } catch (e) {
+      this.error.handleError(e, "Failed to refresh the episodes");
+    }
+    await this.load();
+  }
+
   openSettings() {
     this.router.navigateByUrl("settings");
   }
@@ -445,7 +487,7 @@ export class HomeComponent implements AfterViewInit, OnDestroy {
       this.changeFocusArea(false);
     } else if (tmpFocus > goOverSize && this.focusArea != FocusArea.Tiles) {
       this.changeFocusArea(true);
-    } else if (this.focusArea == FocusArea.Tiles && tmpFocus >= this.filters!.page * 36)
+    } else if (this.focusArea == FocusArea.Tiles && tmpFocus >= this.filters!.page * this.pageSize)
       await this.loadMore();
     else {
       if (tmpFocus >= this.channels.length && this.focusArea == FocusArea.Tiles)
@@ -4,6 +4,8 @@ import { DialogFilter, open } from '@tauri-apps/plugin-dialog';
 import { ErrorService } from '../error.service';
 import { invoke } from '@tauri-apps/api/core';
 import { MemoryService } from '../memory.service';
+import { ImportSummary } from '../models/importSummary';
+import { ToastrService } from 'ngx-toastr';
 
 @Component({
   selector: 'app-import-modal',
@@ -13,7 +15,7 @@ import { MemoryService } from '../memory.service';
 export class ImportModalComponent {
   source_id?: number;
   nameOverride?: string;
-  constructor(public activeModal: NgbActiveModal, public memory: MemoryService) {
+  constructor(public activeModal: NgbActiveModal, public memory: MemoryService, private toastr: ToastrService) {
 
   }
 
@@ -22,11 +24,11 @@ export class ImportModalComponent {
       multiple: false,
       directory: false,
       canCreateDirectories: false,
-      title: "Select Open TV export file (.otv, .otvg)",
+      title: "Select Open TV export file (.otv, .otvg) or Enigma2 bouquet",
       filters:  [
         {
           name: "Extension filter",
-          extensions: ["otv", "otvg"]
+          extensions: ["otv", "otvg", "tv", "radio"]
         }
       ]
     });
@@ -36,9 +38,12 @@ export class ImportModalComponent {
     this.nameOverride = this.nameOverride?.trim();
     if (this.nameOverride == "")
       this.nameOverride = undefined;
+    let summary: ImportSummary | undefined;
     let fail = await this.memory.tryIPC("Successfully imported file", "Failed to import file",
-      () => invoke("import", { sourceId: this.source_id, path: file, nameOverride: this.nameOverride }));
+      async () => summary = await invoke<ImportSummary>("import", { sourceId: this.source_id, path: file, nameOverride: this.nameOverride }));
     this.memory.RefreshSources.next(true);
+    if (summary?.skipped)
+      this.toastr.warning(`${summary.skipped} entries can't be played by Open TV and were skipped`);
     if (!fail)
       this.activeModal.close("close");
   }
@@ -8,6 +8,7 @@ import { ErrorService } from "./error.service";
 import { Channel } from "./models/channel";
 import { NgbModalRef } from "@ng-bootstrap/ng-bootstrap";
 import { invoke } from "@tauri-apps/api/core";
+import { SourceRefreshResult } from "./models/sourceRefreshResult";
 
 @Injectable({
   providedIn: "root",
@@ -33,6 +34,8 @@ export class MemoryService {
   public Watched_epgs: Set<string> = new Set();
   private downloadingChannels: Map<number, [number, Subject<boolean>]> = new Map();
   public LoadingNotification: boolean = false;
+  public SeriesLoadId?: number;
+  private lastSeriesLoadId = 0;
   async tryIPC<T>(
     successMessage: string,
     errorMessage: string,
@@ -51,6 +54,39 @@ export class MemoryService {
     return error;
   }
 
+  // Resolves to false when the load was cancelled with cancelSeriesLoad
+  async loadSeries(command: string, seriesId: number, sourceId: number): Promise<boolean> {
+    const loadId = ++this.lastSeriesLoadId;
+    this.SeriesLoadId = loadId;
+    try {
+      await invoke(command, { seriesId: seriesId, sourceId: sourceId, loadId: loadId });
+      return true;
+    } catch (e) {
+      if (this.SeriesLoadId != loadId)
+        return false;
+      throw e;
+    } finally {
+      if (this.SeriesLoadId == loadId)
+        this.SeriesLoadId = undefined;
+    }
+  }
+
+  async cancelSeriesLoad() {
+    const loadId = this.SeriesLoadId;
+    if (loadId === undefined)
+      return;
+    this.SeriesLoadId = undefined;
+    await invoke("cancel_series_load", { loadId: loadId });
+  }
+
+  async refreshAllSources() {
+    const results: SourceRefreshResult[] = await invoke("refresh_all_sources");
+    for (const result of results.filter((result) => result.error)) {
+      this.toastr.error(result.error, `Failed to refresh ${result.name}`);
+    }
+    this.RefreshSources.next(true);
+  }
+
   async get_epg_ids() {
     let data = await invoke("get_epg_ids");
     let set = new Set(data as Array<string>);
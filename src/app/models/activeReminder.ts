@@ -0,0 +1,6 @@
+import { EPGNotify } from "./epgNotify";
+
+export class ActiveReminder {
+  epg!: EPGNotify;
+  resolved_channel_id?: number;
+}
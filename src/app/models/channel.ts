@@ -10,4 +10,8 @@ export class Channel {
     source_id?: number;
     favorite?: boolean
     stream_id?: number;
+    tvg_id?: string;
+    catchup?: boolean;
+    watched?: boolean;
+    media_types?: number;
 }
\ No newline at end of file
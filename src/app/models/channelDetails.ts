@@ -0,0 +1,17 @@
+import { Channel } from "./channel";
+import { ChannelHeaders } from "./channelHeaders";
+import { EPG } from "./epg";
+import { Group } from "./group";
+
+export class ChannelDetails {
+  channel!: Channel;
+  group?: Group;
+  headers?: ChannelHeaders;
+  favorite!: boolean;
+  catchup!: boolean;
+  last_watched?: number;
+  now?: EPG;
+  next?: EPG;
+  has_note!: boolean;
+  tags!: string[];
+}
@@ -0,0 +1,4 @@
+export class ChannelNote {
+    public note!: string;
+    public updated_at!: number;
+}
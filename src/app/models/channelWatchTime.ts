@@ -0,0 +1,5 @@
+export class ChannelWatchTime {
+    public channel_id!: number;
+    public name!: string;
+    public hours!: number;
+}
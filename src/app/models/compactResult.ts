@@ -0,0 +1,4 @@
+export class CompactResult {
+   public before!: number;
+   public after!: number;
+}
@@ -0,0 +1,4 @@
+export class DayWatchTime {
+    public day!: string;
+    public hours!: number;
+}
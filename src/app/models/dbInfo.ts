@@ -0,0 +1,9 @@
+export class DbInfo {
+   public path!: string;
+   public schema_version!: number;
+   public expected_schema_version!: number;
+   public file_size!: number;
+   public row_counts!: Record<string, number>;
+   public secret_storage!: string;
+   public last_backup_at?: number;
+}
@@ -0,0 +1,8 @@
+export class DeleteImpact {
+    public channels!: number;
+    public groups!: number;
+    public favorites!: number;
+    public headers!: number;
+    public playlist_channels!: number;
+    public reminders!: number;
+}
@@ -0,0 +1,4 @@
+export class DuplicatedGroup {
+    public id!: number;
+    public channel_ids!: number[];
+}
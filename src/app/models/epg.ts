@@ -5,4 +5,5 @@ export class EPG {
   start_time!: string;
   start_timestamp!: number;
   end_time!: string;
+  end_timestamp!: number;
 }
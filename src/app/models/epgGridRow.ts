@@ -0,0 +1,7 @@
+import { Programme } from "./programme";
+
+export class EpgGridRow {
+    public channel_id!: number;
+    public name!: string;
+    public programmes!: Programme[];
+}
@@ -1,6 +1,12 @@
 export class EPGNotify {
   epg_id!: string;
   title!: string;
+  channel_id?: number;
+  tvg_id?: string;
+  source_id?: number;
+  repeat?: number;
+  snooze_minutes?: number;
+  snoozed_until?: number;
   channel_name!: string;
   start_timestamp!: number;
 }
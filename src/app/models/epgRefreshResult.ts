@@ -0,0 +1,6 @@
+export class EpgRefreshResult {
+    public epg_source_id!: number;
+    public programmes!: number;
+    public channels!: number;
+    public pruned!: number;
+}
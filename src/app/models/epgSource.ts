@@ -0,0 +1,8 @@
+export class EpgSource {
+    public id!: number;
+    public name!: string;
+    public url!: string;
+    public source_id?: number;
+    public last_refresh_at?: number;
+    public timezone?: string;
+}
@@ -0,0 +1,73 @@
+import { EpgRefreshResult } from "./epgRefreshResult";
+import { XtreamThrottle } from "./xtreamThrottle";
+
+// Mirrors src-tauri/src/events.rs
+export const SOURCE_REFRESH_PROGRESS = "source_refresh_progress";
+export const IMPORT_PROGRESS = "import_progress";
+export const RECORDING_STATE_CHANGED = "recording_state_changed";
+export const PLAYBACK_STATE_CHANGED = "playback_state_changed";
+export const EPG_REFRESH_DONE = "epg_refresh_done";
+export const HEALTH_CHECK_PROGRESS = "health_check_progress";
+export const FOLDER_SCAN_PROGRESS = "folder_scan_progress";
+export const SERIES_PREFETCH_PROGRESS = "series_prefetch_progress";
+export const REMINDER_FIRED = "reminder_fired";
+
+export const DOWNLOAD_PROGRESS = "progress";
+export const YTDLP_UPDATE_PROGRESS = "ytdlp_update_progress";
+export const RESTREAM_STARTED = "restream_started";
+export const COMPACT_STARTED = "compact_started";
+export const COMPACT_FINISHED = "compact_finished";
+export const SLEEP_TIMER_WARNING = "sleep_timer_warning";
+export const SLEEP_TIMER_ENDED = "sleep_timer_ended";
+export const ADD_SOURCE = "add_source";
+export const BACKUP_FAILED = "backup_failed";
+
+export class OperationEvent {
+    public operation_id!: number;
+}
+
+export class RefreshProgress extends OperationEvent {
+    public source_id!: number;
+    public finished!: boolean;
+    public error?: string;
+}
+
+export class ImportProgress extends OperationEvent {
+    public name!: string;
+    public finished!: boolean;
+    public error?: string;
+    public throttle?: XtreamThrottle;
+}
+
+export class RecordingStateChanged extends OperationEvent {
+    public channel_name!: string;
+    public recording!: boolean;
+    public reason?: string;
+}
+
+export class PlaybackStateChanged extends OperationEvent {
+    public channel_id?: number;
+    public channel_name!: string;
+    public playing!: boolean;
+    public error?: string;
+}
+
+export class EpgRefreshDone extends OperationEvent {
+    public epg_source_id!: number;
+    public result?: EpgRefreshResult;
+    public error?: string;
+}
+
+export class HealthCheckProgress extends OperationEvent {
+    public checked!: number;
+    public total!: number;
+    public dead!: number;
+}
+
+export class ReminderFired extends OperationEvent {
+    public epg_id!: string;
+    public title!: string;
+    public channel_id?: number;
+    public channel_name!: string;
+    public found!: boolean;
+}
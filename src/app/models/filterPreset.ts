@@ -0,0 +1,12 @@
+import { Filters } from "./filters";
+
+export class FilterPreset {
+    public id!: number;
+    public name!: string;
+    public filters!: Filters;
+}
+
+export class AppliedFilterPreset {
+    public filters!: Filters;
+    public missing_sources!: boolean;
+}
@@ -8,6 +8,15 @@ export class Filters {
   public view_type!: ViewMode;
   public page!: number;
   public group_id?: number;
+  public group_ids?: number[];
   public series_id?: number;
   public use_keywords!: boolean;
+  public exclude_dead?: boolean;
+  public playlist_id?: number;
+  public after_id?: number;
+  public after_name?: string;
+  public include_group_names?: boolean;
+  public hide_watched?: boolean;
+  public tag?: string;
+  public advanced_query?: boolean;
 }
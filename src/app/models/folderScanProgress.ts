@@ -0,0 +1,4 @@
+export class FolderScanProgress {
+    public found!: number;
+    public finished!: boolean;
+}
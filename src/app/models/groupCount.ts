@@ -0,0 +1,4 @@
+export class GroupCount {
+    public name!: string;
+    public count!: number;
+}
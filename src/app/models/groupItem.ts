@@ -0,0 +1,10 @@
+export class GroupItem {
+    public id!: number;
+    public name!: string;
+    public image?: string;
+    public source_id!: number;
+    public source_name!: string;
+    public channel_count!: number;
+    public media_types!: number;
+    public favorite!: boolean;
+}
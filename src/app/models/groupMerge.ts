@@ -0,0 +1,5 @@
+export class GroupMerge {
+    public channels!: number;
+    public total_channels!: number;
+    public groups!: number;
+}
@@ -0,0 +1,7 @@
+export class GroupMergeRule {
+    public id!: number;
+    public source_id!: number;
+    public pattern!: string;
+    public target_source_id!: number;
+    public target_name!: string;
+}
@@ -0,0 +1,4 @@
+export class HotkeyConflict {
+  accelerator!: string;
+  message!: string;
+}
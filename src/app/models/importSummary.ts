@@ -0,0 +1,5 @@
+export class ImportSummary {
+    public channels!: number;
+    public duplicates!: number;
+    public skipped!: number;
+}
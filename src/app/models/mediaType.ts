@@ -2,5 +2,6 @@ export enum MediaType {
     livestream = 0,
     movie = 1,
     serie = 2,
-    group = 3
+    group = 3,
+    radio = 4
 }
\ No newline at end of file
@@ -0,0 +1,7 @@
+import { Programme } from "./programme";
+
+export class NowNext {
+    public current?: Programme;
+    public next?: Programme;
+    public progress?: number;
+}
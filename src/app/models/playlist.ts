@@ -0,0 +1,5 @@
+export class Playlist {
+    public id!: number;
+    public name!: string;
+    public channel_count!: number;
+}
@@ -0,0 +1,7 @@
+export class Programme {
+    public tvg_id!: string;
+    public title!: string;
+    public description?: string;
+    public start!: number;
+    public stop!: number;
+}
@@ -0,0 +1,11 @@
+import { Programme } from "./programme";
+
+export class ProgrammeSearchResult {
+    public programme!: Programme;
+    public channel_id!: number;
+    public channel_name!: string;
+    public channel_image?: string;
+    public source_id?: number;
+    public media_type!: number;
+    public catchup!: boolean;
+}
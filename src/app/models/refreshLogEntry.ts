@@ -0,0 +1,8 @@
+import { RefreshStatus } from "./refreshStatus";
+
+export class RefreshLogEntry {
+    public timestamp!: number;
+    public status!: RefreshStatus;
+    public error?: string;
+    public channel_count!: number;
+}
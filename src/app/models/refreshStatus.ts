@@ -0,0 +1,4 @@
+export enum RefreshStatus {
+    Success = 0,
+    Failed = 1
+}
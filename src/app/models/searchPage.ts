@@ -0,0 +1,7 @@
+import { Channel } from "./channel";
+
+export class SearchPage {
+    public channels!: Channel[];
+    public after_id?: number;
+    public after_name?: string;
+}
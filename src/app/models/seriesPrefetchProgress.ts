@@ -0,0 +1,10 @@
+import { XtreamThrottle } from "./xtreamThrottle";
+
+export class SeriesPrefetchProgress {
+    public source_id!: number;
+    public fetched!: number;
+    public total!: number;
+    public throttle?: XtreamThrottle;
+    public finished!: boolean;
+    public error?: string;
+}
@@ -0,0 +1,5 @@
+export class SeriesSummary {
+    public series_id!: number;
+    public episode_count!: number;
+    public last_added_at?: number;
+}
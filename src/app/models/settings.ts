@@ -6,4 +6,46 @@ export class Settings {
   volume?: number;
   refresh_on_start?: boolean;
   restream_port?: number;
+  new_channels_days?: number;
+  preferred_player?: number;
+  health_check_concurrency?: number;
+  refresh_interval_hours?: number;
+  page_size?: number;
+  remember_channel_volume?: boolean;
+  preferred_audio_lang?: string;
+  preferred_subtitle_lang?: string;
+  subtitles_enabled?: boolean;
+  hwdec?: string;
+  vo?: string;
+  ytdlp_auto_update?: boolean;
+  hotkey_show_window?: string;
+  hotkey_play_pause?: string;
+  gamepad_enabled?: boolean;
+  gamepad_mapping?: string;
+  folder_extensions?: string;
+  player_startup_timeout?: number;
+  single_instance_playback?: boolean;
+  recording_filename_template?: string;
+  min_free_space_mb?: number;
+  max_recording_minutes?: number;
+  pip_mode?: boolean;
+  auto_mark_watched?: boolean;
+  epg_media_title?: boolean;
+  epg_retention_days?: number;
+  epg_future_days?: number;
+  xtream_requests_per_second?: number;
+  track_watch_stats?: boolean;
+  hdhomerun_enabled?: boolean;
+  hdhomerun_port?: number;
+  hdhomerun_lan?: boolean;
+  hdhomerun_playlist_id?: number;
+  hdhomerun_max_streams?: number;
+  auto_backup?: string;
+  auto_backup_path?: string;
+  auto_backup_count?: number;
+  proxy_url?: string;
+  use_system_proxy?: boolean;
+  control_api_enabled?: boolean;
+  control_api_port?: number;
+  control_api_lan?: boolean;
 }
@@ -1,3 +1,4 @@
+import { RefreshStatus } from "./refreshStatus";
 import { SourceType } from "./sourceType";
 
 export class Source {
@@ -9,4 +10,12 @@ export class Source {
     source_type?: SourceType;
     enabled?: boolean;
     use_tvg_id?: boolean;
+    last_refresh_at?: number;
+    last_refresh_status?: RefreshStatus;
+    last_refresh_error?: string;
+    channel_count?: number;
+    import_live?: boolean;
+    import_vod?: boolean;
+    import_series?: boolean;
+    group_filter?: string[];
 }
\ No newline at end of file
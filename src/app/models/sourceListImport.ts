@@ -0,0 +1,4 @@
+export class SourceListImport {
+    public imported!: string[];
+    public skipped!: string[];
+}
@@ -0,0 +1,12 @@
+import { Channel } from "./channel";
+import { GroupCount } from "./groupCount";
+
+export class SourcePreview {
+    public token?: number;
+    public channel_count!: number;
+    public media_type_counts!: Record<number, number>;
+    public groups!: GroupCount[];
+    public tvg_id_count!: number;
+    public sample!: Channel[];
+    public duplicates!: number;
+}
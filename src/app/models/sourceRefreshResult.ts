@@ -0,0 +1,9 @@
+export class SourceRefreshResult {
+    public source_id!: number;
+    public name!: string;
+    public added!: number;
+    public updated!: number;
+    public removed!: number;
+    public duplicates!: number;
+    public error?: string;
+}
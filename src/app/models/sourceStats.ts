@@ -0,0 +1,7 @@
+import { MediaType } from "./mediaType";
+import { Source } from "./source";
+
+export class SourceStats extends Source {
+    channels?: Partial<Record<MediaType, number>>;
+    groups?: Partial<Record<MediaType, number>>;
+}
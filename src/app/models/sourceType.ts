@@ -3,5 +3,7 @@ export enum SourceType {
     M3ULink = 1,
     Xtream = 2,
     Custom = 3,
-    CustomImport = 4
+    CustomImport = 4,
+    Folder = 5,
+    TVHeadend = 6
 }
\ No newline at end of file
@@ -0,0 +1,8 @@
+import { IdName } from "./idName";
+
+export class StartupState {
+    public database_error?: string;
+    public corrupt_database_backup?: string;
+    public rolled_back_imports!: string[];
+    public interrupted_imports!: IdName[];
+}
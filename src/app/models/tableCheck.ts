@@ -0,0 +1,5 @@
+export class TableCheck {
+   public table!: string;
+   public total!: number;
+   public invalid!: number;
+}
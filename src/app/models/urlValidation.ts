@@ -0,0 +1,6 @@
+export class UrlValidation {
+    public valid!: boolean;
+    public code!: number;
+    public message?: string;
+    public http_status?: number;
+}
@@ -1,5 +1,7 @@
 export enum ViewMode {
     All,
     Favorites,
-    Categories
+    Categories,
+    New,
+    Playlist
 }
\ No newline at end of file
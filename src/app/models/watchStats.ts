@@ -0,0 +1,9 @@
+import { ChannelWatchTime } from "./channelWatchTime";
+import { DayWatchTime } from "./dayWatchTime";
+
+export class WatchStats {
+    public total_hours!: number;
+    public top_channels!: ChannelWatchTime[];
+    public media_type_hours!: Record<number, number>;
+    public days!: DayWatchTime[];
+}
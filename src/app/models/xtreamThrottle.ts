@@ -0,0 +1,6 @@
+export class XtreamThrottle {
+    public attempt!: number;
+    public wait_seconds!: number;
+    public status?: number;
+    public banned!: boolean;
+}
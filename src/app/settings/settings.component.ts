@@ -3,12 +3,18 @@ import { debounceTime, distinctUntilChanged, fromEvent, map, Subscription } from
 import { Settings } from "../models/settings";
 import { invoke } from "@tauri-apps/api/core";
 import { Router } from "@angular/router";
-import { open } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
+import { SourceListImport } from "../models/sourceListImport";
 import { Source } from "../models/source";
 import { MemoryService } from "../memory.service";
 import { ViewMode } from "../models/viewMode";
 import { NgbModal } from "@ng-bootstrap/ng-bootstrap";
 import { ConfirmDeleteModalComponent } from "../confirm-delete-modal/confirm-delete-modal.component";
+import { ToastrService } from "ngx-toastr";
+import { HotkeyConflict } from "../models/hotkeyConflict";
+import { Playlist } from "../models/playlist";
+import { DbInfo } from "../models/dbInfo";
+import { writeText } from "@tauri-apps/plugin-clipboard-manager";
 
 @Component({
   selector: "app-settings",
@@ -25,6 +31,10 @@ export class SettingsComponent {
   };
   viewModeEnum = ViewMode;
   sources: Source[] = [];
+  playlists: Playlist[] = [];
+  lastBackupAt?: number;
+  controlApiToken?: string;
+  showToken = false;
   @ViewChild("mpvParams") mpvParams!: ElementRef;
 
   constructor(
@@ -32,6 +42,7 @@ export class SettingsComponent {
     public memory: MemoryService,
     private nav: Router,
     private modal: NgbModal,
+    private toastr: ToastrService,
   ) {}
 
   isInputFocused(): boolean {
@@ -62,6 +73,9 @@ export class SettingsComponent {
   ngOnInit(): void {
     this.getSettings();
     this.getSources();
+    this.getPlaylists();
+    this.getLastBackup();
+    this.getControlApiToken();
   }
 
   getSettings() {
@@ -71,9 +85,62 @@ export class SettingsComponent {
       if (this.settings.default_view == undefined) this.settings.default_view = ViewMode.All;
       if (this.settings.volume == undefined) this.settings.volume = 100;
       if (this.settings.restream_port == undefined) this.settings.restream_port = 3000;
+      if (this.settings.hdhomerun_port == undefined) this.settings.hdhomerun_port = 5004;
+      if (this.settings.hdhomerun_max_streams == undefined) this.settings.hdhomerun_max_streams = 1;
+      if (this.settings.auto_backup == undefined) this.settings.auto_backup = "off";
+      if (this.settings.auto_backup_count == undefined) this.settings.auto_backup_count = 5;
+      if (this.settings.control_api_port == undefined) this.settings.control_api_port = 5005;
     });
   }
 
+  getPlaylists() {
+    invoke("get_playlists").then((x) => (this.playlists = x as Playlist[]));
+  }
+
+  getLastBackup() {
+    invoke<DbInfo>("get_db_info").then((info) => (this.lastBackupAt = info.last_backup_at));
+  }
+
+  describeLastBackup(): string {
+    if (this.lastBackupAt == undefined) return "Never";
+    const days = Math.floor((Date.now() / 1000 - this.lastBackupAt) / 86400);
+    if (days == 0) return "Today";
+    if (days == 1) return "Yesterday";
+    return `${days} days ago`;
+  }
+
+  getControlApiToken() {
+    invoke<string | undefined>("get_control_api_token").then(
+      (token) => (this.controlApiToken = token ?? undefined),
+    );
+  }
+
+  async regenerateControlApiToken() {
+    if (!confirm("Apps using the current token will have to be given the new one. Continue?")) return;
+    await this.memory.tryIPC("Generated a new token", "Failed to generate a new token", async () => {
+      this.controlApiToken = await invoke<string>("regenerate_control_api_token");
+      this.showToken = true;
+    });
+  }
+
+  async copyControlApiToken() {
+    if (!this.controlApiToken) return;
+    await writeText(this.controlApiToken);
+    this.toastr.info("Token copied to the clipboard");
+  }
+
+  async selectBackupFolder() {
+    const folder = await open({
+      multiple: false,
+      directory: true,
+      canCreateDirectories: true,
+    });
+    if (folder) {
+      this.settings.auto_backup_path = folder;
+      await this.updateSettings();
+    }
+  }
+
   getSources() {
     invoke("get_sources").then((x) => {
       this.sources = x as Source[];
@@ -112,10 +179,50 @@ export class SettingsComponent {
 
   async refreshAll() {
     await this.memory.tryIPC("Successfully updated all sources", "Failed to refresh sources", () =>
-      invoke("refresh_all"),
+      this.memory.refreshAllSources(),
+    );
+  }
+
+  async exportSources() {
+    const path = await save({
+      defaultPath: "open-tv-sources.json",
+      filters: [{ name: "Source list", extensions: ["json"] }],
+    });
+    if (!path) return;
+    const includeCredentials = confirm("Include usernames and passwords in the exported file?");
+    await this.memory.tryIPC("Successfully exported sources", "Failed to export sources", () =>
+      invoke("export_sources", { path, includeCredentials }),
     );
   }
 
+  async importSources() {
+    const path = await open({
+      multiple: false,
+      directory: false,
+      filters: [{ name: "Source list", extensions: ["json"] }],
+    });
+    if (!path) return;
+    await this.memory.tryIPC("Successfully imported sources", "Failed to import sources", async () => {
+      const summary: SourceListImport = await invoke("import_sources", { path });
+      if (summary.skipped.length > 0) {
+        this.toastr.info(`Skipped existing sources: ${summary.skipped.join(", ")}`);
+      }
+      this.getSources();
+    });
+  }
+
+  async moveDatabase() {
+    const newPath = await save({
+      defaultPath: "db.sqlite",
+      filters: [{ name: "Database", extensions: ["sqlite"] }],
+    });
+    if (!newPath) return;
+    await this.memory.tryIPC("Successfully moved the database", "Failed to move the database", async () => {
+      const path: string = await invoke("migrate_database_location", { newPath });
+      this.toastr.info(`Open TV now uses ${path}, the previous file was kept`);
+    });
+  }
+
   async goBack() {
     await this.updateSettings();
     this.router.navigateByUrl("");
@@ -123,7 +230,18 @@ export class SettingsComponent {
 
   async updateSettings() {
     if (this.settings.mpv_params) this.settings.mpv_params = this.settings.mpv_params?.trim();
-    await invoke("update_settings", { settings: this.settings });
+    let conflicts: HotkeyConflict[];
+    try {
+      conflicts = await invoke("update_settings", { settings: this.settings });
+    } catch (e) {
+      this.toastr.error(`Failed to save settings: ${e}`);
+      return;
+    }
+    for (const conflict of conflicts) {
+      this.toastr.error(`Could not register ${conflict.accelerator}: ${conflict.message}`);
+    }
+    // the token is created the first time the api is enabled
+    if (this.settings.control_api_enabled && !this.controlApiToken) this.getControlApiToken();
   }
 
   async selectFolder() {
@@ -1,6 +1,7 @@
 import { Component, Input } from '@angular/core';
 import { Source } from '../../models/source';
 import { SourceType } from '../../models/sourceType';
+import { RefreshStatus } from '../../models/refreshStatus';
 import { invoke } from '@tauri-apps/api/core';
 import { MemoryService } from '../../memory.service';
 import { EditChannelModalComponent } from '../../edit-channel-modal/edit-channel-modal.component';
@@ -8,6 +9,9 @@ import { NgbModal } from '@ng-bootstrap/ng-bootstrap';
 import { EditGroupModalComponent } from '../../edit-group-modal/edit-group-modal.component';
 import { ImportModalComponent } from '../../import-modal/import-modal.component';
 import { open } from '@tauri-apps/plugin-dialog';
+import { listen } from '@tauri-apps/api/event';
+import { SERIES_PREFETCH_PROGRESS } from '../../models/events';
+import { SeriesPrefetchProgress } from '../../models/seriesPrefetchProgress';
 
 @Component({
   selector: 'app-source-tile',
@@ -21,8 +25,11 @@ export class SourceTileComponent {
   showPassword = false;
   loading = false;
   sourceTypeEnum = SourceType;
+  refreshStatusEnum = RefreshStatus;
   editing = false;
   editableSource: Source = {};
+  groupFilter: string[] = [];
+  prefetch?: SeriesPrefetchProgress;
 
   constructor(public memory: MemoryService, private modal: NgbModal) {
   }
@@ -35,6 +42,22 @@ export class SourceTileComponent {
 
   async refresh() {
     await this.memory.tryIPC("Successfully updated source", "Failed to refresh source", () => invoke("refresh_source", { source: this.source }));
+    this.memory.RefreshSources.next(true);
+  }
+
+  async prefetchEpisodes() {
+    const unlisten = await listen<SeriesPrefetchProgress>(SERIES_PREFETCH_PROGRESS, (event) => {
+      if (event.payload.source_id == this.source?.id)
+        this.prefetch = event.payload;
+    });
+    await this.memory.tryIPC("Successfully loaded the episodes", "Failed to load the episodes", async () => {
+      const progress = await invoke<SeriesPrefetchProgress>("prefetch_series_episodes", { sourceId: this.source?.id });
+      // the provider stopped answering, what was loaded is kept for the next run
+      if (progress.error)
+        throw progress.error;
+    });
+    unlisten();
+    this.prefetch = undefined;
   }
 
   async delete() {
@@ -76,9 +99,18 @@ export class SourceTileComponent {
     );
   }
 
-  edit() {
+  async edit() {
     this.editableSource = { ...this.source };
     this.editing = true;
+    this.groupFilter = await invoke<string[]>("get_source_group_filter", { sourceId: this.source?.id });
+  }
+
+  async clearGroupFilter() {
+    await this.memory.tryIPC("All groups will be imported on next refresh", "Failed to clear group filter",
+      async () => {
+        await invoke("set_source_group_filter", { sourceId: this.source?.id, groups: [] });
+        this.groupFilter = [];
+    });
   }
 
   async save() {
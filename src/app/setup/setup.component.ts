@@ -6,6 +6,10 @@ import { invoke } from '@tauri-apps/api/core';
 import { SourceType } from '../models/sourceType';
 import { Source } from '../models/source';
 import { open } from '@tauri-apps/plugin-dialog';
+import { listen } from '@tauri-apps/api/event';
+import { FolderScanProgress } from '../models/folderScanProgress';
+import { IMPORT_PROGRESS, ImportProgress } from '../models/events';
+import { XtreamThrottle } from '../models/xtreamThrottle';
 import { ConfirmModalComponent } from './confirm-modal/confirm-modal.component';
 import { MemoryService } from '../memory.service';
 import { ErrorService } from '../error.service';
@@ -19,11 +23,18 @@ export class SetupComponent {
   constructor(private nav: Router,
     private toastr: ToastrService, private modalService: NgbModal, public memory: MemoryService, private error: ErrorService, private modal: NgbModal) { }
   loading = false;
+  scanned?: number;
+  throttle?: XtreamThrottle;
+  previewedGroups?: string[];
+  suggestion?: Source;
   sourceTypeEnum = SourceType;
   source: Source = {
     source_type: SourceType.M3U,
     enabled: true,
-    use_tvg_id: true
+    use_tvg_id: true,
+    import_live: true,
+    import_vod: true,
+    import_series: true
   };
 
   @HostListener('document:keydown', ['$event'])
@@ -42,10 +53,55 @@ export class SetupComponent {
   }
 
   ngOnInit(): void {
+    let url = history.state?.url;
+    if (url) {
+      this.source.source_type = SourceType.M3ULink;
+      this.source.url = url;
+      this.detectSourceType(url);
+    }
+  }
+
+  onUrlPaste(event: ClipboardEvent) {
+    const url = event.clipboardData?.getData('text')?.trim();
+    if (url)
+      this.detectSourceType(url);
+  }
+
+  async detectSourceType(url: string) {
+    this.suggestion = undefined;
+    let suggestion: Source | null;
+    try {
+      suggestion = await invoke<Source | null>("detect_source_type", { url: url });
+    }
+    catch (e) {
+      // only a hint, the type can always be picked by hand
+      return;
+    }
+    // the url may have been replaced while the portal was asked
+    if (!suggestion || this.source.url?.trim() != url)
+      return;
+    // a playlist pasted as one, or a portal url with nothing to fill in
+    if (suggestion.source_type == this.source.source_type
+      && (suggestion.source_type == SourceType.M3ULink || (suggestion.url == url && !suggestion.username)))
+      return;
+    this.suggestion = suggestion;
+  }
+
+  acceptSuggestion() {
+    if (!this.suggestion)
+      return;
+    const suggestion = this.suggestion;
+    this.switchMode(suggestion.source_type);
+    this.source.url = suggestion.url;
+    this.source.username = suggestion.username;
+    this.source.password = suggestion.password;
   }
 
   switchMode(sourceType: SourceType) {
     this.source.source_type = sourceType;
+    this.suggestion = undefined;
+    this.previewedGroups = undefined;
+    this.source.group_filter = undefined;
   }
 
   goBack() {
@@ -73,6 +129,51 @@ export class SetupComponent {
     this.loading = false;
   }
 
+  async getFolder() {
+    this.removeUnusedFieldsFromSource();
+    this.source.use_tvg_id = undefined;
+    const folder = await open({
+      multiple: false,
+      directory: true,
+    });
+    if (folder == null) {
+      return;
+    }
+    this.loading = true;
+    this.source.url = folder;
+    const unlisten = await listen<FolderScanProgress>("folder_scan_progress", (event) => {
+      this.scanned = event.payload.found;
+    });
+    try {
+      await invoke("get_folder", { source: this.source });
+      this.success();
+    }
+    catch (e) {
+      this.error.handleError(e, "Could not scan selected folder");
+    }
+    unlisten();
+    this.scanned = undefined;
+    this.loading = false;
+  }
+
+  async previewGroups() {
+    this.loading = true;
+    try {
+      this.previewedGroups = await invoke<string[]>("preview_source_groups", { source: this.source });
+    }
+    catch (e) {
+      this.error.handleError(e, "Could not fetch the groups of this source");
+    }
+    this.loading = false;
+  }
+
+  toggleGroup(group: string) {
+    let groups = this.source.group_filter ?? [];
+    this.source.group_filter = groups.includes(group) ? groups.filter((x) => x != group) : [...groups, group];
+    if (this.source.group_filter.length == 0)
+      this.source.group_filter = undefined;
+  }
+
   success() {
     this.toastr.success(`${this.source.name} successfully added`);
     this.nav.navigateByUrl("");
@@ -101,6 +202,12 @@ export class SetupComponent {
       case SourceType.CustomImport:
         await this.customImport();
         break;
+      case SourceType.Folder:
+        await this.getFolder();
+        break;
+      case SourceType.TVHeadend:
+        await this.getTvheadend();
+        break;
     }
   }
 
@@ -177,6 +284,10 @@ export class SetupComponent {
         this.source.url = url.toString();
       }
     }
+    const unlisten = await listen<ImportProgress>(IMPORT_PROGRESS, (event) => {
+      if (event.payload.name == this.source.name)
+        this.throttle = event.payload.throttle;
+    });
     try {
       await invoke("get_xtream", { source: this.source });
       this.success();
@@ -184,6 +295,26 @@ export class SetupComponent {
     catch (e) {
       this.error.handleError(e, "Invalid URL or credentials. Please try again");
     }
+    unlisten();
+    this.throttle = undefined;
+    this.loading = false;
+  }
+
+  async getTvheadend() {
+    this.loading = true;
+    this.source.url = this.source.url?.trim();
+    this.source.username = this.source.username?.trim() || undefined;
+    if (!this.source?.url?.startsWith('http://') && !this.source?.url?.startsWith('https://')) {
+      this.source.url = `http://${this.source.url}`;
+      this.toastr.info("Since the given URL lacked a protocol, http was assumed");
+    }
+    try {
+      await invoke("get_tvheadend", { source: this.source });
+      this.success();
+    }
+    catch (e) {
+      this.error.handleError(e, "Invalid URL or credentials. Please try again");
+    }
     this.loading = false;
   }
 